@@ -0,0 +1,90 @@
+use std::io::{self, Cursor};
+use std::sync::mpsc::channel;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use sds011_exporter::*;
+
+/// The protocol document's example query reply
+const FRAME: [u8; 10] = [0xAA, 0xC0, 0xD4, 0x04, 0x3A, 0x0A, 0xA1, 0x60, 0x1D, 0xAB];
+
+/// Frames in each synthetic stream
+const FRAMES: usize = 1000;
+
+/// A stream of valid frames, with a garbage byte after every tenth to exercise
+/// resyncing
+fn stream() -> Vec<u8> {
+  let mut bytes = Vec::with_capacity(FRAMES * 11);
+  for i in 0..FRAMES {
+    bytes.extend_from_slice(&FRAME);
+    if i % 10 == 0 {
+      bytes.push(0x42);
+    }
+  }
+
+  bytes
+}
+
+fn bench_checksum(c: &mut Criterion) {
+  let mut group = c.benchmark_group("checksum");
+
+  group.bench_function("reply (6 bytes)", |b| b.iter(|| checksum(black_box(&FRAME[2..8]))));
+  group.bench_function("command (15 bytes)", |b| b.iter(|| checksum(black_box(&[0x04; 15]))));
+
+  group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+  let mut group = c.benchmark_group("decode");
+
+  group.bench_function("parse_packet", |b| b.iter(|| parse_packet(black_box(&FRAME))));
+  group.bench_function("parse_frame", |b| b.iter(|| parse_frame(black_box(&FRAME))));
+
+  group.finish();
+}
+
+fn bench_reader(c: &mut Criterion) {
+  let bytes = stream();
+
+  let mut group = c.benchmark_group("read path");
+  group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+  group.bench_function("PacketReader + parse_frame", |b| b.iter(|| {
+    let mut reader = PacketReader::default();
+    let mut parsed = 0;
+
+    for byte in &bytes {
+      if let Some(ReadEvent::Packet(packet)) = reader.push(*byte) {
+        if parse_frame(&packet).is_ok() {
+          parsed += 1;
+        }
+      }
+    }
+
+    assert_eq!(parsed, FRAMES);
+  }));
+
+  // includes spawning the read and write threads for each stream
+  group.bench_function("open_sensor_stream", |b| b.iter(|| {
+    let (_command_tx, command_rx) = channel();
+    let (response_tx, response_rx) = channel();
+    let (control_tx, _control_rx) = channel();
+
+    open_sensor_stream(
+      "bench",
+      Cursor::new(bytes.clone()),
+      io::sink(),
+      SensorOptions::default(),
+      command_rx,
+      response_tx,
+      control_tx
+    );
+
+    // the read thread drops its sender at the end of the stream
+    assert_eq!(response_rx.iter().count(), FRAMES);
+  }));
+
+  group.finish();
+}
+
+criterion_group!(benches, bench_checksum, bench_parse, bench_reader);
+criterion_main!(benches);
@@ -0,0 +1,129 @@
+#ifndef SDS011_H
+#define SDS011_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Returned on success by `sds011_decode_frame()`
+#define SDS011_OK 0
+
+// A required pointer was null
+#define SDS011_ERR_NULL -1
+
+// The frame isn't 10 bytes long, or the output buffer is too small
+#define SDS011_ERR_LENGTH -2
+
+// The frame's checksum doesn't match its data
+#define SDS011_ERR_CHECKSUM -3
+
+// The frame's command id (or, for 0xC5 replies, its sub-command) isn't known
+#define SDS011_ERR_UNKNOWN_COMMAND -4
+
+// An argument is out of range, e.g. a working period over 30 minutes
+#define SDS011_ERR_INVALID_ARGUMENT -5
+
+// Length of a frame sent by the sensor
+#define SDS011_FRAME_LEN 10
+
+// Length of a command frame sent to the sensor
+#define SDS011_COMMAND_LEN 19
+
+// Which command a decoded frame replies to
+typedef enum {
+  // a reading, whether actively reported or queried
+  SDS011_RESPONSE_KIND_QUERY,
+  SDS011_RESPONSE_KIND_SET_REPORTING_MODE,
+  SDS011_RESPONSE_KIND_SET_DEVICE_ID,
+  SDS011_RESPONSE_KIND_SET_SLEEP_WORK,
+  SDS011_RESPONSE_KIND_SET_WORKING_PERIOD,
+  SDS011_RESPONSE_KIND_GET_FIRMWARE_VERSION,
+} Sds011ResponseKind;
+
+// A decoded frame. Only the fields relevant to its `kind` are set; the rest
+// are zeroed.
+typedef struct {
+  Sds011ResponseKind kind;
+  // the id of the sensor that sent the frame (for `SetDeviceId`, its new id)
+  uint16_t device;
+  // PM2.5 in µg/m³, for `Query`
+  float pm25;
+  // PM10 in µg/m³, for `Query`
+  float pm10;
+  // true if the setting was queried rather than changed
+  bool query;
+  // the setting's value: for `SetReportingMode` 0 is active and 1 is query,
+  // for `SetSleepWork` 0 is sleep and 1 is work, and for `SetWorkingPeriod`
+  // it's the period in minutes, 0 being continuous
+  uint8_t value;
+  // the firmware date, for `GetFirmwareVersion`; the year is 2-digit
+  uint8_t year;
+  uint8_t month;
+  uint8_t day;
+} Sds011Response;
+
+// Decodes a frame sent by the sensor into `out`, returning `SDS011_OK` or a
+// negative `SDS011_ERR_*` code, in which case `out` is left untouched.
+//
+// # Safety
+//
+// `frame` must point to `len` readable bytes, and `out` to a writable
+// `Sds011Response`.
+int32_t sds011_decode_frame(const uint8_t *frame, size_t len, Sds011Response *out);
+
+// Encodes a query for the latest reading into `out`, returning the number of
+// bytes written (always `SDS011_COMMAND_LEN`) or a negative `SDS011_ERR_*`
+// code. Every `sds011_encode_*` function behaves the same way.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_query(uint8_t *out, size_t len);
+
+// Encodes a command to query (if `query`) or set the reporting mode: active
+// if `active`, otherwise query.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_set_reporting_mode(bool query, bool active, uint8_t *out, size_t len);
+
+// Encodes a command to change the sensor's device id to `id`.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_set_device_id(uint16_t id, uint8_t *out, size_t len);
+
+// Encodes a command to query (if `query`) or set whether the sensor works or
+// sleeps.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_set_sleep_work(bool query, bool work, uint8_t *out, size_t len);
+
+// Encodes a command to query (if `query`) or set the working period, in
+// minutes from 0 (continuous) to 30.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_set_working_period(bool query, uint8_t minutes, uint8_t *out, size_t len);
+
+// Encodes a command to get the sensor's firmware version.
+//
+// # Safety
+//
+// `out` must point to `len` writable bytes.
+int32_t sds011_encode_get_firmware_version(uint8_t *out, size_t len);
+
+// Computes the AQI for a PM2.5 concentration in µg/m³; see `pm25_aqi()`
+uint16_t sds011_pm25_aqi(float concentration);
+
+// Computes the AQI for a PM10 concentration in µg/m³; see `pm10_aqi()`
+uint16_t sds011_pm10_aqi(float concentration);
+
+#endif /* SDS011_H */
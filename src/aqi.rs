@@ -0,0 +1,115 @@
+use crate::response::QueryResponse;
+
+/// US EPA air quality index categories
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum AqiCategory {
+  Good,
+  Moderate,
+  UnhealthyForSensitiveGroups,
+  Unhealthy,
+  VeryUnhealthy,
+  Hazardous
+}
+
+impl AqiCategory {
+  pub fn from_aqi(aqi: u16) -> Self {
+    match aqi {
+      0..=50 => AqiCategory::Good,
+      51..=100 => AqiCategory::Moderate,
+      101..=150 => AqiCategory::UnhealthyForSensitiveGroups,
+      151..=200 => AqiCategory::Unhealthy,
+      201..=300 => AqiCategory::VeryUnhealthy,
+      _ => AqiCategory::Hazardous
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      AqiCategory::Good => "Good",
+      AqiCategory::Moderate => "Moderate",
+      AqiCategory::UnhealthyForSensitiveGroups => "Unhealthy for Sensitive Groups",
+      AqiCategory::Unhealthy => "Unhealthy",
+      AqiCategory::VeryUnhealthy => "Very Unhealthy",
+      AqiCategory::Hazardous => "Hazardous"
+    }
+  }
+
+  /// The EPA's standard color for this category as (r, g, b)
+  pub fn color(&self) -> (u8, u8, u8) {
+    match self {
+      AqiCategory::Good => (0, 228, 0),
+      AqiCategory::Moderate => (255, 255, 0),
+      AqiCategory::UnhealthyForSensitiveGroups => (255, 126, 0),
+      AqiCategory::Unhealthy => (255, 0, 0),
+      AqiCategory::VeryUnhealthy => (143, 63, 151),
+      AqiCategory::Hazardous => (126, 0, 35)
+    }
+  }
+}
+
+/// (concentration low, concentration high, index low, index high)
+type Breakpoint = (f32, f32, u16, u16);
+
+/// PM2.5 breakpoints per the EPA's 2024 revision, in ug/m^3 (24-hour)
+const PM25_BREAKPOINTS: &[Breakpoint] = &[
+  (0.0, 9.0, 0, 50),
+  (9.1, 35.4, 51, 100),
+  (35.5, 55.4, 101, 150),
+  (55.5, 125.4, 151, 200),
+  (125.5, 225.4, 201, 300),
+  (225.5, 325.4, 301, 500),
+];
+
+/// PM10 breakpoints in ug/m^3 (24-hour)
+const PM10_BREAKPOINTS: &[Breakpoint] = &[
+  (0.0, 54.0, 0, 50),
+  (55.0, 154.0, 51, 100),
+  (155.0, 254.0, 101, 150),
+  (255.0, 354.0, 151, 200),
+  (355.0, 424.0, 201, 300),
+  (425.0, 604.0, 301, 500),
+];
+
+fn interpolate(concentration: f32, breakpoints: &[Breakpoint]) -> u16 {
+  if concentration <= 0.0 {
+    return 0;
+  }
+
+  for &(c_lo, c_hi, i_lo, i_hi) in breakpoints {
+    // concentrations are truncated before lookup, so anything between one
+    // range's high and the next range's low belongs to the next range
+    if concentration <= c_hi {
+      let c = concentration.max(c_lo);
+      let index = (i_hi - i_lo) as f32 / (c_hi - c_lo) * (c - c_lo) + i_lo as f32;
+      return index.round() as u16;
+    }
+  }
+
+  // off the charts
+  500
+}
+
+/// Computes the AQI for a PM2.5 concentration in ug/m^3
+pub fn pm25_aqi(concentration: f32) -> u16 {
+  interpolate((concentration * 10.0).trunc() / 10.0, PM25_BREAKPOINTS)
+}
+
+/// Computes the AQI for a PM10 concentration in ug/m^3
+pub fn pm10_aqi(concentration: f32) -> u16 {
+  interpolate(concentration.trunc(), PM10_BREAKPOINTS)
+}
+
+impl QueryResponse {
+  /// The overall AQI of this reading, i.e. the worse of the PM2.5 and PM10
+  /// indices.
+  ///
+  /// Note that the AQI is formally defined over 24-hour averages; applied to
+  /// a single reading it's only an indication.
+  pub fn aqi(&self) -> u16 {
+    pm25_aqi(self.pm25).max(pm10_aqi(self.pm10))
+  }
+
+  pub fn aqi_category(&self) -> AqiCategory {
+    AqiCategory::from_aqi(self.aqi())
+  }
+}
@@ -0,0 +1,98 @@
+//! `sds011 completions`: shell completion scripts, completing device arguments
+//! with the serial ports present at the time
+
+use std::io;
+
+use anyhow::Result;
+use structopt::StructOpt;
+use structopt::clap::Shell;
+
+use crate::Command;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct CompletionsAction {
+  /// the shell to generate completions for, e.g.
+  /// `sds011 completions bash > /etc/bash_completion.d/sds011`
+  #[structopt(
+    possible_values = &Shell::variants(),
+    case_insensitive = true,
+    required_unless = "devices"
+  )]
+  shell: Option<Shell>,
+
+  /// lists serial ports, one per line; the generated scripts call this to
+  /// complete device arguments
+  #[structopt(long, hidden = true, conflicts_with = "shell")]
+  devices: bool
+}
+
+/// Appended to bash scripts: drops clap's `<device>`-style placeholders and
+/// offers serial ports in their place
+const BASH_DEVICES: &str = r#"
+_sds011_devices() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" word placeholder reply=()
+    _sds011 "$@"
+    for word in "${COMPREPLY[@]}"; do
+        case "$word" in
+            \<*) placeholder=1 ;;
+            *) reply+=("$word") ;;
+        esac
+    done
+    if [[ -n "$placeholder" || "$cur" == /* ]]; then
+        reply+=($(compgen -W "$(sds011 completions --devices 2>/dev/null)" -- "$cur"))
+    fi
+    COMPREPLY=("${reply[@]}")
+}
+complete -F _sds011_devices -o bashdefault -o default sds011
+"#;
+
+/// Appended to zsh scripts: wraps clap's completion function, adding serial
+/// ports to anything after the subcommand that isn't an option
+const ZSH_DEVICES: &str = r#"
+functions[_sds011_clap]=$functions[_sds011]
+_sds011() {
+    local ret=1
+    _sds011_clap "$@" && ret=0
+    if (( CURRENT > 2 )) && [[ "$PREFIX" != -* ]]; then
+        local -a devices
+        devices=(${(f)"$(sds011 completions --devices 2>/dev/null)"})
+        compadd -X 'serial device' -a devices && ret=0
+    fi
+    return ret
+}
+"#;
+
+/// Appended to fish scripts: offers serial ports after each subcommand that
+/// takes devices
+const FISH_DEVICES: &str = r#"
+function __sds011_devices
+    sds011 completions --devices 2>/dev/null
+end
+complete -c sds011 -n "__fish_seen_subcommand_from export tool watch" -f \
+    -a "(__sds011_devices)" -d 'serial device'
+"#;
+
+pub fn completions(action: CompletionsAction) -> Result<()> {
+  let shell = match action.shell {
+    Some(shell) if !action.devices => shell,
+    _ => {
+      for port in serialport::available_ports()? {
+        println!("{}", port.port_name);
+      }
+
+      return Ok(());
+    }
+  };
+
+  // clap can't complete device arguments itself, so leave its script as-is
+  // and add a function that asks us for serial ports instead
+  Command::clap().gen_completions_to("sds011", shell, &mut io::stdout());
+  match shell {
+    Shell::Bash => print!("{}", BASH_DEVICES),
+    Shell::Zsh => print!("{}", ZSH_DEVICES),
+    Shell::Fish => print!("{}", FISH_DEVICES),
+    _ => ()
+  }
+
+  Ok(())
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use sds011_exporter::{ControlMessage, Error, ErrorKind};
+
+/// Number of invalid frames after which an unanswered command is reported as
+/// a checksum storm rather than a silent sensor
+const CHECKSUM_STORM_THRESHOLD: usize = 5;
+
+/// Invalid frames received from any open sensor
+static INVALID_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Set once SIGINT or SIGTERM is received, if `stop_on_signal()` was called
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Process exit codes, so scripts can branch on the kind of failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+  Success = 0,
+
+  /// Any failure not covered below
+  Failure = 1,
+
+  /// Invalid or missing arguments
+  InvalidArguments = 2,
+
+  /// The serial device couldn't be opened (missing, busy, or not permitted)
+  DeviceNotFound = 3,
+
+  /// The sensor never answered a command
+  NoResponse = 4,
+
+  /// The sensor never answered a command, but sent a stream of invalid
+  /// frames; usually a bad cable, adapter, or power supply
+  ChecksumStorm = 5
+}
+
+impl ExitCode {
+  /// Determines the exit code for an error returned by a subcommand
+  pub fn from_error(error: &anyhow::Error) -> ExitCode {
+    if error.is::<UsageError>() {
+      return ExitCode::InvalidArguments;
+    }
+
+    match error.downcast_ref::<Error>() {
+      Some(Error::SerialPortError(_))
+        | Some(Error::TransportError(_))
+        | Some(Error::DeviceMissing { .. })
+        | Some(Error::DeviceAccess { .. })
+        | Some(Error::DeviceBusy(_)) => ExitCode::DeviceNotFound,
+      Some(Error::RetriesExceeded { .. }) => {
+        if INVALID_FRAMES.load(Ordering::Relaxed) >= CHECKSUM_STORM_THRESHOLD {
+          ExitCode::ChecksumStorm
+        } else {
+          ExitCode::NoResponse
+        }
+      },
+      Some(Error::InvalidWorkMode(_))
+        | Some(Error::InvalidReportingMode(_))
+        | Some(Error::InvalidWorkingPeriod { .. })
+        | Some(Error::InvalidWorkingPeriodUnit { .. })
+        | Some(Error::WorkingPeriodOutOfRange(_))
+        | Some(Error::InvalidDeviceId(_)) => ExitCode::InvalidArguments,
+      _ => ExitCode::Failure
+    }
+  }
+}
+
+/// An error in the arguments that clap couldn't catch itself
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl std::error::Error for UsageError {}
+
+/// Passes control messages through unchanged, counting invalid frames along
+/// the way
+pub fn count_invalid_frames(control_rx: Receiver<ControlMessage>) -> Receiver<ControlMessage> {
+  let (tx, rx) = channel();
+
+  thread::spawn(move || {
+    for message in control_rx {
+      match &message {
+        ControlMessage::Error(e @ Error::PacketError(_))
+          | ControlMessage::Error(e @ Error::Suppressed { kind: ErrorKind::Packet, .. }) => {
+          INVALID_FRAMES.fetch_add(e.count(), Ordering::Relaxed);
+        },
+        _ => ()
+      }
+
+      if tx.send(message).is_err() {
+        break;
+      }
+    }
+  });
+
+  rx
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+  STOPPING.store(true, Ordering::SeqCst);
+
+  // a second signal exits immediately, as usual
+  unsafe {
+    libc::signal(signal, libc::SIG_DFL);
+  }
+}
+
+/// Catches SIGINT and SIGTERM rather than exiting immediately, so a long
+/// running subcommand can stop cleanly once `stopping()`, e.g. to finish
+/// writing a file. Does nothing on other platforms.
+pub fn stop_on_signal() {
+  #[cfg(unix)]
+  unsafe {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    libc::signal(libc::SIGINT, handler);
+    libc::signal(libc::SIGTERM, handler);
+  }
+}
+
+/// True once a signal caught by `stop_on_signal()` was received
+pub fn stopping() -> bool {
+  STOPPING.load(Ordering::SeqCst)
+}
@@ -0,0 +1,2570 @@
+//! `sds011 export`: serves readings as Prometheus metrics, JSON, and more
+
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "modbus")]
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(any(feature = "coap", feature = "snmp"))]
+use std::net::UdpSocket;
+#[cfg(feature = "modbus")]
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, SecondsFormat, Timelike, Utc};
+#[cfg(feature = "mdns")]
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use structopt::StructOpt;
+use sds011_exporter::clock::Timestamp;
+use sds011_exporter::command::*;
+use sds011_exporter::companion::*;
+#[cfg(feature = "coap")]
+use sds011_exporter::coap::{CborWriter, CoapCode, CoapMessage, CoapType};
+#[cfg(feature = "snmp")]
+use sds011_exporter::snmp::{Oid, SnmpRequest, SnmpValue};
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+use sds011_exporter::humidity::HumidityCorrection;
+use sds011_exporter::metadata::SensorMetadata;
+#[cfg(feature = "modbus")]
+use sds011_exporter::modbus::{ModbusRegisterMap, ModbusRequest, ModbusState};
+use sds011_exporter::quality::{ReadingQuality, WARMUP};
+use sds011_exporter::response::{QueryResponse, Resp, ResponseKindSet};
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  fetch_info, find_sensor, pm10_aqi, pm25_aqi, retry_send_default,
+  DeviceSelector, Histogram, LinkStats, PeakHold, RestartPolicy, RetryConfig, Schedule,
+  ScheduleRule, SensorOptions, Supervisor, SupervisorConfig, TimeOfDay,
+  DEFAULT_READING_DEDUP_WINDOW
+};
+use sds011_exporter::logging::LogFormat;
+use serde_json::{self, json};
+use simple_prometheus_exporter::{Exporter, export};
+use tokio::sync::{mpsc, watch};
+use tokio::task;
+use tokio::time::delay_for;
+use warp::filters::BoxedFilter;
+use warp::http::header::{HeaderValue, CACHE_CONTROL, ETAG};
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use crate::exit::UsageError;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct Options {
+  /// sensor serial device, e.g. /dev/ttyUSB0
+  #[structopt(parse(from_os_str), required_unless_one = &["device-serial", "device-id", "sensors"])]
+  device: Option<PathBuf>,
+
+  /// serve several sensors instead, each as NAME=DEVICE and separated by
+  /// commas, e.g. "kitchen=/dev/ttyUSB0,bedroom=/dev/ttyUSB1"; metrics are
+  /// labeled with sensor="NAME", and each reading is also served at
+  /// /sensors/NAME/json
+  #[structopt(
+    long = "sensor",
+    use_delimiter = true,
+    conflicts_with_all = &["device", "device-serial", "device-id"],
+    env = "SDS011_SENSORS"
+  )]
+  sensors: Vec<SensorSpec>,
+
+  /// a name for the sensor, added to metrics as a `sensor` label and to /json
+  #[structopt(long, parse(try_from_str = parse_sensor_name), conflicts_with = "sensors", env = "SDS011_NAME")]
+  name: Option<String>,
+
+  /// where the sensor is, e.g. "north wall", added to metrics as a `location`
+  /// label and to /json; with --sensor, given for each as NAME=LOCATION
+  #[structopt(long = "location", number_of_values = 1, env = "SDS011_LOCATION")]
+  locations: Vec<String>,
+
+  /// a second sensor to fail over to while the first isn't producing valid
+  /// readings, e.g. /dev/ttyUSB1; sds011_active_unit shows which is in use
+  #[structopt(long, parse(from_os_str), conflicts_with = "sensors", env = "SDS011_BACKUP")]
+  backup: Option<PathBuf>,
+
+  /// find the sensor by its USB serial adapter's serial number instead, so
+  /// the config survives device nodes being renumbered
+  #[structopt(long, conflicts_with_all = &["device", "device-id"], env = "SDS011_DEVICE_SERIAL")]
+  device_serial: Option<String>,
+
+  /// find the sensor by its device id instead, e.g. 0xA1B2, by asking each
+  /// USB serial port
+  #[structopt(long, conflicts_with = "device", env = "SDS011_DEVICE_ID")]
+  device_id: Option<DeviceId>,
+
+  /// port for the http server
+  #[structopt(long, short, default_value = "8082", env = "SDS011_PORT")]
+  port: u16,
+
+  /// interface address for the http server
+  #[structopt(long, default_value = "0.0.0.0", env = "SDS011_ADDRESS")]
+  address: IpAddr,
+
+  /// where the http server listens, either ADDRESS:PORT, unix:PATH for a
+  /// Unix domain socket, or fd:N for an inherited listening socket; overrides
+  /// --address and --port. Sockets passed by systemd socket activation are
+  /// used automatically.
+  #[structopt(long, env = "SDS011_LISTEN")]
+  listen: Option<Listen>,
+
+  /// if set, serve the /debug endpoints only here, e.g. 127.0.0.1:8083 or
+  /// unix:/run/sds011-control.sock, rather than alongside the metrics
+  #[structopt(long, env = "SDS011_CONTROL_LISTEN")]
+  control_listen: Option<Listen>,
+
+  /// if set, also serve the latest reading as CBOR at coap://ADDRESS/pm for
+  /// constrained clients, e.g. 0.0.0.0:5683
+  #[cfg(feature = "coap")]
+  #[structopt(long, env = "SDS011_COAP")]
+  coap: Option<SocketAddr>,
+
+  /// if set, also answer SNMPv2c requests here, e.g. 0.0.0.0:161
+  #[cfg(feature = "snmp")]
+  #[structopt(long, env = "SDS011_SNMP")]
+  snmp: Option<SocketAddr>,
+
+  /// the community SNMP requests must give; others are ignored
+  #[cfg(feature = "snmp")]
+  #[structopt(long, default_value = "public", env = "SDS011_SNMP_COMMUNITY")]
+  snmp_community: String,
+
+  /// where the SNMP table of sensors is rooted; the default is under the
+  /// enterprise number reserved for documentation, so set your own if you
+  /// have one
+  #[cfg(feature = "snmp")]
+  #[structopt(long, default_value = "1.3.6.1.4.1.32473.1", env = "SDS011_SNMP_BASE_OID")]
+  snmp_base_oid: Oid,
+
+  /// if set, also serve readings as Modbus TCP registers here, e.g.
+  /// 0.0.0.0:502; unit 1 is the first sensor, 2 the second, and so on
+  #[cfg(feature = "modbus")]
+  #[structopt(long, env = "SDS011_MODBUS")]
+  modbus: Option<SocketAddr>,
+
+  /// which value each Modbus register holds, e.g. 0=pm25,1=pm10,2=aqi, from:
+  /// pm25, pm10 (in tenths of µg/m³), aqi, up, age (seconds since the
+  /// reading), errors, fatal_errors, device; by default, all of them in that
+  /// order from 0
+  #[cfg(feature = "modbus")]
+  #[structopt(long, env = "SDS011_MODBUS_REGISTERS")]
+  modbus_registers: Option<ModbusRegisterMap>,
+
+  /// log format, one of: text, json
+  #[structopt(long, default_value = "text", env = "SDS011_LOG_FORMAT")]
+  log_format: LogFormat,
+
+  /// default /json payload shape, one of: v1 (the latest reading only), v2
+  /// (nested, with units, AQI, and device id); override per request with
+  /// ?schema=
+  #[structopt(long, default_value = "v1", env = "SDS011_JSON_SCHEMA")]
+  json_schema: JsonSchema,
+
+  /// how long browsers and proxies may cache /json, in seconds; with 0 they
+  /// must revalidate each time, which is cheap as the ETag only changes with
+  /// a new reading
+  #[structopt(long, default_value = "0", env = "SDS011_CACHE_MAX_AGE")]
+  cache_max_age: u64,
+
+  /// device working period in minutes, e.g. 5 or 5m; 0 reports every second
+  /// at the cost of accuracy, while 1-30 (inclusive) report once measurement
+  /// every `n` minutes, with 30 seconds of data collection.
+  #[structopt(long, default_value = "1", env = "SDS011_WORKING_PERIOD")]
+  working_period: WorkingPeriod,
+
+  /// working period rules by local time of day, of the form HH:MM=PERIOD and
+  /// separated by commas, e.g. "07:00=0,22:00=30"; overrides --working-period
+  #[structopt(long, env = "SDS011_SCHEDULE")]
+  schedule: Option<Schedule>,
+
+  /// if set, also export histograms of readings with these bucket upper
+  /// bounds, separated by commas, e.g. "5,10,25,50,100"
+  #[structopt(long, use_delimiter = true, env = "SDS011_HISTOGRAM_BUCKETS")]
+  histogram_buckets: Vec<f32>,
+
+  /// the number of recent invalid frames to keep for /debug/errors; 0
+  /// disables it
+  #[structopt(long, default_value = "10", env = "SDS011_INVALID_FRAMES")]
+  invalid_frames: usize,
+
+  /// the number of recent sensor events and errors to keep for /debug/events;
+  /// 0 disables it
+  #[structopt(long, default_value = "50", env = "SDS011_DEBUG_EVENTS")]
+  debug_events: usize,
+
+  /// strip the local echo of each command, for USB adapters that echo
+  /// transmitted bytes; echoes are counted as confirmed writes
+  #[structopt(long, env = "SDS011_LOCAL_ECHO")]
+  local_echo: bool,
+
+  /// drop a reading repeating the previous one's values within 5 seconds, as
+  /// some firmware reports twice at the end of a working period; not for
+  /// continuous reporting, where readings are a second apart
+  #[structopt(long, env = "SDS011_DEDUP_READINGS")]
+  dedup_readings: bool,
+
+  /// attach each reading's receive time to the sds011_pm25 and sds011_pm10
+  /// samples, rather than leaving Prometheus to use the scrape time
+  #[structopt(long, env = "SDS011_EXPORT_TIMESTAMPS")]
+  export_timestamps: bool,
+
+  /// what to do if a sensor is lost, one of: exit, retry-forever (reopening
+  /// it every 10 seconds), retry-N-then-exit (e.g. retry-5-then-exit), or
+  /// serve-stale (keep serving its last reading, flagged stale)
+  #[structopt(long, default_value = "exit", env = "SDS011_ON_FAILURE")]
+  on_failure: FailurePolicy,
+
+  /// leave out the sds011_pm25 or sds011_pm10 gauge while its reading is
+  /// saturated (999.9), rather than exporting a misleading plateau;
+  /// sds011_saturated is exported either way
+  #[structopt(long, env = "SDS011_WITHHOLD_SATURATED")]
+  withhold_saturated: bool,
+
+  /// advertise the exporter on the local network over mDNS/DNS-SD, as a
+  /// _prometheus-http._tcp service and an _sds011._tcp service per sensor
+  #[cfg(feature = "mdns")]
+  #[structopt(long, env = "SDS011_MDNS")]
+  mdns: bool,
+
+  /// a temperature/humidity sensor to read over I²C and export alongside, one
+  /// of: sht3x, bme280
+  #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+  #[structopt(long, env = "SDS011_COMPANION")]
+  companion: Option<CompanionKind>,
+
+  /// the I²C bus the companion sensor is on
+  #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+  #[structopt(
+    long,
+    default_value = "/dev/i2c-1",
+    parse(from_os_str),
+    env = "SDS011_COMPANION_BUS"
+  )]
+  companion_bus: PathBuf,
+
+  /// the companion sensor's I²C address, e.g. 0x45, if not its default (0x44
+  /// for sht3x, 0x76 for bme280)
+  #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+  #[structopt(long, parse(try_from_str = parse_i2c_address), env = "SDS011_COMPANION_ADDRESS")]
+  companion_address: Option<u16>,
+
+  /// how to correct readings for humidity using the companion sensor, one of:
+  /// none, kohler, kohler:<kappa>, piecewise; corrected readings are exported
+  /// as sds011_pm25_corrected and sds011_pm10_corrected, labeled by model
+  #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+  #[structopt(long, default_value = "none", env = "SDS011_HUMIDITY_CORRECTION")]
+  humidity_correction: HumidityCorrection
+}
+
+/// A sensor to serve, from `--sensor`
+#[derive(Debug, Clone)]
+struct SensorSpec {
+  name: String,
+  device: PathBuf
+}
+
+impl FromStr for SensorSpec {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let (name, device) = s.split_once('=')
+      .ok_or_else(|| anyhow!("invalid sensor '{}', expected NAME=DEVICE", s))?;
+
+    Ok(SensorSpec {
+      name: parse_sensor_name(name)?,
+      device: device.into()
+    })
+  }
+}
+
+/// Checks a sensor name, which ends up in urls and label values
+fn parse_sensor_name(name: &str) -> Result<String> {
+  let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+  if name.is_empty() || !name.chars().all(valid) {
+    return Err(anyhow!(
+      "invalid sensor name '{}', expected letters, digits, '-', and '_' only", name
+    ));
+  }
+
+  Ok(name.to_string())
+}
+
+/// Each sensor's location from `--location`, keyed by name with `--sensor`
+fn parse_locations(opts: &Options) -> Result<HashMap<Option<String>, String>> {
+  let mut locations = HashMap::new();
+  for location in &opts.locations {
+    if opts.sensors.is_empty() {
+      locations.insert(None, location.clone());
+      continue;
+    }
+
+    let (name, location) = location.split_once('=')
+      .filter(|(name, _)| opts.sensors.iter().any(|s| s.name == *name))
+      .ok_or_else(|| anyhow!(
+        "invalid location '{}', expected NAME=LOCATION naming a --sensor", location
+      ))?;
+
+    locations.insert(Some(name.to_string()), location.to_string());
+  }
+
+  Ok(locations)
+}
+
+/// Parses an I²C address in hex (with a 0x prefix) or decimal
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+fn parse_i2c_address(s: &str) -> Result<u16> {
+  let address = match s.strip_prefix("0x") {
+    Some(hex) => u16::from_str_radix(hex, 16),
+    None => s.parse()
+  };
+
+  address.map_err(|e| anyhow!("invalid i2c address '{}': {}", s, e))
+}
+
+/// The shape of the /json payload, so existing consumers don't break
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+enum JsonSchema {
+  /// `{"datetime", "pm25", "pm10"}`, or null without a reading; frozen, so
+  /// new fields go in later versions
+  V1,
+
+  /// `{"schema", "up", "sensor", "reading"}`, with each measurement's value,
+  /// unit, and AQI, and the device id; fields may be added, but existing ones
+  /// keep their name and type
+  V2
+}
+
+impl FromStr for JsonSchema {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "v1" => Ok(JsonSchema::V1),
+      "v2" => Ok(JsonSchema::V2),
+      _ => Err(anyhow!("invalid json schema '{}', expected v1 or v2", s))
+    }
+  }
+}
+
+/// How long to wait before each attempt to reopen a lost sensor
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// What the exporter does once a sensor is lost
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FailurePolicy {
+  /// stop serving and exit with an error
+  Exit,
+
+  /// keep trying to reopen it
+  RetryForever,
+
+  /// try to reopen it this many times in a row, then exit
+  RetryThenExit(usize),
+
+  /// keep serving its last reading, which is flagged stale once old enough
+  ServeStale
+}
+
+impl FailurePolicy {
+  fn restart_policy(&self) -> RestartPolicy {
+    let max_restarts = match self {
+      FailurePolicy::Exit | FailurePolicy::ServeStale => return RestartPolicy::Never,
+      FailurePolicy::RetryForever => None,
+      FailurePolicy::RetryThenExit(n) => Some(*n)
+    };
+
+    RestartPolicy::Restart {
+      delay: RESTART_DELAY,
+      max_restarts
+    }
+  }
+}
+
+impl FromStr for FailurePolicy {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let lower = s.to_ascii_lowercase();
+    let retries = lower.strip_prefix("retry-")
+      .and_then(|rest| rest.strip_suffix("-then-exit"))
+      .and_then(|n| n.parse().ok());
+
+    match (lower.as_str(), retries) {
+      ("exit", _) => Ok(FailurePolicy::Exit),
+      ("retry-forever", _) => Ok(FailurePolicy::RetryForever),
+      ("serve-stale", _) => Ok(FailurePolicy::ServeStale),
+      (_, Some(n)) => Ok(FailurePolicy::RetryThenExit(n)),
+      _ => Err(anyhow!(
+        "invalid failure policy '{}', expected exit, retry-forever, retry-N-then-exit, or \
+        serve-stale",
+        s
+      ))
+    }
+  }
+}
+
+/// Where an http server listens
+#[derive(Debug, Clone)]
+enum Listen {
+  Tcp(SocketAddr),
+
+  /// a Unix domain socket, replacing any stale socket at the path
+  #[cfg(unix)]
+  Unix(PathBuf),
+
+  /// an already listening TCP or Unix socket, e.g. from systemd
+  #[cfg(unix)]
+  Fd(std::os::unix::io::RawFd)
+}
+
+/// The first file descriptor passed by socket activation; see sd_listen_fds(3)
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Takes the sockets passed by systemd socket activation, if any, in order
+#[cfg(unix)]
+fn activated_sockets() -> Vec<Listen> {
+  let pid = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+  let count = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse::<i32>().ok());
+
+  // they aren't meant for any child processes
+  for name in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+    std::env::remove_var(name);
+  }
+
+  match (pid, count) {
+    (Some(pid), Some(count)) if pid == std::process::id() => {
+      (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count).map(Listen::Fd).collect()
+    },
+    _ => Vec::new()
+  }
+}
+
+#[cfg(not(unix))]
+fn activated_sockets() -> Vec<Listen> {
+  Vec::new()
+}
+
+/// Whether a socket is a Unix domain socket rather than TCP
+#[cfg(unix)]
+fn is_unix_socket(fd: std::os::unix::io::RawFd) -> std::io::Result<bool> {
+  let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+  let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+  let ret = unsafe {
+    libc::getsockname(fd, &mut address as *mut _ as *mut libc::sockaddr, &mut len)
+  };
+  if ret != 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+
+  Ok(address.ss_family as libc::c_int == libc::AF_UNIX)
+}
+
+impl FromStr for Listen {
+  type Err = anyhow::Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.strip_prefix("unix:") {
+      #[cfg(unix)]
+      Some(path) => Ok(Listen::Unix(path.into())),
+
+      #[cfg(not(unix))]
+      Some(_) => Err(anyhow!("unix sockets aren't supported on this platform")),
+
+      #[cfg(unix)]
+      None if s.starts_with("fd:") => s[3..].parse()
+        .map(Listen::Fd)
+        .map_err(|e| anyhow!("invalid file descriptor '{}': {}", s, e)),
+
+      None => s.parse()
+        .map(Listen::Tcp)
+        .map_err(|e| anyhow!("invalid listen address '{}': {}", s, e))
+    }
+  }
+}
+
+impl fmt::Display for Listen {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Listen::Tcp(address) => write!(f, "{}", address),
+
+      #[cfg(unix)]
+      Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+
+      #[cfg(unix)]
+      Listen::Fd(fd) => write!(f, "fd:{}", fd)
+    }
+  }
+}
+
+/// This host's name, without any domain, to name its mDNS services
+#[cfg(all(unix, feature = "mdns"))]
+fn hostname() -> Result<String> {
+  let mut name = [0u8; 256];
+  let ret = unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) };
+  if ret != 0 {
+    return Err(std::io::Error::last_os_error().into());
+  }
+
+  let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+  let name = String::from_utf8_lossy(&name[..len]);
+  Ok(name.split('.').next().unwrap_or_default().to_string())
+}
+
+#[cfg(all(not(unix), feature = "mdns"))]
+fn hostname() -> Result<String> {
+  std::env::var("COMPUTERNAME").map_err(|_| anyhow!("could not determine the host name"))
+}
+
+/// Advertises `/metrics` as a `_prometheus-http._tcp` service, and each
+/// sensor's readings as an `_sds011._tcp` service with its name, location, and
+/// device in TXT records. The returned daemon answers queries until dropped.
+#[cfg(feature = "mdns")]
+fn advertise(listen: &Listen, sensors: &[Sensor]) -> Result<Option<ServiceDaemon>> {
+  let address = match listen {
+    Listen::Tcp(address) => address,
+    _ => {
+      warn!("not advertising over mDNS, as {} isn't a TCP address", listen);
+      return Ok(None);
+    }
+  };
+
+  let host = hostname()?;
+  let host_name = format!("{}.local.", host);
+  let service = |ty: &str, instance: &str, properties: HashMap<String, String>| {
+    // advertise whichever addresses the host has if listening on all of them
+    let info = if address.ip().is_unspecified() {
+      ServiceInfo::new(ty, instance, &host_name, "", address.port(), properties)?
+        .enable_addr_auto()
+    } else {
+      ServiceInfo::new(ty, instance, &host_name, address.ip(), address.port(), properties)?
+    };
+
+    info!("advertising {} as {}", instance, ty);
+    Ok::<_, mdns_sd::Error>(info)
+  };
+
+  let daemon = ServiceDaemon::new()?;
+
+  let mut properties = HashMap::new();
+  properties.insert("path".to_string(), "/metrics".to_string());
+  daemon.register(service("_prometheus-http._tcp.local.", &host, properties)?)?;
+
+  for sensor in sensors {
+    let (instance, path) = match &sensor.name {
+      Some(name) => (format!("{} {}", host, name), format!("/sensors/{}/json", name)),
+      None => (host.clone(), "/json".to_string())
+    };
+
+    let mut properties: HashMap<_, _> = sensor.metadata.set()
+      .map(|(field, value)| (field.to_string(), value.to_string()))
+      .collect();
+    properties.insert("path".to_string(), path);
+    properties.insert("device".to_string(), sensor.device.display().to_string());
+
+    daemon.register(service("_sds011._tcp.local.", &instance, properties)?)?;
+  }
+
+  Ok(Some(daemon))
+}
+
+/// How often the sensor's configuration is queried for export and checked
+/// against the configured settings
+const CONFIG_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The sensor's configuration as last queried
+#[derive(Debug, Clone, Copy)]
+struct SensorConfig {
+  reporting_mode: ReportingMode,
+  work_mode: WorkMode,
+  working_period: WorkingPeriod
+}
+
+impl SensorConfig {
+  /// Whether the sensor is awake and reporting with `mode` at `period`
+  fn matches(&self, mode: ReportingMode, period: WorkingPeriod) -> bool {
+    self.reporting_mode == mode
+      && self.work_mode == WorkMode::Work
+      && self.working_period == period
+  }
+}
+
+/// State shared between the background tasks and the http routes
+#[derive(Debug)]
+struct ExporterState {
+  /// the sensor's configuration as last queried
+  current: watch::Receiver<Option<SensorConfig>>,
+
+  /// the number of times the sensor's settings were found changed and
+  /// re-applied
+  corrections: AtomicUsize,
+
+  /// true once active reporting was found broken and readings are polled
+  /// instead
+  polling: AtomicBool,
+
+  /// when each background task last did its work, for /debug/state
+  heartbeats: Mutex<HashMap<&'static str, Timestamp>>,
+
+  /// when the sensor was last told to wake, to flag readings during warmup
+  woke: Mutex<Option<Timestamp>>,
+
+  /// the latest companion sensor reading, if there is one; cleared if a read
+  /// fails so stale values aren't exported
+  climate: watch::Receiver<Option<Climate>>,
+
+  /// time between readings as they actually arrive
+  intervals: Mutex<Intervals>,
+
+  /// periods without readings
+  gaps: Mutex<Gaps>,
+
+  /// the highest recent readings
+  peaks: Mutex<Peaks>,
+
+  /// the last reading received before the sensor was lost, kept for
+  /// `--on-failure serve-stale`
+  stale: Mutex<Option<(Timestamp, QueryResponse)>>
+}
+
+/// Upper bounds, in seconds, of reading interval histogram buckets: around
+/// continuous reporting, then 30 seconds past each common working period, so
+/// a sensor that drifts or changes period lands in a different bucket
+const INTERVAL_BUCKETS: &[f32] = &[
+  1.5, 5.0, 30.0, 90.0, 150.0, 210.0, 330.0, 630.0, 930.0, 1230.0, 1830.0, 3600.0
+];
+
+/// Time between received readings
+#[derive(Debug)]
+struct Intervals {
+  /// the most recent interval
+  last: Option<Duration>,
+
+  histogram: Histogram
+}
+
+impl Default for Intervals {
+  fn default() -> Self {
+    Intervals {
+      last: None,
+      histogram: Histogram::new(INTERVAL_BUCKETS)
+    }
+  }
+}
+
+/// The highest readings over the last hour and day, so spikes between scrapes
+/// still show up
+#[derive(Debug)]
+struct Peaks {
+  pm25_1h: PeakHold,
+  pm25_24h: PeakHold,
+  pm10_1h: PeakHold,
+  pm10_24h: PeakHold
+}
+
+impl Default for Peaks {
+  fn default() -> Self {
+    let hour = Duration::from_secs(60 * 60);
+    let day = hour * 24;
+
+    Peaks {
+      pm25_1h: PeakHold::new(hour),
+      pm25_24h: PeakHold::new(day),
+      pm10_1h: PeakHold::new(hour),
+      pm10_24h: PeakHold::new(day)
+    }
+  }
+}
+
+/// How much longer than the working period the sensor may go without a
+/// reading before it's counted as a gap
+const GAP_SLACK: Duration = Duration::from_secs(30);
+
+/// The number of ended gaps kept for /gaps
+const GAP_HISTORY: usize = 100;
+
+/// A period without readings: from the last reading before it until the next
+#[derive(Debug, Clone, Copy)]
+struct Gap {
+  since: Timestamp,
+  until: Timestamp,
+
+  /// how much of the time between them readings were missing for, i.e. less
+  /// the working period
+  missing: Duration
+}
+
+/// Periods without readings, beyond what the working period explains, so
+/// clean air can be told from a sensor that was down
+#[derive(Debug)]
+struct Gaps {
+  /// when the last reading arrived, or when the sensor was started
+  last: Timestamp,
+
+  /// the total missing time of gaps that have ended
+  ended: Duration,
+
+  /// the most recent gaps that have ended, oldest first
+  recent: VecDeque<Gap>
+}
+
+impl Gaps {
+  fn new() -> Self {
+    Gaps {
+      last: Timestamp::now(),
+      ended: Duration::default(),
+      recent: VecDeque::new()
+    }
+  }
+
+  /// The gap from the last reading until `until`, if it's long enough to be
+  /// one given the `expected` time between readings
+  fn gap(&self, until: Timestamp, expected: Duration) -> Option<Gap> {
+    let silent = until.duration_since(self.last);
+    if silent <= expected + GAP_SLACK {
+      return None;
+    }
+
+    Some(Gap {
+      since: self.last,
+      until,
+      missing: silent - expected
+    })
+  }
+
+  fn reading(&mut self, time: Timestamp, expected: Duration) {
+    if let Some(gap) = self.gap(time, expected) {
+      self.ended += gap.missing;
+      self.recent.push_back(gap);
+      if self.recent.len() > GAP_HISTORY {
+        self.recent.pop_front();
+      }
+    }
+
+    self.last = time;
+  }
+
+  /// Missing time of every gap so far, including one still going on
+  fn total(&self, expected: Duration) -> Duration {
+    let ongoing = self.gap(Timestamp::now(), expected).map(|gap| gap.missing);
+    self.ended + ongoing.unwrap_or_default()
+  }
+}
+
+impl ExporterState {
+  fn new(
+    current: watch::Receiver<Option<SensorConfig>>,
+    climate: watch::Receiver<Option<Climate>>
+  ) -> Self {
+    ExporterState {
+      current,
+      corrections: AtomicUsize::default(),
+      polling: AtomicBool::default(),
+      heartbeats: Mutex::default(),
+      woke: Mutex::default(),
+      climate,
+      intervals: Mutex::default(),
+      gaps: Mutex::new(Gaps::new()),
+      peaks: Mutex::default(),
+      stale: Mutex::default()
+    }
+  }
+
+  fn beat(&self, task: &'static str) {
+    self.heartbeats.lock().unwrap().insert(task, Timestamp::now());
+  }
+
+  fn woke(&self) {
+    *self.woke.lock().unwrap() = Some(Timestamp::now());
+  }
+
+  /// The reporting mode the sensor should be in
+  fn reporting_mode(&self) -> ReportingMode {
+    if self.polling.load(Ordering::Relaxed) {
+      ReportingMode::Query
+    } else {
+      ReportingMode::Active
+    }
+  }
+}
+
+/// The latest readings as CBOR, shaped like /json: the only sensor's reading if
+/// it was given by path, or otherwise each sensor's, keyed by name
+#[cfg(feature = "coap")]
+fn coap_readings(sensors: &[Sensor]) -> Vec<u8> {
+  let mut cbor = CborWriter::new();
+  match sensors {
+    [sensor] if sensor.name.is_none() => {
+      let active = sensor.active();
+      cbor.reading(latest_reading(&active.supervisor, &active.state));
+    },
+    _ => {
+      cbor.map(sensors.len());
+      for sensor in sensors {
+        let active = sensor.active();
+        cbor.text(sensor.name.as_deref().unwrap_or_default())
+          .reading(latest_reading(&active.supervisor, &active.state));
+      }
+    }
+  }
+
+  cbor.into_bytes()
+}
+
+/// Answers CoAP requests for `/pm` with the latest readings. Runs on its own
+/// thread, as every request is answered straight away.
+#[cfg(feature = "coap")]
+fn serve_coap(socket: UdpSocket, sensors: Arc<Vec<Sensor>>) {
+  let mut datagram = [0u8; 1152];
+  let mut message_id = 0u16;
+
+  loop {
+    let (len, peer) = match socket.recv_from(&mut datagram) {
+      Ok(received) => received,
+      Err(e) => {
+        warn!("error receiving CoAP request: {}", e);
+        continue;
+      }
+    };
+
+    let request = match CoapMessage::parse(&datagram[..len]) {
+      Some(request) => request,
+      None => {
+        debug!("ignoring invalid CoAP message from {}", peer);
+        continue;
+      }
+    };
+
+    let response = match request.kind {
+      // nothing here expects a reply
+      CoapType::Acknowledgement | CoapType::Reset => continue,
+
+      // e.g. a ping
+      _ if !request.code.is_request() => request.reset(),
+
+      _ => {
+        message_id = message_id.wrapping_add(1);
+        if request.path != ["pm"] {
+          request.response(CoapCode::NOT_FOUND, message_id, &[])
+        } else if request.code != CoapCode::GET {
+          request.response(CoapCode::METHOD_NOT_ALLOWED, message_id, &[])
+        } else {
+          request.response(CoapCode::CONTENT, message_id, &coap_readings(&sensors))
+        }
+      }
+    };
+
+    if let Err(e) = socket.send_to(&response.to_bytes(), peer) {
+      warn!("error sending CoAP response to {}: {}", peer, e);
+    }
+  }
+}
+
+/// What a sensor's Modbus registers are read from
+#[cfg(feature = "modbus")]
+fn modbus_state(sensor: &Sensor) -> ModbusState {
+  let active = sensor.active();
+  let stats = active.supervisor.stats();
+
+  ModbusState {
+    latest: latest_reading(&active.supervisor, &active.state),
+    up: stats.up,
+    errors: stats.errors,
+    fatal_errors: stats.fatal_errors
+  }
+}
+
+/// Answers register reads from one Modbus TCP client until it disconnects.
+/// Unit 0 or 255, as used to address a TCP device directly, is the first sensor.
+#[cfg(feature = "modbus")]
+fn serve_modbus_client(
+  mut stream: TcpStream,
+  map: &ModbusRegisterMap,
+  sensors: &[Sensor]
+) -> std::io::Result<()> {
+  while let Some(request) = ModbusRequest::read_from(&mut stream)? {
+    let sensor = match request.unit_id {
+      0 | 255 => sensors.first(),
+      unit => sensors.get(unit as usize - 1)
+    };
+
+    let state = sensor.map(modbus_state);
+    stream.write_all(&request.respond(map, state.as_ref()))?;
+  }
+
+  Ok(())
+}
+
+/// Serves Modbus TCP clients, each on its own thread as SCADA systems tend to
+/// hold their connection open
+#[cfg(feature = "modbus")]
+fn serve_modbus(listener: TcpListener, map: ModbusRegisterMap, sensors: Arc<Vec<Sensor>>) {
+  let map = Arc::new(map);
+  for stream in listener.incoming() {
+    let stream = match stream {
+      Ok(stream) => stream,
+      Err(e) => {
+        warn!("error accepting Modbus connection: {}", e);
+        continue;
+      }
+    };
+
+    let map = Arc::clone(&map);
+    let sensors = Arc::clone(&sensors);
+    std::thread::spawn(move || {
+      if let Err(e) = serve_modbus_client(stream, &map, &sensors) {
+        debug!("Modbus connection closed: {}", e);
+      }
+    });
+  }
+}
+
+/// Every sensor's values as SNMP objects, sorted: a table at `base.1` with an
+/// entry (`base.1.1.COLUMN.INDEX`) per sensor, numbered from 1
+#[cfg(feature = "snmp")]
+fn snmp_mib(base: &Oid, sensors: &[Sensor]) -> Vec<(Oid, SnmpValue)> {
+  let entry = base.child(&[1, 1]);
+  let mut mib = Vec::new();
+
+  for (i, sensor) in sensors.iter().enumerate() {
+    let index = i as u32 + 1;
+    let active = sensor.active();
+    let stats = active.supervisor.stats();
+    let name = sensor.name.clone().unwrap_or_else(|| sensor.device.display().to_string());
+
+    let mut column = |column: u32, value| mib.push((entry.child(&[column, index]), value));
+    column(1, SnmpValue::Integer(index as i64));
+    column(2, SnmpValue::OctetString(name.into_bytes()));
+
+    // left out without a reading, so they read as noSuchInstance
+    if let Some((time, r)) = latest_reading(&active.supervisor, &active.state) {
+      column(3, SnmpValue::Gauge32((r.pm25 * 10.0).round() as u32));
+      column(4, SnmpValue::Gauge32((r.pm10 * 10.0).round() as u32));
+      column(5, SnmpValue::Gauge32(r.aqi() as u32));
+      column(9, SnmpValue::Gauge32(time.elapsed().as_secs() as u32));
+    }
+
+    // a TruthValue: 1 for true, 2 for false
+    column(6, SnmpValue::Integer(if stats.up { 1 } else { 2 }));
+
+    // counters wrap, as SNMP managers expect
+    column(7, SnmpValue::Counter32(stats.errors as u32));
+    column(8, SnmpValue::Counter32(stats.fatal_errors as u32));
+  }
+
+  mib.sort_by(|a, b| a.0.cmp(&b.0));
+  mib
+}
+
+/// Answers SNMPv2c requests with the community `community` on its own thread;
+/// the agent is read-only, so sets fail
+#[cfg(feature = "snmp")]
+fn serve_snmp(socket: UdpSocket, community: String, base: Oid, sensors: Arc<Vec<Sensor>>) {
+  let mut datagram = [0u8; 4096];
+
+  loop {
+    let (len, peer) = match socket.recv_from(&mut datagram) {
+      Ok(received) => received,
+      Err(e) => {
+        warn!("error receiving SNMP request: {}", e);
+        continue;
+      }
+    };
+
+    let request = match SnmpRequest::parse(&datagram[..len]) {
+      Some(request) if request.community == community.as_bytes() => request,
+      Some(_) => {
+        debug!("ignoring SNMP request from {} with the wrong community", peer);
+        continue;
+      },
+      None => {
+        debug!("ignoring invalid SNMP message from {}", peer);
+        continue;
+      }
+    };
+
+    let response = request.respond(&snmp_mib(&base, &sensors));
+    if let Err(e) = socket.send_to(&response, peer) {
+      warn!("error sending SNMP response to {}: {}", peer, e);
+    }
+  }
+}
+
+/// The latest reading, or if the sensor was lost, its last one with
+/// `--on-failure serve-stale`
+fn latest_reading(
+  supervisor: &Supervisor,
+  state: &ExporterState
+) -> Option<(Timestamp, QueryResponse)> {
+  supervisor.latest().or_else(|| state.stale.lock().unwrap().clone())
+}
+
+/// Histograms of every reading received, if enabled
+struct Histograms {
+  pm25: Histogram,
+  pm10: Histogram
+}
+
+/// Where a sensor falls among those served, so their periodic work can be
+/// spread out rather than all wake and query at once
+#[derive(Debug, Clone, Copy)]
+struct Stagger {
+  index: u32,
+  count: u32
+}
+
+impl Stagger {
+  /// How long to delay this sensor's first run of work repeated every `interval`
+  fn offset(&self, interval: Duration) -> Duration {
+    interval * self.index / self.count
+  }
+}
+
+/// A sensor being served, with everything its routes need
+#[derive(Clone)]
+struct Sensor {
+  /// from `--sensor`, keying its routes; none for a single sensor given by
+  /// path
+  name: Option<String>,
+  metadata: SensorMetadata,
+  device: PathBuf,
+  supervisor: Supervisor,
+  state: Arc<ExporterState>,
+  histograms: Option<Arc<Mutex<Histograms>>>,
+
+  /// a warm standby from `--backup`, served while this one is down
+  backup: Option<Box<Sensor>>
+}
+
+impl Sensor {
+  /// The unit whose readings are served: this one while it's up, i.e. has
+  /// sent something valid recently, otherwise its backup if that's up
+  fn active(&self) -> &Sensor {
+    match &self.backup {
+      Some(backup) if !self.supervisor.stats().up && backup.supervisor.stats().up => backup,
+      _ => self
+    }
+  }
+
+  /// Which unit `active()` is, by `sds011_active_unit`'s label
+  fn active_unit(&self) -> &'static str {
+    if std::ptr::eq(self.active(), self) { "primary" } else { "backup" }
+  }
+}
+
+/// The current local time of day, and seconds into the current minute
+fn local_time() -> (TimeOfDay, u32) {
+  let now = Local::now();
+  let time = TimeOfDay {
+    hour: now.hour() as u8,
+    minute: now.minute() as u8
+  };
+
+  (time, now.second())
+}
+
+/// Runs blocking sensor IO on the runtime's blocking pool, so it doesn't hold
+/// up other tasks
+async fn blocking<T, F>(f: F) -> T
+where
+  F: FnOnce() -> T + Send + 'static,
+  T: Send + 'static
+{
+  task::spawn_blocking(f).await.expect("blocking sensor task panicked")
+}
+
+/// Applies each rule of the schedule as it comes due; failures are logged and
+/// retried at the next rule
+async fn run_schedule(schedule: Schedule, supervisor: Supervisor, state: Arc<ExporterState>) {
+  loop {
+    let (time, second) = local_time();
+    let next = *schedule.next_rule(time);
+    let delay = Duration::from_secs(time.minutes_until(next.start) as u64 * 60 - second as u64);
+
+    debug!("next schedule rule {} in {}s", next, delay.as_secs());
+    state.beat("schedule");
+    delay_for(delay).await;
+
+    // keep polling if active reporting was found broken
+    let rule = ScheduleRule {
+      reporting_mode: state.reporting_mode(),
+      ..next
+    };
+
+    let supervisor = supervisor.clone();
+    let applied = blocking(move || {
+      let responses = supervisor.subscription().filter(ResponseKindSet::ACKS).responses();
+      rule.apply(&supervisor.commands(), &responses, &RetryConfig::default())
+    }).await;
+
+    match applied {
+      Ok(_) => info!("applied schedule rule {}", next),
+      Err(e) => error!("error applying schedule rule {}: {}", next, e)
+    }
+  }
+}
+
+/// The working period that should currently be in effect
+fn scheduled_period(schedule: Option<&Schedule>, default: WorkingPeriod) -> WorkingPeriod {
+  match schedule {
+    Some(schedule) => schedule.rule_at(local_time().0).working_period,
+    None => default
+  }
+}
+
+/// Wakes the sensor and sets its reporting mode and working period
+fn configure(
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  mode: ReportingMode,
+  working_period: WorkingPeriod
+) -> sds011_exporter::Result<()> {
+  retry_send_default(SetSleepWork {
+    query: false,
+    mode: WorkMode::Work
+  }, command_tx, response_rx)?;
+
+  retry_send_default(SetWorkingPeriod {
+    query: false,
+    working_period,
+  }, command_tx, response_rx)?;
+
+  retry_send_default(SetReportingMode {
+    query: false,
+    mode
+  }, command_tx, response_rx)?;
+
+  Ok(())
+}
+
+/// Queries the sensor's reporting mode, work mode, and working period
+fn query_config(supervisor: &Supervisor) -> sds011_exporter::Result<SensorConfig> {
+  let commands = supervisor.commands();
+  let responses = supervisor.subscription().filter(ResponseKindSet::ACKS).responses();
+
+  let (reporting, _) = retry_send_default(SetReportingMode {
+    query: true,
+    mode: ReportingMode::Active
+  }, &commands, &responses)?;
+
+  let (work, _) = retry_send_default(SetSleepWork {
+    query: true,
+    mode: WorkMode::Work
+  }, &commands, &responses)?;
+
+  let (working, _) = retry_send_default(SetWorkingPeriod {
+    query: true,
+    working_period: WorkingPeriod::Continuous
+  }, &commands, &responses)?;
+
+  Ok(SensorConfig {
+    reporting_mode: reporting.mode,
+    work_mode: work.mode,
+    working_period: working.working_period
+  })
+}
+
+/// Re-applies the configured settings if the sensor no longer matches them,
+/// e.g. after losing power, and returns its configuration afterward
+fn correct_drift(
+  supervisor: &Supervisor,
+  queried: SensorConfig,
+  expected: WorkingPeriod,
+  state: &ExporterState
+) -> sds011_exporter::Result<SensorConfig> {
+  let mode = state.reporting_mode();
+  if queried.matches(mode, expected) {
+    return Ok(queried);
+  }
+
+  warn!(
+    "sensor configuration drifted ({:?}); re-applying {:?} reporting with working period {:?}",
+    queried, mode, expected
+  );
+
+  let responses = supervisor.subscription().filter(ResponseKindSet::ACKS).responses();
+  configure(&supervisor.commands(), &responses, mode, expected)?;
+  state.corrections.fetch_add(1, Ordering::Relaxed);
+  state.woke();
+
+  let corrected = query_config(supervisor)?;
+  info!("corrected sensor configuration: {:?}", corrected);
+
+  Ok(corrected)
+}
+
+/// Queries the sensor's configuration every `CONFIG_INTERVAL`, correcting any
+/// drift from the configured settings. It's cleared if a query fails so stale
+/// settings aren't exported.
+async fn run_config_monitor(
+  supervisor: Supervisor,
+  schedule: Option<Schedule>,
+  default_period: WorkingPeriod,
+  stagger: Stagger,
+  state: Arc<ExporterState>,
+  current: watch::Sender<Option<SensorConfig>>
+) {
+  delay_for(stagger.offset(CONFIG_INTERVAL)).await;
+
+  loop {
+    let expected = scheduled_period(schedule.as_ref(), default_period);
+    let queried = {
+      let supervisor = supervisor.clone();
+      let state = Arc::clone(&state);
+      blocking(move || {
+        query_config(&supervisor).and_then(|c| {
+          debug!("queried sensor configuration: {:?}", c);
+          correct_drift(&supervisor, c, expected, &state)
+        })
+      }).await
+    };
+
+    let config = match queried {
+      Ok(c) => Some(c),
+      Err(e) => {
+        warn!("error checking sensor configuration: {}", e);
+        None
+      }
+    };
+
+    // every receiver is gone once the exporter is shutting down
+    if current.broadcast(config).is_err() {
+      return;
+    }
+
+    state.beat("config_monitor");
+    delay_for(CONFIG_INTERVAL).await;
+  }
+}
+
+/// Falls back to polling for readings if none are actively reported for
+/// `fallback_after` but the sensor still answers commands, as some clones'
+/// active reporting is broken. Once polling, the sensor is queried once per
+/// working period, offset by `stagger` so several sensors aren't woken and
+/// queried together.
+async fn run_poller(
+  supervisor: Supervisor,
+  schedule: Option<Schedule>,
+  default_period: WorkingPeriod,
+  fallback_after: Duration,
+  stagger: Stagger,
+  state: Arc<ExporterState>
+) {
+  let offset = stagger.offset(scheduled_period(schedule.as_ref(), default_period).as_duration());
+  if offset > Duration::from_secs(0) {
+    debug!("staggering polls by {}s", offset.as_secs());
+    delay_for(offset).await;
+  }
+
+  let mut since = Timestamp::now();
+  let mut restarts = supervisor.stats().restarts;
+
+  loop {
+    let period = scheduled_period(schedule.as_ref(), default_period);
+    state.beat("poller");
+    delay_for(period.as_duration()).await;
+
+    if state.polling.load(Ordering::Relaxed) {
+      if let Err(e) = supervisor.send_async(Query, &RetryConfig::default()).await {
+        warn!("error polling sensor: {}", e);
+      }
+
+      continue;
+    }
+
+    // a reopened sensor was just set up again; restart the clock
+    let stats = supervisor.stats();
+    if stats.restarts != restarts {
+      restarts = stats.restarts;
+      since = Timestamp::now();
+      continue;
+    }
+
+    let last_reading = supervisor.latest()
+      .map(|(time, _)| time.max(since))
+      .unwrap_or(since);
+    let silent = last_reading.elapsed();
+    if silent < fallback_after {
+      continue;
+    }
+
+    let switched = supervisor.send_async(SetReportingMode {
+      query: false,
+      mode: ReportingMode::Query
+    }, &RetryConfig::default()).await;
+
+    match switched {
+      Ok(_) => {
+        warn!(
+          "no readings reported for {}s, but the sensor answers commands; falling back to \
+           query polling",
+          silent.as_secs()
+        );
+        state.polling.store(true, Ordering::Relaxed);
+      },
+      Err(e) => debug!("sensor isn't answering commands either: {}", e)
+    }
+  }
+}
+
+/// Measures the time between readings as they arrive, to compare against the
+/// working period the sensor should be using. Readings either side of the
+/// sensor being reopened aren't compared.
+async fn run_intervals(supervisor: Supervisor, state: Arc<ExporterState>) {
+  let mut readings = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .async_responses();
+
+  let mut last: Option<Instant> = None;
+  let mut restarts = supervisor.stats().restarts;
+
+  while readings.recv().await.is_some() {
+    let now = Instant::now();
+
+    let stats = supervisor.stats();
+    if stats.restarts != restarts {
+      restarts = stats.restarts;
+      last = None;
+    }
+
+    if let Some(last) = last {
+      let interval = now - last;
+      let mut intervals = state.intervals.lock().unwrap();
+      intervals.last = Some(interval);
+      intervals.histogram.observe(interval.as_secs_f32());
+    }
+
+    last = Some(now);
+  }
+}
+
+/// Records gaps between readings; unlike `run_intervals()`, readings either
+/// side of the sensor being reopened are compared, as it was down in between
+async fn run_gaps(
+  supervisor: Supervisor,
+  schedule: Option<Schedule>,
+  default_period: WorkingPeriod,
+  state: Arc<ExporterState>
+) {
+  let mut readings = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .async_responses();
+
+  while readings.recv().await.is_some() {
+    let expected = scheduled_period(schedule.as_ref(), default_period).as_duration();
+    state.gaps.lock().unwrap().reading(Timestamp::now(), expected);
+  }
+}
+
+/// Adds each reading to the histograms until the supervisor gives up;
+/// saturated values are left out if they're withheld from export
+async fn run_histograms(
+  supervisor: Supervisor,
+  withhold_saturated: bool,
+  histograms: Arc<Mutex<Histograms>>
+) {
+  let mut readings = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .async_responses();
+
+  while let Some(response) = readings.recv().await {
+    if let Resp::Query(r) = response {
+      let mut histograms = histograms.lock().unwrap();
+      if !(withhold_saturated && r.pm25_saturated()) {
+        histograms.pm25.observe(r.pm25);
+      }
+
+      if !(withhold_saturated && r.pm10_saturated()) {
+        histograms.pm10.observe(r.pm10);
+      }
+    }
+  }
+}
+
+/// Adds each reading to the rolling peaks; saturated values are left out if
+/// they're withheld from export
+async fn run_peaks(supervisor: Supervisor, withhold_saturated: bool, state: Arc<ExporterState>) {
+  let mut readings = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .async_responses();
+
+  while let Some(response) = readings.recv().await {
+    if let Resp::Query(r) = response {
+      let mut peaks = state.peaks.lock().unwrap();
+      if !(withhold_saturated && r.pm25_saturated()) {
+        peaks.pm25_1h.observe(r.pm25);
+        peaks.pm25_24h.observe(r.pm25);
+      }
+
+      if !(withhold_saturated && r.pm10_saturated()) {
+        peaks.pm10_1h.observe(r.pm10);
+        peaks.pm10_24h.observe(r.pm10);
+      }
+    }
+  }
+}
+
+/// How often the companion sensor is read
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+const COMPANION_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reads the companion sensor every `COMPANION_INTERVAL`, until every sensor's
+/// receiver is gone
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+async fn run_companion(
+  mut sensor: Box<dyn ClimateSensor>,
+  climate_tx: watch::Sender<Option<Climate>>,
+  states: Vec<Arc<ExporterState>>
+) {
+  loop {
+    let (returned, read) = blocking(move || {
+      let read = sensor.read();
+      (sensor, read)
+    }).await;
+    sensor = returned;
+
+    let climate = match read {
+      Ok(climate) => {
+        debug!("companion sensor: {:?}", climate);
+        Some(climate)
+      },
+      Err(e) => {
+        warn!("error reading companion sensor: {}", e);
+        None
+      }
+    };
+
+    // every sensor is assumed to be in the same place
+    if climate_tx.broadcast(climate).is_err() {
+      return;
+    }
+
+    for state in &states {
+      state.beat("companion");
+    }
+
+    delay_for(COMPANION_INTERVAL).await;
+  }
+}
+
+/// How long the sensor may go without reporting before it's considered down:
+/// three of the longest working periods in use
+fn stall_timeout(opts: &Options) -> Duration {
+  let longest = match &opts.schedule {
+    Some(schedule) => schedule.rules().iter().map(|r| r.working_period.as_duration()).max(),
+    None => Some(opts.working_period.as_duration())
+  };
+
+  // continuous reporting is treated as a 1 minute period for some slack
+  3 * longest.unwrap_or_default().max(Duration::from_secs(60))
+}
+
+/// Number of invalid packets after which an unanswered command is blamed on
+/// the link rather than a silent sensor
+const CHECKSUM_STORM_THRESHOLD: usize = 5;
+
+/// Suggests a fix for a failure to talk to the sensor, judging by the error
+/// and what, if anything, came back over the link. Errors opening it carry
+/// their own `DeviceHint`.
+fn diagnose(device: &Path, error: &sds011_exporter::Error, stats: &LinkStats) -> Option<String> {
+  use sds011_exporter::Error;
+
+  match error {
+    Error::DeviceBusy(_) => Some(format!(
+      "another process has {} open; stop it first",
+      device.display()
+    )),
+    Error::RetriesExceeded { .. } | Error::InconsistentDevice { .. }
+      if stats.invalid_packets() >= CHECKSUM_STORM_THRESHOLD => Some(format!(
+      "replies were corrupt ({} invalid packets, {} garbage bytes); check the cable and \
+      connections, and that the adapter runs at 9600 baud",
+      stats.invalid_packets(),
+      stats.garbage_bytes()
+    )),
+    Error::RetriesExceeded { .. } if stats.packets() == 0 => Some(
+      "the sensor didn't respond at all; check it's powered (its fan should spin \
+      briefly at power on, and it needs a full 5V) and that TX and RX aren't swapped"
+        .to_string()
+    ),
+    _ => None
+  }
+}
+
+/// The sensor's device path, finding it by `--device-serial` or `--device-id`
+/// if given
+fn resolve_device(opts: &Options) -> Result<PathBuf> {
+  let selector = match (&opts.device, &opts.device_serial, opts.device_id) {
+    (Some(device), _, _) => return Ok(device.clone()),
+    (None, Some(serial), _) => DeviceSelector::UsbSerial(serial.clone()),
+    (None, None, Some(id)) => DeviceSelector::DeviceId(id),
+    (None, None, None) => return Err(anyhow!("a sensor device is required, e.g. /dev/ttyUSB0"))
+  };
+
+  Ok(find_sensor(&selector, &RetryConfig::default())?.into())
+}
+
+/// Starts supervising a sensor, along with its histograms if enabled. Its name,
+/// or device if unnamed, is sent on `failed` if the sensor is lost for good,
+/// unless serving stale readings.
+fn start_sensor(
+  opts: &Options,
+  name: Option<String>,
+  metadata: SensorMetadata,
+  device: PathBuf,
+  stagger: Stagger,
+  climate: watch::Receiver<Option<Climate>>,
+  failed: mpsc::UnboundedSender<String>
+) -> Result<Sensor> {
+  let (current_tx, current_rx) = watch::channel(None);
+  let state = Arc::new(ExporterState::new(current_rx, climate));
+  let label = name.clone().unwrap_or_else(|| device.display().to_string());
+  let supervisor = match supervise(opts, &device, stagger, &state, current_tx) {
+    Ok(supervisor) => supervisor,
+    Err(e) => return Err(match &name {
+      Some(name) => e.context(format!("could not start sensor {}", name)),
+      None => e
+    })
+  };
+
+  let histograms = if opts.histogram_buckets.is_empty() {
+    None
+  } else {
+    let histograms = Arc::new(Mutex::new(Histograms {
+      pm25: Histogram::new(&opts.histogram_buckets),
+      pm10: Histogram::new(&opts.histogram_buckets)
+    }));
+
+    let supervisor = supervisor.clone();
+    let h = Arc::clone(&histograms);
+    tokio::spawn(run_histograms(supervisor, opts.withhold_saturated, h));
+
+    Some(histograms)
+  };
+
+  // the watch closes when the supervisor gives up on the sensor
+  let mut latest = supervisor.watch_async();
+  let policy = opts.on_failure;
+  let stale_state = Arc::clone(&state);
+  tokio::spawn(async move {
+    let mut last = None;
+    while let Some(reading) = latest.recv().await {
+      last = reading.or(last);
+    }
+
+    if policy == FailurePolicy::ServeStale {
+      error!("lost sensor {}; serving its last reading", label);
+      *stale_state.stale.lock().unwrap() = last;
+    } else {
+      let _ = failed.send(label);
+    }
+  });
+
+  Ok(Sensor { name, metadata, device, supervisor, state, histograms, backup: None })
+}
+
+/// Opens and configures the sensor, and starts the tasks that keep it
+/// configured; its configuration is sent on `current` as it's queried
+fn supervise(
+  opts: &Options,
+  device: &Path,
+  stagger: Stagger,
+  state: &Arc<ExporterState>,
+  current: watch::Sender<Option<SensorConfig>>
+) -> Result<Supervisor> {
+  let default_period = opts.working_period;
+  let schedule = opts.schedule.clone();
+  let setup_state = Arc::clone(state);
+  let stats = LinkStats::with_invalid_frames(opts.invalid_frames);
+
+  let mut sensor = SensorOptions::builder()
+    .garbage_report_interval(Duration::from_secs(60))
+    .stats(stats.clone())
+    .local_echo(opts.local_echo);
+  if opts.dedup_readings {
+    sensor = sensor.reading_dedup_window(DEFAULT_READING_DEDUP_WINDOW);
+  }
+
+  let supervisor = Supervisor::spawn(
+    device,
+    SupervisorConfig {
+      restart: opts.on_failure.restart_policy(),
+      stall_timeout: Some(stall_timeout(opts)),
+
+      // the sensor sends at most one reading per second, so this means the
+      // supervisor was blocked for several seconds
+      backlog_watermark: Some(8),
+      event_history: opts.debug_events,
+      sensor: sensor.build(),
+      ..SupervisorConfig::default()
+    },
+    move |command_tx, response_rx| {
+      // only queries, so a sensor that can't be talked to fails here first
+      let info = fetch_info(command_tx, response_rx, &RetryConfig::default())?;
+      info!(
+        "found sensor {} with firmware {}: {:?}, {:?} reporting, working period {:?}",
+        info.device_id, info.firmware_date, info.work_mode, info.reporting_mode,
+        info.working_period
+      );
+
+      configure(
+        command_tx,
+        response_rx,
+        setup_state.reporting_mode(),
+        scheduled_period(schedule.as_ref(), default_period)
+      )?;
+
+      setup_state.woke();
+      Ok(())
+    }
+  );
+
+  let supervisor = match supervisor {
+    Ok(supervisor) => supervisor,
+    Err(e) => return Err(match diagnose(device, &e, &stats) {
+      Some(hint) => anyhow::Error::new(e).context(format!("sensor self-test failed: {}", hint)),
+      None => e.into()
+    })
+  };
+
+  match &opts.schedule {
+    Some(schedule) => {
+      info!("configured device to actively report with schedule: {}", schedule);
+
+      let schedule = schedule.clone();
+      let supervisor = supervisor.clone();
+      let state = Arc::clone(state);
+      tokio::spawn(run_schedule(schedule, supervisor, state));
+    },
+    None => info!(
+      "configured device to actively report with working period: {:?}",
+      opts.working_period
+    )
+  }
+
+  {
+    let schedule = opts.schedule.clone();
+    let supervisor = supervisor.clone();
+    let state = Arc::clone(state);
+    tokio::spawn(run_config_monitor(supervisor, schedule, default_period, stagger, state, current));
+  }
+
+  {
+    let schedule = opts.schedule.clone();
+    let supervisor = supervisor.clone();
+    let state = Arc::clone(state);
+    let fallback_after = stall_timeout(opts);
+    tokio::spawn(run_poller(supervisor, schedule, default_period, fallback_after, stagger, state));
+  }
+
+  {
+    let supervisor = supervisor.clone();
+    let state = Arc::clone(state);
+    tokio::spawn(run_intervals(supervisor, state));
+  }
+
+  {
+    let schedule = opts.schedule.clone();
+    let supervisor = supervisor.clone();
+    let state = Arc::clone(state);
+    tokio::spawn(run_gaps(supervisor, schedule, default_period, state));
+  }
+
+  {
+    let supervisor = supervisor.clone();
+    let state = Arc::clone(state);
+    tokio::spawn(run_peaks(supervisor, opts.withhold_saturated, state));
+  }
+
+  Ok(supervisor)
+}
+
+/// Formats a timestamp by the system clock as it is now
+fn to_rfc3339(time: Timestamp) -> String {
+  DateTime::<Utc>::from(time.to_system_time()).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The latest reading as /json returns it
+fn json_reading(
+  opts: &Options,
+  supervisor: &Supervisor,
+  state: &ExporterState,
+  metadata: &SensorMetadata,
+  schema: JsonSchema
+) -> serde_json::Value {
+  let latest = latest_reading(supervisor, state);
+  let datetime = |time: Timestamp| {
+    DateTime::<Utc>::from(time.to_system_time()).to_rfc3339_opts(SecondsFormat::Secs, true)
+  };
+
+  match schema {
+    JsonSchema::V1 => match latest {
+      Some((time, r)) => json!({
+        "datetime": datetime(time),
+        "pm25": r.pm25,
+        "pm10": r.pm10
+      }),
+      None => json!(null)
+    },
+    JsonSchema::V2 => json!({
+      "schema": "v2",
+      "up": supervisor.stats().up,
+      "sensor": {
+        "name": metadata.name,
+        "location": metadata.location
+      },
+      "reading": latest.map(|(time, r)| json!({
+        "datetime": datetime(time),
+        "device": r.device.to_string(),
+        "pm25": { "value": r.pm25, "unit": "µg/m³", "aqi": pm25_aqi(r.pm25) },
+        "pm10": { "value": r.pm10, "unit": "µg/m³", "aqi": pm10_aqi(r.pm10) },
+        "aqi": r.aqi(),
+        "quality": reading_quality(opts, state, time, &r).flags()
+      }))
+    })
+  }
+}
+
+/// The most recent gaps for /gaps, oldest first, and the total missing time
+/// as exported in sds011_gap_seconds_total
+fn json_gaps(opts: &Options, state: &ExporterState) -> serde_json::Value {
+  let expected = scheduled_period(opts.schedule.as_ref(), opts.working_period).as_duration();
+  let gaps = state.gaps.lock().unwrap();
+
+  let recent: Vec<_> = gaps.recent.iter()
+    .map(|gap| json!({
+      "since": to_rfc3339(gap.since),
+      "until": to_rfc3339(gap.until),
+      "missing_seconds": gap.missing.as_secs_f64()
+    }))
+    .collect();
+
+  // a gap still going on has no end yet
+  let ongoing = gaps.gap(Timestamp::now(), expected).map(|gap| json!({
+    "since": to_rfc3339(gap.since),
+    "missing_seconds": gap.missing.as_secs_f64()
+  }));
+
+  json!({
+    "gaps": recent,
+    "ongoing": ongoing,
+    "total_missing_seconds": gaps.total(expected).as_secs_f64()
+  })
+}
+
+/// The response to a /json request: the reading in the requested schema with
+/// caching headers, 304 Not Modified if `if_none_match` shows the client
+/// already has it, or an error if the schema is invalid
+fn json_reply(
+  opts: &Options,
+  sensors: &[Sensor],
+  query: &HashMap<String, String>,
+  if_none_match: Option<&str>
+) -> warp::reply::Response {
+  let schema = match query.get("schema").map(|s| s.parse::<JsonSchema>()) {
+    Some(Err(e)) => {
+      let body = json!({ "error": e.to_string() });
+      return warp::reply::with_status(warp::reply::json(&body), StatusCode::BAD_REQUEST)
+        .into_response();
+    },
+    schema => schema.and_then(|s| s.ok()).unwrap_or(opts.json_schema)
+  };
+
+  let etag = json_etag(opts, sensors, schema);
+  let matched = if_none_match
+    .map(|tags| tags.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"))
+    .unwrap_or(false);
+
+  let mut response = if matched {
+    StatusCode::NOT_MODIFIED.into_response()
+  } else {
+    let body = per_sensor(sensors, |s| {
+      let active = s.active();
+      json_reading(opts, &active.supervisor, &active.state, &s.metadata, schema)
+    });
+    warp::reply::json(&body).into_response()
+  };
+
+  let cache_control = match opts.cache_max_age {
+    0 => "no-cache".to_string(),
+    max_age => format!("max-age={}", max_age)
+  };
+
+  let headers = response.headers_mut();
+  headers.insert(CACHE_CONTROL, HeaderValue::from_str(&cache_control).unwrap());
+  headers.insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+  response
+}
+
+/// An ETag for the /json payload, which only changes with the schema and each
+/// sensor's latest reading and its quality, whether it's up, and which unit is
+/// active. The
+/// reading's wall-clock time is included too, in case the system clock jumps.
+fn json_etag(opts: &Options, sensors: &[Sensor], schema: JsonSchema) -> String {
+  let mut hasher = DefaultHasher::new();
+  schema.hash(&mut hasher);
+
+  for sensor in sensors {
+    let active = sensor.active();
+    let latest = latest_reading(&active.supervisor, &active.state).map(|(time, r)| {
+      let secs = time.to_system_time().duration_since(UNIX_EPOCH).unwrap_or_default();
+      (time, secs.as_secs(), reading_quality(opts, &active.state, time, &r))
+    });
+
+    latest.hash(&mut hasher);
+    active.supervisor.stats().up.hash(&mut hasher);
+    sensor.active_unit().hash(&mut hasher);
+  }
+
+  format!("\"{:016x}\"", hasher.finish())
+}
+
+/// `f` of the only sensor if it was given by path, or otherwise of each
+/// sensor, keyed by name
+fn per_sensor<F>(sensors: &[Sensor], f: F) -> serde_json::Value
+where
+  F: Fn(&Sensor) -> serde_json::Value
+{
+  match sensors {
+    [sensor] if sensor.name.is_none() => f(sensor),
+    _ => sensors.iter()
+      .map(|s| (s.name.clone().unwrap_or_default(), f(s)))
+      .collect::<serde_json::Map<_, _>>()
+      .into()
+  }
+}
+
+/// Error counts and the most recent invalid frames, as evidence for bug reports
+fn debug_errors(supervisor: &Supervisor) -> serde_json::Value {
+  let stats = supervisor.stats();
+  let link = supervisor.link_stats();
+
+  let frames: Vec<_> = link.invalid_frames().into_iter()
+    .map(|f| json!({
+      "datetime": to_rfc3339(f.time),
+      "frame": to_hex(&f.frame),
+      "error": f.error
+    }))
+    .collect();
+
+  json!({
+    "error_count": stats.errors,
+    "fatal_error_count": stats.fatal_errors,
+    "invalid_packet_count": link.invalid_packets(),
+    "garbage_byte_count": link.garbage_bytes(),
+    "invalid_frames": frames
+  })
+}
+
+/// Recent lifecycle events and errors, oldest first, so what went wrong can be
+/// seen without shipping logs anywhere
+fn debug_events(supervisor: &Supervisor) -> serde_json::Value {
+  let events: Vec<_> = supervisor.recent_events().into_iter()
+    .map(|e| json!({
+      "datetime": to_rfc3339(e.time),
+      "kind": e.kind,
+      "message": e.message
+    }))
+    .collect();
+
+  json!(events)
+}
+
+/// Internal state for troubleshooting: supervisor and link counters, the
+/// settings in effect, and when each background task last ran
+fn debug_state(
+  opts: &Options,
+  device: &Path,
+  supervisor: &Supervisor,
+  state: &ExporterState
+) -> serde_json::Value {
+  let stats = supervisor.stats();
+  let link = supervisor.link_stats();
+
+  let sensor = state.current.borrow().map(|c| json!({
+    "reporting_mode": format!("{:?}", c.reporting_mode),
+    "work_mode": format!("{:?}", c.work_mode),
+    "working_period": c.working_period.as_byte()
+  }));
+
+  let threads: HashMap<_, _> = state.heartbeats.lock().unwrap().iter()
+    .map(|(thread, time)| (*thread, to_rfc3339(*time)))
+    .collect();
+
+  json!({
+    "device": device,
+    "supervisor": {
+      "running": stats.running,
+      "up": stats.up,
+      "errors": stats.errors,
+      "fatal_errors": stats.fatal_errors,
+      "restarts": stats.restarts,
+      "panics": stats.panics,
+      "subscribers": stats.subscribers,
+      "listeners": stats.listeners
+    },
+    "link": {
+      "packets": link.packets(),
+      "invalid_packets": link.invalid_packets(),
+      "garbage_bytes": link.garbage_bytes(),
+      "commands": link.commands(),
+      "echoes": link.echoes(),
+      "last_packet": link.last_packet().map(to_rfc3339)
+    },
+    "latest_reading": supervisor.latest().map(|(time, _)| to_rfc3339(time)),
+    "config": {
+      "working_period": opts.working_period.as_byte(),
+      "schedule": opts.schedule.as_ref().map(|s| s.to_string()),
+      "reporting_mode": format!("{:?}", state.reporting_mode()),
+      "query_fallback": state.polling.load(Ordering::Relaxed),
+      "corrections": state.corrections.load(Ordering::Relaxed),
+      "histogram_buckets": opts.histogram_buckets,
+      "invalid_frames": opts.invalid_frames,
+      "debug_events": opts.debug_events,
+      "sensor": sensor
+    },
+    "threads": threads
+  })
+}
+
+/// Metrics that are a reading, rather than the state at scrape time
+const READING_METRICS: &[&str] = &[
+  "sds011_pm25", "sds011_pm10", "sds011_pm25_corrected", "sds011_pm10_corrected"
+];
+
+/// Adds a label to every sample in exposition format text
+fn with_label(text: &str, key: &str, value: &str) -> String {
+  let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+  let label = format!("{}=\"{}\"", key, value);
+
+  let mut out = String::with_capacity(text.len());
+  for line in text.lines() {
+    if line.is_empty() || line.starts_with('#') {
+      out.push_str(line);
+    } else {
+      let (name, rest) = line.split_at(line.find(['{', ' ']).unwrap_or(line.len()));
+      out.push_str(&match rest.strip_prefix('{') {
+        Some(rest) if rest.starts_with('}') => format!("{}{{{}{}", name, label, rest),
+        Some(rest) => format!("{}{{{},{}", name, label, rest),
+        None => format!("{}{{{}}}{}", name, label, rest)
+      });
+    }
+
+    out.push('\n');
+  }
+
+  out
+}
+
+/// Merges several exposition format texts into one, keeping the samples of
+/// each metric together under one set of comments as Prometheus requires
+fn merge_metrics(texts: &[String]) -> String {
+  let mut order: Vec<&str> = Vec::new();
+  let mut families: HashMap<&str, (Vec<&str>, Vec<&str>)> = HashMap::new();
+
+  for text in texts {
+    // the metric named by the last HELP or TYPE comment, which may cover
+    // several sample names, e.g. a histogram's _bucket, _sum, and _count
+    let mut described = "";
+
+    for line in text.lines() {
+      let family = if line.starts_with('#') {
+        described = line.split_whitespace().nth(2).unwrap_or("");
+        described
+      } else {
+        let name = line.split(['{', ' ']).next().unwrap_or("");
+        let suffix = name.strip_prefix(described).filter(|_| !described.is_empty());
+        match suffix {
+          Some("") | Some("_bucket") | Some("_sum") | Some("_count") => described,
+          _ => name
+        }
+      };
+
+      let (comments, samples) = families.entry(family).or_insert_with(|| {
+        order.push(family);
+        (Vec::new(), Vec::new())
+      });
+
+      if !line.starts_with('#') {
+        samples.push(line);
+      } else if !comments.contains(&line) {
+        comments.push(line);
+      }
+    }
+  }
+
+  let mut out = String::new();
+  for family in order {
+    let (comments, samples) = &families[family];
+    for line in comments.iter().chain(samples) {
+      out.push_str(line);
+      out.push('\n');
+    }
+  }
+
+  out
+}
+
+/// Every sensor's metrics, labeled with its name and location if set
+fn export_sensors(exporter: &Exporter, opts: &Options, sensors: &[Sensor]) -> String {
+  let texts: Vec<String> = sensors.iter()
+    .map(|s| {
+      let active = s.active();
+      let mut text = export_reading(
+        exporter,
+        opts,
+        &active.supervisor,
+        &active.state,
+        active.histograms.as_deref()
+      );
+
+      if s.backup.is_some() {
+        let mut session = exporter.session();
+        let unit = s.active_unit();
+        for name in &["primary", "backup"] {
+          let value = if *name == unit { 1.0 } else { 0.0 };
+          export!(session, "sds011_active_unit", value, unit = *name);
+        }
+
+        text.push_str(&session.to_string());
+      }
+
+      s.metadata.set().fold(text, |text, (field, value)| {
+        // "name" is taken by Prometheus for the metric name
+        let label = if field == "name" { "sensor" } else { field };
+        with_label(&text, label, value)
+      })
+    })
+    .collect();
+
+  match texts.as_slice() {
+    [text] => text.clone(),
+    texts => merge_metrics(texts)
+  }
+}
+
+/// Appends a timestamp, in milliseconds since the epoch, to each sample of the
+/// given metrics in exposition format text
+fn with_timestamps(text: &str, metrics: &[&str], time: Timestamp) -> String {
+  let millis = time.to_system_time().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis();
+
+  let mut out = String::with_capacity(text.len());
+  for line in text.lines() {
+    out.push_str(line);
+
+    let name = line.split(['{', ' ']).next().unwrap_or("");
+    if !line.starts_with('#') && metrics.contains(&name) {
+      out.push_str(&format!(" {}", millis));
+    }
+
+    out.push('\n');
+  }
+
+  out
+}
+
+/// The quality of a reading received at `time`: whether it was taken during
+/// warmup, or hasn't been followed by a newer one for longer than the sensor
+/// should go without reporting
+fn reading_quality(
+  opts: &Options,
+  state: &ExporterState,
+  time: Timestamp,
+  reading: &QueryResponse
+) -> ReadingQuality {
+  let warmup = state.woke.lock().unwrap()
+    .filter(|woke| time >= *woke)
+    .map(|woke| time.duration_since(woke) < WARMUP)
+    .unwrap_or(false);
+
+  ReadingQuality {
+    warmup,
+    stale: time.elapsed() > stall_timeout(opts),
+    ..ReadingQuality::of(reading)
+  }
+}
+
+fn export_reading(
+  exporter: &Exporter,
+  opts: &Options,
+  supervisor: &Supervisor,
+  state: &ExporterState,
+  histograms: Option<&Mutex<Histograms>>
+) -> String {
+  let mut s = exporter.session();
+  let latest = latest_reading(supervisor, state);
+
+  if let Some((time, r)) = &latest {
+    if !(opts.withhold_saturated && r.pm25_saturated()) {
+      export!(s, "sds011_pm25", r.pm25, unit = "pm2.5");
+    }
+
+    if !(opts.withhold_saturated && r.pm10_saturated()) {
+      export!(s, "sds011_pm10", r.pm10, unit = "pm10");
+    }
+
+    export!(s, "sds011_saturated", if r.saturated() { 1.0 } else { 0.0 });
+
+    let quality = reading_quality(opts, state, *time, r);
+    for (flag, set) in quality.iter() {
+      export!(s, "sds011_reading_quality", if set { 1.0 } else { 0.0 }, flag = flag);
+    }
+  }
+
+  let stats = supervisor.stats();
+  export!(s, "sds011_up", if stats.up { 1.0 } else { 0.0 });
+  export!(s, "sds011_error_count", stats.errors as f64);
+  export!(s, "sds011_fatal_error_count", stats.fatal_errors as f64);
+  export!(s, "sds011_restart_count", stats.restarts as f64);
+  export!(s, "sds011_thread_panic_count", stats.panics as f64);
+  export!(s, "sds011_garbage_byte_count", supervisor.link_stats().garbage_bytes() as f64);
+  export!(
+    s, "sds011_duplicate_reading_count",
+    supervisor.link_stats().duplicate_readings() as f64
+  );
+
+  if let Some(climate) = *state.climate.borrow() {
+    export!(s, "sds011_temperature_celsius", climate.temperature);
+    export!(s, "sds011_relative_humidity_percent", climate.humidity);
+
+    #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+    if let Some((_, r)) = &latest {
+      let model = opts.humidity_correction;
+      if model != HumidityCorrection::None {
+        let corrected = model.correct(r, climate.humidity);
+        let model = model.to_string();
+
+        if !(opts.withhold_saturated && r.pm25_saturated()) {
+          export!(s, "sds011_pm25_corrected", corrected.pm25, unit = "pm2.5", model = model);
+        }
+
+        if !(opts.withhold_saturated && r.pm10_saturated()) {
+          export!(s, "sds011_pm10_corrected", corrected.pm10, unit = "pm10", model = model);
+        }
+      }
+    }
+  }
+
+  export!(
+    s, "sds011_config_correction_count",
+    state.corrections.load(Ordering::Relaxed) as f64
+  );
+  export!(
+    s, "sds011_query_fallback",
+    if state.polling.load(Ordering::Relaxed) { 1.0 } else { 0.0 }
+  );
+
+  let expected = scheduled_period(opts.schedule.as_ref(), opts.working_period);
+  export!(s, "sds011_expected_reading_interval_seconds", expected.as_duration().as_secs_f64());
+
+  let gaps = state.gaps.lock().unwrap().total(expected.as_duration());
+  export!(s, "sds011_gap_seconds_total", gaps.as_secs_f64());
+
+  let mut peaks = state.peaks.lock().unwrap();
+  let all_peaks = [
+    ("sds011_pm25_max_1h", "pm2.5", peaks.pm25_1h.peak()),
+    ("sds011_pm25_max_24h", "pm2.5", peaks.pm25_24h.peak()),
+    ("sds011_pm10_max_1h", "pm10", peaks.pm10_1h.peak()),
+    ("sds011_pm10_max_24h", "pm10", peaks.pm10_24h.peak())
+  ];
+  drop(peaks);
+
+  for (name, unit, peak) in &all_peaks {
+    if let Some(peak) = peak {
+      export!(s, *name, *peak, unit = *unit);
+    }
+  }
+
+  let intervals = state.intervals.lock().unwrap();
+  if let Some(interval) = intervals.last {
+    export!(s, "sds011_reading_interval_seconds", interval.as_secs_f64());
+  }
+
+  let histograms = histograms.map(|h| h.lock().unwrap());
+  let mut all = vec![("sds011_reading_interval_seconds_histogram", &intervals.histogram)];
+  if let Some(histograms) = &histograms {
+    all.push(("sds011_pm25_histogram", &histograms.pm25));
+    all.push(("sds011_pm10_histogram", &histograms.pm10));
+  }
+
+  for (name, histogram) in &all {
+    for (bound, count) in histogram.buckets() {
+      let le = if bound.is_infinite() { "+Inf".to_string() } else { bound.to_string() };
+      export!(s, &format!("{}_bucket", name), count as f64, le = le);
+    }
+
+    export!(s, &format!("{}_sum", name), histogram.sum());
+    export!(s, &format!("{}_count", name), histogram.count() as f64);
+  }
+
+  if let Some(config) = *state.current.borrow() {
+    export!(s, "sds011_working_period_minutes", config.working_period.as_byte() as f64);
+
+    for (mode, name) in &[(ReportingMode::Active, "active"), (ReportingMode::Query, "query")] {
+      let value = if config.reporting_mode == *mode { 1.0 } else { 0.0 };
+      export!(s, "sds011_reporting_mode", value, mode = *name);
+    }
+
+    for (mode, name) in &[(WorkMode::Work, "work"), (WorkMode::Sleep, "sleep")] {
+      let value = if config.work_mode == *mode { 1.0 } else { 0.0 };
+      export!(s, "sds011_work_mode", value, mode = *name);
+    }
+  }
+
+  match latest {
+    Some((time, _)) if opts.export_timestamps => with_timestamps(&s.to_string(), READING_METRICS, time),
+    _ => s.to_string()
+  }
+}
+
+type Routes = BoxedFilter<(Box<dyn Reply>,)>;
+
+fn boxed<F, R>(routes: F) -> Routes
+where
+  F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+  R: Reply + 'static
+{
+  routes.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed()
+}
+
+/// A socket bound (or inherited) from a `Listen`, ready to serve
+enum Listener {
+  Tcp(tokio::net::TcpListener),
+
+  #[cfg(unix)]
+  Unix(tokio::net::UnixListener)
+}
+
+/// Binds `listen`, so a bad address fails startup rather than a server
+async fn bind(listen: &Listen) -> Result<Listener> {
+  match listen {
+    Listen::Tcp(address) => {
+      let listener = tokio::net::TcpListener::bind(*address).await
+        .map_err(|e| anyhow!("could not bind {}: {}", address, e))?;
+
+      Ok(Listener::Tcp(listener))
+    },
+
+    #[cfg(unix)]
+    Listen::Unix(path) => {
+      use std::os::unix::fs::FileTypeExt;
+
+      // a socket left behind by a previous run would make binding fail
+      let stale = std::fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_socket())
+        .unwrap_or(false);
+      if stale {
+        std::fs::remove_file(path)?;
+      }
+
+      let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| anyhow!("could not bind {}: {}", path.display(), e))?;
+
+      Ok(Listener::Unix(listener))
+    },
+
+    #[cfg(unix)]
+    Listen::Fd(fd) => {
+      use std::os::unix::io::FromRawFd;
+
+      let fd = *fd;
+      let unix = is_unix_socket(fd)
+        .map_err(|e| anyhow!("fd {} isn't a listening socket: {}", fd, e))?;
+
+      // safety: the descriptor was handed to this process to listen on, and
+      // nothing else uses it
+      if unix {
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+
+        Ok(Listener::Unix(tokio::net::UnixListener::from_std(listener)?))
+      } else {
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+
+        Ok(Listener::Tcp(tokio::net::TcpListener::from_std(listener)?))
+      }
+    }
+  }
+}
+
+/// Serves `routes` on `listener` until the process exits
+async fn serve(routes: Routes, listener: Listener) -> Result<()> {
+  match listener {
+    Listener::Tcp(mut listener) => {
+      warp::serve(routes).run_incoming(listener.incoming()).await;
+    },
+
+    #[cfg(unix)]
+    Listener::Unix(mut listener) => {
+      warp::serve(routes).run_incoming(listener.incoming()).await;
+    }
+  }
+
+  Ok(())
+}
+
+pub fn run(opts: Options) -> Result<()> {
+  tokio::runtime::Builder::new()
+    .basic_scheduler()
+    .enable_all()
+    .build()?
+    .block_on(exporter(opts))
+}
+
+async fn exporter(opts: Options) -> Result<()> {
+  let activated = activated_sockets();
+
+  // readings are only collected via active reporting
+  if let Some(schedule) = &opts.schedule {
+    if schedule.rules().iter().any(|r| r.reporting_mode != ReportingMode::Active) {
+      return Err(UsageError("--schedule rules must use the active reporting mode".into()).into());
+    }
+  }
+
+  crate::init_logging(false, opts.log_format);
+
+  // companion readings are shared by every sensor, and each sensor's failure
+  // ends the exporter
+  #[cfg_attr(
+    not(all(target_os = "linux", feature = "companion-sensors")),
+    allow(unused_variables)
+  )]
+  let (climate_tx, climate_rx) = watch::channel(None);
+  let (failed_tx, mut failed_rx) = mpsc::unbounded_channel();
+
+  let mut locations = parse_locations(&opts)?;
+  let sensors = if opts.sensors.is_empty() {
+    let metadata = SensorMetadata {
+      name: opts.name.clone(),
+      location: locations.remove(&None)
+    };
+
+    let device = resolve_device(&opts)?;
+    let units = if opts.backup.is_some() { 2 } else { 1 };
+    let stagger = |index| Stagger { index, count: units };
+    let mut sensor = start_sensor(
+      &opts, None, metadata.clone(), device, stagger(0), climate_rx.clone(), failed_tx.clone()
+    )?;
+
+    if let Some(backup) = &opts.backup {
+      let device = backup.clone();
+      let backup = start_sensor(&opts, None, metadata, device, stagger(1), climate_rx, failed_tx)
+        .map_err(|e| e.context("could not start backup sensor"))?;
+      sensor.backup = Some(Box::new(backup));
+    }
+
+    vec![sensor]
+  } else {
+    let mut sensors: Vec<Sensor> = Vec::new();
+    for (index, spec) in opts.sensors.iter().enumerate() {
+      if sensors.iter().any(|s| s.name.as_ref() == Some(&spec.name)) {
+        return Err(anyhow!("sensor name '{}' is used more than once", spec.name));
+      }
+
+      let stagger = Stagger {
+        index: index as u32,
+        count: opts.sensors.len() as u32
+      };
+
+      let name = Some(spec.name.clone());
+      let metadata = SensorMetadata {
+        name: name.clone(),
+        location: locations.remove(&name)
+      };
+
+      let device = spec.device.clone();
+      let climate = climate_rx.clone();
+      let failed = failed_tx.clone();
+      sensors.push(start_sensor(&opts, name, metadata, device, stagger, climate, failed)?);
+    }
+
+    sensors
+  };
+  let sensors = Arc::new(sensors);
+
+  #[cfg(feature = "coap")]
+  if let Some(address) = opts.coap {
+    let socket = UdpSocket::bind(address)
+      .map_err(|e| anyhow!("could not listen for CoAP on {}: {}", address, e))?;
+    info!("serving CoAP on {}", address);
+
+    let sensors = Arc::clone(&sensors);
+    std::thread::spawn(move || serve_coap(socket, sensors));
+  }
+
+  #[cfg(feature = "snmp")]
+  if let Some(address) = opts.snmp {
+    let socket = UdpSocket::bind(address)
+      .map_err(|e| anyhow!("could not listen for SNMP on {}: {}", address, e))?;
+    info!("serving SNMP on {} under {}", address, opts.snmp_base_oid);
+
+    let community = opts.snmp_community.clone();
+    let base = opts.snmp_base_oid.clone();
+    let sensors = Arc::clone(&sensors);
+    std::thread::spawn(move || serve_snmp(socket, community, base, sensors));
+  }
+
+  #[cfg(feature = "modbus")]
+  if let Some(address) = opts.modbus {
+    let listener = TcpListener::bind(address)
+      .map_err(|e| anyhow!("could not listen for Modbus TCP on {}: {}", address, e))?;
+    let map = opts.modbus_registers.clone().unwrap_or_default();
+    info!("serving Modbus TCP on {} with registers {}", address, map);
+
+    let sensors = Arc::clone(&sensors);
+    std::thread::spawn(move || serve_modbus(listener, map, sensors));
+  }
+
+  #[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+  if let Some(kind) = opts.companion {
+    let sensor = open_companion(kind, &opts.companion_bus, opts.companion_address)?;
+    info!("reading {} companion sensor on {}", kind, opts.companion_bus.display());
+
+    let states = sensors.iter().map(|s| Arc::clone(&s.state)).collect();
+    tokio::spawn(run_companion(sensor, climate_tx, states));
+  }
+
+  let json_opts = opts.clone();
+  let json_sensors = Arc::clone(&sensors);
+  // warp rejects a missing query string outright
+  let json_query = warp::query::<HashMap<String, String>>()
+    .or(warp::any().map(HashMap::new))
+    .unify();
+  let if_none_match = warp::header::optional::<String>("if-none-match");
+  let r_json = warp::path("json")
+    .and(json_query)
+    .and(if_none_match)
+    .map(move |query: HashMap<String, String>, tags: Option<String>| {
+      json_reply(&json_opts, &json_sensors, &query, tags.as_deref())
+    });
+
+  let sensor_json_opts = opts.clone();
+  let sensor_json_sensors = Arc::clone(&sensors);
+  let r_sensor_json = warp::path!("sensors" / String / "json")
+    .and(json_query)
+    .and(if_none_match)
+    .map(move |name: String, query: HashMap<String, String>, tags: Option<String>| {
+      match sensor_json_sensors.iter().find(|s| s.name.as_ref() == Some(&name)) {
+        Some(sensor) => {
+          let sensors = std::slice::from_ref(sensor);
+          json_reply(&sensor_json_opts, sensors, &query, tags.as_deref())
+        },
+        None => {
+          let body = json!({ "error": format!("no sensor named '{}'", name) });
+          warp::reply::with_status(warp::reply::json(&body), StatusCode::NOT_FOUND)
+            .into_response()
+        }
+      }
+    });
+
+  let gap_opts = opts.clone();
+  let gap_sensors = Arc::clone(&sensors);
+  let r_gaps = warp::path("gaps").map(move || {
+    warp::reply::json(&per_sensor(&gap_sensors, |s| json_gaps(&gap_opts, &s.active().state)))
+  });
+
+  let debug_sensors = Arc::clone(&sensors);
+  let r_debug_errors = warp::path!("debug" / "errors")
+    .map(move || warp::reply::json(&per_sensor(&debug_sensors, |s| debug_errors(&s.supervisor))));
+
+  let debug_sensors = Arc::clone(&sensors);
+  let r_debug_events = warp::path!("debug" / "events")
+    .map(move || warp::reply::json(&per_sensor(&debug_sensors, |s| debug_events(&s.supervisor))));
+
+  let debug_opts = opts.clone();
+  let debug_sensors = Arc::clone(&sensors);
+  let r_debug_state = warp::path!("debug" / "state").map(move || {
+    warp::reply::json(&per_sensor(&debug_sensors, |s| {
+      let mut state = debug_state(&debug_opts, &s.device, &s.supervisor, &s.state);
+      if let Some(backup) = &s.backup {
+        state["active_unit"] = s.active_unit().into();
+        state["backup"] = debug_state(
+          &debug_opts,
+          &backup.device,
+          &backup.supervisor,
+          &backup.state
+        );
+      }
+
+      state
+    }))
+  });
+
+  let exporter = Arc::new(Exporter::new());
+  let metrics_opts = opts.clone();
+  let metrics_sensors = Arc::clone(&sensors);
+  let r_metrics = warp::path("metrics").map(move || {
+    export_sensors(&exporter, &metrics_opts, &metrics_sensors)
+  });
+
+  // with socket activation, the first socket serves everything unless there's
+  // a second for the control endpoints
+  let mut activated = activated.into_iter();
+  let listen = opts.listen.clone()
+    .or_else(|| activated.next())
+    .unwrap_or_else(|| Listen::Tcp(SocketAddr::new(opts.address, opts.port)));
+  let control_listen = opts.control_listen.clone().or_else(|| activated.next());
+
+  // kept until the exporter stops, to keep answering queries
+  #[cfg(feature = "mdns")]
+  let _mdns = if opts.mdns { advertise(&listen, &sensors)? } else { None };
+  let public = warp::get().and(r_json.or(r_sensor_json).or(r_gaps).or(r_metrics));
+  let control = warp::get().and(r_debug_errors.or(r_debug_events).or(r_debug_state));
+
+  // bind everything up front, so a bad address fails startup
+  let listener = bind(&listen).await?;
+  let control_listener = match control_listen {
+    Some(control_listen) => Some((bind(&control_listen).await?, control_listen)),
+    None => None
+  };
+
+  let served = async {
+    match control_listener {
+      Some((control_listener, control_listen)) => {
+        info!("starting exporter on {}, control endpoints on {}", listen, control_listen);
+
+        // either server failing ends the exporter
+        tokio::try_join!(
+          serve(boxed(public), listener),
+          serve(boxed(control), control_listener)
+        ).map(|_| ())
+      },
+      None => {
+        info!("starting exporter on {}", listen);
+        serve(boxed(public.or(control)), listener).await
+      }
+    }
+  };
+
+  // returning drops the listeners and everything else cleanly, rather than
+  // exiting from whichever task noticed
+  // with a backup, the exporter carries on until both units are lost
+  let mut spares = if opts.backup.is_some() { 1 } else { 0 };
+  let lost = async {
+    while let Some(sensor) = failed_rx.recv().await {
+      if spares == 0 {
+        return Some(sensor);
+      }
+
+      warn!("lost sensor {}; carrying on with the other unit", sensor);
+      spares -= 1;
+    }
+
+    None
+  };
+
+  tokio::select! {
+    served = served => served,
+    Some(sensor) = lost => Err(anyhow!(
+      "lost sensor {}; refer to the log for details",
+      sensor
+    ))
+  }
+}
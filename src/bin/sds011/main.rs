@@ -0,0 +1,103 @@
+#[macro_use] extern crate log;
+
+use std::env;
+use std::io::Write;
+use std::process;
+
+use sds011_exporter::logging::{self, LogFormat};
+use structopt::StructOpt;
+use structopt::clap::{AppSettings, ErrorKind};
+use anyhow::Result;
+
+mod completions;
+// the exporter only needs the exit codes
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+mod exit;
+#[cfg(feature = "exporter")]
+mod export;
+#[cfg(feature = "cli")]
+mod tool;
+
+use completions::CompletionsAction;
+use exit::ExitCode;
+
+// only ever built once, at startup
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(name = "sds011", setting = AppSettings::SubcommandRequiredElseHelp)]
+enum Command {
+  /// Serves readings from one or more sensors as Prometheus metrics and JSON
+  /// over HTTP, e.g. `sds011 export /dev/ttyUSB0`
+  #[cfg(feature = "exporter")]
+  Export(export::Options),
+
+  /// Inspects, configures, and tests a sensor, e.g.
+  /// `sds011 tool /dev/ttyUSB0 info`
+  #[cfg(feature = "cli")]
+  Tool(tool::Options),
+
+  /// Displays sensor events; short for `sds011 tool watch`, e.g.
+  /// `sds011 watch '/dev/ttyUSB*' --output-mode csv`
+  #[cfg(feature = "cli")]
+  Watch(tool::WatchOptions),
+
+  /// Writes a shell completion script to stdout, one of: bash, zsh, fish,
+  /// powershell, elvish
+  Completions(CompletionsAction)
+}
+
+/// Logs to stderr, at the level given by `SDS011_LOG` (default: info), or
+/// only warnings and errors if `quiet`
+fn init_logging(quiet: bool, format: LogFormat) {
+  let env = env_logger::Env::default()
+    .filter_or("SDS011_LOG", "info")
+    .write_style_or("SDS011_STYLE", "always");
+
+  let mut builder = env_logger::Builder::from_env(env);
+  if quiet {
+    builder.filter_level(log::LevelFilter::Warn);
+  }
+
+  if format == LogFormat::Json {
+    builder.format(|buf, record| writeln!(buf, "{}", logging::format_json(record)));
+  }
+
+  builder
+    .target(env_logger::Target::Stderr)
+    .init();
+}
+
+fn run(command: Command) -> Result<()> {
+  match command {
+    #[cfg(feature = "exporter")]
+    Command::Export(opts) => export::run(opts),
+    #[cfg(feature = "cli")]
+    Command::Tool(opts) => tool::run(opts),
+    #[cfg(feature = "cli")]
+    Command::Watch(opts) => tool::run(opts.into()),
+    Command::Completions(action) => completions::completions(action)
+  }
+}
+
+fn main() {
+  let command = match Command::from_iter_safe(env::args_os()) {
+    Ok(command) => command,
+    Err(e) => match e.kind {
+      ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+      _ => {
+        eprintln!("{}", e.message);
+        process::exit(ExitCode::InvalidArguments as i32);
+      }
+    }
+  };
+
+  let code = match run(command) {
+    Ok(()) => ExitCode::Success,
+    Err(e) => {
+      eprintln!("Error: {:?}", e);
+      ExitCode::from_error(&e)
+    }
+  };
+
+  process::exit(code as i32);
+}
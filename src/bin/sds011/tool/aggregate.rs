@@ -0,0 +1,77 @@
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use sds011_exporter::response::QueryResponse;
+
+/// Running min/max/mean/stddev of a single measurement
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Series {
+  pub count: usize,
+  pub sum: f64,
+  pub sum_sq: f64,
+  pub min: f32,
+  pub max: f32
+}
+
+impl Series {
+  pub fn push(&mut self, value: f32) {
+    if self.count == 0 {
+      self.min = value;
+      self.max = value;
+    } else {
+      self.min = self.min.min(value);
+      self.max = self.max.max(value);
+    }
+
+    self.count += 1;
+    self.sum += value as f64;
+    self.sum_sq += (value as f64).powi(2);
+  }
+
+  pub fn mean(&self) -> f64 {
+    if self.count == 0 {
+      0.0
+    } else {
+      self.sum / self.count as f64
+    }
+  }
+
+  /// Population standard deviation
+  pub fn stddev(&self) -> f64 {
+    if self.count == 0 {
+      return 0.0;
+    }
+
+    let mean = self.mean();
+    (self.sum_sq / self.count as f64 - mean * mean).max(0.0).sqrt()
+  }
+}
+
+/// A bucket of readings collected over one `--aggregate` interval
+#[derive(Debug, Clone)]
+pub struct Aggregate {
+  pub started: Instant,
+  pub datetime: DateTime<Utc>,
+  pub pm25: Series,
+  pub pm10: Series
+}
+
+impl Aggregate {
+  pub fn new() -> Self {
+    Aggregate {
+      started: Instant::now(),
+      datetime: Utc::now(),
+      pm25: Series::default(),
+      pm10: Series::default()
+    }
+  }
+
+  pub fn push(&mut self, query: &QueryResponse) {
+    self.pm25.push(query.pm25);
+    self.pm10.push(query.pm10);
+  }
+
+  pub fn count(&self) -> usize {
+    self.pm25.count
+  }
+}
@@ -0,0 +1,385 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use parquet::basic::{Compression, Type as PhysicalType};
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use parquet::schema::types::TypePtr;
+use sds011_exporter::metadata::SensorMetadata;
+use sds011_exporter::quality::ReadingQuality;
+use sds011_exporter::response::QueryResponse;
+
+use crate::tool::aggregate::Aggregate;
+use crate::tool::output::{parent_dir, unused_path, RotationPolicy, ROTATED_FORMAT};
+
+/// Schema of individual readings. Columns are only ever added to the end of
+/// these, so queries over old and new archives keep working.
+const READING_SCHEMA: &str = "
+  message sds011_reading {
+    required int64 datetime (TIMESTAMP(MILLIS,true));
+    optional binary port (STRING);
+    required binary device (STRING);
+    required float pm25;
+    required float pm10;
+    optional binary quality (STRING);
+    optional binary name (STRING);
+    optional binary location (STRING);
+  }
+";
+
+/// Schema of `--aggregate` buckets, named like the CSV columns
+const AGGREGATE_SCHEMA: &str = "
+  message sds011_aggregate {
+    required int64 datetime (TIMESTAMP(MILLIS,true));
+    optional binary port (STRING);
+    required int64 count;
+    required double pm25_mean;
+    required float pm25_min;
+    required float pm25_max;
+    required double pm10_mean;
+    required float pm10_min;
+    required float pm10_max;
+    optional binary name (STRING);
+    optional binary location (STRING);
+  }
+";
+
+/// Rows buffered before they're written out as a row group; about 3 hours of
+/// readings from one sensor in continuous mode
+const ROW_GROUP_ROWS: usize = 10_000;
+
+/// A single value in a row
+enum Value {
+  Int64(i64),
+  Float(f32),
+  Double(f64),
+  Text(Option<String>)
+}
+
+/// Buffered values of a single column
+enum Column {
+  Int64(Vec<i64>),
+  Float(Vec<f32>),
+  Double(Vec<f64>),
+  Text(Vec<Option<String>>)
+}
+
+impl Column {
+  fn for_field(field: &TypePtr) -> Result<Column> {
+    Ok(match field.get_physical_type() {
+      PhysicalType::INT64 => Column::Int64(Vec::new()),
+      PhysicalType::FLOAT => Column::Float(Vec::new()),
+      PhysicalType::DOUBLE => Column::Double(Vec::new()),
+      PhysicalType::BYTE_ARRAY => Column::Text(Vec::new()),
+      other => return Err(anyhow!("unsupported parquet type {} for {}", other, field.name()))
+    })
+  }
+
+  fn push(&mut self, value: Value) -> Result<()> {
+    match (self, value) {
+      (Column::Int64(column), Value::Int64(value)) => column.push(value),
+      (Column::Float(column), Value::Float(value)) => column.push(value),
+      (Column::Double(column), Value::Double(value)) => column.push(value),
+      (Column::Text(column), Value::Text(value)) => column.push(value),
+      _ => return Err(anyhow!("row doesn't match the parquet schema"))
+    };
+
+    Ok(())
+  }
+
+  fn clear(&mut self) {
+    match self {
+      Column::Int64(column) => column.clear(),
+      Column::Float(column) => column.clear(),
+      Column::Double(column) => column.clear(),
+      Column::Text(column) => column.clear()
+    }
+  }
+
+  fn write(&self, writer: &mut ColumnWriter<'_>) -> Result<()> {
+    match (writer, self) {
+      (ColumnWriter::Int64ColumnWriter(w), Column::Int64(values)) => {
+        w.write_batch(values, None, None)?;
+      },
+      (ColumnWriter::FloatColumnWriter(w), Column::Float(values)) => {
+        w.write_batch(values, None, None)?;
+      },
+      (ColumnWriter::DoubleColumnWriter(w), Column::Double(values)) => {
+        w.write_batch(values, None, None)?;
+      },
+      (ColumnWriter::ByteArrayColumnWriter(w), Column::Text(values)) => {
+        let present: Vec<ByteArray> = values.iter()
+          .flatten()
+          .map(|value| ByteArray::from(value.as_str()))
+          .collect();
+
+        // optional columns mark each null with a definition level of 0
+        if w.get_descriptor().max_def_level() > 0 {
+          let levels: Vec<i16> = values.iter().map(|v| v.is_some() as i16).collect();
+          w.write_batch(&present, Some(&levels), None)?;
+        } else {
+          w.write_batch(&present, None, None)?;
+        }
+      },
+      _ => return Err(anyhow!("column doesn't match the parquet schema"))
+    };
+
+    Ok(())
+  }
+}
+
+/// An Apache Parquet output file, written for `--output-mode parquet`.
+///
+/// Rows are buffered and written in row groups, and the file is only readable
+/// once it's closed (on rotation, `close()`, or drop) and its footer written.
+/// Existing files can't be appended to, so one already at the path is moved
+/// aside first, as is each file rotated out. Rotated files are named
+/// `<stem>.<timestamp>.parquet` so a glob like `readings*.parquet` matches all
+/// of them, and `RotationPolicy::retention` applies to them as usual.
+pub struct ParquetFile {
+  path: PathBuf,
+  schema: TypePtr,
+  policy: RotationPolicy,
+  writer: Option<SerializedFileWriter<File>>,
+  columns: Vec<Column>,
+  rows: usize,
+  opened: Instant
+}
+
+impl ParquetFile {
+  /// Opens a file of readings, or of aggregates if `aggregate` is set
+  pub fn open<P: Into<PathBuf>>(path: P, aggregate: bool, policy: RotationPolicy) -> Result<Self> {
+    let schema = if aggregate { AGGREGATE_SCHEMA } else { READING_SCHEMA };
+    let schema = Arc::new(parse_message_type(schema)?);
+    let columns = schema.get_fields()
+      .iter()
+      .map(Column::for_field)
+      .collect::<Result<_>>()?;
+
+    let mut file = ParquetFile {
+      path: path.into(),
+      schema,
+      policy,
+      writer: None,
+      columns,
+      rows: 0,
+      opened: Instant::now()
+    };
+
+    if file.path.exists() {
+      file.move_aside()?;
+    }
+
+    file.writer = Some(file.create()?);
+
+    Ok(file)
+  }
+
+  fn create(&self) -> Result<SerializedFileWriter<File>> {
+    let file = File::create(&self.path)
+      .with_context(|| format!("could not open output file {}", self.path.display()))?;
+
+    let properties = WriterProperties::builder()
+      .set_compression(Compression::SNAPPY)
+      .set_created_by(format!("sds011 {}", env!("CARGO_PKG_VERSION")))
+      .build();
+
+    Ok(SerializedFileWriter::new(file, Arc::clone(&self.schema), Arc::new(properties))?)
+  }
+
+  /// Moves the file at `path` to a timestamped name alongside it
+  fn move_aside(&self) -> Result<()> {
+    let now = Utc::now();
+    let rotated = unused_path(|n| rotated_path(&self.path, now, n));
+
+    fs::rename(&self.path, &rotated)
+      .with_context(|| format!("could not rotate {}", self.path.display()))?;
+
+    info!("rotated output file {} to {}", self.path.display(), rotated.display());
+
+    let mut prefix = self.path.file_stem().unwrap_or_default().to_owned();
+    prefix.push(".");
+    self.policy.retention.prune(parent_dir(&self.path), &prefix.to_string_lossy())
+  }
+
+  pub fn write_query(
+    &mut self,
+    port: Option<&str>,
+    metadata: Option<&SensorMetadata>,
+    query: &QueryResponse,
+    datetime: DateTime<Utc>
+  ) -> Result<()> {
+    let metadata = metadata.cloned().unwrap_or_default();
+
+    self.push(vec![
+      Value::Int64(datetime.timestamp_millis()),
+      Value::Text(port.map(String::from)),
+      Value::Text(Some(query.device.to_string())),
+      Value::Float(query.pm25),
+      Value::Float(query.pm10),
+      Value::Text(Some(ReadingQuality::of(query).to_string())),
+      Value::Text(metadata.name),
+      Value::Text(metadata.location)
+    ])
+  }
+
+  pub fn write_aggregate(
+    &mut self,
+    port: Option<&str>,
+    metadata: Option<&SensorMetadata>,
+    aggregate: &Aggregate
+  ) -> Result<()> {
+    let (pm25, pm10) = (&aggregate.pm25, &aggregate.pm10);
+    let metadata = metadata.cloned().unwrap_or_default();
+
+    self.push(vec![
+      Value::Int64(aggregate.datetime.timestamp_millis()),
+      Value::Text(port.map(String::from)),
+      Value::Int64(aggregate.count() as i64),
+      Value::Double(pm25.mean()),
+      Value::Float(pm25.min),
+      Value::Float(pm25.max),
+      Value::Double(pm10.mean()),
+      Value::Float(pm10.min),
+      Value::Float(pm10.max),
+      Value::Text(metadata.name),
+      Value::Text(metadata.location)
+    ])
+  }
+
+  fn push(&mut self, row: Vec<Value>) -> Result<()> {
+    if self.writer.is_none() {
+      return Err(anyhow!("output file {} is closed", self.path.display()));
+    }
+
+    if self.should_rotate() {
+      self.rotate()?;
+    }
+
+    if row.len() != self.columns.len() {
+      return Err(anyhow!("row doesn't match the parquet schema"));
+    }
+
+    for (column, value) in self.columns.iter_mut().zip(row) {
+      column.push(value)?;
+    }
+
+    self.rows += 1;
+    if self.rows >= ROW_GROUP_ROWS {
+      self.write_row_group()?;
+    }
+
+    Ok(())
+  }
+
+  /// Writes out buffered rows, if any, as a row group
+  fn write_row_group(&mut self) -> Result<()> {
+    if self.rows == 0 {
+      return Ok(());
+    }
+
+    let writer = match self.writer.as_mut() {
+      Some(writer) => writer,
+      None => return Err(anyhow!("output file {} is closed", self.path.display()))
+    };
+
+    let mut group = writer.next_row_group()?;
+    for column in &self.columns {
+      let mut column_writer = group.next_column()?
+        .ok_or_else(|| anyhow!("row doesn't match the parquet schema"))?;
+
+      column.write(column_writer.untyped())?;
+      column_writer.close()?;
+    }
+
+    group.close()?;
+
+    self.columns.iter_mut().for_each(Column::clear);
+    self.rows = 0;
+
+    Ok(())
+  }
+
+  /// Size is only known for row groups already written, so files may run
+  /// over `max_bytes` by up to a row group
+  fn should_rotate(&self) -> bool {
+    let writer = match self.writer.as_ref() {
+      Some(writer) => writer,
+      None => return false
+    };
+
+    // never rotate an empty file
+    if self.rows == 0 && writer.flushed_row_groups().is_empty() {
+      return false;
+    }
+
+    let written = writer.bytes_written() as u64;
+
+    let too_big = self.policy.max_bytes
+      .map(|max| written >= max)
+      .unwrap_or(false);
+
+    let too_old = self.policy.max_age
+      .map(|max| self.opened.elapsed() >= max)
+      .unwrap_or(false);
+
+    too_big || too_old
+  }
+
+  fn rotate(&mut self) -> Result<()> {
+    self.close()?;
+    self.move_aside()?;
+
+    self.writer = Some(self.create()?);
+    self.opened = Instant::now();
+
+    Ok(())
+  }
+
+  /// Writes any buffered rows and the footer, leaving a complete file. Later
+  /// writes fail.
+  pub fn close(&mut self) -> Result<()> {
+    self.write_row_group()?;
+
+    if let Some(writer) = self.writer.take() {
+      writer.close()
+        .with_context(|| format!("could not close output file {}", self.path.display()))?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Drop for ParquetFile {
+  fn drop(&mut self) {
+    if let Err(e) = self.close() {
+      error!("{:#}", e);
+    }
+  }
+}
+
+/// Inserts a timestamp, and a counter `n` if nonzero, before the file's
+/// extension, e.g. `readings.20200101T000000Z.parquet` or
+/// `readings.20200101T000000Z.1.parquet`
+fn rotated_path(path: &Path, now: DateTime<Utc>, n: usize) -> PathBuf {
+  let timestamp = now.format(ROTATED_FORMAT);
+
+  let mut name = path.file_stem().unwrap_or_default().to_owned();
+  name.push(format!(".{}", timestamp));
+  if n > 0 {
+    name.push(format!(".{}", n));
+  }
+  if let Some(extension) = path.extension() {
+    name.push(".");
+    name.push(extension);
+  }
+
+  path.with_file_name(name)
+}
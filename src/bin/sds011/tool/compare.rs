@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use sds011_exporter::response::*;
+use sds011_exporter::ControlMessage;
+use structopt::StructOpt;
+
+use crate::tool::open;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct CompareAction {
+  /// the first (reference) sensor, e.g. /dev/ttyUSB0
+  #[structopt(parse(from_os_str))]
+  a: PathBuf,
+
+  /// the second sensor, e.g. /dev/ttyUSB1
+  #[structopt(parse(from_os_str))]
+  b: PathBuf,
+
+  /// How long to collect readings for, e.g. 10m, 1h
+  #[structopt(long, short, default_value = "1h", parse(try_from_str = humantime::parse_duration))]
+  duration: Duration,
+
+  /// Maximum time between two readings for them to count as simultaneous.
+  /// Increase this for sensors with a working period.
+  #[structopt(long, short, default_value = "500ms", parse(try_from_str = humantime::parse_duration))]
+  tolerance: Duration
+}
+
+type Reading = (Instant, QueryResponse);
+
+/// Running sums for bias and correlation between paired values
+#[derive(Debug, Default)]
+struct Pairs {
+  n: usize,
+  sum_a: f64,
+  sum_b: f64,
+  sum_aa: f64,
+  sum_bb: f64,
+  sum_ab: f64,
+  sum_abs_diff: f64
+}
+
+impl Pairs {
+  fn push(&mut self, a: f32, b: f32) {
+    let (a, b) = (a as f64, b as f64);
+
+    self.n += 1;
+    self.sum_a += a;
+    self.sum_b += b;
+    self.sum_aa += a * a;
+    self.sum_bb += b * b;
+    self.sum_ab += a * b;
+    self.sum_abs_diff += (b - a).abs();
+  }
+
+  fn mean_a(&self) -> f64 {
+    self.sum_a / self.n as f64
+  }
+
+  fn mean_b(&self) -> f64 {
+    self.sum_b / self.n as f64
+  }
+
+  /// Mean of `b - a`
+  fn bias(&self) -> f64 {
+    self.mean_b() - self.mean_a()
+  }
+
+  /// Mean absolute difference
+  fn mae(&self) -> f64 {
+    self.sum_abs_diff / self.n as f64
+  }
+
+  /// Pearson correlation coefficient, if defined (i.e. neither sensor's
+  /// readings were constant)
+  fn correlation(&self) -> Option<f64> {
+    let n = self.n as f64;
+    let cov = n * self.sum_ab - self.sum_a * self.sum_b;
+    let var_a = n * self.sum_aa - self.sum_a * self.sum_a;
+    let var_b = n * self.sum_bb - self.sum_b * self.sum_b;
+
+    if var_a <= 0.0 || var_b <= 0.0 {
+      None
+    } else {
+      Some(cov / (var_a.sqrt() * var_b.sqrt()))
+    }
+  }
+}
+
+/// Pairs each reading from `a` with the closest unused reading from `b` that
+/// arrived within `tolerance`. Both inputs must be in arrival order.
+fn align<'a>(
+  a: &'a [Reading],
+  b: &'a [Reading],
+  tolerance: Duration
+) -> Vec<(&'a QueryResponse, &'a QueryResponse)> {
+  let distance = |x: Instant, y: Instant| if x > y { x - y } else { y - x };
+
+  let mut pairs = Vec::new();
+  let mut j = 0;
+
+  for (time_a, reading_a) in a {
+    // skip b readings too early to ever match this or any later a reading
+    while j < b.len() && b[j].0 < *time_a && *time_a - b[j].0 > tolerance {
+      j += 1;
+    }
+
+    // of the remaining candidates, take whichever of the next two is closer
+    let mut best = None;
+    for (k, (time_b, _)) in b.iter().enumerate().skip(j).take(2) {
+      let d = distance(*time_a, *time_b);
+      if d <= tolerance && best.map(|(_, best_d)| d < best_d).unwrap_or(true) {
+        best = Some((k, d));
+      }
+    }
+
+    if let Some((k, _)) = best {
+      pairs.push((reading_a, &b[k].1));
+      j = k + 1;
+    }
+  }
+
+  pairs
+}
+
+/// Collects readings from both sensors concurrently and reports how well they
+/// agree
+pub fn compare(action: CompareAction) -> Result<()> {
+  let (_a_tx, a_rx, a_control) = open(&action.a)?;
+  let (_b_tx, b_rx, b_control) = open(&action.b)?;
+
+  info!(
+    "comparing {} and {} for {}",
+    action.a.display(), action.b.display(),
+    humantime::format_duration(action.duration)
+  );
+
+  let mut a: Vec<Reading> = Vec::new();
+  let mut b: Vec<Reading> = Vec::new();
+  let start = Instant::now();
+
+  while start.elapsed() < action.duration {
+    for (rx, control, readings) in &mut [
+      (&a_rx, &a_control, &mut a),
+      (&b_rx, &b_control, &mut b)
+    ] {
+      for response in rx.try_iter() {
+        if let Resp::Query(q) = response {
+          readings.push((Instant::now(), q));
+        }
+      }
+
+      for message in control.try_iter() {
+        match message {
+          ControlMessage::Error(e) => warn!("sensor warning: {}", e),
+          ControlMessage::FatalError(e) => return Err(e.into()),
+          message => debug!("sensor event: {:?}", message)
+        }
+      }
+    }
+
+    // readings are timestamped on receipt, so poll more often than the
+    // tolerance allows for
+    thread::sleep(Duration::from_millis(50));
+  }
+
+  let pairs = align(&a, &b, action.tolerance);
+
+  let describe = |path: &PathBuf, readings: &[Reading]| match readings.first() {
+    Some((_, q)) => format!("{} (0x{:04x}), {} readings", path.display(), q.device, readings.len()),
+    None => format!("{}, no readings", path.display())
+  };
+
+  println!("Sensor A:         {}", describe(&action.a, &a));
+  println!("Sensor B:         {}", describe(&action.b, &b));
+  println!(
+    "Aligned pairs:    {} (tolerance {})",
+    pairs.len(), humantime::format_duration(action.tolerance)
+  );
+
+  if pairs.is_empty() {
+    println!("No simultaneous readings to compare; try increasing --tolerance");
+    return Ok(());
+  }
+
+  let mut pm25 = Pairs::default();
+  let mut pm10 = Pairs::default();
+  for (reading_a, reading_b) in &pairs {
+    pm25.push(reading_a.pm25, reading_b.pm25);
+    pm10.push(reading_a.pm10, reading_b.pm10);
+  }
+
+  for (name, stats) in &[("PM2.5", &pm25), ("PM10", &pm10)] {
+    let correlation = match stats.correlation() {
+      Some(r) => format!("{:.3}", r),
+      None => "n/a".into()
+    };
+
+    println!(
+      "{:<6}            mean A {:.1}  mean B {:.1}  bias (B - A) {:+.2}  MAE {:.2}  r {}",
+      format!("{}:", name), stats.mean_a(), stats.mean_b(), stats.bias(), stats.mae(),
+      correlation
+    );
+  }
+
+  Ok(())
+}
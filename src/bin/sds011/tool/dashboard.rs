@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::io::{self, Stdout};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+  disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Sparkline};
+use ratatui::{Frame, Terminal};
+use sds011_exporter::aqi::{pm10_aqi, pm25_aqi, AqiCategory};
+use sds011_exporter::response::*;
+use sds011_exporter::ControlMessage;
+
+/// number of readings kept for the sparklines
+const HISTORY: usize = 300;
+
+#[derive(Debug, Default)]
+struct State {
+  latest: Option<QueryResponse>,
+  last_reading: Option<Instant>,
+
+  /// readings scaled by 10 (sparklines only take integers)
+  pm25_history: VecDeque<u64>,
+  pm10_history: VecDeque<u64>,
+
+  readings: usize,
+  errors: usize,
+  last_error: Option<String>,
+  fatal_error: Option<String>
+}
+
+impl State {
+  fn push(&mut self, query: QueryResponse) {
+    for (history, value) in &mut [
+      (&mut self.pm25_history, query.pm25),
+      (&mut self.pm10_history, query.pm10)
+    ] {
+      if history.len() == HISTORY {
+        history.pop_front();
+      }
+
+      history.push_back((*value * 10.0) as u64);
+    }
+
+    self.readings += 1;
+    self.last_reading = Some(Instant::now());
+    self.latest = Some(query);
+  }
+}
+
+fn category_color(category: AqiCategory) -> Color {
+  let (r, g, b) = category.color();
+  Color::Rgb(r, g, b)
+}
+
+fn draw_value(f: &mut Frame, area: Rect, title: &str, value: Option<(f32, u16)>) {
+  let block = Block::default().title(title).borders(Borders::ALL);
+
+  let lines = match value {
+    Some((value, aqi)) => {
+      let category = AqiCategory::from_aqi(aqi);
+      let color = category_color(category);
+
+      vec![
+        Line::from(Span::styled(
+          format!("{:.1} µg/m³", value),
+          Style::default().add_modifier(Modifier::BOLD)
+        )),
+        Line::from(Span::styled(
+          format!("AQI {} ({})", aqi, category.name()),
+          Style::default().fg(color)
+        ))
+      ]
+    },
+    None => vec![Line::from("waiting for data...")]
+  };
+
+  f.render_widget(
+    Paragraph::new(lines).block(block).alignment(Alignment::Center),
+    area
+  );
+}
+
+fn draw_history(f: &mut Frame, area: Rect, title: &str, history: &VecDeque<u64>, color: Color) {
+  // show the most recent readings that fit in the available width
+  let width = area.width.saturating_sub(2) as usize;
+  let data: Vec<u64> = history.iter()
+    .skip(history.len().saturating_sub(width))
+    .cloned()
+    .collect();
+
+  let sparkline = Sparkline::default()
+    .block(Block::default().title(title).borders(Borders::ALL))
+    .data(&data)
+    .style(Style::default().fg(color));
+
+  f.render_widget(sparkline, area);
+}
+
+fn draw(f: &mut Frame, state: &State) {
+  let rows = Layout::default()
+    .direction(Direction::Vertical)
+    .constraints([
+      Constraint::Length(4),
+      Constraint::Min(4),
+      Constraint::Min(4),
+      Constraint::Length(3)
+    ])
+    .split(f.size());
+
+  let values = Layout::default()
+    .direction(Direction::Horizontal)
+    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+    .split(rows[0]);
+
+  let pm25 = state.latest.as_ref().map(|q| (q.pm25, pm25_aqi(q.pm25)));
+  let pm10 = state.latest.as_ref().map(|q| (q.pm10, pm10_aqi(q.pm10)));
+
+  draw_value(f, values[0], "PM2.5", pm25);
+  draw_value(f, values[1], "PM10", pm10);
+
+  let color = |value: Option<(f32, u16)>| value
+    .map(|(_, aqi)| category_color(AqiCategory::from_aqi(aqi)))
+    .unwrap_or(Color::Gray);
+
+  draw_history(f, rows[1], "PM2.5 history", &state.pm25_history, color(pm25));
+  draw_history(f, rows[2], "PM10 history", &state.pm10_history, color(pm10));
+
+  let age = match state.last_reading {
+    Some(t) => format!("{}s ago", t.elapsed().as_secs()),
+    None => "never".into()
+  };
+
+  let device = match &state.latest {
+    Some(q) => q.device.to_string(),
+    None => "?".into()
+  };
+
+  let mut status = vec![
+    Span::raw(format!(
+      "device: {}  readings: {}  last reading: {}  ",
+      device, state.readings, age
+    )),
+    Span::styled(
+      format!("errors: {}", state.errors),
+      if state.errors > 0 {
+        Style::default().fg(Color::Yellow)
+      } else {
+        Style::default()
+      }
+    )
+  ];
+
+  if let Some(e) = state.fatal_error.as_ref().or(state.last_error.as_ref()) {
+    status.push(Span::styled(format!("  last: {}", e), Style::default().fg(Color::Red)));
+  }
+
+  f.render_widget(
+    Paragraph::new(Line::from(status))
+      .block(Block::default().title("status (q to quit)").borders(Borders::ALL)),
+    rows[3]
+  );
+}
+
+fn run(
+  terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>
+) -> Result<()> {
+  let mut state = State::default();
+
+  loop {
+    for response in response_rx.try_iter() {
+      if let Resp::Query(q) = response {
+        state.push(q);
+      }
+    }
+
+    for control in control_rx.try_iter() {
+      match control {
+        ControlMessage::Error(e) => {
+          state.errors += e.count();
+          state.last_error = Some(e.to_string());
+        },
+        ControlMessage::FatalError(e) => {
+          state.fatal_error = Some(format!("fatal: {}", e));
+        },
+        _ => ()
+      }
+    }
+
+    terminal.draw(|f| draw(f, &state))?;
+
+    if event::poll(Duration::from_millis(250))? {
+      if let Event::Key(key) = event::read()? {
+        let ctrl_c = key.code == KeyCode::Char('c')
+          && key.modifiers.contains(KeyModifiers::CONTROL);
+
+        if key.kind == KeyEventKind::Press
+          && (ctrl_c || key.code == KeyCode::Char('q') || key.code == KeyCode::Esc)
+        {
+          break;
+        }
+      }
+    }
+  }
+
+  match state.fatal_error {
+    Some(e) => Err(anyhow!("{}", e)),
+    None => Ok(())
+  }
+}
+
+/// Shows a live dashboard until the user quits
+pub fn dashboard(
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>
+) -> Result<()> {
+  // log lines would scribble over the UI; errors are shown in the status bar
+  // instead
+  let log_level = log::max_level();
+  log::set_max_level(log::LevelFilter::Off);
+
+  enable_raw_mode()?;
+  let mut stdout = io::stdout();
+  execute!(stdout, EnterAlternateScreen)?;
+  let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+  let result = run(&mut terminal, response_rx, control_rx);
+
+  // always try to restore the terminal, even if the dashboard failed
+  disable_raw_mode()?;
+  execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+  terminal.show_cursor()?;
+  log::set_max_level(log_level);
+
+  result
+}
@@ -0,0 +1,144 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use sds011_exporter::command::Query;
+use sds011_exporter::{retry_send, RetryConfig};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::tool::open;
+use crate::exit::UsageError;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct HealthcheckAction {
+  /// Checks a running `sds011 export`'s json endpoint, e.g.
+  /// http://localhost:8082/json, rather than querying the sensor directly
+  #[structopt(long)]
+  url: Option<String>,
+
+  /// With --url, the maximum age of the exporter's latest reading, e.g. 120s.
+  /// Should be longer than the sensor's working period.
+  #[structopt(long, default_value = "120s", parse(try_from_str = humantime::parse_duration))]
+  max_age: Duration,
+
+  /// With --url, how long to wait for the exporter to respond
+  #[structopt(long, default_value = "5s", parse(try_from_str = humantime::parse_duration))]
+  http_timeout: Duration
+}
+
+/// The exporter's `/json` response
+#[derive(Debug, Deserialize)]
+struct ExporterReading {
+  datetime: String,
+  pm25: f32,
+  pm10: f32
+}
+
+/// Makes a bare-bones HTTP/1.0 GET request, returning the response body
+fn http_get(url: &str, timeout: Duration) -> Result<String> {
+  let rest = url.strip_prefix("http://")
+    .ok_or_else(|| UsageError(format!("unsupported url '{}', expected http://...", url)))?;
+
+  let (authority, path) = match rest.find('/') {
+    Some(i) => (&rest[..i], &rest[i..]),
+    None => (rest, "/")
+  };
+
+  let host = authority.rsplit_once(':').map(|(host, _)| host).unwrap_or(authority);
+  let addr_str = if authority.contains(':') {
+    authority.to_string()
+  } else {
+    format!("{}:80", authority)
+  };
+
+  let addr = addr_str.to_socket_addrs()?
+    .next()
+    .ok_or_else(|| anyhow!("could not resolve {}", authority))?;
+
+  let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+  stream.set_read_timeout(Some(timeout))?;
+  stream.set_write_timeout(Some(timeout))?;
+
+  write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host)?;
+
+  let mut response = String::new();
+  stream.read_to_string(&mut response)?;
+
+  let (head, body) = response.split_once("\r\n\r\n")
+    .ok_or_else(|| anyhow!("malformed http response"))?;
+
+  let status = head.lines().next().unwrap_or("");
+  if status.split_whitespace().nth(1) != Some("200") {
+    return Err(anyhow!("unexpected http status: {}", status));
+  }
+
+  Ok(body.to_string())
+}
+
+fn check_url(url: &str, action: &HealthcheckAction) -> Result<String> {
+  let body = http_get(url, action.http_timeout)
+    .with_context(|| format!("error fetching {}", url))?;
+
+  let reading: Option<ExporterReading> = serde_json::from_str(&body)
+    .with_context(|| format!("invalid response from {}", url))?;
+
+  let reading = reading.ok_or_else(|| anyhow!("exporter has no reading"))?;
+
+  let datetime = DateTime::parse_from_rfc3339(&reading.datetime)
+    .with_context(|| format!("invalid reading datetime '{}'", reading.datetime))?;
+
+  let age = (Utc::now() - datetime.with_timezone(&Utc)).to_std().unwrap_or_default();
+  if age > action.max_age {
+    return Err(anyhow!(
+      "latest reading is {} old (max {})",
+      humantime::format_duration(Duration::from_secs(age.as_secs())),
+      humantime::format_duration(action.max_age)
+    ));
+  }
+
+  Ok(format!(
+    "PM2.5: {}, PM10: {} ({} old)",
+    reading.pm25, reading.pm10,
+    humantime::format_duration(Duration::from_secs(age.as_secs()))
+  ))
+}
+
+fn check_device(device: Option<PathBuf>, retry: &RetryConfig) -> Result<String> {
+  let device = device
+    .ok_or_else(|| UsageError("either --url or a sensor device is required".into()))?;
+
+  let (command_tx, response_rx, _control_rx) = open(&device)?;
+  let (reading, _) = retry_send(Query, &command_tx, &response_rx, retry)?;
+
+  Ok(format!("PM2.5: {}, PM10: {}", reading.pm25, reading.pm10))
+}
+
+/// Prints a Nagios-style status line and exits with 0 if the exporter (or
+/// sensor) is healthy, or 1 otherwise
+pub fn healthcheck(
+  device: Option<PathBuf>,
+  action: HealthcheckAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  let result = match &action.url {
+    Some(url) => check_url(url, &action),
+    None => check_device(device, retry)
+  };
+
+  match result {
+    Ok(status) => {
+      println!("OK - {}", status);
+      Ok(())
+    },
+    Err(e) if e.is::<UsageError>() => Err(e),
+    Err(e) => {
+      println!("CRITICAL - {:#}", e);
+      process::exit(1);
+    }
+  }
+}
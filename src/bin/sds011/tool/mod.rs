@@ -0,0 +1,924 @@
+//! `sds011 tool`: inspects, configures, and tests a sensor
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, Receiver};
+use std::time::{Duration, Instant};
+use std::thread;
+
+use sds011_exporter::command::*;
+use sds011_exporter::metadata::SensorMetadata;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  fetch_info, find_sensor, measure_n, retry_send, ControlMessage, DeviceSelector, RetryConfig,
+  SummaryStats
+};
+use sds011_exporter::logging::LogFormat;
+use structopt::StructOpt;
+use anyhow::Result;
+use chrono::Utc;
+
+mod aggregate;
+#[cfg(feature = "parquet")]
+mod archive;
+mod compare;
+#[cfg(feature = "dashboard")]
+mod dashboard;
+mod healthcheck;
+mod output;
+mod provision;
+mod raw;
+mod repl;
+mod replay;
+mod schedule;
+mod scheduler;
+mod selftest;
+mod sniff;
+
+use aggregate::{Aggregate, Series};
+use compare::CompareAction;
+use crate::exit::{count_invalid_frames, stopping, UsageError};
+use healthcheck::HealthcheckAction;
+use provision::ProvisionAction;
+use raw::RawAction;
+use replay::ReplayCsvAction;
+use schedule::ScheduleAction;
+use scheduler::MeasureAction;
+use selftest::SelftestAction;
+use sniff::SniffAction;
+use output::*;
+
+#[derive(Debug, Clone, StructOpt)]
+struct SetWorkModeAction {
+  /// If set, queries the current state and does not set a value.
+  #[structopt(long, short)]
+  query: bool,
+
+  /// The working mode, one of: work (on), sleep (off)
+  mode: WorkMode
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct SetReportingModeAction {
+  /// If set, queries the current state and does not set a value.
+  #[structopt(long, short)]
+  query: bool,
+
+  /// the reporting mode, one of: active, query
+  mode: ReportingMode
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct SetWorkingPeriodAction {
+  /// If set, queries the current state and does not set a value.
+  #[structopt(long, short)]
+  query: bool,
+
+  /// the working period in minutes (e.g. 5 or 5m); 0 for continuous
+  ///
+  /// 0: continuous, actively reports every second{n}
+  /// 1-30: actively reports every `n` minutes after 30s of measurement
+  working_period: WorkingPeriod
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct WatchAction {
+  /// Additional sensor devices or glob patterns (e.g. "/dev/ttyUSB*") to watch
+  /// along with the global device. Readings from all sensors are merged into
+  /// one stream, with a `port` column (or field, or tag) identifying each.
+  #[structopt(parse(from_os_str))]
+  devices: Vec<PathBuf>,
+
+  /// A name for the sensor, included in output as a `name` column (or field,
+  /// or tag). When watching several, given for each as DEVICE=NAME.
+  #[structopt(long = "name", number_of_values = 1)]
+  names: Vec<String>,
+
+  /// Where the sensor is, e.g. "north wall", included in output as a
+  /// `location` column (or field, or tag). When watching several, given for
+  /// each as DEVICE=LOCATION.
+  #[structopt(long = "location", number_of_values = 1)]
+  locations: Vec<String>,
+
+  /// If set, writes incoming queries to stdout in the given format. Note that
+  /// log messages are always written to stderr. JSON messages are one JSON
+  /// object per line. One of: none, json, csv, influx, prom-textfile, human,
+  /// parquet
+  ///
+  /// prom-textfile requires --output-file, e.g. a `.prom` file in
+  /// node_exporter's textfile collector directory. parquet (if built with the
+  /// `parquet` feature) also requires --output-file, which is only complete
+  /// once watch exits, e.g. on Ctrl-C.
+  #[structopt(long, short, default_value = "none")]
+  output_mode: OutputMode,
+
+  /// If set, buckets readings over the given interval (e.g. 30s, 1m, 1h) and
+  /// writes the mean, min, max, and count of each bucket rather than every
+  /// individual reading.
+  #[structopt(long, short, parse(try_from_str = humantime::parse_duration))]
+  aggregate: Option<Duration>,
+
+  /// If set, writes output to the given file rather than stdout
+  #[structopt(long, parse(from_os_str))]
+  output_file: Option<PathBuf>,
+
+  /// Rotates the output file once it reaches the given size, e.g. 512K, 10M
+  #[structopt(long, parse(try_from_str = parse_size), requires = "output-file")]
+  rotate_size: Option<u64>,
+
+  /// Rotates the output file after the given interval, e.g. 1h, 1day
+  #[structopt(
+    long,
+    parse(try_from_str = humantime::parse_duration),
+    requires = "output-file"
+  )]
+  rotate_interval: Option<Duration>,
+
+  /// Gzips output files after they've been rotated
+  #[structopt(long, requires = "output-file")]
+  compress: bool,
+
+  /// Compresses output files with the given format after they've been
+  /// rotated, one of: gzip, zstd
+  #[structopt(long, requires = "output-file", conflicts_with = "compress")]
+  compression: Option<CompressionFormat>,
+
+  /// Keeps at most the given number of rotated output files, deleting the
+  /// oldest
+  #[structopt(long, requires = "output-file")]
+  keep_files: Option<usize>,
+
+  /// Deletes rotated output files older than the given age, e.g. 30days
+  #[structopt(
+    long,
+    parse(try_from_str = humantime::parse_duration),
+    requires = "output-file"
+  )]
+  keep_age: Option<Duration>,
+
+  /// Keeps at most the given total size of rotated output files, e.g. 1G,
+  /// deleting the oldest
+  #[structopt(long, parse(try_from_str = parse_size), requires = "output-file")]
+  keep_size: Option<u64>
+}
+
+impl WatchAction {
+  fn rotation_policy(&self) -> RotationPolicy {
+    let compress = match (self.compression, self.compress) {
+      (Some(format), _) => Some(format),
+      (None, true) => Some(CompressionFormat::Gzip),
+      (None, false) => None
+    };
+
+    RotationPolicy {
+      max_bytes: self.rotate_size,
+      max_age: self.rotate_interval,
+      compress,
+      retention: RetentionPolicy {
+        max_files: self.keep_files,
+        max_age: self.keep_age,
+        max_bytes: self.keep_size
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct QueryAction {
+  /// Number of readings to take and summarize
+  #[structopt(long, short, default_value = "1")]
+  samples: usize,
+
+  /// Time between readings
+  #[structopt(long, default_value = "2s", parse(try_from_str = humantime::parse_duration))]
+  spacing: Duration,
+
+  /// If set, prints the summary as a single JSON object
+  #[structopt(long)]
+  json: bool
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct StatsAction {
+  /// How long to collect readings for, e.g. 10m, 1h
+  #[structopt(long, short, default_value = "10m", parse(try_from_str = humantime::parse_duration))]
+  duration: Duration,
+
+  /// If the sensor is in query reporting mode, how often to query it
+  #[structopt(long, short, default_value = "1s", parse(try_from_str = humantime::parse_duration))]
+  interval: Duration
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+enum Action {
+  /// Fetches sensor information
+  Info,
+
+  /// Queries the sensor for a reading; with --samples, takes several and
+  /// reports their mean, median, stddev, min, and max
+  Query(QueryAction),
+
+  /// Displays sensor events
+  Watch(WatchAction),
+
+  /// Collects readings for a while and reports statistics and frame error
+  /// rates, e.g. to validate a new sensor or cable
+  Stats(StatsAction),
+
+  /// Sends every command to the sensor, verifying that each reply echoes it
+  /// and measuring round-trip latencies, and prints a pass/fail report, e.g.
+  /// to validate hardware; settings are left as they were
+  Selftest(SelftestAction),
+
+  /// Reads two sensors concurrently and reports how well they agree, e.g.
+  /// `compare /dev/ttyUSB0 /dev/ttyUSB1 --duration 1h`
+  Compare(CompareAction),
+
+  /// Sends a raw command frame (checksum filled in automatically) and prints
+  /// any responses, e.g. for debugging clone sensors
+  Raw(RawAction),
+
+  /// Prints every byte received from the sensor as annotated hex, without
+  /// sending anything
+  Dump,
+
+  /// Decodes traffic in both directions, e.g. between the sensor and other
+  /// software using it, with timestamps; never writes to the port
+  Sniff(SniffAction),
+
+  /// Starts an interactive prompt for sending commands over a single open
+  /// connection
+  Repl,
+
+  /// Checks that a sensor (or, with --url, a running exporter) is returning
+  /// readings, exiting with 0 if healthy or 1 if not, e.g. for Docker's
+  /// HEALTHCHECK
+  Healthcheck(HealthcheckAction),
+
+  /// Replays readings from a CSV log (as written by `watch --output-mode csv`)
+  /// in another output format, with original or shifted timestamps, e.g. to
+  /// backfill InfluxDB from local archives
+  ReplayCsv(ReplayCsvAction),
+
+  /// Shows a live terminal dashboard of readings and errors
+  #[cfg(feature = "dashboard")]
+  Dashboard,
+
+  /// Applies a device profile (device id, reporting mode, working period,
+  /// work mode) from a TOML file, verifying each setting, and prints what
+  /// changed
+  Provision(ProvisionAction),
+
+  /// Switches the working period and reporting mode by local time of day,
+  /// e.g. continuous readings during the day and every 30 minutes at night
+  Schedule(ScheduleAction),
+
+  /// Takes measurements on a cron schedule, e.g. for battery or solar
+  /// installations: wakes the sensor, warms it up, aggregates several samples,
+  /// puts it back to sleep, and publishes the result
+  Measure(MeasureAction),
+
+  /// Sets the sensor's working mode (work / sleep)
+  SetWorkMode(SetWorkModeAction),
+
+  /// Sets the device reporting mode (active / query)
+  SetReportingMode(SetReportingModeAction),
+
+  /// Sets the device working period
+  ///
+  /// 0: continuous (actively reports every ~1s, never sleeps){n}
+  /// 1-30: reports every `n` minutes
+  SetWorkingPeriod(SetWorkingPeriodAction),
+}
+
+/// Options shared by every tool command, and by `sds011 watch`
+#[derive(Debug, Clone, StructOpt)]
+pub struct GlobalOptions {
+  /// only log warnings and errors
+  #[structopt(long, global = true)]
+  quiet: bool,
+
+  /// log format, one of: text, json
+  #[structopt(long, global = true, default_value = "text", env = "SDS011_LOG_FORMAT")]
+  log_format: LogFormat,
+
+  /// maximum number of attempts for each command before giving up
+  #[structopt(long, global = true, default_value = "5", env = "SDS011_RETRIES")]
+  retries: usize,
+
+  /// how long to wait for a response before resending a command, e.g. 500ms,
+  /// 2s
+  #[structopt(
+    long, global = true, default_value = "500ms", env = "SDS011_TIMEOUT",
+    parse(try_from_str = humantime::parse_duration)
+  )]
+  timeout: Duration
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct Options {
+  /// sensor serial device, e.g. /dev/ttyUSB0; required for all commands that
+  /// talk to a single sensor
+  #[structopt(parse(from_os_str))]
+  device: Option<PathBuf>,
+
+  /// find the sensor by its USB serial adapter's serial number instead of by
+  /// path, so scripts survive device nodes being renumbered
+  #[structopt(long, conflicts_with_all = &["device", "device-id"], env = "SDS011_DEVICE_SERIAL")]
+  device_serial: Option<String>,
+
+  /// find the sensor by its device id instead of by path, e.g. 0xA1B2, by
+  /// asking each USB serial port
+  #[structopt(long, conflicts_with = "device", env = "SDS011_DEVICE_ID")]
+  device_id: Option<DeviceId>,
+
+  #[structopt(flatten)]
+  global: GlobalOptions,
+
+  #[structopt(subcommand)]
+  action: Action
+}
+
+/// `sds011 watch`, i.e. `sds011 tool watch` with every device given after
+/// `watch`
+#[derive(Debug, Clone, StructOpt)]
+pub struct WatchOptions {
+  #[structopt(flatten)]
+  global: GlobalOptions,
+
+  #[structopt(flatten)]
+  action: WatchAction
+}
+
+impl From<WatchOptions> for Options {
+  fn from(watch: WatchOptions) -> Options {
+    Options {
+      device: None,
+      device_serial: None,
+      device_id: None,
+      global: watch.global,
+      action: Action::Watch(watch.action)
+    }
+  }
+}
+
+impl Options {
+  fn retry_config(&self) -> RetryConfig {
+    RetryConfig::builder()
+      .retries(self.global.retries)
+      .timeout(self.global.timeout)
+      .build()
+  }
+
+  /// The sensor device, finding it by `--device-serial` or `--device-id` if
+  /// given
+  fn device(&self) -> Result<Option<PathBuf>> {
+    let selector = match (&self.device_serial, self.device_id) {
+      (Some(serial), _) => DeviceSelector::UsbSerial(serial.clone()),
+      (None, Some(id)) => DeviceSelector::DeviceId(id),
+      (None, None) => return Ok(self.device.clone())
+    };
+
+    Ok(Some(find_sensor(&selector, &self.retry_config())?.into()))
+  }
+}
+
+fn info(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  retry: &RetryConfig
+) -> Result<()> {
+  let info = fetch_info(&command_tx, &response_rx, retry)?;
+  let device = info.device_id;
+
+  println!("Device ID:        {} ({})", device, device.0);
+  println!("Working mode:     {:?}", info.work_mode);
+  println!("Reporting mode:   {:?}", info.reporting_mode);
+  println!("Working period:   {:?}", info.working_period);
+  println!("Firmware version: {}", info.firmware_date);
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  Ok(())
+}
+
+/// Expands any glob patterns among `patterns`, e.g. `/dev/ttyUSB*`. Returns
+/// the matching paths, and whether any patterns were globs.
+fn expand_devices(patterns: &[PathBuf]) -> Result<(Vec<PathBuf>, bool)> {
+  let mut paths = Vec::new();
+  let mut globbed = false;
+
+  for pattern in patterns {
+    let pattern_str = pattern.to_string_lossy();
+    if !pattern_str.contains(&['*', '?', '['][..]) {
+      paths.push(pattern.clone());
+      continue;
+    }
+
+    globbed = true;
+
+    let matches = glob::glob(&pattern_str)
+      .map_err(|e| UsageError(format!("invalid device pattern '{}': {}", pattern_str, e)))?
+      .collect::<Result<Vec<_>, _>>()?;
+
+    if matches.is_empty() {
+      return Err(UsageError(format!("no devices match '{}'", pattern_str)).into());
+    }
+
+    paths.extend(matches);
+  }
+
+  Ok((paths, globbed))
+}
+
+/// A sensor being watched, with its own formatting history and aggregation
+/// bucket
+struct Watched {
+  _command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  port: String,
+  formatter: Formatter,
+  aggregate: Aggregate
+}
+
+/// The value given for `port` by `--name` or `--location` arguments, which
+/// must each be DEVICE=VALUE when watching several sensors. CSV output isn't
+/// quoted, so values can't contain commas or line breaks.
+fn assigned(
+  option: &str,
+  values: &[String],
+  ports: &[String],
+  multiple: bool
+) -> Result<HashMap<String, String>> {
+  let mut assigned = HashMap::new();
+  for value in values {
+    let (port, value) = if multiple {
+      value.split_once('=')
+        .filter(|(port, _)| ports.iter().any(|p| p == port))
+        .ok_or_else(|| UsageError(format!(
+          "invalid --{} '{}', expected DEVICE={} naming a watched device",
+          option, value, option.to_ascii_uppercase()
+        )))?
+    } else {
+      (ports[0].as_str(), value.as_str())
+    };
+
+    if value.contains(',') || value.contains('\n') {
+      return Err(UsageError(format!("--{} '{}' can't contain commas or line breaks", option, value)).into());
+    }
+
+    assigned.insert(port.to_string(), value.to_string());
+  }
+
+  Ok(assigned)
+}
+
+fn watch(device: Option<PathBuf>, action: WatchAction) -> Result<()> {
+  let patterns: Vec<PathBuf> = device.into_iter().chain(action.devices.clone()).collect();
+  let (devices, globbed) = expand_devices(&patterns)?;
+  if devices.is_empty() {
+    return Err(UsageError("a sensor device is required, e.g. /dev/ttyUSB0".into()).into());
+  }
+
+  // label readings by port whenever there could be more than one
+  let multiple = devices.len() > 1 || globbed;
+
+  let ports: Vec<String> = devices.iter().map(|d| d.display().to_string()).collect();
+  let mut names = assigned("name", &action.names, &ports, multiple)?;
+  let mut locations = assigned("location", &action.locations, &ports, multiple)?;
+  let metadata = !names.is_empty() || !locations.is_empty();
+
+  let header = action.output_mode.header(action.aggregate.is_some(), multiple, metadata);
+  let header = header.as_deref();
+  let mut sink = match (&action.output_mode, &action.output_file) {
+    (OutputMode::PromTextfile, Some(path)) => {
+      if action.rotate_size.is_some() || action.rotate_interval.is_some() {
+        return Err(UsageError("prom-textfile output files can't be rotated".into()).into());
+      }
+
+      if multiple {
+        return Err(UsageError("prom-textfile output only supports a single device".into()).into());
+      }
+
+      Sink::Textfile(path.clone())
+    },
+    (OutputMode::PromTextfile, None) => {
+      return Err(UsageError("prom-textfile output requires --output-file".into()).into());
+    },
+    #[cfg(feature = "parquet")]
+    (OutputMode::Parquet, Some(path)) => {
+      let policy = action.rotation_policy();
+      if policy.compress.is_some() {
+        return Err(UsageError("parquet output files are already compressed".into()).into());
+      }
+
+      Sink::Parquet(archive::ParquetFile::open(path, action.aggregate.is_some(), policy)?)
+    },
+    #[cfg(feature = "parquet")]
+    (OutputMode::Parquet, None) => {
+      return Err(UsageError("parquet output requires --output-file".into()).into());
+    },
+    (_, Some(path)) => Sink::File(RotatingFile::open(path, action.rotation_policy(), header)?),
+    (_, None) => Sink::stdout(header)
+  };
+
+  // finish on Ctrl-C rather than exiting mid-write, so the in-progress
+  // aggregate bucket is written and the sink is closed cleanly (parquet files
+  // are unreadable without the footer written on close)
+  crate::exit::stop_on_signal();
+
+  // only colorize human output headed for a terminal, and respect NO_COLOR
+  let color = action.output_file.is_none()
+    && io::stdout().is_terminal()
+    && env::var_os("NO_COLOR").is_none();
+
+  let mut sensors = Vec::new();
+  for device in devices {
+    let (command_tx, response_rx, control_rx) = open(&device)?;
+    let port = device.display().to_string();
+    let label = if multiple { Some(port.clone()) } else { None };
+
+    let mut formatter = Formatter::new(action.output_mode, color, label);
+    if metadata {
+      formatter = formatter.with_metadata(SensorMetadata {
+        name: names.remove(&port),
+        location: locations.remove(&port)
+      });
+    }
+
+    sensors.push(Watched {
+      _command_tx: command_tx,
+      response_rx,
+      control_rx,
+      formatter,
+      port,
+      aggregate: Aggregate::new()
+    });
+  }
+
+  while !stopping() {
+    for sensor in &mut sensors {
+      for response in sensor.response_rx.try_iter() {
+        if multiple {
+          info!("{}: {:x?}", sensor.port, response);
+        } else {
+          info!("{:x?}", response);
+        }
+
+        if let Resp::Query(q) = &response {
+          if action.aggregate.is_some() {
+            sensor.aggregate.push(q);
+          } else {
+            sink.query(&mut sensor.formatter, q, Utc::now())?;
+          }
+        }
+      }
+
+      if let Some(interval) = action.aggregate {
+        if sensor.aggregate.started.elapsed() >= interval {
+          // empty buckets (e.g. while the sensor sleeps between working
+          // periods) aren't worth a row
+          if sensor.aggregate.count() > 0 {
+            sink.aggregate(&mut sensor.formatter, &sensor.aggregate)?;
+          }
+
+          sensor.aggregate = Aggregate::new();
+        }
+      }
+
+      for control in sensor.control_rx.try_iter() {
+        match control {
+          ControlMessage::Error(e) => error!(
+            device = sensor.port.as_str(),
+            error_kind = e.kind().as_str();
+            "Error ({}): {}", sensor.port, e
+          ),
+          ControlMessage::FatalError(e) => {
+            error!(
+              device = sensor.port.as_str(),
+              error_kind = e.kind().as_str();
+              "Fatal error ({}): {:?}", sensor.port, e
+            );
+            return Err(e.into());
+          },
+          message => debug!("sensor event ({}): {:?}", sensor.port, message)
+        }
+      }
+    }
+
+    thread::sleep(Duration::from_millis(100));
+  }
+
+  info!("stopping");
+
+  // don't lose partial buckets
+  if action.aggregate.is_some() {
+    for sensor in &mut sensors {
+      if sensor.aggregate.count() > 0 {
+        sink.aggregate(&mut sensor.formatter, &sensor.aggregate)?;
+      }
+    }
+  }
+
+  sink.close()
+}
+
+fn stats(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: StatsAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  let (reporting, _) = retry_send(SetReportingMode {
+    query: true,
+    mode: ReportingMode::Active
+  }, &command_tx, &response_rx, retry)?;
+
+  let (working, _) = retry_send(SetWorkingPeriod {
+    query: true,
+    working_period: WorkingPeriod::Continuous
+  }, &command_tx, &response_rx, retry)?;
+
+  let period = match (reporting.mode, working.working_period) {
+    (ReportingMode::Query, _) => action.interval,
+    (ReportingMode::Active, period) => period.as_duration()
+  };
+
+  info!(
+    "collecting readings for {} (reporting mode: {:?}, working period: {:?})",
+    humantime::format_duration(action.duration), reporting.mode, working.working_period
+  );
+
+  let mut pm25 = Series::default();
+  let mut pm10 = Series::default();
+  let mut frames = 0usize;
+  let mut invalid = 0usize;
+  let mut queries = 0usize;
+
+  let start = Instant::now();
+  let mut last_query: Option<Instant> = None;
+
+  while start.elapsed() < action.duration {
+    if reporting.mode == ReportingMode::Query
+      && last_query.map(|t| t.elapsed() >= action.interval).unwrap_or(true)
+    {
+      command_tx.send(Query.to_cmd())?;
+      last_query = Some(Instant::now());
+      queries += 1;
+    }
+
+    for response in response_rx.try_iter() {
+      frames += 1;
+
+      if let Resp::Query(q) = response {
+        pm25.push(q.pm25);
+        pm10.push(q.pm10);
+      }
+    }
+
+    for control in control_rx.try_iter() {
+      match control {
+        ControlMessage::Error(e) => {
+          debug!("invalid frame: {}", e);
+          invalid += e.count();
+        },
+        ControlMessage::FatalError(e) => return Err(e.into()),
+        _ => ()
+      }
+    }
+
+    thread::sleep(Duration::from_millis(100));
+  }
+
+  let expected = match reporting.mode {
+    ReportingMode::Query => queries,
+    ReportingMode::Active => (action.duration.as_secs_f64() / period.as_secs_f64()) as usize
+  };
+
+  let dropped = expected.saturating_sub(pm25.count);
+  let percent = |n: usize, total: usize| if total == 0 {
+    0.0
+  } else {
+    n as f64 / total as f64 * 100.0
+  };
+
+  println!("Duration:         {}", humantime::format_duration(action.duration));
+  println!(
+    "Readings:         {} (expected {}, {:.1}% dropped)",
+    pm25.count, expected, percent(dropped, expected)
+  );
+  println!(
+    "Invalid frames:   {} of {} ({:.1}%)",
+    invalid, frames + invalid, percent(invalid, frames + invalid)
+  );
+
+  for (name, series) in &[("PM2.5", &pm25), ("PM10", &pm10)] {
+    println!(
+      "{:<6}            mean {:.1}  stddev {:.2}  min {}  max {}",
+      format!("{}:", name), series.mean(), series.stddev(), series.min, series.max
+    );
+  }
+
+  Ok(())
+}
+
+fn query(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: QueryAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  if action.samples == 0 {
+    return Err(UsageError("--samples must be at least 1".into()).into());
+  }
+
+  let summary = measure_n(&command_tx, &response_rx, action.samples, action.spacing, retry)?;
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  if action.json {
+    let stats = |s: &SummaryStats| serde_json::json!({
+      "mean": s.mean,
+      "median": s.median,
+      "stddev": s.stddev,
+      "min": s.min,
+      "max": s.max
+    });
+
+    println!("{}", serde_json::json!({
+      "count": summary.readings.len(),
+      "requested": summary.requested,
+      "pm25": stats(&summary.pm25),
+      "pm10": stats(&summary.pm10)
+    }));
+
+    return Ok(());
+  }
+
+  println!("Readings:         {} of {}", summary.readings.len(), summary.requested);
+  for (name, stats) in &[("PM2.5", &summary.pm25), ("PM10", &summary.pm10)] {
+    println!(
+      "{:<6}            mean {:.1}  median {:.1}  stddev {:.2}  min {}  max {}",
+      format!("{}:", name), stats.mean, stats.median, stats.stddev, stats.min, stats.max
+    );
+  }
+
+  Ok(())
+}
+
+fn set_work_mode(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: SetWorkModeAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  match (action.query, action.mode) {
+    (true, _) => info!("sending working mode query..."),
+    (false, mode) => info!("attempting to set working mode: {:?}", mode)
+  };
+
+  let (response, _) = retry_send(SetSleepWork {
+    query: action.query,
+    mode: action.mode,
+  }, &command_tx, &response_rx, retry)?;
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  info!("working mode is now: {:?}", response);
+
+  Ok(())
+}
+
+fn set_reporting_mode(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: SetReportingModeAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  match (action.query, action.mode) {
+    (true, _) => info!("sending reporting mode query..."),
+    (false, mode) => info!("attempting to set reporting mode: {:?}", mode)
+  };
+
+  let (response, _) = retry_send(SetReportingMode {
+    query: action.query,
+    mode: action.mode
+  }, &command_tx, &response_rx, retry)?;
+
+  info!("reporting mode is now: {:?}", response);
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  Ok(())
+}
+
+fn set_working_period(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: SetWorkingPeriodAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  match (action.query, action.working_period) {
+    (true, _) => info!("sent working period query..."),
+    (false, period) => info!("attempting to set working period: {:?}", period)
+  };
+
+  let (response, _) = retry_send(SetWorkingPeriod {
+    query: action.query,
+    working_period: action.working_period
+  }, &command_tx, &response_rx, retry)?;
+
+  info!("working period is now: {:?}", response);
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  Ok(())
+}
+
+/// Opens the sensor at `device`, returning its command, response, and control
+/// channels
+fn open(device: &Path) -> Result<(Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>)> {
+  let (command_tx, response_rx, control_rx) = sds011_exporter::open_sensor_channels(device)?;
+
+  Ok((command_tx, response_rx, count_invalid_frames(control_rx)))
+}
+
+pub fn run(opts: Options) -> Result<()> {
+  crate::init_logging(opts.global.quiet, opts.global.log_format);
+
+  let retry = &opts.retry_config();
+
+  // commands that don't use the global device
+  match opts.action {
+    Action::Compare(action) => return compare::compare(action),
+    Action::ReplayCsv(action) => return replay::replay_csv(action),
+    _ => ()
+  };
+
+  let device = opts.device()?;
+
+  // commands that may use more than one device, or none
+  match opts.action {
+    Action::Watch(action) => return watch(device, action),
+    Action::Healthcheck(action) => return healthcheck::healthcheck(device, action, retry),
+    _ => ()
+  };
+
+  let device = device
+    .ok_or_else(|| UsageError("a sensor device is required, e.g. /dev/ttyUSB0".into()))?;
+
+  // dump and sniff read the port directly
+  match opts.action {
+    Action::Dump => return raw::dump(&device),
+    Action::Sniff(action) => return sniff::sniff(&device, action),
+    _ => ()
+  };
+
+  let (command_tx, response_rx, control_rx) = open(&device)?;
+
+  match opts.action {
+    Action::Compare(_) | Action::Watch(_) | Action::Healthcheck(_)
+      | Action::ReplayCsv(_) | Action::Dump | Action::Sniff(_) => unreachable!(),
+    Action::Raw(action) => raw::raw(command_tx, response_rx, control_rx, action),
+    Action::Repl => repl::repl(command_tx, response_rx, control_rx, retry),
+    Action::Provision(action) => provision::provision(command_tx, response_rx, control_rx, action, retry),
+    Action::Schedule(action) => schedule::schedule(command_tx, response_rx, control_rx, action, retry),
+    Action::Measure(action) => scheduler::measure(command_tx, response_rx, control_rx, action, retry),
+    Action::Query(action) => query(command_tx, response_rx, control_rx, action, retry),
+    Action::Info => info(command_tx, response_rx, control_rx, retry),
+    #[cfg(feature = "dashboard")]
+    Action::Dashboard => dashboard::dashboard(response_rx, control_rx),
+    Action::Stats(action) => stats(command_tx, response_rx, control_rx, action, retry),
+    Action::Selftest(action) => {
+      selftest::selftest(command_tx, response_rx, control_rx, action, retry)
+    },
+    Action::SetWorkMode(action) => set_work_mode(command_tx, response_rx, control_rx, action, retry),
+    Action::SetReportingMode(action) => {
+      set_reporting_mode(command_tx, response_rx, control_rx, action, retry)
+    },
+    Action::SetWorkingPeriod(action) => {
+      set_working_period(command_tx, response_rx, control_rx, action, retry)
+    }
+  }
+}
@@ -0,0 +1,815 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Error, Result};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sds011_exporter::aqi::{pm10_aqi, pm25_aqi, AqiCategory};
+use sds011_exporter::metadata::SensorMetadata;
+use sds011_exporter::quality::ReadingQuality;
+use sds011_exporter::response::QueryResponse;
+use serde_json::{json, Map, Value};
+
+use crate::tool::aggregate::Aggregate;
+#[cfg(feature = "parquet")]
+use crate::tool::archive::ParquetFile;
+
+#[derive(Debug, Copy, Clone)]
+pub enum OutputMode {
+  None,
+  JSON,
+  CSV,
+
+  /// InfluxDB line protocol, one line per reading
+  Influx,
+
+  /// Prometheus text format for node_exporter's textfile collector; the output
+  /// file is atomically replaced with each reading
+  PromTextfile,
+
+  /// One readable (and, on a terminal, colorized) line per reading with its
+  /// AQI category and a sparkline of recent history
+  Human,
+
+  /// Apache Parquet rows with a fixed schema; requires an output file
+  #[cfg(feature = "parquet")]
+  Parquet
+}
+
+impl FromStr for OutputMode {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "" | "none" => Ok(OutputMode::None),
+      "json" => Ok(OutputMode::JSON),
+      "csv" => Ok(OutputMode::CSV),
+      "influx" => Ok(OutputMode::Influx),
+      "prom-textfile" => Ok(OutputMode::PromTextfile),
+      "human" => Ok(OutputMode::Human),
+      #[cfg(feature = "parquet")]
+      "parquet" => Ok(OutputMode::Parquet),
+      #[cfg(not(feature = "parquet"))]
+      "parquet" => Err(anyhow!("parquet output requires building with the `parquet` feature")),
+      s => Err(anyhow!(
+        "invalid output mode '{}', expected one of: none, json, csv, influx, prom-textfile, human",
+        s
+      ))
+    }
+  }
+}
+
+impl OutputMode {
+  /// Returns the line to write at the start of each output file, if any
+  pub fn header(&self, aggregate: bool, port: bool, metadata: bool) -> Option<String> {
+    let columns = match (self, aggregate) {
+      (OutputMode::CSV, false) => "pm25,pm10,quality",
+      (OutputMode::CSV, true) => "count,pm25_mean,pm25_min,pm25_max,pm10_mean,pm10_min,pm10_max",
+      _ => return None
+    };
+
+    let mut header = vec!["datetime"];
+    if port {
+      header.push("port");
+    }
+
+    if metadata {
+      header.extend(&SensorMetadata::FIELDS);
+    }
+
+    header.push(columns);
+    Some(header.join(","))
+  }
+}
+
+/// Nanoseconds since the epoch, as influx wants them
+fn timestamp_nanos(datetime: &DateTime<Utc>) -> i64 {
+  datetime.timestamp() * 1_000_000_000 + datetime.timestamp_subsec_nanos() as i64
+}
+
+/// Escapes commas, spaces, and equals signs in an influx tag value
+fn influx_tag(value: &str) -> String {
+  value.replace(',', "\\,").replace(' ', "\\ ").replace('=', "\\=")
+}
+
+/// Escapes backslashes, quotes, and newlines in a Prometheus label value
+fn prom_label(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Quotes a CSV field if it contains commas, quotes, or line breaks, doubling
+/// any quotes, per RFC 4180
+fn csv_field(value: &str) -> String {
+  if value.contains(&[',', '"', '\n', '\r'][..]) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+/// number of values shown in `human` sparklines
+const SPARKLINE_LEN: usize = 30;
+
+const SPARKLINE_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Renders values as a unicode sparkline scaled between their min and max
+fn sparkline(values: &VecDeque<f32>) -> String {
+  let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+  let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+  let range = max - min;
+
+  values.iter()
+    .map(|v| {
+      let level = if range > 0.0 {
+        ((v - min) / range * (SPARKLINE_CHARS.len() - 1) as f32).round() as usize
+      } else {
+        0
+      };
+
+      SPARKLINE_CHARS[level.min(SPARKLINE_CHARS.len() - 1)]
+    })
+    .collect()
+}
+
+/// Formats readings for an `OutputMode`, keeping any history the mode needs
+pub struct Formatter {
+  mode: OutputMode,
+
+  /// if true, `human` output includes ANSI colors
+  color: bool,
+
+  /// the sensor's serial port, included in output when watching several
+  port: Option<String>,
+
+  /// the sensor's name and location, included in output if given for any
+  /// sensor; CSV columns are left empty for fields that aren't set
+  metadata: Option<SensorMetadata>,
+
+  /// recent PM2.5 values for `human` sparklines
+  history: VecDeque<f32>
+}
+
+impl Formatter {
+  pub fn new(mode: OutputMode, color: bool, port: Option<String>) -> Self {
+    Formatter {
+      mode,
+      color,
+      port,
+      metadata: None,
+      history: VecDeque::with_capacity(SPARKLINE_LEN)
+    }
+  }
+
+  /// Includes the sensor's name and location in output
+  pub fn with_metadata(mut self, metadata: SensorMetadata) -> Self {
+    self.metadata = Some(metadata);
+    self
+  }
+
+  /// Formats a `human` line: the reading, its AQI category, and a sparkline
+  /// of recent PM2.5 values
+  fn human(&mut self, datetime: &DateTime<Utc>, readings: &str, pm25: f32, pm10: f32) -> String {
+    if self.history.len() == SPARKLINE_LEN {
+      self.history.pop_front();
+    }
+    self.history.push_back(pm25);
+
+    let aqi = pm25_aqi(pm25).max(pm10_aqi(pm10));
+    let category = AqiCategory::from_aqi(aqi);
+    let mut aqi_text = format!("AQI {:>3} {}", aqi, category.name());
+    if self.color {
+      let (r, g, b) = category.color();
+      aqi_text = format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, aqi_text);
+    }
+
+    // a name is more recognizable than the port it's on
+    let name = self.metadata.as_ref().and_then(|m| m.name.as_ref());
+    let port = match name.or(self.port.as_ref()) {
+      Some(label) => format!("{}  ", label),
+      None => String::new()
+    };
+
+    format!(
+      "{}  {}{}  {}  {}",
+      datetime.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+      port,
+      readings,
+      aqi_text,
+      sparkline(&self.history)
+    )
+  }
+
+  #[cfg(feature = "parquet")]
+  pub fn port(&self) -> Option<&str> {
+    self.port.as_deref()
+  }
+
+  #[cfg(feature = "parquet")]
+  pub fn metadata(&self) -> Option<&SensorMetadata> {
+    self.metadata.as_ref()
+  }
+
+  /// The CSV columns, JSON object, and influx tags identifying the port and
+  /// sensor, if any
+  fn port_fields(&self) -> (String, Map<String, Value>, String) {
+    let mut csv = String::new();
+    let mut object = Map::new();
+    let mut tags = String::new();
+
+    if let Some(port) = &self.port {
+      csv.push_str(&format!("{},", csv_field(port)));
+      object.insert("port".into(), port.clone().into());
+      tags.push_str(&format!(",port={}", influx_tag(port)));
+    }
+
+    if let Some(metadata) = &self.metadata {
+      for (_, value) in metadata.iter() {
+        csv.push_str(&format!("{},", csv_field(value.unwrap_or_default())));
+      }
+
+      for (field, value) in metadata.set() {
+        object.insert(field.into(), value.into());
+        tags.push_str(&format!(",{}={}", field, influx_tag(value)));
+      }
+    }
+
+    (csv, object, tags)
+  }
+
+  /// Prometheus labels for the sensor's name and location following any
+  /// given, e.g. `{device="0xa1b2",name="kitchen"}`, or nothing if none
+  fn prom_labels(&self, labels: &[(&str, &str)]) -> String {
+    let mut labels: Vec<String> = labels.iter()
+      .map(|(label, value)| format!("{}=\"{}\"", label, prom_label(value)))
+      .collect();
+
+    if let Some(metadata) = &self.metadata {
+      for (field, value) in metadata.set() {
+        labels.push(format!("{}=\"{}\"", field, prom_label(value)));
+      }
+    }
+
+    if labels.is_empty() {
+      String::new()
+    } else {
+      format!("{{{}}}", labels.join(","))
+    }
+  }
+
+  /// Formats a reading taken at the given time, e.g. one replayed from a log
+  pub fn query_at(&mut self, query: &QueryResponse, now: DateTime<Utc>) -> Result<Option<String>> {
+    let datetime = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let (port_csv, mut port_json, port_tag) = self.port_fields();
+    let quality = ReadingQuality::of(query);
+
+    Ok(match self.mode {
+      OutputMode::None => None,
+      OutputMode::CSV => Some(format!(
+        "{},{}{},{},{}", datetime, port_csv, query.pm25, query.pm10, quality
+      )),
+      OutputMode::JSON => {
+        port_json.insert("datetime".into(), datetime.into());
+        port_json.insert("pm25".into(), query.pm25.into());
+        port_json.insert("pm10".into(), query.pm10.into());
+        port_json.insert("quality".into(), quality.flags().into());
+        Some(serde_json::to_string(&port_json)?)
+      },
+      OutputMode::Influx => Some(format!(
+        "sds011,device={}{} pm25={},pm10={},quality=\"{}\" {}",
+        query.device, port_tag, query.pm25, query.pm10, quality, timestamp_nanos(&now)
+      )),
+      OutputMode::PromTextfile => Some(format!(
+        concat!(
+          "# HELP sds011_pm25 PM2.5 concentration in micrograms per cubic meter\n",
+          "# TYPE sds011_pm25 gauge\n",
+          "sds011_pm25{labels} {pm25}\n",
+          "# HELP sds011_pm10 PM10 concentration in micrograms per cubic meter\n",
+          "# TYPE sds011_pm10 gauge\n",
+          "sds011_pm10{labels} {pm10}\n",
+          "# HELP sds011_reading_quality 1 if the flag is set on the last reading\n",
+          "# TYPE sds011_reading_quality gauge\n",
+          "{quality}",
+          "# HELP sds011_last_reading_timestamp_seconds time of the last reading\n",
+          "# TYPE sds011_last_reading_timestamp_seconds gauge\n",
+          "sds011_last_reading_timestamp_seconds{timestamp_labels} {timestamp}"
+        ),
+        labels = self.prom_labels(&[("device", &query.device.to_string())]),
+        quality = quality.iter()
+          .map(|(flag, set)| format!(
+            "sds011_reading_quality{} {}\n", self.prom_labels(&[("flag", flag)]), set as u8
+          ))
+          .collect::<String>(),
+        timestamp_labels = self.prom_labels(&[]),
+        pm25 = query.pm25,
+        pm10 = query.pm10,
+        timestamp = now.timestamp()
+      )),
+      OutputMode::Human => {
+        let mut readings = format!("PM2.5 {:>6.1}  PM10 {:>6.1} µg/m³", query.pm25, query.pm10);
+        if !quality.is_good() {
+          readings.push_str(&format!(" [{}]", quality));
+        }
+
+        Some(self.human(&now, &readings, query.pm25, query.pm10))
+      },
+      #[cfg(feature = "parquet")]
+      OutputMode::Parquet => None
+    })
+  }
+
+  pub fn aggregate(&mut self, aggregate: &Aggregate) -> Result<Option<String>> {
+    let datetime = aggregate.datetime.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let (pm25, pm10) = (&aggregate.pm25, &aggregate.pm10);
+    let (port_csv, mut port_json, port_tag) = self.port_fields();
+
+    Ok(match self.mode {
+      OutputMode::None => None,
+      OutputMode::CSV => Some(format!(
+        "{},{}{},{:.1},{},{},{:.1},{},{}",
+        datetime, port_csv, aggregate.count(),
+        pm25.mean(), pm25.min, pm25.max,
+        pm10.mean(), pm10.min, pm10.max
+      )),
+      OutputMode::JSON => {
+        port_json.insert("datetime".into(), datetime.into());
+        port_json.insert("count".into(), aggregate.count().into());
+        port_json.insert(
+          "pm25".into(),
+          json!({ "mean": pm25.mean(), "min": pm25.min, "max": pm25.max })
+        );
+        port_json.insert(
+          "pm10".into(),
+          json!({ "mean": pm10.mean(), "min": pm10.min, "max": pm10.max })
+        );
+        Some(serde_json::to_string(&port_json)?)
+      },
+      OutputMode::Influx => Some(format!(
+        concat!(
+          "sds011{} count={}i,pm25_mean={:.1},pm25_min={},pm25_max={},",
+          "pm10_mean={:.1},pm10_min={},pm10_max={} {}"
+        ),
+        port_tag,
+        aggregate.count(),
+        pm25.mean(), pm25.min, pm25.max,
+        pm10.mean(), pm10.min, pm10.max,
+        timestamp_nanos(&aggregate.datetime)
+      )),
+      OutputMode::PromTextfile => {
+        let labels = self.prom_labels(&[]);
+        let mut lines = vec![
+          "# HELP sds011_readings number of readings in the last aggregation interval".to_string(),
+          "# TYPE sds011_readings gauge".to_string(),
+          format!("sds011_readings{} {}", labels, aggregate.count())
+        ];
+
+        for (name, series) in &[("pm25", pm25), ("pm10", pm10)] {
+          lines.push(format!("# TYPE sds011_{}_mean gauge", name));
+          lines.push(format!("sds011_{}_mean{} {:.1}", name, labels, series.mean()));
+          lines.push(format!("# TYPE sds011_{}_min gauge", name));
+          lines.push(format!("sds011_{}_min{} {}", name, labels, series.min));
+          lines.push(format!("# TYPE sds011_{}_max gauge", name));
+          lines.push(format!("sds011_{}_max{} {}", name, labels, series.max));
+        }
+
+        lines.push("# TYPE sds011_last_reading_timestamp_seconds gauge".to_string());
+        lines.push(format!(
+          "sds011_last_reading_timestamp_seconds{} {}", labels, aggregate.datetime.timestamp()
+        ));
+
+        Some(lines.join("\n"))
+      },
+      OutputMode::Human => {
+        let readings = format!(
+          "PM2.5 {:>6.1} ({}-{})  PM10 {:>6.1} ({}-{}) µg/m³  n={}",
+          pm25.mean(), pm25.min, pm25.max,
+          pm10.mean(), pm10.min, pm10.max,
+          aggregate.count()
+        );
+
+        Some(self.human(&aggregate.datetime, &readings, pm25.mean() as f32, pm10.mean() as f32))
+      },
+      #[cfg(feature = "parquet")]
+      OutputMode::Parquet => None
+    })
+  }
+}
+
+/// Parses a byte size with an optional K, M, or G suffix (powers of 1024),
+/// e.g. `512K` or `10M`
+pub fn parse_size(s: &str) -> Result<u64> {
+  let s = s.trim();
+  let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+    Some('K') => (&s[..s.len() - 1], 1024),
+    Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+    Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+    _ => (s, 1)
+  };
+
+  let value = digits.trim().parse::<u64>()
+    .map_err(|e| anyhow!("invalid size '{}': {}", s, e))?;
+
+  value.checked_mul(multiplier).ok_or_else(|| anyhow!("invalid size '{}': too large", s))
+}
+
+/// Format of rotated file timestamps, e.g. `20200101T000000Z`
+pub const ROTATED_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// How rotated output files are compressed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompressionFormat {
+  Gzip,
+  Zstd
+}
+
+impl FromStr for CompressionFormat {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "gzip" | "gz" => Ok(CompressionFormat::Gzip),
+      "zstd" | "zst" => Ok(CompressionFormat::Zstd),
+      s => Err(anyhow!("invalid compression format '{}', expected one of: gzip, zstd", s))
+    }
+  }
+}
+
+impl CompressionFormat {
+  fn extension(&self) -> &'static str {
+    match self {
+      CompressionFormat::Gzip => "gz",
+      CompressionFormat::Zstd => "zst"
+    }
+  }
+}
+
+/// Conditions under which a `RotatingFile` is rotated; if neither limit is set
+/// the file grows forever
+#[derive(Debug, Clone, Default)]
+pub struct RotationPolicy {
+  /// rotate once the current file reaches this many bytes
+  pub max_bytes: Option<u64>,
+
+  /// rotate once the current file has been open for this long
+  pub max_age: Option<Duration>,
+
+  /// if set, rotated files are compressed
+  pub compress: Option<CompressionFormat>,
+
+  /// which rotated files to keep
+  pub retention: RetentionPolicy
+}
+
+/// Limits on the rotated files kept alongside an output file; the oldest files
+/// beyond any limit are deleted after each rotation. The current file never
+/// counts towards them.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+  /// keep at most this many rotated files
+  pub max_files: Option<usize>,
+
+  /// delete files rotated longer ago than this
+  pub max_age: Option<Duration>,
+
+  /// keep at most this many bytes of rotated files
+  pub max_bytes: Option<u64>
+}
+
+impl RetentionPolicy {
+  fn is_unlimited(&self) -> bool {
+    self.max_files.is_none() && self.max_age.is_none() && self.max_bytes.is_none()
+  }
+
+  /// Deletes rotated files in `dir` beyond the policy's limits, oldest first.
+  /// Rotated files are those named `<prefix><timestamp>`, with any suffix
+  /// (e.g. `.1.gz`), where the timestamp is in `ROTATED_FORMAT`.
+  pub fn prune(&self, dir: &Path, prefix: &str) -> Result<()> {
+    if self.is_unlimited() {
+      return Ok(());
+    }
+
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(dir)? {
+      let entry = entry?;
+      let name = entry.file_name();
+
+      let timestamp = name.to_str()
+        .and_then(|name| name.strip_prefix(prefix))
+        .and_then(|rest| rest.get(..16))
+        .and_then(|ts| NaiveDateTime::parse_from_str(ts, ROTATED_FORMAT).ok());
+
+      if let Some(timestamp) = timestamp {
+        rotated.push((Utc.from_utc_datetime(&timestamp), entry.path(), entry.metadata()?.len()));
+      }
+    }
+
+    // newest first; everything after the first file over a limit goes too
+    rotated.sort_by_key(|(rotated_at, _, _)| Reverse(*rotated_at));
+
+    let now = Utc::now();
+    let mut total = 0;
+    for (i, (rotated_at, path, len)) in rotated.into_iter().enumerate() {
+      total += len;
+
+      let too_many = self.max_files
+        .map(|max| i >= max)
+        .unwrap_or(false);
+
+      let too_old = self.max_age
+        .and_then(|max| chrono::Duration::from_std(max).ok())
+        .map(|max| now - rotated_at > max)
+        .unwrap_or(false);
+
+      let too_big = self.max_bytes
+        .map(|max| total > max)
+        .unwrap_or(false);
+
+      if too_many || too_old || too_big {
+        fs::remove_file(&path)
+          .with_context(|| format!("could not remove old output file {}", path.display()))?;
+
+        info!("removed old output file {}", path.display());
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// An append-only output file that is moved aside (to `<path>.<timestamp>`,
+/// optionally compressed) and reopened when its `RotationPolicy` says so.
+pub struct RotatingFile {
+  path: PathBuf,
+  policy: RotationPolicy,
+  header: Option<String>,
+  file: Option<BufWriter<File>>,
+  written: u64,
+  opened: Instant
+}
+
+impl RotatingFile {
+  pub fn open<P: Into<PathBuf>>(
+    path: P,
+    policy: RotationPolicy,
+    header: Option<&str>
+  ) -> Result<Self> {
+    let path = path.into();
+    let header = header.map(String::from);
+    let (file, written) = open_file(&path, header.as_deref())?;
+
+    Ok(RotatingFile {
+      path,
+      policy,
+      header,
+      file: Some(file),
+      written,
+      opened: Instant::now()
+    })
+  }
+
+  fn header_len(&self) -> u64 {
+    self.header.as_ref().map(|h| h.len() as u64 + 1).unwrap_or(0)
+  }
+
+  fn should_rotate(&self) -> bool {
+    // never rotate a file with nothing but a header in it
+    if self.written <= self.header_len() {
+      return false;
+    }
+
+    let too_big = self.policy.max_bytes
+      .map(|max| self.written >= max)
+      .unwrap_or(false);
+
+    let too_old = self.policy.max_age
+      .map(|max| self.opened.elapsed() >= max)
+      .unwrap_or(false);
+
+    too_big || too_old
+  }
+
+  fn rotate(&mut self) -> Result<()> {
+    // close the current file first; windows won't rename open files
+    if let Some(mut file) = self.file.take() {
+      file.flush()?;
+    }
+
+    let timestamp = Utc::now().format(ROTATED_FORMAT).to_string();
+    let rotated = unused_path(|n| {
+      let mut rotated = self.path.clone().into_os_string();
+      rotated.push(format!(".{}", timestamp));
+      if n > 0 {
+        rotated.push(format!(".{}", n));
+      }
+
+      PathBuf::from(rotated)
+    });
+
+    fs::rename(&self.path, &rotated)
+      .with_context(|| format!("could not rotate {}", self.path.display()))?;
+
+    if let Some(format) = self.policy.compress {
+      compress(&rotated, format)?;
+    }
+
+    info!("rotated output file {} to {}", self.path.display(), rotated.display());
+
+    let mut prefix = self.path.file_name().unwrap_or_default().to_owned();
+    prefix.push(".");
+    self.policy.retention.prune(parent_dir(&self.path), &prefix.to_string_lossy())?;
+
+    let (file, written) = open_file(&self.path, self.header.as_deref())?;
+    self.file = Some(file);
+    self.written = written;
+    self.opened = Instant::now();
+
+    Ok(())
+  }
+
+  pub fn write_line(&mut self, line: &str) -> Result<()> {
+    if self.should_rotate() {
+      self.rotate()?;
+    }
+
+    let file = match self.file.as_mut() {
+      Some(file) => file,
+      None => return Err(anyhow!("output file {} is closed", self.path.display()))
+    };
+
+    writeln!(file, "{}", line)?;
+    file.flush()?;
+    self.written += line.len() as u64 + 1;
+
+    Ok(())
+  }
+}
+
+fn open_file(path: &Path, header: Option<&str>) -> Result<(BufWriter<File>, u64)> {
+  let file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)
+    .with_context(|| format!("could not open output file {}", path.display()))?;
+
+  let mut written = file.metadata()?.len();
+  let mut file = BufWriter::new(file);
+
+  // only new files get a header, appending to an existing file continues it
+  if written == 0 {
+    if let Some(header) = header {
+      writeln!(file, "{}", header)?;
+      file.flush()?;
+      written += header.len() as u64 + 1;
+    }
+  }
+
+  Ok((file, written))
+}
+
+/// Compresses the file at `path` to e.g. `<path>.gz` and removes the original
+fn compress(path: &Path, format: CompressionFormat) -> Result<()> {
+  let mut compressed_path = path.as_os_str().to_owned();
+  compressed_path.push(format!(".{}", format.extension()));
+
+  let mut input = File::open(path)?;
+  let output = File::create(&compressed_path)
+    .with_context(|| format!("could not create {:?}", compressed_path))?;
+
+  match format {
+    CompressionFormat::Gzip => {
+      let mut encoder = GzEncoder::new(output, Compression::default());
+      io::copy(&mut input, &mut encoder)?;
+      encoder.finish()?;
+    },
+    CompressionFormat::Zstd => {
+      let mut encoder = zstd::Encoder::new(output, 0)?;
+      io::copy(&mut input, &mut encoder)?;
+      encoder.finish()?;
+    }
+  }
+
+  fs::remove_file(path)?;
+
+  Ok(())
+}
+
+/// The first of `candidate(0)`, `candidate(1)`, ... that doesn't exist, even
+/// compressed, so files rotated within the same second don't overwrite each
+/// other
+pub fn unused_path(candidate: impl Fn(usize) -> PathBuf) -> PathBuf {
+  let formats = [CompressionFormat::Gzip, CompressionFormat::Zstd];
+  let taken = |path: &Path| path.exists() || formats.iter().any(|format| {
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(format!(".{}", format.extension()));
+    Path::new(&compressed).exists()
+  });
+
+  (0..).map(candidate).find(|path| !taken(path)).unwrap()
+}
+
+/// The directory containing `path`, which may be relative to the current one
+pub fn parent_dir(path: &Path) -> &Path {
+  match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new(".")
+  }
+}
+
+/// Writes `contents` to `path` by way of a temporary file in the same
+/// directory, so readers (e.g. node_exporter) never see a partial file
+fn replace_file(path: &Path, contents: &str) -> Result<()> {
+  let mut tmp_path = path.as_os_str().to_owned();
+  tmp_path.push(format!(".{}.tmp", std::process::id()));
+  let tmp_path = PathBuf::from(tmp_path);
+
+  {
+    let mut tmp = File::create(&tmp_path)
+      .with_context(|| format!("could not create {}", tmp_path.display()))?;
+    writeln!(tmp, "{}", contents)?;
+    tmp.sync_all()?;
+  }
+
+  fs::rename(&tmp_path, path)
+    .with_context(|| format!("could not replace {}", path.display()))?;
+
+  Ok(())
+}
+
+/// Destination for formatted `watch` output
+pub enum Sink {
+  Stdout,
+  File(RotatingFile),
+
+  /// a file whose contents are atomically replaced by every write
+  Textfile(PathBuf),
+
+  #[cfg(feature = "parquet")]
+  Parquet(ParquetFile)
+}
+
+impl Sink {
+  pub fn stdout(header: Option<&str>) -> Self {
+    if let Some(header) = header {
+      println!("{}", header);
+    }
+
+    Sink::Stdout
+  }
+
+  pub fn write_line(&mut self, line: &str) -> Result<()> {
+    match self {
+      Sink::Stdout => println!("{}", line),
+      Sink::File(file) => file.write_line(line)?,
+      Sink::Textfile(path) => replace_file(path, line)?,
+      #[cfg(feature = "parquet")]
+      Sink::Parquet(_) => return Err(anyhow!("can't write a line to a parquet file"))
+    };
+
+    Ok(())
+  }
+
+  /// Writes a reading taken at the given time, formatted by `formatter`
+  pub fn query(
+    &mut self,
+    formatter: &mut Formatter,
+    query: &QueryResponse,
+    datetime: DateTime<Utc>
+  ) -> Result<()> {
+    #[cfg(feature = "parquet")]
+    if let Sink::Parquet(file) = self {
+      return file.write_query(formatter.port(), formatter.metadata(), query, datetime);
+    }
+
+    if let Some(line) = formatter.query_at(query, datetime)? {
+      self.write_line(&line)?;
+    }
+
+    Ok(())
+  }
+
+  /// Writes an aggregate bucket, formatted by `formatter`
+  pub fn aggregate(&mut self, formatter: &mut Formatter, aggregate: &Aggregate) -> Result<()> {
+    #[cfg(feature = "parquet")]
+    if let Sink::Parquet(file) = self {
+      return file.write_aggregate(formatter.port(), formatter.metadata(), aggregate);
+    }
+
+    if let Some(line) = formatter.aggregate(aggregate)? {
+      self.write_line(&line)?;
+    }
+
+    Ok(())
+  }
+
+  /// Finishes writing, e.g. a parquet file's footer
+  pub fn close(&mut self) -> Result<()> {
+    match self {
+      #[cfg(feature = "parquet")]
+      Sink::Parquet(file) => file.close(),
+      _ => Ok(())
+    }
+  }
+}
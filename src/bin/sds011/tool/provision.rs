@@ -0,0 +1,155 @@
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+
+use anyhow::{anyhow, Context, Result};
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{fetch_info, retry_send, set_device_id, ControlMessage, RetryConfig, SensorInfo};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct ProvisionAction {
+  /// TOML device profile; any of `device_id` (e.g. 0xA1B2 or "0xa1b2"),
+  /// `reporting_mode` (active, query), `working_period` (0-30, or e.g. "5m"),
+  /// and `work_mode` (work, sleep).
+  /// Settings left out of the profile aren't changed.
+  #[structopt(long, short, parse(from_os_str))]
+  config: PathBuf
+}
+
+/// A device profile as read from TOML
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+  device_id: Option<DeviceId>,
+  reporting_mode: Option<String>,
+  working_period: Option<WorkingPeriod>,
+  work_mode: Option<String>
+}
+
+fn verify<T: PartialEq + Debug>(setting: &str, requested: T, reported: T) -> Result<()> {
+  if requested == reported {
+    Ok(())
+  } else {
+    Err(anyhow!(
+      "{} was not applied: requested {:?}, but the sensor reports {:?}",
+      setting, requested, reported
+    ))
+  }
+}
+
+fn print_diff<T: PartialEq + Debug>(setting: &str, before: T, after: T) {
+  let label = format!("{}:", setting);
+
+  if before == after {
+    println!("{:<17} {:?} (unchanged)", label, before);
+  } else {
+    println!("{:<17} {:?} -> {:?}", label, before, after);
+  }
+}
+
+/// Applies a device profile, verifying each setting, and prints what changed
+pub fn provision(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: ProvisionAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  let contents = fs::read_to_string(&action.config)
+    .with_context(|| format!("could not read profile {}", action.config.display()))?;
+  let profile: Profile = toml::from_str(&contents)
+    .with_context(|| format!("invalid profile {}", action.config.display()))?;
+
+  let reporting_mode = profile.reporting_mode.as_deref()
+    .map(str::parse::<ReportingMode>)
+    .transpose()?;
+  let work_mode = profile.work_mode.as_deref()
+    .map(str::parse::<WorkMode>)
+    .transpose()?;
+
+  // a sleeping sensor ignores everything but work mode commands, so wake it
+  // up for the duration
+  let (initial, _) = retry_send(SetSleepWork {
+    query: true,
+    mode: WorkMode::Work
+  }, &command_tx, &response_rx, retry)?;
+
+  if initial.mode == WorkMode::Sleep {
+    info!("waking sensor to apply profile...");
+
+    let (r, _) = retry_send(SetSleepWork {
+      query: false,
+      mode: WorkMode::Work
+    }, &command_tx, &response_rx, retry)?;
+    verify("work mode", WorkMode::Work, r.mode)?;
+  }
+
+  let before = SensorInfo {
+    work_mode: initial.mode,
+    ..fetch_info(&command_tx, &response_rx, retry)?
+  };
+
+  if let Some(id) = profile.device_id {
+    if id != before.device_id {
+      info!("setting device id: {}", id);
+      set_device_id(id, &command_tx, &response_rx, retry)?;
+    }
+  }
+
+  if let Some(mode) = reporting_mode {
+    info!("setting reporting mode: {:?}", mode);
+    let (r, _) = retry_send(SetReportingMode {
+      query: false,
+      mode
+    }, &command_tx, &response_rx, retry)?;
+    verify("reporting mode", mode, r.mode)?;
+  }
+
+  if let Some(period) = profile.working_period {
+    info!("setting working period: {:?}", period);
+    let (r, _) = retry_send(SetWorkingPeriod {
+      query: false,
+      working_period: period
+    }, &command_tx, &response_rx, retry)?;
+    verify("working period", period, r.working_period)?;
+  }
+
+  // read everything back while the sensor is still awake
+  let mut after = fetch_info(&command_tx, &response_rx, retry)?;
+
+  if let Some(id) = profile.device_id {
+    verify("device id", id, after.device_id)?;
+  }
+
+  // finally restore (or apply) the work mode
+  let final_mode = work_mode.unwrap_or(initial.mode);
+  if final_mode != after.work_mode {
+    info!("setting work mode: {:?}", final_mode);
+    let (r, _) = retry_send(SetSleepWork {
+      query: false,
+      mode: final_mode
+    }, &command_tx, &response_rx, retry)?;
+    verify("work mode", final_mode, r.mode)?;
+    after.work_mode = r.mode;
+  }
+
+  print_diff(
+    "Device ID",
+    before.device_id.to_string(),
+    after.device_id.to_string()
+  );
+  print_diff("Working mode", before.work_mode, after.work_mode);
+  print_diff("Reporting mode", before.reporting_mode, after.reporting_mode);
+  print_diff("Working period", before.working_period, after.working_period);
+
+  for message in control_rx.try_iter() {
+    warn!("{:?}", message);
+  }
+
+  Ok(())
+}
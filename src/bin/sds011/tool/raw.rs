@@ -0,0 +1,123 @@
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use sds011_exporter::command::Cmd;
+use sds011_exporter::response::Resp;
+use sds011_exporter::util::{checksum, parse_hex, to_hex};
+use sds011_exporter::{
+  open_port, parse_command_frame, parse_frame, ControlMessage, PacketReader, ReadEvent
+};
+use structopt::StructOpt;
+
+use crate::exit::UsageError;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct RawAction {
+  /// The command frame as hex bytes, e.g. "AA B4 06 01 00 ...". Either the 15
+  /// data bytes alone, head + command id + data (17 bytes), or a complete
+  /// 19-byte frame; the checksum (and tail, if missing) are filled in
+  /// automatically.
+  #[structopt(long)]
+  hex: String,
+
+  /// How long to wait for (and print) responses after sending
+  #[structopt(long, short, default_value = "2s", parse(try_from_str = humantime::parse_duration))]
+  wait: Duration
+}
+
+/// Completes a (possibly partial) command frame: adds the head and command id
+/// to bare data bytes, and (re)computes the checksum and tail.
+pub fn fill_frame(bytes: &[u8]) -> Result<Vec<u8>> {
+  let data = match bytes.len() {
+    15 => bytes,
+    17 | 19 => {
+      if bytes[0] != 0xAA {
+        return Err(anyhow!("frame must start with head byte AA, got {:02X}", bytes[0]));
+      }
+
+      &bytes[2..17]
+    },
+    n => return Err(anyhow!(
+      "expected 15 data bytes, 17 bytes (head, id, data), or a 19 byte frame; got {} bytes",
+      n
+    ))
+  };
+
+  let id = if bytes.len() == 15 { 0xB4 } else { bytes[1] };
+
+  let mut frame = vec![0xAA, id];
+  frame.extend_from_slice(data);
+  frame.push(checksum(data));
+  frame.push(0xAB);
+
+  if bytes.len() == 19 && bytes[17] != frame[17] {
+    info!("replaced checksum {:02X} with {:02X}", bytes[17], frame[17]);
+  }
+
+  Ok(frame)
+}
+
+/// Sends a raw command frame and prints whatever comes back
+pub fn raw(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: RawAction
+) -> Result<()> {
+  let frame = parse_hex(&action.hex)
+    .map_err(anyhow::Error::from)
+    .and_then(|bytes| fill_frame(&bytes))
+    .map_err(|e| UsageError(e.to_string()))?;
+  println!("sent:     {}", to_hex(&frame));
+  command_tx.send(Cmd::raw(&frame))?;
+
+  let start = Instant::now();
+  while start.elapsed() < action.wait {
+    for response in response_rx.try_iter() {
+      println!("received: {:x?}", response);
+    }
+
+    for message in control_rx.try_iter() {
+      match message {
+        ControlMessage::Error(e) => println!("error:    {}", e),
+        ControlMessage::FatalError(e) => return Err(e.into()),
+        message => println!("event:    {:?}", message)
+      }
+    }
+
+    thread::sleep(Duration::from_millis(50));
+  }
+
+  Ok(())
+}
+
+/// Prints every byte read from the sensor, annotated with how it was decoded.
+/// Doesn't send anything.
+pub fn dump(device: &Path) -> Result<()> {
+  let port = open_port(device)?;
+  let mut reader = PacketReader::default();
+
+  for byte in BufReader::new(port).bytes() {
+    let timestamp = Local::now().format("%H:%M:%S%.3f");
+
+    match reader.push(byte?) {
+      Some(ReadEvent::Packet(packet)) => match parse_frame(&packet) {
+        Ok(response) => println!("{} frame   {}  {:x?}", timestamp, to_hex(&packet), response),
+        Err(e) => println!("{} invalid {}  {}", timestamp, to_hex(&packet), e)
+      },
+      Some(ReadEvent::Command(frame)) => match parse_command_frame(&frame) {
+        Ok(command) => println!("{} command {}  {:x?}", timestamp, to_hex(&frame), command),
+        Err(e) => println!("{} invalid {}  {}", timestamp, to_hex(&frame), e)
+      },
+      Some(ReadEvent::Garbage(byte)) => println!("{} garbage {:02X}", timestamp, byte),
+      None => ()
+    }
+  }
+
+  Ok(())
+}
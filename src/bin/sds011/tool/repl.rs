@@ -0,0 +1,161 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+use anyhow::Result;
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{retry_send, set_device_id, ControlMessage, RetryConfig};
+
+const HELP: &str = "\
+commands:
+  query              request a measurement
+  sleep              put the sensor to sleep (stops the fan and laser)
+  wake               wake the sensor up
+  period [N]         show the working period, or set it to N minutes (0-30)
+  mode [MODE]        show the reporting mode, or set it to active or query
+  id 0xXXXX          set the device id
+  info               show the firmware version and device id
+  dump on|off        print all other received frames and errors
+  help               show this message
+  quit               exit";
+
+fn print_other(other: Vec<Resp>, dump: bool) {
+  if dump {
+    for response in other {
+      println!("  received: {:x?}", response);
+    }
+  }
+}
+
+fn run_command(
+  words: &[&str],
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  retry: &RetryConfig,
+  dump: &mut bool
+) -> Result<()> {
+  match words {
+    ["query"] => {
+      let (r, other) = retry_send(Query, command_tx, response_rx, retry)?;
+      print_other(other, *dump);
+      println!("PM2.5: {} µg/m³, PM10: {} µg/m³ (AQI {})", r.pm25, r.pm10, r.aqi());
+    },
+
+    ["sleep"] | ["wake"] => {
+      let mode = if words[0] == "sleep" { WorkMode::Sleep } else { WorkMode::Work };
+      let (r, other) = retry_send(SetSleepWork {
+        query: false,
+        mode
+      }, command_tx, response_rx, retry)?;
+
+      print_other(other, *dump);
+      println!("working mode: {:?}", r.mode);
+    },
+
+    ["period"] | ["period", _] => {
+      let (query, working_period) = match words.get(1) {
+        Some(n) => (false, n.parse::<WorkingPeriod>()?),
+        None => (true, WorkingPeriod::Continuous)
+      };
+
+      let (r, other) = retry_send(SetWorkingPeriod {
+        query,
+        working_period
+      }, command_tx, response_rx, retry)?;
+
+      print_other(other, *dump);
+      println!("working period: {:?}", r.working_period);
+    },
+
+    ["mode"] | ["mode", _] => {
+      let (query, mode) = match words.get(1) {
+        Some(m) => (false, m.parse::<ReportingMode>()?),
+        None => (true, ReportingMode::Active)
+      };
+
+      let (r, other) = retry_send(SetReportingMode {
+        query,
+        mode
+      }, command_tx, response_rx, retry)?;
+
+      print_other(other, *dump);
+      println!("reporting mode: {:?}", r.mode);
+    },
+
+    ["id", id] => {
+      let (r, other) = set_device_id(id.parse()?, command_tx, response_rx, retry)?;
+
+      print_other(other, *dump);
+      println!("{:x?}", r);
+    },
+
+    ["info"] => {
+      let (r, other) = retry_send(GetFirmwareVersion, command_tx, response_rx, retry)?;
+      print_other(other, *dump);
+      println!("device id: 0x{:04x}, firmware: {:?}", r.device, r);
+    },
+
+    ["dump", "on"] => *dump = true,
+    ["dump", "off"] => *dump = false,
+
+    ["help"] | ["?"] => println!("{}", HELP),
+
+    _ => println!("unknown command '{}', try 'help'", words.join(" "))
+  }
+
+  Ok(())
+}
+
+/// Runs an interactive prompt against the open sensor until EOF or `quit`.
+///
+/// Frames received between commands (e.g. active reports) are printed after
+/// each line of input when dumping is enabled.
+pub fn repl(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  retry: &RetryConfig
+) -> Result<()> {
+  let mut dump = false;
+
+  println!("type 'help' for a list of commands");
+
+  let stdin = io::stdin();
+  let mut lines = stdin.lock().lines();
+
+  loop {
+    print!("sds011> ");
+    io::stdout().flush()?;
+
+    let line = match lines.next() {
+      Some(line) => line?,
+      None => break
+    };
+
+    print_other(response_rx.try_iter().collect(), dump);
+
+    for message in control_rx.try_iter() {
+      match message {
+        ControlMessage::Error(e) => if dump {
+          println!("  error: {}", e);
+        },
+        ControlMessage::FatalError(e) => return Err(e.into()),
+        message => if dump {
+          println!("  event: {:?}", message);
+        }
+      }
+    }
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+      [] => continue,
+      ["quit"] | ["exit"] => break,
+      words => if let Err(e) = run_command(words, &command_tx, &response_rx, retry, &mut dump) {
+        println!("error: {}", e);
+      }
+    }
+  }
+
+  Ok(())
+}
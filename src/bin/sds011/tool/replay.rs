@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use sds011_exporter::metadata::SensorMetadata;
+use sds011_exporter::response::QueryResponse;
+use sds011_exporter::util::DeviceId;
+use structopt::StructOpt;
+
+use crate::exit::UsageError;
+use crate::tool::output::*;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct ReplayCsvAction {
+  /// A CSV file written by `watch --output-mode csv`, with or without port,
+  /// name, and location columns. Aggregated files are replayed using each
+  /// bucket's mean.
+  #[structopt(parse(from_os_str))]
+  file: PathBuf,
+
+  /// The format to replay readings in, one of: json, csv, influx,
+  /// prom-textfile, human, parquet
+  #[structopt(long, short)]
+  sink: OutputMode,
+
+  /// If set, writes output to the given file rather than stdout
+  #[structopt(long, parse(from_os_str))]
+  output_file: Option<PathBuf>,
+
+  /// Shifts every timestamp by the given amount, e.g. 1h, or -7days to move
+  /// readings back in time
+  #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_shift))]
+  shift: Option<Duration>,
+
+  /// The device id to label readings with, as CSV logs don't record it
+  #[structopt(long, default_value = "0")]
+  device_id: DeviceId
+}
+
+/// Parses a humantime duration with an optional leading `-`
+fn parse_shift(s: &str) -> Result<Duration> {
+  let (negative, duration) = match s.strip_prefix('-') {
+    Some(duration) => (true, duration),
+    None => (false, s)
+  };
+
+  let duration = Duration::from_std(humantime::parse_duration(duration.trim())?)?;
+  Ok(if negative { -duration } else { duration })
+}
+
+/// Column indices of the values needed from a CSV log
+struct Columns {
+  datetime: usize,
+  port: Option<usize>,
+  name: Option<usize>,
+  location: Option<usize>,
+  pm25: usize,
+  pm10: usize
+}
+
+impl Columns {
+  fn from_header(header: &str) -> Result<Self> {
+    let names: Vec<_> = header.split(',').map(str::trim).collect();
+    let find = |candidates: &[&str]| candidates.iter()
+      .find_map(|c| names.iter().position(|name| name == c));
+
+    let missing = |column: &str| anyhow!("CSV header '{}' has no {} column", header, column);
+
+    Ok(Columns {
+      datetime: find(&["datetime"]).ok_or_else(|| missing("datetime"))?,
+      port: find(&["port"]),
+      name: find(&["name"]),
+      location: find(&["location"]),
+      pm25: find(&["pm25", "pm25_mean"]).ok_or_else(|| missing("pm25"))?,
+      pm10: find(&["pm10", "pm10_mean"]).ok_or_else(|| missing("pm10"))?
+    })
+  }
+}
+
+/// Splits a CSV record into its trimmed fields, unquoting any quoted per
+/// RFC 4180
+fn csv_fields(record: &str) -> Vec<String> {
+  let mut fields = vec![String::new()];
+  let mut quoted = false;
+  let mut chars = record.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    let field = fields.last_mut().unwrap();
+    match c {
+      '"' if quoted && chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      },
+      '"' => quoted = !quoted,
+      ',' if !quoted => fields.push(String::new()),
+      c => field.push(c)
+    }
+  }
+
+  fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Replays readings from a CSV log through an output format, e.g. to backfill
+/// a new database from local archives
+pub fn replay_csv(action: ReplayCsvAction) -> Result<()> {
+  let file = File::open(&action.file)
+    .with_context(|| format!("could not open {}", action.file.display()))?;
+  let mut lines = BufReader::new(file).lines();
+
+  let header = lines.next()
+    .ok_or_else(|| anyhow!("{} is empty", action.file.display()))??;
+  let columns = Columns::from_header(&header)?;
+
+  let metadata = columns.name.is_some() || columns.location.is_some();
+  let header = action.sink.header(false, columns.port.is_some(), metadata);
+  let header = header.as_deref();
+  let mut sink = match (&action.sink, &action.output_file) {
+    (OutputMode::None, _) => return Err(UsageError("--sink can't be none".into()).into()),
+    (OutputMode::PromTextfile, Some(path)) => Sink::Textfile(path.clone()),
+    (OutputMode::PromTextfile, None) => {
+      return Err(UsageError("prom-textfile output requires --output-file".into()).into());
+    },
+    #[cfg(feature = "parquet")]
+    (OutputMode::Parquet, Some(path)) => {
+      Sink::Parquet(super::archive::ParquetFile::open(path, false, RotationPolicy::default())?)
+    },
+    #[cfg(feature = "parquet")]
+    (OutputMode::Parquet, None) => {
+      return Err(UsageError("parquet output requires --output-file".into()).into());
+    },
+    (_, Some(path)) => Sink::File(RotatingFile::open(path, RotationPolicy::default(), header)?),
+    (_, None) => Sink::stdout(header)
+  };
+
+  // one formatter per port and sensor, as each labels its own output
+  let mut formatters: HashMap<(Option<String>, SensorMetadata), Formatter> = HashMap::new();
+  let mut count = 0;
+
+  // the header is line 1
+  let mut line_number = 1;
+  while let Some(line) = lines.next() {
+    let mut record = line?;
+    line_number += 1;
+    let start = line_number;
+
+    // a quoted field may span lines
+    while record.matches('"').count() % 2 == 1 {
+      let next = lines.next()
+        .ok_or_else(|| anyhow!("line {}: unterminated quoted field", start))??;
+      record.push('\n');
+      record.push_str(&next);
+      line_number += 1;
+    }
+
+    if record.trim().is_empty() {
+      continue;
+    }
+
+    let fields = csv_fields(&record);
+    let field = |index: usize| fields.get(index)
+      .map(String::as_str)
+      .ok_or_else(|| anyhow!("line {}: missing column {}", start, index + 1));
+
+    let datetime = DateTime::parse_from_rfc3339(field(columns.datetime)?)
+      .with_context(|| format!("line {}: invalid datetime", start))?
+      .with_timezone(&Utc);
+    let datetime = datetime + action.shift.unwrap_or_else(Duration::zero);
+
+    let reading = QueryResponse {
+      pm25: field(columns.pm25)?.parse()
+        .with_context(|| format!("line {}: invalid pm25", start))?,
+      pm10: field(columns.pm10)?.parse()
+        .with_context(|| format!("line {}: invalid pm10", start))?,
+      device: action.device_id
+    };
+
+    let port = columns.port.map(field).transpose()?.map(String::from);
+
+    // empty columns are fields that weren't set
+    let optional = |index: Option<usize>| -> Result<Option<String>> {
+      let value = index.map(field).transpose()?;
+      Ok(value.filter(|v| !v.is_empty()).map(String::from))
+    };
+    let sensor = SensorMetadata {
+      name: optional(columns.name)?,
+      location: optional(columns.location)?
+    };
+
+    let formatter = formatters.entry((port.clone(), sensor.clone()))
+      .or_insert_with(|| {
+        let formatter = Formatter::new(action.sink, false, port);
+        if metadata { formatter.with_metadata(sensor) } else { formatter }
+      });
+
+    sink.query(formatter, &reading, datetime)?;
+    count += 1;
+  }
+
+  sink.close()?;
+
+  info!("replayed {} readings from {}", count, action.file.display());
+
+  Ok(())
+}
@@ -0,0 +1,99 @@
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, Timelike};
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::{drain_responses, ControlMessage, RetryConfig, Schedule, TimeOfDay};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct ScheduleAction {
+  /// Rules of the form HH:MM=PERIOD[/MODE], separated by commas, e.g.
+  /// "07:00=0,22:00=30" for continuous readings during the day and every 30
+  /// minutes at night. Each rule lasts until the next; MODE defaults to active.
+  schedule: Schedule,
+
+  /// If set, applies the rule currently in effect and exits, e.g. when run
+  /// from cron
+  #[structopt(long)]
+  once: bool
+}
+
+/// The current local time of day, and seconds into the current minute
+fn local_time() -> (TimeOfDay, u32) {
+  let now = Local::now();
+  let time = TimeOfDay {
+    hour: now.hour() as u8,
+    minute: now.minute() as u8
+  };
+
+  (time, now.second())
+}
+
+/// Waits for `duration`, discarding readings and returning early on fatal
+/// errors
+pub fn wait(
+  duration: Duration,
+  response_rx: &Receiver<Resp>,
+  control_rx: &Receiver<ControlMessage>
+) -> Result<()> {
+  let until = Instant::now() + duration;
+
+  loop {
+    let now = Instant::now();
+    if now >= until {
+      return Ok(());
+    }
+
+    match control_rx.recv_timeout((until - now).min(Duration::from_secs(1))) {
+      Ok(ControlMessage::FatalError(e)) => return Err(e.into()),
+      Ok(ControlMessage::Error(e)) => warn!("{}", e),
+      Ok(ControlMessage::Closed)
+        | Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("sensor closed unexpectedly")),
+      Ok(message) => debug!("{:?}", message),
+      Err(RecvTimeoutError::Timeout) => ()
+    }
+
+    for response in response_rx.try_iter() {
+      debug!("{:?}", response);
+    }
+  }
+}
+
+/// Switches the sensor's working period and reporting mode as each rule of the
+/// schedule comes due
+pub fn schedule(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: ScheduleAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  let schedule = action.schedule;
+  let mut current = None;
+
+  loop {
+    let (time, second) = local_time();
+    let rule = *schedule.rule_at(time);
+
+    if current != Some(rule) {
+      drain_responses(&response_rx);
+      rule.apply(&command_tx, &response_rx, retry)?;
+      info!("applied schedule rule {}", rule);
+
+      current = Some(rule);
+    }
+
+    if action.once {
+      return Ok(());
+    }
+
+    let next = schedule.next_rule(time);
+    let delay = Duration::from_secs(time.minutes_until(next.start) as u64 * 60 - second as u64);
+    info!("next rule {} in {}", next, humantime::format_duration(delay));
+
+    wait(delay, &response_rx, &control_rx)?;
+  }
+}
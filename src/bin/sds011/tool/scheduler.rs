@@ -0,0 +1,232 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{drain_responses, measure_n, retry_send, ControlMessage, RetryConfig};
+use structopt::StructOpt;
+
+use crate::tool::aggregate::Aggregate;
+use crate::exit::UsageError;
+use crate::tool::output::{Formatter, OutputMode, RotatingFile, RotationPolicy, Sink};
+use crate::tool::schedule::wait;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct MeasureAction {
+  /// When to take measurements, as a cron expression in local time, e.g.
+  /// "*/15 * * * *" for every 15 minutes. Six fields (with seconds first) are
+  /// also accepted.
+  #[structopt(long)]
+  cron: String,
+
+  /// How long to run the fan before sampling, so readings settle
+  #[structopt(long, default_value = "30s", parse(try_from_str = humantime::parse_duration))]
+  warmup: Duration,
+
+  /// Number of samples to take and aggregate per measurement
+  #[structopt(long, default_value = "5")]
+  samples: usize,
+
+  /// Time between samples
+  #[structopt(long, default_value = "2s", parse(try_from_str = humantime::parse_duration))]
+  sample_interval: Duration,
+
+  /// How each measurement is published, one of: json, csv, influx,
+  /// prom-textfile, human
+  #[structopt(long, short, default_value = "human")]
+  output_mode: OutputMode,
+
+  /// If set, appends measurements to the given file (or, for prom-textfile,
+  /// replaces it) rather than writing to stdout
+  #[structopt(long, parse(from_os_str))]
+  output_file: Option<PathBuf>
+}
+
+/// Parses a standard five-field cron expression, or the `cron` crate's
+/// six/seven-field form with seconds
+fn parse_cron(s: &str) -> Result<cron::Schedule> {
+  let expression = match s.split_whitespace().count() {
+    5 => format!("0 {}", s),
+    _ => s.to_string()
+  };
+
+  cron::Schedule::from_str(&expression)
+    .map_err(|e| UsageError(format!("invalid cron expression '{}': {}", s, e)).into())
+}
+
+/// What to do for each measurement run
+#[derive(Debug, Clone)]
+pub struct MeasurementPlan {
+  pub warmup: Duration,
+  pub samples: usize,
+  pub sample_interval: Duration
+}
+
+/// Takes measurements on a cron schedule for installations (e.g. battery or
+/// solar powered) that keep the sensor asleep between runs. Each run wakes the
+/// sensor, lets it warm up, queries it `samples` times, puts it back to sleep,
+/// and publishes the aggregate.
+pub struct Scheduler {
+  schedule: cron::Schedule,
+  plan: MeasurementPlan
+}
+
+impl Scheduler {
+  pub fn new(schedule: cron::Schedule, plan: MeasurementPlan) -> Self {
+    Scheduler { schedule, plan }
+  }
+
+  /// The next time a measurement is due
+  pub fn next_run(&self) -> Option<DateTime<Local>> {
+    self.schedule.upcoming(Local).next()
+  }
+
+  fn set_work_mode(
+    &self,
+    mode: WorkMode,
+    command_tx: &Sender<Cmd>,
+    response_rx: &Receiver<Resp>,
+    retry: &RetryConfig
+  ) -> Result<()> {
+    retry_send(SetSleepWork { query: false, mode }, command_tx, response_rx, retry)?;
+    Ok(())
+  }
+
+  fn sample(
+    &self,
+    command_tx: &Sender<Cmd>,
+    response_rx: &Receiver<Resp>,
+    retry: &RetryConfig
+  ) -> Result<Aggregate> {
+    self.set_work_mode(WorkMode::Work, command_tx, response_rx, retry)?;
+
+    debug!("warming up for {}", humantime::format_duration(self.plan.warmup));
+    thread::sleep(self.plan.warmup);
+    drain_responses(response_rx);
+
+    let summary = measure_n(
+      command_tx,
+      response_rx,
+      self.plan.samples,
+      self.plan.sample_interval,
+      retry
+    )?;
+
+    let mut aggregate = Aggregate::new();
+    for reading in &summary.readings {
+      aggregate.push(reading);
+    }
+
+    Ok(aggregate)
+  }
+
+  /// Runs a single measurement, putting the sensor back to sleep even if
+  /// sampling fails
+  pub fn measure(
+    &self,
+    command_tx: &Sender<Cmd>,
+    response_rx: &Receiver<Resp>,
+    retry: &RetryConfig
+  ) -> Result<Aggregate> {
+    let result = self.sample(command_tx, response_rx, retry);
+
+    if let Err(e) = self.set_work_mode(WorkMode::Sleep, command_tx, response_rx, retry) {
+      warn!("could not put sensor to sleep: {}", e);
+    }
+
+    result
+  }
+
+  /// Takes measurements as they come due, passing each to `publish`, until a
+  /// fatal sensor error or publish error. Failed measurements are logged and
+  /// skipped.
+  pub fn run<F>(
+    &self,
+    command_tx: &Sender<Cmd>,
+    response_rx: &Receiver<Resp>,
+    control_rx: &Receiver<ControlMessage>,
+    retry: &RetryConfig,
+    mut publish: F
+  ) -> Result<()>
+  where
+    F: FnMut(&Aggregate) -> Result<()>
+  {
+    // samples are explicitly queried, and the sensor sleeps between runs
+    retry_send(SetReportingMode {
+      query: false,
+      mode: ReportingMode::Query
+    }, command_tx, response_rx, retry)?;
+    self.set_work_mode(WorkMode::Sleep, command_tx, response_rx, retry)?;
+
+    loop {
+      let next = self.next_run()
+        .ok_or_else(|| anyhow!("cron expression has no upcoming runs"))?;
+      info!("next measurement at {}", next.format("%Y-%m-%d %H:%M:%S"));
+
+      let delay = (next - Local::now()).to_std().unwrap_or_default();
+      wait(delay, response_rx, control_rx)?;
+
+      let started = Utc::now();
+      match self.measure(command_tx, response_rx, retry) {
+        Ok(mut aggregate) => {
+          aggregate.datetime = started;
+          publish(&aggregate)?;
+        },
+        Err(e) => error!("measurement failed: {}", e)
+      }
+    }
+  }
+}
+
+/// Takes measurements on a cron schedule and publishes each aggregate
+pub fn measure(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: MeasureAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  let schedule = parse_cron(&action.cron)?;
+  if action.samples == 0 {
+    return Err(UsageError("--samples must be at least 1".into()).into());
+  }
+
+  let header = action.output_mode.header(true, false, false);
+  let header = header.as_deref();
+  let mut sink = match (&action.output_mode, &action.output_file) {
+    (OutputMode::PromTextfile, Some(path)) => Sink::Textfile(path.clone()),
+    (OutputMode::PromTextfile, None) => {
+      return Err(UsageError("prom-textfile output requires --output-file".into()).into());
+    },
+    #[cfg(feature = "parquet")]
+    (OutputMode::Parquet, _) => {
+      return Err(UsageError("measure doesn't support parquet output; use watch".into()).into());
+    },
+    (_, Some(path)) => Sink::File(RotatingFile::open(path, RotationPolicy::default(), header)?),
+    (_, None) => Sink::stdout(header)
+  };
+
+  // only colorize human output headed for a terminal, and respect NO_COLOR
+  let color = action.output_file.is_none()
+    && io::stdout().is_terminal()
+    && env::var_os("NO_COLOR").is_none();
+
+  let mut formatter = Formatter::new(action.output_mode, color, None);
+  let scheduler = Scheduler::new(schedule, MeasurementPlan {
+    warmup: action.warmup,
+    samples: action.samples,
+    sample_interval: action.sample_interval
+  });
+
+  scheduler.run(&command_tx, &response_rx, &control_rx, retry, |aggregate| {
+    sink.aggregate(&mut formatter, aggregate)
+  })
+}
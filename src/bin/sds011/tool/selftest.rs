@@ -0,0 +1,324 @@
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  parse_command_frame, retry_send, ControlMessage, Error, ErrorKind, FrameError, RetryConfig
+};
+use structopt::StructOpt;
+
+use crate::exit::UsageError;
+
+/// How often to check for replies; much finer than the default, so latencies
+/// are measured to the millisecond
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct SelftestAction {
+  /// How many times to send each command, for round-trip latencies
+  #[structopt(long, short, default_value = "3")]
+  rounds: usize,
+
+  /// Also test SetDeviceId by writing the sensor's current id back to it; off
+  /// by default since it writes to the sensor's flash
+  #[structopt(long)]
+  set_device_id: bool
+}
+
+/// The outcome of one check
+#[derive(Debug)]
+struct Check {
+  name: &'static str,
+  rounds: usize,
+
+  /// round-trip latencies of each round answered, including any retries
+  latencies: Vec<Duration>,
+
+  /// why the check failed, if it did
+  failure: Option<String>
+}
+
+impl Check {
+  fn new(name: &'static str, rounds: usize) -> Self {
+    Check { name, rounds, latencies: Vec::new(), failure: None }
+  }
+
+  fn print(&self) {
+    let status = if self.failure.is_some() { "FAIL" } else { "PASS" };
+    let ms = |d: &Duration| d.as_secs_f64() * 1000.0;
+
+    let latency = match (self.latencies.iter().min(), self.latencies.iter().max()) {
+      (Some(min), Some(max)) => {
+        let mean = self.latencies.iter().map(ms).sum::<f64>() / self.latencies.len() as f64;
+        format!(
+          "{}/{}  min {:.1}ms  mean {:.1}ms  max {:.1}ms",
+          self.latencies.len(), self.rounds, ms(min), mean, ms(max)
+        )
+      },
+      _ => String::new()
+    };
+
+    println!("{}  {:<22} {}", status, self.name, latency);
+    if let Some(failure) = &self.failure {
+      println!("      {}", failure);
+    }
+  }
+}
+
+/// Sends commands to the sensor, verifying and timing its replies
+struct Tester<'a> {
+  command_tx: &'a Sender<Cmd>,
+  response_rx: &'a Receiver<Resp>,
+  retry: &'a RetryConfig,
+  rounds: usize,
+
+  /// the id of the first reply, which every later reply must match
+  device: Option<DeviceId>,
+
+  checks: Vec<Check>
+}
+
+impl Tester<'_> {
+  /// Sends `command` once per round, checking that each reply comes from the
+  /// same device as the others and that `verify` finds nothing wrong with it.
+  /// Returns the last reply, if any.
+  fn check<C, T, F>(&mut self, name: &'static str, command: C, verify: F) -> Option<T>
+  where
+    C: Command<ResponseType = T> + Clone,
+    T: Response + Into<Resp> + Clone + Debug,
+    F: Fn(&T) -> Option<String>
+  {
+    let mut check = Check::new(name, self.rounds);
+    let mut last = None;
+
+    for _ in 0..self.rounds {
+      let start = Instant::now();
+      let sent = retry_send(command.clone(), self.command_tx, self.response_rx, self.retry);
+      let reply = match sent {
+        Ok((reply, _)) => reply,
+        Err(e) => {
+          check.failure = Some(e.to_string());
+          break;
+        }
+      };
+
+      check.latencies.push(start.elapsed());
+
+      let device = reply.clone().into().device();
+      let expected = *self.device.get_or_insert(device);
+      check.failure = if device != expected {
+        Some(format!("reply came from device {}, but earlier ones from {}", device, expected))
+      } else {
+        verify(&reply).map(|problem| format!("{}: {:?}", problem, reply))
+      };
+
+      last = Some(reply);
+      if check.failure.is_some() {
+        break;
+      }
+    }
+
+    self.checks.push(check);
+    last
+  }
+}
+
+/// Checks that a reply to a setting command echoes the request: whether it was
+/// a query, and for a change, the new value
+fn echoes<V: PartialEq + Debug>(
+  query: bool,
+  value: V,
+  reply_query: bool,
+  reply_value: V
+) -> Option<String> {
+  if query != reply_query {
+    Some(format!("reply doesn't echo query={}", query))
+  } else if !query && value != reply_value {
+    Some(format!("reply doesn't echo the new value {:?}", value))
+  } else {
+    None
+  }
+}
+
+/// Checks that every command frame this library sends decodes back to the same
+/// command with a valid checksum, without talking to the sensor
+fn check_command_frames(id: DeviceId) -> Check {
+  let commands = vec![
+    Req::Query(Query),
+    Req::GetFirmwareVersion(GetFirmwareVersion),
+    Req::SetDeviceId(SetDeviceId { id }),
+    Req::SetReportingMode(SetReportingMode { query: true, mode: ReportingMode::Active }),
+    Req::SetReportingMode(SetReportingMode { query: false, mode: ReportingMode::Query }),
+    Req::SetSleepWork(SetSleepWork { query: true, mode: WorkMode::Work }),
+    Req::SetSleepWork(SetSleepWork { query: false, mode: WorkMode::Sleep }),
+    Req::SetWorkingPeriod(SetWorkingPeriod {
+      query: true,
+      working_period: WorkingPeriod::Continuous
+    }),
+    Req::SetWorkingPeriod(SetWorkingPeriod {
+      query: false,
+      working_period: WorkingPeriod::Periodic(30)
+    })
+  ];
+
+  let mut check = Check::new("command frames", 1);
+  for command in commands {
+    let cmd = command.to_cmd();
+    let decoded = cmd.bytes().try_into()
+      .map_err(|_| Error::PacketError(FrameError::InvalidLength(cmd.bytes().len())))
+      .and_then(parse_command_frame);
+
+    let problem = match decoded {
+      Ok(decoded) if decoded.command == command && decoded.target == DeviceId(0xFFFF) => continue,
+      Ok(decoded) => format!("decoded as {:?}", decoded),
+      Err(e) => e.to_string()
+    };
+
+    check.failure = Some(format!("{:?} ({}): {}", command, to_hex(cmd.bytes()), problem));
+    break;
+  }
+
+  check
+}
+
+/// Counts invalid frames received during the test, failing on any, or on the
+/// sensor being lost
+fn check_frames(control_rx: &Receiver<ControlMessage>) -> Check {
+  let mut check = Check::new("frame integrity", 1);
+  let mut invalid = 0;
+
+  for message in control_rx.try_iter() {
+    match message {
+      ControlMessage::Error(e @ Error::PacketError(_))
+        | ControlMessage::Error(e @ Error::Suppressed { kind: ErrorKind::Packet, .. }) => {
+        debug!("invalid frame: {}", e);
+        invalid += e.count();
+      },
+      ControlMessage::Error(e) => warn!("{}", e),
+      ControlMessage::FatalError(e) => {
+        check.failure = Some(format!("sensor lost: {}", e));
+        return check;
+      },
+      _ => ()
+    }
+  }
+
+  if invalid > 0 {
+    check.failure = Some(format!("{} invalid frames received, e.g. with a bad checksum", invalid));
+  }
+
+  check
+}
+
+/// Exercises every command against the sensor, leaving its settings as they
+/// were, and prints a pass/fail report
+pub fn selftest(
+  command_tx: Sender<Cmd>,
+  response_rx: Receiver<Resp>,
+  control_rx: Receiver<ControlMessage>,
+  action: SelftestAction,
+  retry: &RetryConfig
+) -> Result<()> {
+  if action.rounds == 0 {
+    return Err(UsageError("--rounds must be at least 1".into()).into());
+  }
+
+  let mut retry = retry.clone();
+  retry.sleep = POLL_INTERVAL;
+
+  let mut tester = Tester {
+    command_tx: &command_tx,
+    response_rx: &response_rx,
+    retry: &retry,
+    rounds: action.rounds,
+    device: None,
+    checks: Vec::new()
+  };
+
+  // a sleeping sensor ignores everything but work mode commands, so this goes
+  // first, and the sensor is woken up for the duration
+  let initial = tester.check(
+    "query work mode",
+    SetSleepWork { query: true, mode: WorkMode::Work },
+    |r| echoes(true, WorkMode::Work, r.query, r.mode)
+  );
+
+  let work = SetSleepWork { query: false, mode: WorkMode::Work };
+  tester.check("set work mode", work, |r| echoes(false, WorkMode::Work, r.query, r.mode));
+
+  tester.check("get firmware version", GetFirmwareVersion, |r| {
+    if (1..=12).contains(&r.month) && (1..=31).contains(&r.day) {
+      None
+    } else {
+      Some("implausible firmware date".to_string())
+    }
+  });
+
+  let query = SetReportingMode { query: true, mode: ReportingMode::Active };
+  let reporting = tester.check("query reporting mode", query, |r| {
+    echoes(true, ReportingMode::Active, r.query, r.mode)
+  });
+
+  if let Some(mode) = reporting.map(|r| r.mode) {
+    let set = SetReportingMode { query: false, mode };
+    tester.check("set reporting mode", set, |r| echoes(false, mode, r.query, r.mode));
+  }
+
+  let query = SetWorkingPeriod { query: true, working_period: WorkingPeriod::Continuous };
+  let working = tester.check("query working period", query, |r| {
+    echoes(true, WorkingPeriod::Continuous, r.query, r.working_period)
+  });
+
+  if let Some(period) = working.map(|r| r.working_period) {
+    let set = SetWorkingPeriod { query: false, working_period: period };
+    tester.check("set working period", set, |r| {
+      echoes(false, period, r.query, r.working_period)
+    });
+  }
+
+  tester.check("query reading", Query, |r| {
+    let valid = |v: f32| (0.0..=SATURATION_LIMIT).contains(&v);
+    if valid(r.pm25) && valid(r.pm10) {
+      None
+    } else {
+      Some("reading out of range".to_string())
+    }
+  });
+
+  if let (true, Some(id)) = (action.set_device_id, tester.device) {
+    tester.check("set device id", SetDeviceId { id }, |r| {
+      if r.device == id { None } else { Some(format!("reply doesn't echo id {}", id)) }
+    });
+  }
+
+  // put it back to sleep, if it was
+  if let Some(WorkMode::Sleep) = initial.map(|r| r.mode) {
+    let sleep = SetSleepWork { query: false, mode: WorkMode::Sleep };
+    tester.rounds = 1;
+    tester.check("restore work mode", sleep, |r| {
+      echoes(false, WorkMode::Sleep, r.query, r.mode)
+    });
+  }
+
+  let mut checks = vec![check_command_frames(tester.device.unwrap_or(DeviceId(0xFFFF)))];
+  checks.append(&mut tester.checks);
+  checks.push(check_frames(&control_rx));
+
+  for check in &checks {
+    check.print();
+  }
+
+  let failed = checks.iter().filter(|c| c.failure.is_some()).count();
+  if failed > 0 {
+    return Err(anyhow!("selftest failed: {} of {} checks failed", failed, checks.len()));
+  }
+
+  println!("selftest passed: {} checks", checks.len());
+
+  Ok(())
+}
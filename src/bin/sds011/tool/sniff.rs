@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use sds011_exporter::util::to_hex;
+use sds011_exporter::{Direction, Error, PcapngWriter, SniffEvent, SnifferMode};
+use structopt::StructOpt;
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct SniffAction {
+  /// also write the capture to this file as pcapng, for Wireshark; frames use
+  /// link type USER0 (147) and carry their decoded form as a comment
+  #[structopt(long, parse(from_os_str))]
+  pcapng: Option<PathBuf>
+}
+
+/// Prints every frame on the bus in both directions, decoded and timestamped,
+/// without ever writing to it
+pub fn sniff(device: &Path, action: SniffAction) -> Result<()> {
+  let mut pcapng = match &action.pcapng {
+    Some(path) => Some(PcapngWriter::new(BufWriter::new(File::create(path)?))?),
+    None => None
+  };
+
+  for sniffed in SnifferMode::open(device)? {
+    let sniffed = match sniffed {
+      Ok(sniffed) => sniffed,
+
+      // the bus may well be idle for longer than the read timeout
+      Err(Error::ReadError(e)) if e.kind() == ErrorKind::TimedOut => continue,
+      Err(e) => return Err(e.into())
+    };
+
+    // flushed as it goes, since sniffing only ends when interrupted
+    if let Some(pcapng) = pcapng.as_mut() {
+      pcapng.write_sniffed(&sniffed)?;
+      pcapng.flush()?;
+    }
+
+    let timestamp = DateTime::<Local>::from(sniffed.time).format("%H:%M:%S%.3f");
+    let direction = match sniffed.event.direction() {
+      Some(Direction::ToSensor) => "host -> sensor",
+      Some(Direction::FromSensor) => "sensor -> host",
+      None => "              "
+    };
+
+    match sniffed.event {
+      SniffEvent::Response { frame, response } => {
+        println!("{} {}  {}  {:x?}", timestamp, direction, to_hex(&frame), response)
+      },
+      SniffEvent::Command { frame, command } => {
+        println!("{} {}  {}  {:x?}", timestamp, direction, to_hex(&frame), command)
+      },
+      SniffEvent::Invalid { frame, error } => {
+        println!("{} {}  {}  invalid: {}", timestamp, direction, to_hex(&frame), error)
+      },
+      SniffEvent::Garbage(byte) => println!("{} {}  {:02X}  garbage", timestamp, direction, byte)
+    }
+  }
+
+  Ok(())
+}
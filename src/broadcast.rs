@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// Fans a single receiver out to any number of subscribers, e.g. so a CSV
+/// logger and a reading cache can both see every response from
+/// `open_sensor()` without one draining the channel first. `Supervisor` does
+/// this itself; see `Supervisor::subscribe()`.
+///
+/// Messages are forwarded on a background thread as they arrive; each
+/// subscriber receives those sent after it subscribed, and messages arriving
+/// with no subscribers are dropped. Clones share the same subscribers.
+#[derive(Debug)]
+pub struct Broadcast<T> {
+  /// None once the source has disconnected
+  subscribers: Arc<Mutex<Option<Vec<Sender<T>>>>>
+}
+
+impl<T> Clone for Broadcast<T> {
+  fn clone(&self) -> Self {
+    Broadcast {
+      subscribers: Arc::clone(&self.subscribers)
+    }
+  }
+}
+
+impl<T: Clone + Send + 'static> Broadcast<T> {
+  /// Starts forwarding messages from `source` until its sender is dropped
+  pub fn spawn(source: Receiver<T>) -> Broadcast<T> {
+    let broadcast = Broadcast {
+      subscribers: Arc::new(Mutex::new(Some(Vec::new())))
+    };
+
+    let subscribers = Arc::clone(&broadcast.subscribers);
+    thread::spawn(move || {
+      for message in source {
+        if let Some(subscribers) = subscribers.lock().unwrap().as_mut() {
+          subscribers.retain(|tx| tx.send(message.clone()).is_ok());
+        }
+      }
+
+      // drop every sender so subscribers' iteration ends
+      subscribers.lock().unwrap().take();
+    });
+
+    broadcast
+  }
+
+  /// Returns a new receiver for every message from now on. Its sender is
+  /// dropped (i.e. iteration ends) once the source disconnects.
+  pub fn subscribe(&self) -> Receiver<T> {
+    let (tx, rx) = channel();
+
+    if let Some(subscribers) = self.subscribers.lock().unwrap().as_mut() {
+      subscribers.push(tx);
+    }
+
+    rx
+  }
+
+  /// Subscribers not yet known to be dropped
+  pub fn subscribers(&self) -> usize {
+    self.subscribers.lock().unwrap().as_ref().map(Vec::len).unwrap_or(0)
+  }
+}
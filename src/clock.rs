@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A moment recorded against the monotonic clock and only converted to
+/// wall-clock time when it's output.
+///
+/// The system clock can jump while running, e.g. when NTP first syncs on a
+/// Raspberry Pi without a real-time clock that booted thinking it was 1970.
+/// A `SystemTime` taken beforehand would then be decades old, or in the
+/// future after a jump backwards, while a `Timestamp`'s age is unaffected and
+/// its wall-clock time follows the corrected clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(Instant);
+
+impl Timestamp {
+  pub fn now() -> Self {
+    Timestamp(Instant::now())
+  }
+
+  /// How long ago this was; never negative
+  pub fn elapsed(&self) -> Duration {
+    self.0.elapsed()
+  }
+
+  /// How long after `earlier` this was, or zero if it wasn't
+  pub fn duration_since(&self, earlier: Timestamp) -> Duration {
+    self.0.saturating_duration_since(earlier.0)
+  }
+
+  /// The wall-clock time this was, by the system clock as it is now
+  pub fn to_system_time(&self) -> SystemTime {
+    let now = SystemTime::now();
+    now.checked_sub(self.elapsed()).unwrap_or(UNIX_EPOCH)
+  }
+}
+
+impl From<Instant> for Timestamp {
+  fn from(instant: Instant) -> Self {
+    Timestamp(instant)
+  }
+}
+
+impl From<Timestamp> for Instant {
+  fn from(timestamp: Timestamp) -> Self {
+    timestamp.0
+  }
+}
@@ -0,0 +1,285 @@
+use std::time::UNIX_EPOCH;
+
+use crate::clock::Timestamp;
+use crate::response::QueryResponse;
+
+/// The Content-Format of CBOR payloads, from the CoAP Content-Formats registry
+pub const COAP_CONTENT_FORMAT_CBOR: u16 = 60;
+
+const VERSION: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xFF;
+
+const OPTION_URI_PATH: u16 = 11;
+const OPTION_CONTENT_FORMAT: u16 = 12;
+
+/// The type of a CoAP message, from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoapType {
+  Confirmable,
+  NonConfirmable,
+  Acknowledgement,
+  Reset
+}
+
+/// CoAP request and response codes, as `class << 5 | detail`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoapCode(pub u8);
+
+impl CoapCode {
+  pub const EMPTY: CoapCode = CoapCode(0x00);
+  pub const GET: CoapCode = CoapCode(0x01);
+  pub const CONTENT: CoapCode = CoapCode(0x45);
+  pub const NOT_FOUND: CoapCode = CoapCode(0x84);
+  pub const METHOD_NOT_ALLOWED: CoapCode = CoapCode(0x85);
+
+  pub fn is_request(&self) -> bool {
+    self.0 >> 5 == 0 && *self != CoapCode::EMPTY
+  }
+}
+
+/// A CoAP (RFC 7252) message, parsed only as far as a server answering GETs
+/// needs: options other than Uri-Path are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoapMessage {
+  pub kind: CoapType,
+  pub code: CoapCode,
+  pub message_id: u16,
+  pub token: Vec<u8>,
+
+  /// the Uri-Path segments, e.g. `["pm"]` for `/pm`
+  pub path: Vec<String>,
+  pub payload: Vec<u8>
+}
+
+impl CoapMessage {
+  /// Parses a datagram, or returns None if it isn't a valid CoAP message
+  pub fn parse(datagram: &[u8]) -> Option<CoapMessage> {
+    let (header, rest) = split(datagram, 4)?;
+    if header[0] >> 6 != VERSION {
+      return None;
+    }
+
+    let kind = match (header[0] >> 4) & 0b11 {
+      0 => CoapType::Confirmable,
+      1 => CoapType::NonConfirmable,
+      2 => CoapType::Acknowledgement,
+      _ => CoapType::Reset
+    };
+
+    let token_len = (header[0] & 0x0F) as usize;
+    if token_len > 8 {
+      return None;
+    }
+
+    let code = CoapCode(header[1]);
+    let message_id = u16::from_be_bytes([header[2], header[3]]);
+    let (token, mut rest) = split(rest, token_len)?;
+
+    let mut path = Vec::new();
+    let mut number = 0u16;
+    let payload = loop {
+      match rest.split_first() {
+        None => break Vec::new(),
+        Some((&PAYLOAD_MARKER, payload)) if !payload.is_empty() => break payload.to_vec(),
+        Some((&byte, tail)) => {
+          let (delta, tail) = option_field(byte >> 4, tail)?;
+          let (len, tail) = option_field(byte & 0x0F, tail)?;
+          let (value, tail) = split(tail, len as usize)?;
+
+          number = number.checked_add(delta)?;
+          if number == OPTION_URI_PATH {
+            path.push(String::from_utf8(value.to_vec()).ok()?);
+          }
+
+          rest = tail;
+        }
+      }
+    };
+
+    Some(CoapMessage {
+      kind,
+      code,
+      message_id,
+      token: token.to_vec(),
+      path,
+      payload
+    })
+  }
+
+  /// A response to this request: piggybacked on the acknowledgement if it's
+  /// confirmable, otherwise non-confirmable with the given `message_id`. A
+  /// payload is marked as CBOR.
+  pub fn response(&self, code: CoapCode, message_id: u16, payload: &[u8]) -> CoapMessage {
+    let (kind, message_id) = match self.kind {
+      CoapType::Confirmable => (CoapType::Acknowledgement, self.message_id),
+      _ => (CoapType::NonConfirmable, message_id)
+    };
+
+    CoapMessage {
+      kind,
+      code,
+      message_id,
+      token: self.token.clone(),
+      path: Vec::new(),
+      payload: payload.to_vec()
+    }
+  }
+
+  /// A reset for a message that can't be handled, e.g. a ping
+  pub fn reset(&self) -> CoapMessage {
+    CoapMessage {
+      kind: CoapType::Reset,
+      code: CoapCode::EMPTY,
+      message_id: self.message_id,
+      token: Vec::new(),
+      path: Vec::new(),
+      payload: Vec::new()
+    }
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let kind = match self.kind {
+      CoapType::Confirmable => 0,
+      CoapType::NonConfirmable => 1,
+      CoapType::Acknowledgement => 2,
+      CoapType::Reset => 3
+    };
+
+    let mut out = vec![VERSION << 6 | kind << 4 | self.token.len() as u8, self.code.0];
+    out.extend_from_slice(&self.message_id.to_be_bytes());
+    out.extend_from_slice(&self.token);
+
+    let mut number = 0;
+    for segment in &self.path {
+      push_option(&mut out, OPTION_URI_PATH - number, segment.as_bytes());
+      number = OPTION_URI_PATH;
+    }
+
+    if !self.payload.is_empty() {
+      let format = COAP_CONTENT_FORMAT_CBOR.to_be_bytes();
+      push_option(&mut out, OPTION_CONTENT_FORMAT - number, &format[1..]);
+
+      out.push(PAYLOAD_MARKER);
+      out.extend_from_slice(&self.payload);
+    }
+
+    out
+  }
+}
+
+fn split(bytes: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+  if bytes.len() < len {
+    None
+  } else {
+    Some(bytes.split_at(len))
+  }
+}
+
+/// Decodes an option delta or length nibble, reading its extended bytes
+fn option_field(nibble: u8, bytes: &[u8]) -> Option<(u16, &[u8])> {
+  match nibble {
+    13 => split(bytes, 1).map(|(ext, rest)| (ext[0] as u16 + 13, rest)),
+    14 => split(bytes, 2).and_then(|(ext, rest)| {
+      u16::from_be_bytes([ext[0], ext[1]]).checked_add(269).map(|v| (v, rest))
+    }),
+    15 => None,
+    nibble => Some((nibble as u16, bytes))
+  }
+}
+
+/// Appends an option; values here are always short enough for a 1-byte length
+fn push_option(out: &mut Vec<u8>, delta: u16, value: &[u8]) {
+  let nibble = |n: usize| if n < 13 { n as u8 } else { 13 };
+
+  out.push(nibble(delta as usize) << 4 | nibble(value.len()));
+  if delta >= 13 {
+    out.push((delta - 13) as u8);
+  }
+
+  if value.len() >= 13 {
+    out.push((value.len() - 13) as u8);
+  }
+
+  out.extend_from_slice(value);
+}
+
+/// Writes the small subset of CBOR (RFC 8949) needed to encode readings
+#[derive(Debug, Default)]
+pub struct CborWriter {
+  out: Vec<u8>
+}
+
+impl CborWriter {
+  pub fn new() -> Self {
+    CborWriter::default()
+  }
+
+  fn head(&mut self, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+      0..=23 => self.out.push(major | value as u8),
+      24..=0xFF => self.out.extend_from_slice(&[major | 24, value as u8]),
+      0x100..=0xFFFF => {
+        self.out.push(major | 25);
+        self.out.extend_from_slice(&(value as u16).to_be_bytes());
+      },
+      0x1_0000..=0xFFFF_FFFF => {
+        self.out.push(major | 26);
+        self.out.extend_from_slice(&(value as u32).to_be_bytes());
+      },
+      _ => {
+        self.out.push(major | 27);
+        self.out.extend_from_slice(&value.to_be_bytes());
+      }
+    }
+  }
+
+  pub fn uint(&mut self, value: u64) -> &mut Self {
+    self.head(0, value);
+    self
+  }
+
+  pub fn text(&mut self, value: &str) -> &mut Self {
+    self.head(3, value.len() as u64);
+    self.out.extend_from_slice(value.as_bytes());
+    self
+  }
+
+  /// Starts a map of `len` pairs, which must follow as alternating keys and
+  /// values
+  pub fn map(&mut self, len: usize) -> &mut Self {
+    self.head(5, len as u64);
+    self
+  }
+
+  pub fn float(&mut self, value: f32) -> &mut Self {
+    self.out.push(0xFA);
+    self.out.extend_from_slice(&value.to_bits().to_be_bytes());
+    self
+  }
+
+  pub fn null(&mut self) -> &mut Self {
+    self.out.push(0xF6);
+    self
+  }
+
+  /// Writes a reading as a map of `t` (when it was received, in seconds since
+  /// the Unix epoch), `pm25`, `pm10`, and `aqi`, or null without one
+  pub fn reading(&mut self, latest: Option<(Timestamp, QueryResponse)>) -> &mut Self {
+    match latest {
+      Some((time, r)) => {
+        let secs = time.to_system_time().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.map(4)
+          .text("t").uint(secs.as_secs())
+          .text("pm25").float(r.pm25)
+          .text("pm10").float(r.pm10)
+          .text("aqi").uint(r.aqi() as u64)
+      },
+      None => self.null()
+    }
+  }
+
+  pub fn into_bytes(self) -> Vec<u8> {
+    self.out
+  }
+}
@@ -3,6 +3,12 @@ use bytes::{BytesMut, BufMut};
 use crate::response::*;
 use crate::util::*;
 
+/// Length of a command frame: head, id, 15 data bytes, checksum, tail
+pub const COMMAND_LEN: usize = 19;
+
+/// A complete command frame sent to the sensor, including its head and tail
+pub type CommandFrame = [u8; COMMAND_LEN];
+
 pub trait Command : std::fmt::Debug {
   type ResponseType: Response;
 
@@ -31,13 +37,42 @@ pub trait Command : std::fmt::Debug {
     let mut data = BytesMut::new();
     self.write(&mut data);
 
-    Cmd { data }
+    Cmd { data, flush: false }
   }
 }
 
 #[derive(Debug)]
 pub struct Cmd {
-  pub(crate) data: BytesMut
+  pub(crate) data: BytesMut,
+
+  /// if true, the serial input buffer is cleared before writing
+  pub(crate) flush: bool
+}
+
+impl Cmd {
+  /// Creates a command from raw bytes, which are sent as-is: no header, tail,
+  /// or checksum is added.
+  pub fn raw(bytes: &[u8]) -> Self {
+    Cmd { data: BytesMut::from(bytes), flush: false }
+  }
+
+  /// Creates an empty command that only clears the serial input buffer,
+  /// discarding any unread (e.g. actively reported) frames.
+  pub fn flush() -> Self {
+    Cmd { data: BytesMut::new(), flush: true }
+  }
+
+  /// Clears the serial input buffer before this command is written, so stale
+  /// frames queued up while idle aren't mistaken for its response.
+  pub fn flush_input(mut self) -> Self {
+    self.flush = true;
+    self
+  }
+
+  /// The bytes that will be written to the sensor
+  pub fn bytes(&self) -> &[u8] {
+    &self.data[..]
+  }
 }
 
 impl<C: Command> From<C> for Cmd {
@@ -88,7 +123,7 @@ impl Command for Query {
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub struct SetDeviceId {
-  pub id: u16
+  pub id: DeviceId
 }
 
 impl Command for SetDeviceId {
@@ -97,7 +132,7 @@ impl Command for SetDeviceId {
   fn data(&self, bytes: &mut BytesMut) {
     bytes.put_u8(0x05);
     bytes.put(&[0x00; 10][..]);
-    bytes.put_u16(self.id);
+    bytes.put_u16(self.id.0);
     bytes.put(&[0xFF; 2][..]);
   }
 }
@@ -150,3 +185,37 @@ impl Command for GetFirmwareVersion {
     bytes.put(&[0xFF; 2][..]);
   }
 }
+
+/// A command decoded from a frame sent to the sensor, e.g. a local echo or one
+/// seen on a shared bus; see `parse_command_frame()`
+#[derive(Debug, PartialEq, Clone)]
+pub enum Req {
+  SetReportingMode(SetReportingMode),
+  Query(Query),
+  SetDeviceId(SetDeviceId),
+  SetSleepWork(SetSleepWork),
+  SetWorkingPeriod(SetWorkingPeriod),
+  GetFirmwareVersion(GetFirmwareVersion)
+}
+
+impl Req {
+  pub fn to_cmd(&self) -> Cmd {
+    match self {
+      Req::SetReportingMode(c) => c.to_cmd(),
+      Req::Query(c) => c.to_cmd(),
+      Req::SetDeviceId(c) => c.to_cmd(),
+      Req::SetSleepWork(c) => c.to_cmd(),
+      Req::SetWorkingPeriod(c) => c.to_cmd(),
+      Req::GetFirmwareVersion(c) => c.to_cmd()
+    }
+  }
+}
+
+/// A decoded command frame and the sensor it was addressed to
+#[derive(Debug, PartialEq, Clone)]
+pub struct DecodedCommand {
+  pub command: Req,
+
+  /// 0xffff addresses any sensor, as with every command this library sends
+  pub target: DeviceId
+}
@@ -0,0 +1,291 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::*;
+
+/// A temperature and humidity reading from a companion sensor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+  /// degrees Celsius
+  pub temperature: f32,
+
+  /// relative humidity in percent
+  pub humidity: f32
+}
+
+/// A temperature/humidity sensor commonly paired with an SDS011, read over I²C
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompanionKind {
+  /// Sensirion SHT30/SHT31/SHT35
+  Sht3x,
+
+  /// Bosch BME280 (the BMP280 has no humidity sensor)
+  Bme280
+}
+
+impl CompanionKind {
+  /// The sensor's I²C address unless its address pin is strapped otherwise
+  pub fn default_address(&self) -> u16 {
+    match self {
+      CompanionKind::Sht3x => 0x44,
+      CompanionKind::Bme280 => 0x76
+    }
+  }
+}
+
+impl FromStr for CompanionKind {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "sht3x" | "sht30" | "sht31" | "sht35" => Ok(CompanionKind::Sht3x),
+      "bme280" => Ok(CompanionKind::Bme280),
+      _ => Err(Error::InvalidCompanion(s.to_string()))
+    }
+  }
+}
+
+impl fmt::Display for CompanionKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CompanionKind::Sht3x => write!(f, "sht3x"),
+      CompanionKind::Bme280 => write!(f, "bme280")
+    }
+  }
+}
+
+/// A source of `Climate` readings
+pub trait ClimateSensor: Send {
+  fn read(&mut self) -> Result<Climate>;
+}
+
+/// The SHT3x's CRC-8: polynomial 0x31, initialized to 0xFF
+pub fn sht3x_crc(data: &[u8]) -> u8 {
+  data.iter().fold(0xFF, |crc, &byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0x80 != 0 { (crc << 1) ^ 0x31 } else { crc << 1 }
+    })
+  })
+}
+
+/// Decodes an SHT3x measurement: temperature and humidity words, each followed
+/// by its CRC
+pub fn decode_sht3x(data: &[u8; 6]) -> Result<Climate> {
+  for word in data.chunks(3) {
+    if sht3x_crc(&word[..2]) != word[2] {
+      return Err(Error::InvalidCompanionReading(format!("sht3x checksum mismatch: {:x?}", data)));
+    }
+  }
+
+  let temperature = u16::from_be_bytes([data[0], data[1]]) as f32;
+  let humidity = u16::from_be_bytes([data[3], data[4]]) as f32;
+
+  Ok(Climate {
+    temperature: -45.0 + 175.0 * temperature / 65535.0,
+    humidity: 100.0 * humidity / 65535.0
+  })
+}
+
+/// A BME280's factory calibration, needed to make sense of its raw readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bme280Calibration {
+  t1: u16,
+  t2: i16,
+  t3: i16,
+  h1: u8,
+  h2: i16,
+  h3: u8,
+  h4: i16,
+  h5: i16,
+  h6: i8
+}
+
+impl Bme280Calibration {
+  /// Parses calibration registers 0x88..=0xA1 and 0xE1..=0xE7
+  pub fn from_registers(low: &[u8; 26], high: &[u8; 7]) -> Self {
+    Bme280Calibration {
+      t1: u16::from_le_bytes([low[0], low[1]]),
+      t2: i16::from_le_bytes([low[2], low[3]]),
+      t3: i16::from_le_bytes([low[4], low[5]]),
+      h1: low[25],
+      h2: i16::from_le_bytes([high[0], high[1]]),
+      h3: high[2],
+
+      // two signed 12-bit values sharing a nibble
+      h4: ((high[3] as i8 as i16) << 4) | (high[4] & 0x0F) as i16,
+      h5: ((high[5] as i8 as i16) << 4) | (high[4] >> 4) as i16,
+      h6: high[6] as i8
+    }
+  }
+
+  /// Compensates raw data registers 0xF7..=0xFE, per the datasheet's
+  /// floating point formulas
+  pub fn compensate(&self, data: &[u8; 8]) -> Climate {
+    let adc_t = ((data[3] as u32) << 12 | (data[4] as u32) << 4 | (data[5] as u32) >> 4) as f64;
+    let adc_h = ((data[6] as u32) << 8 | data[7] as u32) as f64;
+
+    let (t1, t2, t3) = (self.t1 as f64, self.t2 as f64, self.t3 as f64);
+    let var1 = (adc_t / 16384.0 - t1 / 1024.0) * t2;
+    let var2 = (adc_t / 131072.0 - t1 / 8192.0).powi(2) * t3;
+    let t_fine = var1 + var2;
+
+    let h = t_fine - 76800.0;
+    let h = (adc_h - (self.h4 as f64 * 64.0 + self.h5 as f64 / 16384.0 * h))
+      * (self.h2 as f64 / 65536.0
+        * (1.0 + self.h6 as f64 / 67108864.0 * h * (1.0 + self.h3 as f64 / 67108864.0 * h)));
+    let h = h * (1.0 - self.h1 as f64 * h / 524288.0);
+
+    Climate {
+      temperature: (t_fine / 5120.0) as f32,
+      humidity: h.clamp(0.0, 100.0) as f32
+    }
+  }
+}
+
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+mod i2c {
+  use std::fs::{File, OpenOptions};
+  use std::io::{self, Read, Write};
+  use std::os::unix::io::AsRawFd;
+  use std::path::Path;
+  use std::thread;
+  use std::time::Duration;
+
+  use super::*;
+
+  /// ioctl to set the address of the device on an i2c-dev bus
+  const I2C_SLAVE: u16 = 0x0703;
+
+  const SHT3X_MEASURE: [u8; 2] = [0x24, 0x00];
+
+  const BME280_CHIP_ID: u8 = 0x60;
+  const BME280_REG_CHIP_ID: u8 = 0xD0;
+  const BME280_REG_CTRL_HUM: u8 = 0xF2;
+  const BME280_REG_CTRL_MEAS: u8 = 0xF4;
+  const BME280_REG_DATA: u8 = 0xF7;
+
+  /// A single device on a Linux i2c-dev bus, e.g. /dev/i2c-1
+  #[derive(Debug)]
+  pub struct I2cDevice {
+    file: File
+  }
+
+  impl I2cDevice {
+    pub fn open<P: AsRef<Path>>(bus: P, address: u16) -> Result<Self> {
+      let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(bus)
+        .map_err(Error::CompanionError)?;
+
+      if unsafe { libc::ioctl(file.as_raw_fd(), I2C_SLAVE as _, address as libc::c_ulong) } < 0 {
+        return Err(Error::CompanionError(io::Error::last_os_error()));
+      }
+
+      Ok(I2cDevice { file })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+      self.file.write_all(data).map_err(Error::CompanionError)
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<()> {
+      self.file.read_exact(buf).map_err(Error::CompanionError)
+    }
+
+    /// Reads consecutive registers starting at `register`
+    pub fn read_registers(&mut self, register: u8, buf: &mut [u8]) -> Result<()> {
+      self.write(&[register])?;
+      self.read(buf)
+    }
+  }
+
+  /// An SHT3x, read with single shot, high repeatability measurements
+  #[derive(Debug)]
+  pub struct Sht3x {
+    device: I2cDevice
+  }
+
+  impl Sht3x {
+    pub fn new(device: I2cDevice) -> Self {
+      Sht3x { device }
+    }
+  }
+
+  impl ClimateSensor for Sht3x {
+    fn read(&mut self) -> Result<Climate> {
+      self.device.write(&SHT3X_MEASURE)?;
+
+      // at most 15.5ms at high repeatability
+      thread::sleep(Duration::from_millis(20));
+
+      let mut data = [0; 6];
+      self.device.read(&mut data)?;
+      decode_sht3x(&data)
+    }
+  }
+
+  /// A BME280, read with forced mode measurements and no oversampling
+  #[derive(Debug)]
+  pub struct Bme280 {
+    device: I2cDevice,
+    calibration: Bme280Calibration
+  }
+
+  impl Bme280 {
+    /// Checks the chip id and reads the calibration
+    pub fn new(mut device: I2cDevice) -> Result<Self> {
+      let mut id = [0];
+      device.read_registers(BME280_REG_CHIP_ID, &mut id)?;
+      if id[0] != BME280_CHIP_ID {
+        return Err(Error::InvalidCompanionReading(format!(
+          "unexpected chip id {:#04x}, expected {:#04x} (a BMP280 has no humidity sensor)",
+          id[0], BME280_CHIP_ID
+        )));
+      }
+
+      let mut low = [0; 26];
+      let mut high = [0; 7];
+      device.read_registers(0x88, &mut low)?;
+      device.read_registers(0xE1, &mut high)?;
+
+      Ok(Bme280 {
+        device,
+        calibration: Bme280Calibration::from_registers(&low, &high)
+      })
+    }
+  }
+
+  impl ClimateSensor for Bme280 {
+    fn read(&mut self) -> Result<Climate> {
+      // ctrl_hum only applies after ctrl_meas is written
+      self.device.write(&[BME280_REG_CTRL_HUM, 0x01])?;
+      self.device.write(&[BME280_REG_CTRL_MEAS, 0x25])?;
+
+      // at most 9.3ms with 1x oversampling
+      thread::sleep(Duration::from_millis(15));
+
+      let mut data = [0; 8];
+      self.device.read_registers(BME280_REG_DATA, &mut data)?;
+      Ok(self.calibration.compensate(&data))
+    }
+  }
+
+  /// Opens a companion sensor on an I²C bus, at its default address unless
+  /// given one
+  pub fn open_companion<P: AsRef<Path>>(
+    kind: CompanionKind,
+    bus: P,
+    address: Option<u16>
+  ) -> Result<Box<dyn ClimateSensor>> {
+    let device = I2cDevice::open(bus, address.unwrap_or_else(|| kind.default_address()))?;
+
+    Ok(match kind {
+      CompanionKind::Sht3x => Box::new(Sht3x::new(device)),
+      CompanionKind::Bme280 => Box::new(Bme280::new(device)?)
+    })
+  }
+}
+
+#[cfg(all(target_os = "linux", feature = "companion-sensors"))]
+pub use i2c::*;
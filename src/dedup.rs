@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use crate::Frame;
+use crate::response::QueryResponse;
+
+/// A reasonable window for `Deduplicator`: long enough to catch a repeated
+/// ack, but well short of the sensor's 1s active reporting interval
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_millis(200);
+
+/// A reasonable window for `ReadingDeduplicator`: long enough to catch a
+/// reading repeated at the end of a working period, but only suitable with a
+/// working period, as continuous reporting sends a reading every second
+pub const DEFAULT_READING_DEDUP_WINDOW: Duration = Duration::from_secs(5);
+
+/// Drops frames identical to the previous one received within a short window.
+///
+/// Some firmware sends the same ack twice in quick succession; without this,
+/// consumers see two confirmations for a single configuration command.
+#[derive(Debug)]
+pub struct Deduplicator {
+  window: Duration,
+
+  /// the last frame passed through, and when it was received
+  last: Option<(Frame, Instant)>
+}
+
+impl Default for Deduplicator {
+  fn default() -> Self {
+    Deduplicator::new(DEFAULT_DEDUP_WINDOW)
+  }
+}
+
+impl Deduplicator {
+  pub fn new(window: Duration) -> Self {
+    Deduplicator {
+      window,
+      last: None
+    }
+  }
+
+  /// Returns true if `packet` repeats the previous frame within the window and
+  /// should be dropped
+  pub fn is_duplicate(&mut self, packet: &Frame) -> bool {
+    let now = Instant::now();
+
+    if let Some((last, received)) = &self.last {
+      if last == packet && now.duration_since(*received) < self.window {
+        return true;
+      }
+    }
+
+    self.last = Some((*packet, now));
+    false
+  }
+}
+
+/// Drops readings with the same values from the same sensor as the previous
+/// one received within a window.
+///
+/// Some firmware reports the same measurement twice at the end of a working
+/// period, far enough apart that `Deduplicator` lets the repeat through; left
+/// alone, it would be counted twice in averages and histograms.
+#[derive(Debug)]
+pub struct ReadingDeduplicator {
+  window: Duration,
+
+  /// the last reading passed through, and when it was received
+  last: Option<(QueryResponse, Instant)>
+}
+
+impl Default for ReadingDeduplicator {
+  fn default() -> Self {
+    ReadingDeduplicator::new(DEFAULT_READING_DEDUP_WINDOW)
+  }
+}
+
+impl ReadingDeduplicator {
+  pub fn new(window: Duration) -> Self {
+    ReadingDeduplicator {
+      window,
+      last: None
+    }
+  }
+
+  /// Returns true if `reading` repeats the previous one within the window and
+  /// should be dropped
+  pub fn is_duplicate(&mut self, reading: &QueryResponse) -> bool {
+    let now = Instant::now();
+
+    if let Some((last, received)) = &self.last {
+      if last == reading && now.duration_since(*received) < self.window {
+        return true;
+      }
+    }
+
+    self.last = Some((reading.clone(), now));
+    false
+  }
+}
@@ -0,0 +1,130 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::time::Instant;
+
+use serialport::SerialPortType;
+
+use crate::command::*;
+use crate::error::*;
+use crate::lock::DeviceLock;
+use crate::port::DeviceHint;
+use crate::transport::Transport;
+use crate::util::DeviceId;
+use crate::{open_port, parse_frame, PacketReader, ReadEvent, RetryConfig};
+
+/// Identifies a sensor by something that survives its device node being
+/// renumbered, e.g. across reboots or when adapters are replugged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+  /// the serial number of its USB serial adapter
+  UsbSerial(String),
+
+  /// the sensor's own device id; found by asking each USB serial port
+  DeviceId(DeviceId)
+}
+
+impl fmt::Display for DeviceSelector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DeviceSelector::UsbSerial(serial) => write!(f, "USB serial number {}", serial),
+      DeviceSelector::DeviceId(id) => write!(f, "device id {}", id)
+    }
+  }
+}
+
+/// Finds the port of the sensor matching `selector`, e.g. `/dev/ttyUSB1`.
+///
+/// Only USB serial ports are considered: built-in UARTs keep their names
+/// anyway, and probing them could upset whatever else is attached. Selecting
+/// by device id probes each port in turn (see `probe_device_id()`), skipping
+/// any that can't be opened or are locked by another process. Returns
+/// `Error::DeviceMissing` if no port matches.
+pub fn find_sensor(selector: &DeviceSelector, config: &RetryConfig) -> Result<String> {
+  let ports = serialport::available_ports().map_err(Error::SerialPortError)?;
+  let usb_ports: Vec<_> = ports.iter()
+    .filter_map(|port| match &port.port_type {
+      SerialPortType::UsbPort(info) => Some((&port.port_name, info)),
+      _ => None
+    })
+    .collect();
+
+  let found = match selector {
+    DeviceSelector::UsbSerial(serial) => usb_ports.iter()
+      .find(|(_, info)| info.serial_number.as_deref() == Some(serial.as_str()))
+      .map(|(name, _)| name.to_string()),
+    DeviceSelector::DeviceId(id) => usb_ports.iter()
+      .find(|(name, _)| match probe_device_id(name, config) {
+        Ok(found) => {
+          debug!("found sensor {} at {}", found, name);
+          found == *id
+        },
+        Err(e) => {
+          debug!("could not probe {}: {}", name, e);
+          false
+        }
+      })
+      .map(|(name, _)| name.to_string())
+  };
+
+  match found {
+    Some(name) => {
+      info!("found {} at {}", selector, name);
+      Ok(name)
+    },
+    None => Err(Error::DeviceMissing {
+      path: selector.to_string(),
+      hint: DeviceHint::AvailablePorts(ports.into_iter().map(|p| p.port_name).collect())
+    })
+  }
+}
+
+/// Asks the sensor at `device` for its device id, closing the port again
+/// before returning. The port is locked while probing, so returns
+/// `Error::DeviceBusy` rather than disturb a sensor another process has open.
+pub fn probe_device_id<P: AsRef<OsStr>>(device: P, config: &RetryConfig) -> Result<DeviceId> {
+  let _lock = DeviceLock::acquire(&device)?;
+  let mut port = open_port(&device)?;
+
+  probe_transport(&mut port, config)
+}
+
+/// Asks the sensor on `transport` for its device id, taking it from the first
+/// valid frame received: the firmware version reply, or an actively reported
+/// reading that got there first. Makes up to `config.retries` attempts.
+pub fn probe_transport<T: Transport>(transport: &mut T, config: &RetryConfig) -> Result<DeviceId> {
+  transport.set_timeout(config.timeout).map_err(Error::TransportError)?;
+
+  let cmd = GetFirmwareVersion.to_cmd();
+  let attempts = config.retries.max(1);
+  let mut reader = PacketReader::default();
+  let mut buf = [0u8; 32];
+
+  for _ in 0..attempts {
+    transport.clear_input().map_err(Error::TransportError)?;
+    transport.write_all(cmd.bytes()).map_err(Error::WriteError)?;
+
+    let deadline = Instant::now() + config.timeout;
+    while Instant::now() < deadline {
+      let n = match transport.read(&mut buf) {
+        Ok(0) => return Err(Error::ReadError(io::ErrorKind::UnexpectedEof.into())),
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+        Err(e) => return Err(Error::ReadError(e))
+      };
+
+      for &byte in &buf[..n] {
+        if let Some(ReadEvent::Packet(frame)) = reader.push(byte) {
+          if let Ok(resp) = parse_frame(&frame) {
+            return Ok(resp.device());
+          }
+        }
+      }
+    }
+  }
+
+  Err(Error::RetriesExceeded {
+    command: format!("{:?}", GetFirmwareVersion),
+    attempts
+  })
+}
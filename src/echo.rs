@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long after a command is written its echo may arrive; writing a command
+/// takes about 20ms at 9600 baud
+pub const DEFAULT_ECHO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Each command written, and when
+type Pending = VecDeque<(Instant, Vec<u8>)>;
+
+/// Commands written to the sensor whose echo hasn't been seen yet, shared by the
+/// write thread (which records them) and an `EchoFilter` on the read thread.
+/// Clones share the same commands.
+#[derive(Debug, Clone, Default)]
+pub struct WrittenCommands {
+  pending: Arc<Mutex<Pending>>
+}
+
+impl WrittenCommands {
+  pub fn new() -> Self {
+    WrittenCommands::default()
+  }
+
+  /// Records a command about to be written, whose echo should be stripped
+  pub fn push(&self, command: &[u8]) {
+    if !command.is_empty() {
+      self.pending.lock().unwrap().push_back((Instant::now(), command.to_vec()));
+    }
+  }
+
+  /// Commands still expecting an echo
+  pub fn len(&self) -> usize {
+    self.pending.lock().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+/// The result of feeding a byte to an `EchoFilter`
+#[derive(Debug, PartialEq)]
+pub enum Echo<'a> {
+  /// The byte may be part of an echo and is held back for now
+  Partial,
+
+  /// The byte completed the echo of a written command, confirming that it was
+  /// physically sent
+  Complete,
+
+  /// These bytes aren't an echo and should be read as usual, in order
+  Bytes(&'a [u8])
+}
+
+/// Strips the local echo of written commands from the byte stream read from
+/// the sensor.
+///
+/// Some USB adapters echo every byte transmitted back to the receiver. Echoed
+/// commands start with the same 0xAA header as the sensor's replies, so left in
+/// place they would be misread as corrupt packets. Bytes are held back while
+/// they match the oldest command written; a full match is dropped as its echo,
+/// while a mismatch releases everything held back. Commands not echoed within
+/// the timeout are forgotten.
+#[derive(Debug)]
+pub struct EchoFilter {
+  written: WrittenCommands,
+  timeout: Duration,
+
+  /// bytes matching the oldest written command so far
+  held: Vec<u8>,
+
+  /// bytes most recently returned by `push()`
+  released: Vec<u8>
+}
+
+impl EchoFilter {
+  pub fn new(written: WrittenCommands, timeout: Duration) -> Self {
+    EchoFilter {
+      written,
+      timeout,
+      held: Vec::new(),
+      released: Vec::new()
+    }
+  }
+
+  /// Feeds a single byte read from the sensor to the filter
+  pub fn push(&mut self, byte: u8) -> Echo<'_> {
+    let mut pending = self.written.pending.lock().unwrap();
+
+    // an echo in progress is never expired
+    if self.held.is_empty() {
+      while pending.front().map(|(t, _)| t.elapsed() > self.timeout).unwrap_or(false) {
+        if let Some((_, command)) = pending.pop_front() {
+          debug!("no echo received for command: {:x?}", command);
+        }
+      }
+    }
+
+    self.released.clear();
+
+    let matches = match pending.front() {
+      Some((_, expected)) => expected[self.held.len()] == byte,
+      None => false
+    };
+
+    if !matches {
+      // e.g. the sensor's own reply, or an echo garbled in transit
+      self.released.append(&mut self.held);
+      self.released.push(byte);
+      return Echo::Bytes(&self.released);
+    }
+
+    self.held.push(byte);
+    if pending.front().map(|(_, expected)| expected.len()) == Some(self.held.len()) {
+      pending.pop_front();
+      self.held.clear();
+
+      return Echo::Complete;
+    }
+
+    Echo::Partial
+  }
+}
@@ -1,18 +1,185 @@
+use std::fmt;
 use std::io;
+use std::num::ParseIntError;
 
 use err_derive::Error;
 
 use crate::command::Cmd;
+use crate::port::DeviceHint;
 use crate::response::Resp;
+use crate::util::DeviceId;
+
+/// Why a packet couldn't be parsed. Carries the raw frame rather than a
+/// formatted message, so parsing never allocates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameError {
+  /// The packet wasn't 10 bytes long
+  InvalidLength(usize),
+
+  InvalidChecksum {
+    frame: [u8; 10],
+    expected: u8,
+    received: u8
+  },
+
+  /// The command id (or, for 0xC5 replies, the sub-command) isn't known
+  UnknownCommand {
+    frame: [u8; 10]
+  },
+
+  /// A command frame sent to the sensor has an invalid checksum
+  InvalidCommandChecksum {
+    frame: [u8; 19],
+    expected: u8,
+    received: u8
+  },
+
+  /// A command frame's sub-command isn't known
+  UnknownCommandFrame {
+    frame: [u8; 19]
+  }
+}
+
+impl fmt::Display for FrameError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FrameError::InvalidLength(len) => write!(f, "packet has invalid length: {}", len),
+      FrameError::InvalidChecksum { frame, expected, received } => write!(
+        f,
+        "packet ({:x?}) has invalid checksum: expected={:x?} received={:x?}",
+        frame, expected, received
+      ),
+      FrameError::UnknownCommand { frame } => write!(
+        f,
+        "packet ({:x?}) has invalid command: {:x?}/{:x?}",
+        frame, frame[1], frame[2]
+      ),
+      FrameError::InvalidCommandChecksum { frame, expected, received } => write!(
+        f,
+        "command frame ({:x?}) has invalid checksum: expected={:x?} received={:x?}",
+        frame, expected, received
+      ),
+      FrameError::UnknownCommandFrame { frame } => write!(
+        f,
+        "command frame ({:x?}) has invalid command: {:x?}",
+        frame, frame[2]
+      )
+    }
+  }
+}
+
+/// A machine-readable category of `Error`, stable across releases even as
+/// variants gain or change fields
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+  SerialPort,
+  Transport,
+  DeviceNotFound,
+  DeviceBusy,
+  DeviceAccess,
+  Packet,
+  Read,
+  Write,
+  ThreadPanic,
+  InvalidWorkMode,
+  InvalidReportingMode,
+  InvalidWorkingPeriod,
+  InvalidDeviceId,
+  ChannelSend,
+  ChannelClosed,
+  RetriesExceeded,
+  InvalidResponseConversion,
+  DeviceIdMismatch,
+  InconsistentDevice,
+  InvalidSchedule,
+  InvalidLogFormat,
+  Companion,
+  InvalidCompanion,
+  InvalidHumidityCorrection,
+  #[cfg(feature = "modbus")]
+  InvalidModbusRegisterMap,
+  #[cfg(feature = "snmp")]
+  InvalidOid,
+  InvalidHex,
+  Suppressed
+}
+
+impl ErrorKind {
+  /// A short, stable identifier for this kind, e.g. for structured logs
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ErrorKind::SerialPort => "serial_port",
+      ErrorKind::Transport => "transport",
+      ErrorKind::DeviceNotFound => "device_not_found",
+      ErrorKind::DeviceBusy => "device_busy",
+      ErrorKind::DeviceAccess => "device_access",
+      ErrorKind::Packet => "packet",
+      ErrorKind::Read => "read",
+      ErrorKind::Write => "write",
+      ErrorKind::ThreadPanic => "thread_panic",
+      ErrorKind::InvalidWorkMode => "invalid_work_mode",
+      ErrorKind::InvalidReportingMode => "invalid_reporting_mode",
+      ErrorKind::InvalidWorkingPeriod => "invalid_working_period",
+      ErrorKind::InvalidDeviceId => "invalid_device_id",
+      ErrorKind::ChannelSend => "channel_send",
+      ErrorKind::ChannelClosed => "channel_closed",
+      ErrorKind::RetriesExceeded => "retries_exceeded",
+      ErrorKind::InvalidResponseConversion => "invalid_response_conversion",
+      ErrorKind::DeviceIdMismatch => "device_id_mismatch",
+      ErrorKind::InconsistentDevice => "inconsistent_device",
+      ErrorKind::InvalidSchedule => "invalid_schedule",
+      ErrorKind::InvalidLogFormat => "invalid_log_format",
+      ErrorKind::Companion => "companion",
+      ErrorKind::InvalidCompanion => "invalid_companion",
+      ErrorKind::InvalidHumidityCorrection => "invalid_humidity_correction",
+      #[cfg(feature = "modbus")]
+      ErrorKind::InvalidModbusRegisterMap => "invalid_modbus_register_map",
+      #[cfg(feature = "snmp")]
+      ErrorKind::InvalidOid => "invalid_oid",
+      ErrorKind::InvalidHex => "invalid_hex",
+      ErrorKind::Suppressed => "suppressed"
+    }
+  }
+}
+
+impl fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
 
 #[derive(Debug, Error)]
 #[error(no_from)]
+#[non_exhaustive]
 pub enum Error {
+  #[cfg(not(target_arch = "wasm32"))]
   #[error(display = "error opening serial port: {:?}", _0)]
   SerialPortError(#[error(source)] serialport::Error),
 
+  #[error(display = "sensor connection error: {}", _0)]
+  TransportError(#[source] io::Error),
+
+  #[error(display = "device {} is in use by another process", _0)]
+  DeviceBusy(String),
+
+  #[error(display = "permission denied opening {}; {}", path, hint)]
+  DeviceAccess {
+    path: String,
+    hint: DeviceHint
+  },
+
+  #[error(display = "{} not found; {}", path, hint)]
+  DeviceMissing {
+    /// the device path, or a description of the `DeviceSelector` that
+    /// matched nothing
+    path: String,
+    hint: DeviceHint
+  },
+
   #[error(display = "error parsing packet: {}", _0)]
-  PacketError(String),
+  PacketError(FrameError),
 
   #[error(display = "error reading response: {}", _0)]
   ReadError(#[source] io::Error),
@@ -20,31 +187,174 @@ pub enum Error {
   #[error(display = "error sending command: {}", _0)]
   WriteError(#[source] io::Error),
 
+  #[error(display = "{} thread panicked: {}", thread, message)]
+  ThreadPanic {
+    /// `read` or `write`
+    thread: &'static str,
+    message: String
+  },
+
   #[error(display = "invalid work mode: {}", _0)]
   InvalidWorkMode(String),
 
   #[error(display = "invalid reporting mode: {}", _0)]
   InvalidReportingMode(String),
 
-  #[error(display = "invalid working period '{}': {}", period, reason)]
+  #[error(display = "invalid working period '{}': could not parse int: {}", period, source)]
   InvalidWorkingPeriod {
     period: String,
-    reason: String
+    #[error(source)] source: ParseIntError
   },
 
+  #[error(display = "invalid working period '{}': unknown unit '{}'", period, unit)]
+  InvalidWorkingPeriodUnit {
+    period: String,
+    unit: String
+  },
+
+  #[error(display = "invalid working period '{}': value out of range (0 <= n <= 30)", _0)]
+  WorkingPeriodOutOfRange(usize),
+
+  #[error(display = "invalid device id {}", _0)]
+  InvalidDeviceId(String),
+
   #[error(display = "error sending to channel")]
   ChannelSendError(#[source] std::sync::mpsc::SendError<Cmd>),
 
-  #[error(display = "never received response to command: {:?}", command)]
+  #[error(display = "response channel closed waiting for a response to {}", _0)]
+  ChannelClosed(String),
+
+  #[error(
+    display = "never received response to command after {} attempt(s): {:?}",
+    attempts, command
+  )]
   RetriesExceeded {
     /// a debug-ified representation of the command being retried
-    command: String
+    command: String,
+
+    /// the number of times the command was sent
+    attempts: usize
   },
 
   #[error(display = "response {:?} cannot be converted into {}", resp, target)]
   InvalidResponseConversion {
     resp: Resp,
-    target: String
+
+    /// the name of the response type requested
+    target: &'static str
+  },
+
+  #[error(
+    display = "device id was not applied: requested {}, but the sensor reports {}",
+    requested, reported
+  )]
+  DeviceIdMismatch {
+    requested: DeviceId,
+    reported: DeviceId
+  },
+
+  /// Replies in one series of commands came from different devices, e.g. two
+  /// sensors on a shared bus
+  #[error(
+    display = "inconsistent device id: {} replied to {}, expected {}",
+    reported, command, expected
+  )]
+  InconsistentDevice {
+    command: String,
+    expected: DeviceId,
+    reported: DeviceId
+  },
+
+  #[error(display = "invalid schedule: {}", _0)]
+  InvalidSchedule(String),
+
+  #[error(display = "invalid log format: {}", _0)]
+  InvalidLogFormat(String),
+
+  #[error(display = "companion sensor error: {}", _0)]
+  CompanionError(#[source] io::Error),
+
+  #[error(display = "invalid companion sensor reading: {}", _0)]
+  InvalidCompanionReading(String),
+
+  #[error(display = "invalid companion sensor '{}', expected one of: sht3x, bme280", _0)]
+  InvalidCompanion(String),
+
+  #[error(
+    display = "invalid humidity correction '{}', expected one of: none, kohler[:kappa], piecewise",
+    _0
+  )]
+  InvalidHumidityCorrection(String),
+
+  #[cfg(feature = "modbus")]
+  #[error(display = "invalid Modbus register map: {}", _0)]
+  InvalidModbusRegisterMap(String),
+
+  #[cfg(feature = "snmp")]
+  #[error(display = "invalid SNMP object identifier '{}'", _0)]
+  InvalidOid(String),
+
+  #[error(display = "invalid hex {}", _0)]
+  InvalidHex(String),
+
+  /// Summarizes repeated errors collapsed by an `ErrorLimiter`
+  #[error(display = "{} error ×{} in last {}s", kind, count, seconds)]
+  Suppressed {
+    /// the `kind()` of the suppressed errors
+    kind: ErrorKind,
+    count: usize,
+    seconds: u64
+  }
+}
+
+impl Error {
+  /// The category of this error, e.g. for structured logs or for matching
+  /// without depending on a variant's fields
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      #[cfg(not(target_arch = "wasm32"))]
+      Error::SerialPortError(_) => ErrorKind::SerialPort,
+      Error::TransportError(_) => ErrorKind::Transport,
+      Error::DeviceMissing { .. } => ErrorKind::DeviceNotFound,
+      Error::DeviceAccess { .. } => ErrorKind::DeviceAccess,
+      Error::DeviceBusy(_) => ErrorKind::DeviceBusy,
+      Error::PacketError(_) => ErrorKind::Packet,
+      Error::ReadError(_) => ErrorKind::Read,
+      Error::WriteError(_) => ErrorKind::Write,
+      Error::ThreadPanic { .. } => ErrorKind::ThreadPanic,
+      Error::InvalidWorkMode(_) => ErrorKind::InvalidWorkMode,
+      Error::InvalidReportingMode(_) => ErrorKind::InvalidReportingMode,
+      Error::InvalidWorkingPeriod { .. }
+        | Error::InvalidWorkingPeriodUnit { .. }
+        | Error::WorkingPeriodOutOfRange(_) => ErrorKind::InvalidWorkingPeriod,
+      Error::InvalidDeviceId(_) => ErrorKind::InvalidDeviceId,
+      Error::ChannelSendError(_) => ErrorKind::ChannelSend,
+      Error::ChannelClosed(_) => ErrorKind::ChannelClosed,
+      Error::RetriesExceeded { .. } => ErrorKind::RetriesExceeded,
+      Error::InvalidResponseConversion { .. } => ErrorKind::InvalidResponseConversion,
+      Error::DeviceIdMismatch { .. } => ErrorKind::DeviceIdMismatch,
+      Error::InconsistentDevice { .. } => ErrorKind::InconsistentDevice,
+      Error::InvalidSchedule(_) => ErrorKind::InvalidSchedule,
+      Error::InvalidLogFormat(_) => ErrorKind::InvalidLogFormat,
+      Error::CompanionError(_) | Error::InvalidCompanionReading(_) => ErrorKind::Companion,
+      Error::InvalidCompanion(_) => ErrorKind::InvalidCompanion,
+      Error::InvalidHumidityCorrection(_) => ErrorKind::InvalidHumidityCorrection,
+      #[cfg(feature = "modbus")]
+      Error::InvalidModbusRegisterMap(_) => ErrorKind::InvalidModbusRegisterMap,
+      #[cfg(feature = "snmp")]
+      Error::InvalidOid(_) => ErrorKind::InvalidOid,
+      Error::InvalidHex(_) => ErrorKind::InvalidHex,
+      Error::Suppressed { .. } => ErrorKind::Suppressed
+    }
+  }
+
+  /// The number of errors this represents, i.e. `count` for a summary of
+  /// suppressed errors and 1 otherwise
+  pub fn count(&self) -> usize {
+    match self {
+      Error::Suppressed { count, .. } => *count,
+      _ => 1
+    }
   }
 }
 
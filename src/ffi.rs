@@ -0,0 +1,270 @@
+//! A C ABI for the protocol layer: decoding the sensor's frames, encoding
+//! commands, and computing AQI, for firmware and applications that can't link
+//! Rust directly. `include/sds011.h` is generated from this module by cbindgen.
+
+use std::convert::TryFrom;
+use std::ptr;
+use std::slice;
+
+use crate::aqi::{pm10_aqi, pm25_aqi};
+use crate::command::*;
+use crate::error::*;
+use crate::response::*;
+use crate::util::*;
+
+/// Returned on success by `sds011_decode_frame()`
+pub const SDS011_OK: i32 = 0;
+
+/// A required pointer was null
+pub const SDS011_ERR_NULL: i32 = -1;
+
+/// The frame isn't 10 bytes long, or the output buffer is too small
+pub const SDS011_ERR_LENGTH: i32 = -2;
+
+/// The frame's checksum doesn't match its data
+pub const SDS011_ERR_CHECKSUM: i32 = -3;
+
+/// The frame's command id (or, for 0xC5 replies, its sub-command) isn't known
+pub const SDS011_ERR_UNKNOWN_COMMAND: i32 = -4;
+
+/// An argument is out of range, e.g. a working period over 30 minutes
+pub const SDS011_ERR_INVALID_ARGUMENT: i32 = -5;
+
+/// Length of a frame sent by the sensor
+pub const SDS011_FRAME_LEN: usize = 10;
+
+/// Length of a command frame sent to the sensor
+pub const SDS011_COMMAND_LEN: usize = 19;
+
+/// Which command a decoded frame replies to
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sds011ResponseKind {
+  /// a reading, whether actively reported or queried
+  Query,
+  SetReportingMode,
+  SetDeviceId,
+  SetSleepWork,
+  SetWorkingPeriod,
+  GetFirmwareVersion
+}
+
+/// A decoded frame. Only the fields relevant to its `kind` are set; the rest
+/// are zeroed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sds011Response {
+  pub kind: Sds011ResponseKind,
+
+  /// the id of the sensor that sent the frame (for `SetDeviceId`, its new id)
+  pub device: u16,
+
+  /// PM2.5 in µg/m³, for `Query`
+  pub pm25: f32,
+
+  /// PM10 in µg/m³, for `Query`
+  pub pm10: f32,
+
+  /// true if the setting was queried rather than changed
+  pub query: bool,
+
+  /// the setting's value: for `SetReportingMode` 0 is active and 1 is query,
+  /// for `SetSleepWork` 0 is sleep and 1 is work, and for `SetWorkingPeriod`
+  /// it's the period in minutes, 0 being continuous
+  pub value: u8,
+
+  /// the firmware date, for `GetFirmwareVersion`; the year is 2-digit
+  pub year: u8,
+  pub month: u8,
+  pub day: u8
+}
+
+impl From<Resp> for Sds011Response {
+  fn from(resp: Resp) -> Self {
+    let mut out = Sds011Response {
+      kind: Sds011ResponseKind::Query,
+      device: resp.device().0,
+      pm25: 0.0,
+      pm10: 0.0,
+      query: false,
+      value: 0,
+      year: 0,
+      month: 0,
+      day: 0
+    };
+
+    match resp {
+      Resp::Query(r) => {
+        out.pm25 = r.pm25;
+        out.pm10 = r.pm10;
+      },
+      Resp::SetReportingMode(r) => {
+        out.kind = Sds011ResponseKind::SetReportingMode;
+        out.query = r.query;
+        out.value = r.mode.as_byte();
+      },
+      Resp::SetDeviceId(_) => out.kind = Sds011ResponseKind::SetDeviceId,
+      Resp::SetSleepWork(r) => {
+        out.kind = Sds011ResponseKind::SetSleepWork;
+        out.query = r.query;
+        out.value = r.mode.as_byte();
+      },
+      Resp::SetWorkingPeriod(r) => {
+        out.kind = Sds011ResponseKind::SetWorkingPeriod;
+        out.query = r.query;
+        out.value = r.working_period.as_byte();
+      },
+      Resp::GetFirmwareVersion(r) => {
+        out.kind = Sds011ResponseKind::GetFirmwareVersion;
+        out.year = r.year;
+        out.month = r.month;
+        out.day = r.day;
+      }
+    }
+
+    out
+  }
+}
+
+/// Decodes a frame sent by the sensor into `out`, returning `SDS011_OK` or a
+/// negative `SDS011_ERR_*` code, in which case `out` is left untouched.
+///
+/// # Safety
+///
+/// `frame` must point to `len` readable bytes, and `out` to a writable
+/// `Sds011Response`.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_decode_frame(
+  frame: *const u8,
+  len: usize,
+  out: *mut Sds011Response
+) -> i32 {
+  if frame.is_null() || out.is_null() {
+    return SDS011_ERR_NULL;
+  }
+
+  match crate::parse_packet(slice::from_raw_parts(frame, len)) {
+    Ok(resp) => {
+      *out = resp.into();
+      SDS011_OK
+    },
+    Err(Error::PacketError(FrameError::InvalidChecksum { .. })) => SDS011_ERR_CHECKSUM,
+    Err(Error::PacketError(FrameError::UnknownCommand { .. })) => SDS011_ERR_UNKNOWN_COMMAND,
+    Err(_) => SDS011_ERR_LENGTH
+  }
+}
+
+/// Copies a command's frame into `out`, returning its length or a negative
+/// `SDS011_ERR_*` code
+unsafe fn write_command(cmd: Cmd, out: *mut u8, len: usize) -> i32 {
+  let bytes = cmd.bytes();
+  if out.is_null() {
+    return SDS011_ERR_NULL;
+  }
+
+  if len < bytes.len() {
+    return SDS011_ERR_LENGTH;
+  }
+
+  ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len());
+  bytes.len() as i32
+}
+
+/// Encodes a query for the latest reading into `out`, returning the number of
+/// bytes written (always `SDS011_COMMAND_LEN`) or a negative `SDS011_ERR_*`
+/// code. Every `sds011_encode_*` function behaves the same way.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_query(out: *mut u8, len: usize) -> i32 {
+  write_command(Query.to_cmd(), out, len)
+}
+
+/// Encodes a command to query (if `query`) or set the reporting mode: active
+/// if `active`, otherwise query.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_set_reporting_mode(
+  query: bool,
+  active: bool,
+  out: *mut u8,
+  len: usize
+) -> i32 {
+  let mode = if active { ReportingMode::Active } else { ReportingMode::Query };
+  write_command(SetReportingMode { query, mode }.to_cmd(), out, len)
+}
+
+/// Encodes a command to change the sensor's device id to `id`.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_set_device_id(id: u16, out: *mut u8, len: usize) -> i32 {
+  write_command(SetDeviceId { id: DeviceId(id) }.to_cmd(), out, len)
+}
+
+/// Encodes a command to query (if `query`) or set whether the sensor works or
+/// sleeps.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_set_sleep_work(
+  query: bool,
+  work: bool,
+  out: *mut u8,
+  len: usize
+) -> i32 {
+  let mode = if work { WorkMode::Work } else { WorkMode::Sleep };
+  write_command(SetSleepWork { query, mode }.to_cmd(), out, len)
+}
+
+/// Encodes a command to query (if `query`) or set the working period, in
+/// minutes from 0 (continuous) to 30.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_set_working_period(
+  query: bool,
+  minutes: u8,
+  out: *mut u8,
+  len: usize
+) -> i32 {
+  let working_period = match WorkingPeriod::try_from(minutes as usize) {
+    Ok(working_period) => working_period,
+    Err(_) => return SDS011_ERR_INVALID_ARGUMENT
+  };
+
+  write_command(SetWorkingPeriod { query, working_period }.to_cmd(), out, len)
+}
+
+/// Encodes a command to get the sensor's firmware version.
+///
+/// # Safety
+///
+/// `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sds011_encode_get_firmware_version(out: *mut u8, len: usize) -> i32 {
+  write_command(GetFirmwareVersion.to_cmd(), out, len)
+}
+
+/// Computes the AQI for a PM2.5 concentration in µg/m³; see `pm25_aqi()`
+#[no_mangle]
+pub extern "C" fn sds011_pm25_aqi(concentration: f32) -> u16 {
+  pm25_aqi(concentration)
+}
+
+/// Computes the AQI for a PM10 concentration in µg/m³; see `pm10_aqi()`
+#[no_mangle]
+pub extern "C" fn sds011_pm10_aqi(concentration: f32) -> u16 {
+  pm10_aqi(concentration)
+}
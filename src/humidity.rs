@@ -0,0 +1,126 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::*;
+use crate::response::QueryResponse;
+
+/// Hygroscopic growth parameter for `HumidityCorrection::Kohler` if none is
+/// given, typical of mixed urban aerosol
+pub const DEFAULT_KAPPA: f32 = 0.4;
+
+/// Assumed density of dry particles, in g/cm³
+const PARTICLE_DENSITY: f32 = 1.65;
+
+/// Humidity is clamped to this before correcting: growth factors diverge
+/// approaching saturation, and humidity sensors are least accurate there
+pub const MAX_CORRECTED_HUMIDITY: f32 = 95.0;
+
+/// (relative humidity, growth factor) points for `HumidityCorrection::Piecewise`
+const PIECEWISE_GROWTH: &[(f32, f32)] = &[
+  (50.0, 1.0),
+  (60.0, 1.05),
+  (70.0, 1.15),
+  (80.0, 1.35),
+  (90.0, 1.8),
+  (95.0, 2.4)
+];
+
+/// A model of how much particles have grown by taking up water, used to
+/// estimate dry mass from readings taken in humid air. The sensor counts wet
+/// particles, so it overreads as humidity rises.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HumidityCorrection {
+  /// readings are used as-is
+  None,
+
+  /// κ-Köhler theory: mass grows by `1 + (κ / ρ) / (1 / a_w - 1)`, where `a_w`
+  /// is relative humidity as a fraction and ρ the dry particle density
+  Kohler { kappa: f32 },
+
+  /// a growth factor interpolated between fixed points, flat below 50% RH and
+  /// steepening towards saturation
+  Piecewise
+}
+
+impl HumidityCorrection {
+  /// The model's name, without its parameters
+  pub fn name(&self) -> &'static str {
+    match self {
+      HumidityCorrection::None => "none",
+      HumidityCorrection::Kohler { .. } => "kohler",
+      HumidityCorrection::Piecewise => "piecewise"
+    }
+  }
+
+  /// How much larger a wet reading is than a dry one at the given relative
+  /// humidity, in percent
+  pub fn growth_factor(&self, humidity: f32) -> f32 {
+    let humidity = humidity.clamp(0.0, MAX_CORRECTED_HUMIDITY);
+
+    match self {
+      HumidityCorrection::None => 1.0,
+      HumidityCorrection::Kohler { kappa } => {
+        let activity = humidity / 100.0;
+        1.0 + (kappa / PARTICLE_DENSITY) / (1.0 / activity - 1.0)
+      },
+      HumidityCorrection::Piecewise => {
+        let mut lower = (0.0, 1.0);
+        for &(rh, factor) in PIECEWISE_GROWTH {
+          if humidity <= rh {
+            let (lower_rh, lower_factor) = lower;
+            return lower_factor + (factor - lower_factor) * (humidity - lower_rh) / (rh - lower_rh);
+          }
+
+          lower = (rh, factor);
+        }
+
+        lower.1
+      }
+    }
+  }
+
+  /// Estimates the dry reading given the relative humidity it was taken at.
+  /// Saturated values are left alone since the true value is unknown anyway.
+  pub fn correct(&self, reading: &QueryResponse, humidity: f32) -> QueryResponse {
+    let factor = self.growth_factor(humidity);
+
+    QueryResponse {
+      pm25: if reading.pm25_saturated() { reading.pm25 } else { reading.pm25 / factor },
+      pm10: if reading.pm10_saturated() { reading.pm10 } else { reading.pm10 / factor },
+      ..*reading
+    }
+  }
+}
+
+/// Parses `none`, `kohler` (with `DEFAULT_KAPPA`), `kohler:<kappa>`, or
+/// `piecewise`
+impl FromStr for HumidityCorrection {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let lower = s.to_ascii_lowercase();
+    let mut parts = lower.splitn(2, ':');
+
+    match (parts.next(), parts.next()) {
+      (Some("none"), None) => Ok(HumidityCorrection::None),
+      (Some("kohler"), None) => Ok(HumidityCorrection::Kohler { kappa: DEFAULT_KAPPA }),
+      (Some("kohler"), Some(kappa)) => match kappa.parse::<f32>() {
+        Ok(kappa) if kappa.is_finite() && kappa >= 0.0 => Ok(HumidityCorrection::Kohler { kappa }),
+        _ => Err(Error::InvalidHumidityCorrection(s.to_string()))
+      },
+      (Some("piecewise"), None) => Ok(HumidityCorrection::Piecewise),
+      _ => Err(Error::InvalidHumidityCorrection(s.to_string()))
+    }
+  }
+}
+
+/// Formats the model with its parameters, e.g. `kohler:0.4`, such that it
+/// parses back to the same model
+impl fmt::Display for HumidityCorrection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HumidityCorrection::Kohler { kappa } => write!(f, "kohler:{}", kappa),
+      other => write!(f, "{}", other.name())
+    }
+  }
+}
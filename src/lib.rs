@@ -1,177 +1,769 @@
 
+#[cfg(not(target_arch = "wasm32"))]
 use std::ffi::OsStr;
-use std::sync::mpsc::{Sender, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender, Receiver};
 use std::thread;
 use std::time::{Duration, Instant};
-use std::io::Read;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
 
 #[macro_use] extern crate log;
 
-use bytes::{BytesMut, BufMut};
-
+#[cfg(not(target_arch = "wasm32"))]
 use serialport::{
   open_with_settings,
-  SerialPort, SerialPortSettings, DataBits, FlowControl, Parity, StopBits
+  SerialPort, SerialPortSettings, DataBits, FlowControl, Parity,
+  StopBits
 };
 use thread::JoinHandle;
 
 pub mod error;
 pub mod util;
+pub mod clock;
 pub mod command;
 pub mod response;
+pub mod aqi;
+pub mod quality;
+pub mod metadata;
+pub mod companion;
+pub mod humidity;
+pub mod supervisor;
+pub mod broadcast;
+pub mod ratelimit;
+pub mod dedup;
+pub mod echo;
+pub mod link;
+pub mod lock;
+pub mod port;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod discover;
+pub mod transport;
+pub mod mock;
+pub mod schedule;
+pub mod sniff;
+pub mod pcap;
+pub mod measure;
+#[cfg(feature = "coap")]
+pub mod coap;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "snmp")]
+pub mod snmp;
+#[cfg(feature = "log-json")]
+pub mod logging;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use util::*;
+pub use clock::*;
 pub use command::*;
 pub use response::*;
 pub use error::*;
+pub use aqi::*;
+pub use quality::*;
+pub use metadata::*;
+pub use companion::*;
+pub use humidity::*;
+pub use supervisor::*;
+pub use broadcast::*;
+pub use ratelimit::*;
+pub use dedup::*;
+pub use echo::*;
+pub use link::*;
+pub use lock::*;
+pub use port::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use discover::*;
+pub use transport::*;
+pub use mock::*;
+pub use schedule::*;
+pub use sniff::*;
+pub use pcap::*;
+#[cfg(feature = "coap")]
+pub use coap::*;
+#[cfg(feature = "modbus")]
+pub use modbus::*;
+#[cfg(feature = "snmp")]
+pub use snmp::*;
+pub use measure::*;
+
+/// Length of every packet sent by the sensor, including its head and tail
+pub const FRAME_LEN: usize = 10;
+
+/// A complete packet sent by the sensor, including its head and tail
+pub type Frame = [u8; FRAME_LEN];
+
+/// Parses a complete packet into a response; see `parse_frame()`.
+pub fn parse_packet(packet: &[u8]) -> Result<Resp> {
+  let frame: &Frame = packet.try_into()
+    .map_err(|_| Error::PacketError(FrameError::InvalidLength(packet.len())))?;
+
+  parse_frame(frame)
+}
 
-fn parse_packet(packet: &[u8]) -> Result<Resp> {
+/// Parses a complete packet (as returned by `PacketReader`) into a response,
+/// without allocating.
+pub fn parse_frame(frame: &Frame) -> Result<Resp> {
   // this parse implementation makes some protocol assumptions based on the docs
-  // note: buf is &packet[1..9]; head and tail are stripped during read
   //  - all packets are 10 bytes long (8, excluding head/tail)
-  //  - &packet[1] (&buf[0]) is command id
-  //  - &packet[2..=7] (&buf[1..=6]) are data bytes, for checksum purposes
-  //  - &packet[2..=5] (&buf[1..=4]) is actual data (&packet[3] is usually
-  //    constant)
-  //  - &packet[6..=7] is device id (u16)
-  //  - &packet[8] (&buf[]) is checksum(&packet[2..=7]) (or checksum(&buf[1..=6]))
-
-  if packet.len() != 10 {
-    return Err(Error::PacketError(format!(
-      "packet has invalid length: {:x?}", packet
-    )));
-  }
+  //  - &frame[1] is command id
+  //  - &frame[2..=7] are data bytes, for checksum purposes
+  //  - &frame[2..=5] is actual data (&frame[3] is usually constant)
+  //  - &frame[6..=7] is device id (u16)
+  //  - &frame[8] is checksum(&frame[2..=7])
 
-  let checksum_received = packet[8];
-  let checksum_bytes = &packet[2..=7];
-  let checksum_calculated = checksum(checksum_bytes);
+  let checksum_received = frame[8];
+  let checksum_calculated = checksum(&frame[2..=7]);
   if checksum_calculated != checksum_received {
-    return Err(Error::PacketError(format!(
-      "packet ({:x?}) has invalid checksum: expected={:x?} received={:x?}",
-      packet, checksum_calculated, checksum_received
-    )));
+    return Err(Error::PacketError(FrameError::InvalidChecksum {
+      frame: *frame,
+      expected: checksum_calculated,
+      received: checksum_received
+    }));
   }
 
   debug!(
     "packet ({:x?}) checksum is valid: expected={:x?} received={:x?}",
-    packet, checksum_calculated, checksum_received
+    frame, checksum_calculated, checksum_received
   );
 
-  let buf = packet.to_owned();
-  let command = buf[1];
-  let command_extra = buf[2];
+  let command = frame[1];
+  let command_extra = frame[2];
 
   Ok(match (command, command_extra) {
-    (0xC0, _) => QueryResponse::parse(&buf),
-
-    (0xC5, 0x02) => SetReportingModeResponse::parse(&buf),
-    (0xC5, 0x05) => SetDeviceIdResponse::parse(&buf),
-    (0xC5, 0x06) => SetSleepWorkResponse::parse(&buf),
-    (0xC5, 0x08) => SetWorkingPeriodResponse::parse(&buf),
-    (0xC5, 0x07) => GetFirmwareVersionResponse::parse(&buf),
-
-    (other, other_extra) => return Err(Error::PacketError(format!(
-      "packet ({:x?}) has invalid command: {:x?}/{:x?}",
-      buf, other, other_extra
-    )))
+    (0xC0, _) => QueryResponse::parse(frame),
+
+    (0xC5, 0x02) => SetReportingModeResponse::parse(frame),
+    (0xC5, 0x05) => SetDeviceIdResponse::parse(frame),
+    (0xC5, 0x06) => SetSleepWorkResponse::parse(frame),
+    (0xC5, 0x08) => SetWorkingPeriodResponse::parse(frame),
+    (0xC5, 0x07) => GetFirmwareVersionResponse::parse(frame),
+
+    _ => return Err(Error::PacketError(FrameError::UnknownCommand { frame: *frame }))
+  })
+}
+
+/// Parses a complete command frame sent to the sensor (as returned by
+/// `PacketReader`, e.g. a local echo or one seen on a shared bus), without
+/// allocating.
+pub fn parse_command_frame(frame: &CommandFrame) -> Result<DecodedCommand> {
+  // command frames are 19 bytes:
+  //  - &frame[1] is always 0xB4
+  //  - &frame[2..=16] are data bytes, for checksum purposes
+  //  - &frame[2] is the sub-command, &frame[3] is 0 to query or 1 to set, and
+  //    &frame[4] is the value to set
+  //  - &frame[13..=14] is the new id, for 0x05 (set device id)
+  //  - &frame[15..=16] is the target device id (u16), 0xFFFF for any
+  //  - &frame[17] is checksum(&frame[2..=16])
+
+  let checksum_received = frame[17];
+  let checksum_calculated = checksum(&frame[2..=16]);
+  if checksum_calculated != checksum_received {
+    return Err(Error::PacketError(FrameError::InvalidCommandChecksum {
+      frame: *frame,
+      expected: checksum_calculated,
+      received: checksum_received
+    }));
+  }
+
+  let query = frame[3] == 0x00;
+  let command = match frame[2] {
+    0x02 => Req::SetReportingMode(SetReportingMode {
+      query,
+      mode: ReportingMode::from_byte(frame[4])
+    }),
+    0x04 => Req::Query(Query),
+    0x05 => Req::SetDeviceId(SetDeviceId {
+      id: DeviceId(u16::from_be_bytes([frame[13], frame[14]]))
+    }),
+    0x06 => Req::SetSleepWork(SetSleepWork {
+      query,
+      mode: WorkMode::from_byte(frame[4])
+    }),
+    0x07 => Req::GetFirmwareVersion(GetFirmwareVersion),
+    0x08 => Req::SetWorkingPeriod(SetWorkingPeriod {
+      query,
+      working_period: WorkingPeriod::from_byte(frame[4])
+    }),
+
+    _ => return Err(Error::PacketError(FrameError::UnknownCommandFrame { frame: *frame }))
+  };
+
+  Ok(DecodedCommand {
+    command,
+    target: DeviceId(u16::from_be_bytes([frame[15], frame[16]]))
   })
 }
 
+/// Something of interest read from the sensor by a `PacketReader`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadEvent {
+  /// A complete, unparsed packet, including its head and tail
+  Packet(Frame),
+
+  /// A complete, unparsed command frame going to the sensor rather than coming
+  /// from it, e.g. a local echo, or another host's command on a shared bus
+  Command(CommandFrame),
+
+  /// A byte received outside of any packet
+  Garbage(u8)
+}
+
+/// Reassembles packets from the raw byte stream read from the sensor, without
+/// allocating. Command frames (id 0xB4) are recognized too, so a stream with
+/// both directions in it decodes cleanly.
+#[derive(Debug, Default)]
+pub struct PacketReader {
+  /// large enough for either direction
+  current: CommandFrame,
+
+  /// bytes of the current packet received so far; 0 between packets
+  len: usize
+}
+
+impl PacketReader {
+  /// Feeds a single byte to the reader, returning a `ReadEvent` if the byte
+  /// completed a packet or couldn't be part of one.
+  pub fn push(&mut self, byte: u8) -> Option<ReadEvent> {
+    // packet format (10 bytes):
+    // header:    1 byte (0xAA)
+    // command:   1 byte
+    // data:      4 bytes
+    // device id: 2 bytes (counts as data for checksum purposes)
+    // checksum:  1 byte
+    // tail:      1 byte (0xAB)
+    //
+    // command frames sent to the sensor use the same head and tail, with id
+    // 0xB4 and 15 data bytes (19 in total)
+
+    // unfortunately if there's any crosstalk on the port (either from our own
+    // write thread or from the sensor itself), packets tend to become corrupt
+    // it's easy enough to work around this with gratuitous retries and some
+    // acceptance of lost actively-reported queries, but we do have to sanely
+    // handle partial packets here
+
+    if self.len > 0 {
+      self.current[self.len] = byte;
+      self.len += 1;
+
+      if self.current[1] == 0xB4 {
+        if self.len == COMMAND_LEN {
+          self.len = 0;
+          return Some(ReadEvent::Command(self.current));
+        }
+      } else if self.len == FRAME_LEN {
+        self.len = 0;
+
+        let mut frame = [0u8; FRAME_LEN];
+        frame.copy_from_slice(&self.current[..FRAME_LEN]);
+        return Some(ReadEvent::Packet(frame));
+      }
+
+      None
+    } else if byte == 0xAA {
+      self.current[0] = byte;
+      self.len = 1;
+      None
+    } else {
+      Some(ReadEvent::Garbage(byte))
+    }
+  }
+}
+
+/// Errors and lifecycle events from an opened sensor
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ControlMessage {
   /// A non-fatal error, e.g. a single bad packet
   Error(Error),
 
-  /// An error that halts either of the read or write threads
+  /// An error that halts either of the read or write threads, including
+  /// either of them panicking (`Error::ThreadPanic`)
   FatalError(Error),
+
+  /// The sensor was opened; always the first message
+  Opened,
+
+  /// A `Supervisor` is about to reopen its sensor after it was lost
+  Reconnecting {
+    /// the number of consecutive reopen attempts, starting at 1
+    attempt: usize
+  },
+
+  /// A `Supervisor` reopened its sensor
+  Reconnected,
+
+  /// A `Supervisor` hasn't received anything from its sensor within its
+  /// configured `stall_timeout`
+  Stalled,
+
+  /// A valid packet was received after garbage bytes or invalid packets
+  Resynced,
+
+  /// Bytes were received outside of any packet; only sent if enabled with
+  /// `SensorOptions::garbage_report_interval`, and at most once per interval
+  Garbage {
+    count: usize,
+
+    /// up to `GARBAGE_SAMPLE_LEN` of the bytes, for diagnosis
+    sample: Vec<u8>
+  },
+
+  /// More than `SupervisorConfig::backlog_watermark` responses were waiting
+  /// to be handled at once, i.e. the consumer is too slow; sent once each time
+  /// the backlog crosses the watermark
+  Backlog {
+    depth: usize
+  },
+
+  /// The read thread stopped, so no more responses will be received; for a
+  /// `Supervisor`, sent once it gives up on its sensor
+  Closed
+}
+
+impl ControlMessage {
+  /// Copies a lifecycle event, or returns None for errors, which can't be
+  /// cloned
+  pub(crate) fn try_clone(&self) -> Option<ControlMessage> {
+    match self {
+      ControlMessage::Error(_) | ControlMessage::FatalError(_) => None,
+      ControlMessage::Opened => Some(ControlMessage::Opened),
+      ControlMessage::Reconnecting { attempt } => {
+        Some(ControlMessage::Reconnecting { attempt: *attempt })
+      },
+      ControlMessage::Reconnected => Some(ControlMessage::Reconnected),
+      ControlMessage::Stalled => Some(ControlMessage::Stalled),
+      ControlMessage::Resynced => Some(ControlMessage::Resynced),
+      ControlMessage::Garbage { count, sample } => Some(ControlMessage::Garbage {
+        count: *count,
+        sample: sample.clone()
+      }),
+      ControlMessage::Backlog { depth } => Some(ControlMessage::Backlog { depth: *depth }),
+      ControlMessage::Closed => Some(ControlMessage::Closed)
+    }
+  }
+}
+
+/// Runs the body of the read or write thread, reporting a panic in it as a
+/// `ControlMessage::FatalError` so it's handled like the sensor being lost,
+/// e.g. by a `Supervisor` reopening it, rather than leave it half working.
+/// Returns false if it panicked.
+fn catch_panic<F: FnOnce()>(
+  thread: &'static str,
+  control_tx: &Sender<ControlMessage>,
+  f: F
+) -> bool {
+  let payload = match panic::catch_unwind(AssertUnwindSafe(f)) {
+    Ok(()) => return true,
+    Err(payload) => payload
+  };
+
+  let message = payload.downcast_ref::<&str>()
+    .map(|message| message.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned())
+    .unwrap_or_else(|| "unknown cause".to_string());
+
+  let error = Error::ThreadPanic { thread, message };
+  control_tx.send(ControlMessage::FatalError(error)).ok();
+
+  false
+}
+
+/// How often the read and write threads check whether the other has stopped;
+/// reads time out this often, so the transport's own timeout is tracked
+/// separately
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// State shared by a sensor's read and write threads
+#[derive(Clone)]
+struct SharedState {
+  /// commands written, for the read thread to strip their local echo
+  written: Option<WrittenCommands>,
+
+  /// set once either thread stops, so the other stops too
+  shutdown: Arc<AtomicBool>
+}
+
+impl SharedState {
+  fn new(local_echo: bool) -> Self {
+    SharedState {
+      written: if local_echo { Some(WrittenCommands::new()) } else { None },
+      shutdown: Arc::new(AtomicBool::new(false))
+    }
+  }
 }
 
-fn read_thread(
-  port: Box<dyn SerialPort>,
+fn read_thread<R: Read + Send + 'static>(
+  port: R,
+  device: String,
   tx: Sender<Resp>,
   control_tx: Sender<ControlMessage>,
+  options: SensorOptions,
+  lock: Option<DeviceLock>,
+  shared: SharedState
 ) -> JoinHandle<()> {
   thread::spawn(move || {
-    debug!("started read_thread");
+    let panic_tx = control_tx.clone();
+    let SharedState { written, shutdown } = shared;
+    let thread_shutdown = Arc::clone(&shutdown);
+    let finished = catch_panic("read", &panic_tx, move || {
+      debug!(device = device.as_str(); "started read_thread");
 
-    let mut current_packet: Option<BytesMut> = None;
+      // held until the port is closed
+      let _lock = lock;
 
-    for byte in port.bytes() {
-      let byte = match byte {
-        Ok(byte) => byte,
-        Err(e) => {
-          control_tx.send(ControlMessage::FatalError(Error::ReadError(e))).ok();
-          break;
-        }
-      };
+      let mut reader = PacketReader::default();
+      let mut limiter = ErrorLimiter::default();
+      let mut dedup = options.dedup_window.map(Deduplicator::new);
+      let mut reading_dedup = options.reading_dedup_window.map(ReadingDeduplicator::new);
+      let mut garbage = options.garbage_report_interval.map(GarbageReporter::new);
+      let mut echo = written.map(|w| EchoFilter::new(w, options.echo_timeout));
+      let stats = options.stats;
 
-      // packet format (10 bytes):
-      // header:    1 byte (0xAA)
-      // command:   1 byte
-      // data:      4 bytes
-      // device id: 2 bytes (counts as data for checksum purposes)
-      // checksum:  1 byte
-      // tail:      1 byte (0xAB)
-
-      // unfortunately if there's any crosstalk on the port (either from our own
-      // write thread or from the sensor itself), packets tend to become corrupt
-      // it's easy enough to work around this with gratuitous retries and some
-      // acceptance of lost actively-reported queries, but we do have to sanely
-      // handle partial packets here
-
-      if let Some(packet) = current_packet.as_mut() {
-        packet.put_u8(byte);
-
-        match packet.len() {
-          10 => {
-            match parse_packet(packet) {
-              Ok(response) => tx.send(response).ok(),
-              Err(e) => control_tx.send(ControlMessage::Error(e)).ok()
-            };
-
-            current_packet = None;
-          },
+      // set by garbage bytes or invalid packets, until the next valid packet
+      let mut desynced = false;
 
-          len if len > 10 => {
-            control_tx.send(ControlMessage::Error(Error::PacketError(format!(
-              "packet is too long, will discard: {:x?}", packet
-            )))).ok();
+      let mut last_read = Instant::now();
+      'read: for byte in port.bytes() {
+        let byte = match byte {
+          Ok(byte) => byte,
+          Err(e) if is_timeout(&e) && last_read.elapsed() < READ_TIMEOUT => {
+            if thread_shutdown.load(Ordering::SeqCst) {
+              debug!(device = device.as_str(); "write_thread stopped, closing");
+              break;
+            }
+
+            continue;
           },
+          Err(e) => {
+            control_tx.send(ControlMessage::FatalError(Error::ReadError(e))).ok();
+            break;
+          }
+        };
 
-          _ => ()
+        last_read = Instant::now();
+
+        for summary in limiter.flush() {
+          control_tx.send(ControlMessage::Error(summary)).ok();
+        }
+
+        let bytes = match echo.as_mut().map(|e| e.push(byte)) {
+          None => std::slice::from_ref(&byte),
+          Some(Echo::Bytes(bytes)) => bytes,
+          Some(Echo::Partial) => continue,
+          Some(Echo::Complete) => {
+            stats.add_echo();
+            debug!(device = device.as_str(); "write confirmed by local echo");
+            continue;
+          }
         };
-      } else if byte == 0xAA {
-        let mut packet = BytesMut::with_capacity(10);
-        packet.put_u8(byte);
 
-        current_packet = Some(packet);
-      } else {
-        debug!("garbage byte: {:x?}", byte);
+        for &byte in bytes {
+          let event = reader.push(byte);
+          match event {
+            Some(ReadEvent::Packet(packet)) => {
+              if dedup.as_mut().map(|d| d.is_duplicate(&packet)).unwrap_or(false) {
+                debug!(
+                  device = device.as_str(),
+                  frame = to_hex(&packet).as_str();
+                  "dropped duplicate frame"
+                );
+                continue;
+              }
+
+              let result = parse_frame(&packet);
+              stats.add_packet(result.is_ok());
+
+              match result {
+                Ok(response) => {
+                  if desynced {
+                    desynced = false;
+                    control_tx.send(ControlMessage::Resynced).ok();
+                  }
+
+                  let duplicate = match (&response, reading_dedup.as_mut()) {
+                    (Resp::Query(reading), Some(dedup)) => dedup.is_duplicate(reading),
+                    _ => false
+                  };
+
+                  if duplicate {
+                    stats.add_duplicate_reading();
+                    debug!(device = device.as_str(); "dropped duplicate reading");
+                    continue;
+                  }
+
+                  if tx.send(response).is_err() {
+                    debug!(device = device.as_str(); "response receiver dropped, closing");
+                    break 'read;
+                  }
+                },
+                Err(e) => {
+                  desynced = true;
+                  stats.add_invalid_frame(&packet, &e);
+
+                  debug!(
+                    device = device.as_str(),
+                    frame = to_hex(&packet).as_str(),
+                    error_kind = e.kind().as_str();
+                    "invalid frame: {}", e
+                  );
+
+                  // a bad cable can produce thousands of these per second
+                  if let Some(e) = limiter.push(e) {
+                    control_tx.send(ControlMessage::Error(e)).ok();
+                  }
+                }
+              }
+            },
+            Some(ReadEvent::Command(frame)) => match parse_command_frame(&frame) {
+              Ok(decoded) => debug!(
+                device = device.as_str(),
+                frame = to_hex(&frame).as_str();
+                "read a command frame, e.g. a local echo: {:?}", decoded
+              ),
+              Err(e) => {
+                desynced = true;
+                if let Some(e) = limiter.push(e) {
+                  control_tx.send(ControlMessage::Error(e)).ok();
+                }
+              }
+            },
+            Some(ReadEvent::Garbage(byte)) => {
+              desynced = true;
+              stats.add_garbage();
+              if let Some(garbage) = garbage.as_mut() {
+                garbage.push(byte);
+              }
+
+              debug!(
+                device = device.as_str(),
+                frame = to_hex(&[byte]).as_str();
+                "garbage byte: {:x?}", byte
+              );
+            },
+            None => ()
+          }
+
+          let in_run = matches!(event, Some(ReadEvent::Garbage(_)));
+          if let Some((count, sample)) = garbage.as_mut().and_then(|g| g.poll(in_run)) {
+            control_tx.send(ControlMessage::Garbage { count, sample }).ok();
+          }
+        }
       }
+
+      debug!(device = device.as_str(); "read_thread closed");
+      control_tx.send(ControlMessage::Closed).ok();
+    });
+
+    // stop the write thread too, so the port is released and can be reopened
+    shutdown.store(true, Ordering::SeqCst);
+
+    // nothing more will be received
+    if !finished {
+      panic_tx.send(ControlMessage::Closed).ok();
     }
   })
 }
 
-fn write_thread(
-  mut port: Box<dyn SerialPort>,
+/// Whether a read failed only because nothing arrived in time
+fn is_timeout(e: &io::Error) -> bool {
+  matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+/// Clears the port's input buffer, for `Cmd::flush_input()`
+type ClearInputFn = dyn Fn() -> io::Result<()> + Send;
+
+fn write_thread<W: Write + Send + 'static>(
+  mut port: W,
+  clear_input: Box<ClearInputFn>,
   rx: Receiver<Cmd>,
   control_tx: Sender<ControlMessage>,
+  stats: LinkStats,
+  shared: SharedState
 ) -> JoinHandle<()> {
   thread::spawn(move || {
-    debug!("started write_thread");
-
-    for cmd in rx {
-      match port.write_all(&cmd.data) {
-        Ok(_) => debug!("sent command: {:x?}", cmd),
-        Err(e) => {
-          control_tx.send(ControlMessage::FatalError(Error::WriteError(e))).ok();
-          break;
+    let panic_tx = control_tx.clone();
+    let SharedState { written, shutdown } = shared;
+    let thread_shutdown = Arc::clone(&shutdown);
+    catch_panic("write", &panic_tx, move || {
+      debug!("started write_thread");
+
+      loop {
+        let cmd = match rx.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+          Ok(cmd) => cmd,
+          Err(RecvTimeoutError::Timeout) if thread_shutdown.load(Ordering::SeqCst) => {
+            debug!("read_thread stopped, closing");
+            break;
+          },
+          Err(RecvTimeoutError::Timeout) => continue,
+          Err(RecvTimeoutError::Disconnected) => break
+        };
+
+        if cmd.flush {
+          match clear_input() {
+            Ok(_) => debug!("cleared input buffer"),
+            Err(e) => {
+              control_tx.send(ControlMessage::Error(Error::TransportError(e))).ok();
+            }
+          }
+        }
+
+        if cmd.data.is_empty() {
+          continue;
+        }
+
+        // recorded first, as the echo may arrive before the write returns
+        if let Some(written) = &written {
+          written.push(&cmd.data);
+        }
+
+        match port.write_all(&cmd.data) {
+          Ok(_) => {
+            stats.add_command();
+            debug!("sent command: {:x?}", cmd);
+          },
+          Err(e) => {
+            control_tx.send(ControlMessage::FatalError(Error::WriteError(e))).ok();
+            break;
+          }
         }
       }
-    }
+    });
+
+    // stop the read thread too, so the port and its lock are released
+    shutdown.store(true, Ordering::SeqCst);
   })
 }
 
+/// Opens the serial port at the given path with the sensor's settings, without
+/// starting any threads; most users want `open_sensor()` instead.
+///
+/// On Windows, names like `COM12`, `com12:`, and `\\.\COM12` are all
+/// accepted; see `normalize_port_name()`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_port<P: AsRef<OsStr>>(device: P) -> Result<Box<dyn SerialPort>> {
+  let settings = SerialPortSettings {
+    baud_rate: 9600,
+    data_bits: DataBits::Eight,
+    flow_control: FlowControl::None,
+    parity: Parity::None,
+    stop_bits: StopBits::One,
+    timeout: READ_TIMEOUT
+  };
+
+  let device = normalize_port_name(device.as_ref());
+  open_with_settings(&*device, &settings)
+    .map_err(|e| open_error(&device, e))
+}
+
+/// Options for the read path of an opened sensor; use
+/// `SensorOptions::default()` or `SensorOptions::builder()` to create one
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SensorOptions {
+  /// If set, frames identical to the previous one received within this window
+  /// are dropped; see `Deduplicator`. Off by default.
+  pub dedup_window: Option<Duration>,
+
+  /// If set, readings with the same values as the previous one received
+  /// within this window are dropped and counted in
+  /// `LinkStats::duplicate_readings()`; see `ReadingDeduplicator`. Off by
+  /// default.
+  pub reading_dedup_window: Option<Duration>,
+
+  /// If true, the device is locked while open; see `DeviceLock`. On by default.
+  pub lock: bool,
+
+  /// If set, garbage bytes are reported as `ControlMessage::Garbage` at most
+  /// once per interval; see `GarbageReporter`. Off by default.
+  pub garbage_report_interval: Option<Duration>,
+
+  /// If true, the local echo of each command written, as sent back by some
+  /// USB adapters, is stripped from the bytes read and counted in
+  /// `LinkStats::echoes()`; see `EchoFilter`. Off by default.
+  pub local_echo: bool,
+
+  /// How long after a command is written its echo may arrive, if `local_echo`
+  /// is set
+  pub echo_timeout: Duration,
+
+  /// Counters updated by the read thread; keep a clone to read them
+  pub stats: LinkStats,
+}
+
+impl Default for SensorOptions {
+  fn default() -> Self {
+    SensorOptions {
+      dedup_window: None,
+      reading_dedup_window: None,
+      lock: true,
+      garbage_report_interval: None,
+      local_echo: false,
+      echo_timeout: DEFAULT_ECHO_TIMEOUT,
+      stats: LinkStats::default(),
+    }
+  }
+}
+
+impl SensorOptions {
+  /// Returns a builder starting from the default options
+  pub fn builder() -> SensorOptionsBuilder {
+    SensorOptionsBuilder::default()
+  }
+}
+
+/// Builds a `SensorOptions`; see `SensorOptions::builder()`
+#[derive(Debug, Clone, Default)]
+pub struct SensorOptionsBuilder {
+  options: SensorOptions
+}
+
+impl SensorOptionsBuilder {
+  pub fn dedup_window(mut self, window: Duration) -> Self {
+    self.options.dedup_window = Some(window);
+    self
+  }
+
+  pub fn reading_dedup_window(mut self, window: Duration) -> Self {
+    self.options.reading_dedup_window = Some(window);
+    self
+  }
+
+  pub fn lock(mut self, lock: bool) -> Self {
+    self.options.lock = lock;
+    self
+  }
+
+  pub fn garbage_report_interval(mut self, interval: Duration) -> Self {
+    self.options.garbage_report_interval = Some(interval);
+    self
+  }
+
+  pub fn local_echo(mut self, local_echo: bool) -> Self {
+    self.options.local_echo = local_echo;
+    self
+  }
+
+  pub fn echo_timeout(mut self, timeout: Duration) -> Self {
+    self.options.echo_timeout = timeout;
+    self
+  }
+
+  pub fn stats(mut self, stats: LinkStats) -> Self {
+    self.options.stats = stats;
+    self
+  }
+
+  pub fn build(self) -> SensorOptions {
+    self.options
+  }
+}
+
 /// Opens a sensor at the given path
 ///
 /// Requires three channels:
@@ -179,11 +771,28 @@ fn write_thread(
 ///  - a Sender to which parsed device responses can be written (including
 ///    query results and automatic readings)
 ///  - a Sender to which informational messages can be written, e.g. errors, EoF
+///
+/// Returns `Error::DeviceBusy` if another process has the device open via this
+/// library.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn open_sensor<P: AsRef<OsStr>>(
   device: P,
   command_rx: Receiver<Cmd>,
   response_tx: Sender<Resp>,
   control_tx: Sender<ControlMessage>
+) -> Result<()> {
+  open_sensor_with_options(device, SensorOptions::default(), command_rx, response_tx, control_tx)
+}
+
+/// Opens a sensor at the given path with the given read path options; see
+/// `open_sensor()`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_sensor_with_options<P: AsRef<OsStr>>(
+  device: P,
+  options: SensorOptions,
+  command_rx: Receiver<Cmd>,
+  response_tx: Sender<Resp>,
+  control_tx: Sender<ControlMessage>
 ) -> Result<()> {
   // implementation note: writing commands to the sensor is unreliable
   // I tried a number of different implementations to reduce the issue, e.g.:
@@ -195,33 +804,153 @@ pub fn open_sensor<P: AsRef<OsStr>>(
   // the above helped anyway
   // probably related to active reporting
 
-  let settings = SerialPortSettings {
-    baud_rate: 9600,
-    data_bits: DataBits::Eight,
-    flow_control: FlowControl::None,
-    parity: Parity::None,
-    stop_bits: StopBits::One,
-
-    // timeout longer than the worst-case working period
-    timeout: Duration::from_secs(60 * 31)
+  let lock = if options.lock {
+    Some(DeviceLock::acquire(&device)?)
+  } else {
+    None
   };
 
-  let read_port = open_with_settings(device.as_ref(), &settings)
-    .map_err(Error::SerialPortError)?;
+  let port = open_port(&device)?;
+  let name = device.as_ref().to_string_lossy();
+  start(&name, port, options, lock, command_rx, response_tx, control_tx)?;
 
-  let write_port = read_port.try_clone()
-    .map_err(Error::SerialPortError)?;
+  info!("opened sensor at {:?}", device.as_ref());
 
-  read_thread(read_port, response_tx, control_tx.clone());
-  write_thread(write_port, command_rx, control_tx);
+  Ok(())
+}
 
-  info!("opened sensor at {:?}", device.as_ref());
+/// Opens a sensor over the given transport, e.g. a `TcpStream` connected to a
+/// serial bridge; see `open_sensor()` for the channels. `name` identifies the
+/// sensor in logs. Transports aren't locked.
+pub fn open_sensor_transport<T: Transport>(
+  name: &str,
+  transport: T,
+  options: SensorOptions,
+  command_rx: Receiver<Cmd>,
+  response_tx: Sender<Resp>,
+  control_tx: Sender<ControlMessage>
+) -> Result<()> {
+  start(name, transport, options, None, command_rx, response_tx, control_tx)?;
+
+  info!("opened sensor transport {}", name);
 
   Ok(())
 }
 
+/// Starts the read and write threads on a transport
+fn start<T: Transport>(
+  name: &str,
+  mut transport: T,
+  options: SensorOptions,
+  lock: Option<DeviceLock>,
+  command_rx: Receiver<Cmd>,
+  response_tx: Sender<Resp>,
+  control_tx: Sender<ControlMessage>
+) -> Result<()> {
+  // the read thread enforces `READ_TIMEOUT` itself, waking up in between to
+  // check whether the write thread has stopped
+  transport.set_timeout(SHUTDOWN_POLL_INTERVAL)
+    .map_err(Error::TransportError)?;
+
+  let writer = transport.try_clone()
+    .map_err(Error::TransportError)?;
+
+  let clear = transport.try_clone()
+    .map_err(Error::TransportError)?;
+  let clear_input = Box::new(move || clear.clear_input());
+
+  control_tx.send(ControlMessage::Opened).ok();
+
+  let stats = options.stats.clone();
+  let shared = SharedState::new(options.local_echo);
+  read_thread(
+    transport, name.to_string(), response_tx, control_tx.clone(), options, lock, shared.clone()
+  );
+  write_thread(writer, clear_input, command_rx, control_tx, stats, shared);
+
+  Ok(())
+}
+
+/// Opens a sensor over an arbitrary byte stream rather than a serial port, e.g.
+/// a simulator or a network serial bridge; see `open_sensor()` for the
+/// channels. Streams aren't locked, and `Cmd::flush_input()` has no effect.
+/// As reads can't time out, the read thread only notices the write thread
+/// stopping, e.g. on a write error, once `reader` next returns.
+pub fn open_sensor_stream<R, W>(
+  name: &str,
+  reader: R,
+  writer: W,
+  options: SensorOptions,
+  command_rx: Receiver<Cmd>,
+  response_tx: Sender<Resp>,
+  control_tx: Sender<ControlMessage>
+)
+where
+  R: Read + Send + 'static,
+  W: Write + Send + 'static
+{
+  control_tx.send(ControlMessage::Opened).ok();
+
+  let stats = options.stats.clone();
+  let shared = SharedState::new(options.local_echo);
+  read_thread(
+    reader, name.to_string(), response_tx, control_tx.clone(), options, None, shared.clone()
+  );
+  write_thread(writer, Box::new(|| Ok(())), command_rx, control_tx, stats, shared);
+
+  info!("opened sensor stream {}", name);
+}
+
+/// Opens a sensor at the given path, creating its channels
+///
+/// Returns a Sender for device commands, a Receiver for parsed device
+/// responses, and a Receiver for informational messages; see `open_sensor()`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_sensor_channels<P: AsRef<OsStr>>(
+  device: P
+) -> Result<(Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>)> {
+  open_sensor_channels_with_options(device, SensorOptions::default())
+}
+
+/// Opens a sensor at the given path with the given read path options, creating
+/// its channels; see `open_sensor_channels()`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn open_sensor_channels_with_options<P: AsRef<OsStr>>(
+  device: P,
+  options: SensorOptions
+) -> Result<(Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>)> {
+  let (command_tx, command_rx) = channel();
+  let (response_tx, response_rx) = channel();
+  let (control_tx, control_rx) = channel();
+
+  open_sensor_with_options(device, options, command_rx, response_tx, control_tx)?;
+
+  Ok((command_tx, response_rx, control_rx))
+}
+
+/// Opens a sensor over the given transport, creating its channels; see
+/// `open_sensor_transport()` and `open_sensor_channels()`
+pub fn open_transport_channels<T: Transport>(
+  name: &str,
+  transport: T,
+  options: SensorOptions
+) -> Result<(Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>)> {
+  let (command_tx, command_rx) = channel();
+  let (response_tx, response_rx) = channel();
+  let (control_tx, control_rx) = channel();
+
+  open_sensor_transport(name, transport, options, command_rx, response_tx, control_tx)?;
+
+  Ok((command_tx, response_rx, control_rx))
+}
+
+/// Options for `retry_send()`; use `RetryConfig::default()` or
+/// `RetryConfig::builder()` to create one
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct RetryConfig {
-  /// The maximum number of attempts before giving up
+  /// The maximum number of attempts before giving up; at least one attempt is
+  /// always made
   pub retries: usize,
 
   /// The time to wait between each check for responses.
@@ -229,6 +958,13 @@ pub struct RetryConfig {
 
   /// The maximum time to wait before retrying (i.e. resending the command).
   pub timeout: Duration,
+
+  /// If set, the maximum total time to spend across all attempts
+  pub deadline: Option<Duration>,
+
+  /// If true, pending responses and unread serial input are discarded before
+  /// the first attempt; see `drain_responses()` and `Cmd::flush_input()`
+  pub flush: bool,
 }
 
 impl Default for RetryConfig {
@@ -237,12 +973,70 @@ impl Default for RetryConfig {
       retries: 5,
       timeout: Duration::from_millis(500),
       sleep: Duration::from_millis(100),
+      deadline: None,
+      flush: false,
     }
   }
 }
 
-/// Sends the given command and waits for a response, retrying up to 5 times if
-/// necessary.
+impl RetryConfig {
+  /// Returns a builder starting from the default options, e.g.
+  /// `RetryConfig::builder().retries(10).timeout(Duration::from_secs(1)).build()`
+  pub fn builder() -> RetryConfigBuilder {
+    RetryConfigBuilder::default()
+  }
+}
+
+/// Builds a `RetryConfig`; see `RetryConfig::builder()`
+#[derive(Debug, Clone, Default)]
+pub struct RetryConfigBuilder {
+  config: RetryConfig
+}
+
+impl RetryConfigBuilder {
+  pub fn retries(mut self, retries: usize) -> Self {
+    self.config.retries = retries;
+    self
+  }
+
+  pub fn sleep(mut self, sleep: Duration) -> Self {
+    self.config.sleep = sleep;
+    self
+  }
+
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.config.timeout = timeout;
+    self
+  }
+
+  pub fn deadline(mut self, deadline: Duration) -> Self {
+    self.config.deadline = Some(deadline);
+    self
+  }
+
+  pub fn flush(mut self, flush: bool) -> Self {
+    self.config.flush = flush;
+    self
+  }
+
+  pub fn build(self) -> RetryConfig {
+    self.config
+  }
+}
+
+/// Discards any responses already waiting in the channel, e.g. actively
+/// reported readings queued up while the caller was busy, returning them.
+pub fn drain_responses(response_rx: &Receiver<Resp>) -> Vec<Resp> {
+  let drained: Vec<Resp> = response_rx.try_iter().collect();
+  if !drained.is_empty() {
+    debug!("drained {} stale responses", drained.len());
+  }
+
+  drained
+}
+
+/// Sends the given command and waits for a response, retrying up to
+/// `config.retries` times (or until `config.deadline`) if necessary.
 ///
 /// Returns the first matching response for the input command, as well as a list
 /// of all other responses received.
@@ -252,13 +1046,33 @@ pub fn retry_send<T: Response>(
   response_rx: &Receiver<Resp>,
   config: &RetryConfig
 ) -> Result<(T, Vec<Resp>)> {
-  let mut other: Vec<Resp> = Vec::new();
+  // stale responses can't be mistaken for ours, but are still returned
+  let mut other: Vec<Resp> = if config.flush {
+    drain_responses(response_rx)
+  } else {
+    Vec::new()
+  };
+
+  let deadline = config.deadline.map(|d| Instant::now() + d);
+  let attempts = config.retries.max(1);
+  let mut sent = 0;
+
+  for attempt in 1..=attempts {
+    let attempt_deadline = match deadline {
+      Some(deadline) => deadline.min(Instant::now() + config.timeout),
+      None => Instant::now() + config.timeout
+    };
 
-  for i in 0..config.retries {
-    let start = Instant::now();
-    command_tx.send(command.to_cmd()).map_err(Error::ChannelSendError)?;
+    let cmd = if config.flush && attempt == 1 {
+      command.to_cmd().flush_input()
+    } else {
+      command.to_cmd()
+    };
+
+    command_tx.send(cmd).map_err(Error::ChannelSendError)?;
+    sent += 1;
 
-    while start.elapsed() < config.timeout {
+    loop {
       for resp in response_rx.try_iter() {
         match resp.clone().try_into_response::<T>() {
           Ok(r) => return Ok((r, other)),
@@ -270,17 +1084,101 @@ pub fn retry_send<T: Response>(
         };
       }
 
-      thread::sleep(config.sleep);
+      let now = Instant::now();
+      if now >= attempt_deadline {
+        break;
+      }
+
+      thread::sleep(config.sleep.min(attempt_deadline - now));
+    }
+
+    if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+      debug!("deadline passed waiting for response to {:?}", command);
+      break;
+    } else if attempt == attempts {
+      debug!("giving up waiting for response to {:?}", command);
+    } else {
+      debug!("retrying command {:?}, attempt #{}", command, attempt + 1);
+    }
+  }
+
+  Err(Error::RetriesExceeded {
+    command: format!("{:?}", command),
+    attempts: sent
+  })
+}
+
+/// Like `retry_send()`, but waits for responses asynchronously rather than
+/// sleeping between checks, e.g. from a tokio task with responses from
+/// `Subscription::async_responses()`. `config.sleep` is unused.
+///
+/// Dropping the future, e.g. when another branch of a `select!` completes
+/// first, cancels it cleanly: nothing is left running, though a command may
+/// already have been sent, and any unrelated responses received so far are
+/// lost. Returns `Error::ChannelClosed` if the responses end, i.e. once the
+/// supervisor gives up on the sensor.
+#[cfg(feature = "async")]
+pub async fn retry_send_async<T: Response>(
+  command: impl Command<ResponseType = T>,
+  command_tx: &Sender<Cmd>,
+  response_rx: &mut tokio::sync::mpsc::UnboundedReceiver<Resp>,
+  config: &RetryConfig
+) -> Result<(T, Vec<Resp>)> {
+  let mut other: Vec<Resp> = Vec::new();
+  if config.flush {
+    while let Ok(resp) = response_rx.try_recv() {
+      other.push(resp);
     }
+  }
+
+  let deadline = config.deadline.map(|d| Instant::now() + d);
+  let attempts = config.retries.max(1);
+  let mut sent = 0;
 
-    if i == 4 {
+  for attempt in 1..=attempts {
+    let attempt_deadline = match deadline {
+      Some(deadline) => deadline.min(Instant::now() + config.timeout),
+      None => Instant::now() + config.timeout
+    };
+
+    let cmd = if config.flush && attempt == 1 {
+      command.to_cmd().flush_input()
+    } else {
+      command.to_cmd()
+    };
+
+    command_tx.send(cmd).map_err(Error::ChannelSendError)?;
+    sent += 1;
+
+    loop {
+      let remaining = attempt_deadline.saturating_duration_since(Instant::now());
+      let resp = match tokio::time::timeout(remaining, response_rx.recv()).await {
+        Ok(Some(resp)) => resp,
+        Ok(None) => return Err(Error::ChannelClosed(format!("{:?}", command))),
+        Err(_) => break
+      };
+
+      match resp.clone().try_into_response::<T>() {
+        Ok(r) => return Ok((r, other)),
+        Err(Error::InvalidResponseConversion { .. }) => other.push(resp),
+        Err(e) => return Err(e)
+      };
+    }
+
+    if deadline.map(|d| Instant::now() >= d).unwrap_or(false) {
+      debug!("deadline passed waiting for response to {:?}", command);
+      break;
+    } else if attempt == attempts {
       debug!("giving up waiting for response to {:?}", command);
     } else {
-      debug!("retrying command {:?}, attempt #{}", command, i + 1);
+      debug!("retrying command {:?}, attempt #{}", command, attempt + 1);
     }
   }
 
-  Err(Error::RetriesExceeded { command: format!("{:?}", command) })
+  Err(Error::RetriesExceeded {
+    command: format!("{:?}", command),
+    attempts: sent
+  })
 }
 
 /// Sends the given command and waits for a response, using default retry
@@ -296,3 +1194,135 @@ pub fn retry_send_default<T: Response>(
 ) -> Result<(T, Vec<Resp>)> {
   retry_send(command, command_tx, response_rx, &RetryConfig::default())
 }
+
+/// Switches the sensor to query reporting while `f` runs its command
+/// transactions, then restores the previous reporting mode, even if `f` fails.
+///
+/// Actively reported readings tend to collide with command acks on the wire
+/// (see the notes in `PacketReader`), so a long series of commands is far more
+/// reliable with reporting paused. Readings already queued when reporting is
+/// paused are discarded.
+pub fn with_reporting_paused<T, F>(
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  config: &RetryConfig,
+  f: F
+) -> Result<T>
+where
+  F: FnOnce(&Sender<Cmd>, &Receiver<Resp>) -> Result<T>
+{
+  let (current, _) = retry_send(SetReportingMode {
+    query: true,
+    mode: ReportingMode::Query
+  }, command_tx, response_rx, config)?;
+
+  if current.mode == ReportingMode::Query {
+    return f(command_tx, response_rx);
+  }
+
+  retry_send(SetReportingMode {
+    query: false,
+    mode: ReportingMode::Query
+  }, command_tx, response_rx, config)?;
+  drain_responses(response_rx);
+
+  let result = f(command_tx, response_rx);
+
+  let restored = retry_send(SetReportingMode {
+    query: false,
+    mode: current.mode
+  }, command_tx, response_rx, config);
+
+  match (result, restored) {
+    (Ok(value), Ok(_)) => Ok(value),
+    (Err(e), Ok(_)) => Err(e),
+    (Ok(_), Err(e)) => Err(e),
+    (Err(e), Err(restore_error)) => {
+      warn!("could not restore reporting mode: {}", restore_error);
+      Err(e)
+    }
+  }
+}
+
+/// Sets the sensor's device id, verifying that the id echoed in its reply
+/// matches. Returns `Error::DeviceIdMismatch` if it doesn't.
+///
+/// Like `retry_send()`, also returns all other responses received.
+pub fn set_device_id(
+  id: DeviceId,
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  config: &RetryConfig
+) -> Result<(SetDeviceIdResponse, Vec<Resp>)> {
+  let (response, other) = retry_send(SetDeviceId { id }, command_tx, response_rx, config)?;
+
+  if response.device != id {
+    return Err(Error::DeviceIdMismatch {
+      requested: id,
+      reported: response.device
+    });
+  }
+
+  Ok((response, other))
+}
+
+/// A snapshot of the sensor's identity and configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorInfo {
+  pub device_id: DeviceId,
+  pub firmware_date: FirmwareDate,
+  pub work_mode: WorkMode,
+  pub reporting_mode: ReportingMode,
+  pub working_period: WorkingPeriod
+}
+
+/// Queries the sensor's firmware version and current configuration, one
+/// command at a time, without changing anything.
+///
+/// Returns `Error::InconsistentDevice` if any reply comes from a different
+/// device than the first, since the snapshot would mix up two sensors.
+pub fn fetch_info(
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  config: &RetryConfig
+) -> Result<SensorInfo> {
+  let (firmware, _) = retry_send(GetFirmwareVersion, command_tx, response_rx, config)?;
+
+  let verify = |command: &str, reported: DeviceId| {
+    if reported == firmware.device {
+      Ok(())
+    } else {
+      Err(Error::InconsistentDevice {
+        command: command.to_string(),
+        expected: firmware.device,
+        reported
+      })
+    }
+  };
+
+  let (reporting, _) = retry_send(SetReportingMode {
+    query: true,
+    mode: ReportingMode::Active
+  }, command_tx, response_rx, config)?;
+  verify("SetReportingMode", reporting.device)?;
+
+  let (working, _) = retry_send(SetWorkingPeriod {
+    query: true,
+    working_period: WorkingPeriod::Continuous
+  }, command_tx, response_rx, config)?;
+  verify("SetWorkingPeriod", working.device)?;
+
+  let (sleeping, _) = retry_send(SetSleepWork {
+    query: true,
+    mode: WorkMode::Work
+  }, command_tx, response_rx, config)?;
+  verify("SetSleepWork", sleeping.device)?;
+
+  Ok(SensorInfo {
+    device_id: firmware.device,
+    firmware_date: firmware.date(),
+    work_mode: sleeping.mode,
+    reporting_mode: reporting.mode,
+    working_period: working.working_period
+  })
+}
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::clock::Timestamp;
+use crate::error::Error;
+use crate::Frame;
+
+/// The number of garbage bytes kept as a sample for each report
+pub const GARBAGE_SAMPLE_LEN: usize = 16;
+
+/// A packet that failed to parse, kept as evidence for bug reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFrame {
+  pub time: Timestamp,
+  pub frame: Frame,
+
+  /// why it failed to parse
+  pub error: String
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+  packets: AtomicUsize,
+  invalid_packets: AtomicUsize,
+  garbage_bytes: AtomicUsize,
+  commands: AtomicUsize,
+  echoes: AtomicUsize,
+  duplicate_readings: AtomicUsize,
+  last_packet: Mutex<Option<Timestamp>>,
+
+  /// the most recent invalid frames, up to `sample_len`
+  invalid_frames: Mutex<VecDeque<InvalidFrame>>,
+  sample_len: usize
+}
+
+/// Counters for the byte stream read from a sensor. Clones share the same
+/// counters, so keep a clone of the one passed in `SensorOptions` to read them.
+#[derive(Debug, Clone, Default)]
+pub struct LinkStats {
+  counters: Arc<Counters>
+}
+
+impl LinkStats {
+  pub fn new() -> Self {
+    LinkStats::default()
+  }
+
+  /// Creates stats that also keep the last `count` invalid frames; see
+  /// `invalid_frames()`
+  pub fn with_invalid_frames(count: usize) -> Self {
+    LinkStats {
+      counters: Arc::new(Counters {
+        invalid_frames: Mutex::new(VecDeque::with_capacity(count)),
+        sample_len: count,
+        ..Counters::default()
+      })
+    }
+  }
+
+  /// Complete packets received, valid or not
+  pub fn packets(&self) -> usize {
+    self.counters.packets.load(Ordering::Relaxed)
+  }
+
+  /// Packets that failed to parse, e.g. due to an invalid checksum
+  pub fn invalid_packets(&self) -> usize {
+    self.counters.invalid_packets.load(Ordering::Relaxed)
+  }
+
+  /// Commands written to the sensor, including retries
+  pub fn commands(&self) -> usize {
+    self.counters.commands.load(Ordering::Relaxed)
+  }
+
+  /// Commands confirmed sent by their local echo; only counted with
+  /// `SensorOptions::local_echo`
+  pub fn echoes(&self) -> usize {
+    self.counters.echoes.load(Ordering::Relaxed)
+  }
+
+  /// Readings dropped as repeats of the previous one; only counted with
+  /// `SensorOptions::reading_dedup_window`
+  pub fn duplicate_readings(&self) -> usize {
+    self.counters.duplicate_readings.load(Ordering::Relaxed)
+  }
+
+  /// When the last complete packet was received, valid or not
+  pub fn last_packet(&self) -> Option<Timestamp> {
+    *self.counters.last_packet.lock().unwrap()
+  }
+
+  /// Bytes received outside of any packet; a steady stream of these usually
+  /// means bad wiring or a baud rate mismatch
+  pub fn garbage_bytes(&self) -> usize {
+    self.counters.garbage_bytes.load(Ordering::Relaxed)
+  }
+
+  /// The most recent invalid frames, oldest first; empty unless created with
+  /// `with_invalid_frames()`
+  pub fn invalid_frames(&self) -> Vec<InvalidFrame> {
+    self.counters.invalid_frames.lock().unwrap().iter().cloned().collect()
+  }
+
+  pub(crate) fn add_invalid_frame(&self, frame: &Frame, error: &Error) {
+    if self.counters.sample_len == 0 {
+      return;
+    }
+
+    let mut frames = self.counters.invalid_frames.lock().unwrap();
+    if frames.len() >= self.counters.sample_len {
+      frames.pop_front();
+    }
+
+    frames.push_back(InvalidFrame {
+      time: Timestamp::now(),
+      frame: *frame,
+      error: error.to_string()
+    });
+  }
+
+  pub(crate) fn add_command(&self) {
+    self.counters.commands.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn add_echo(&self) {
+    self.counters.echoes.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn add_duplicate_reading(&self) {
+    self.counters.duplicate_readings.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn add_packet(&self, valid: bool) {
+    self.counters.packets.fetch_add(1, Ordering::Relaxed);
+    *self.counters.last_packet.lock().unwrap() = Some(Timestamp::now());
+    if !valid {
+      self.counters.invalid_packets.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  pub(crate) fn add_garbage(&self) {
+    self.counters.garbage_bytes.fetch_add(1, Ordering::Relaxed);
+  }
+}
+
+/// Batches garbage bytes into reports sent at most once per interval.
+///
+/// Bytes are held until their run ends (i.e. a packet starts), or until the run
+/// has lasted a full interval, so one burst yields one report.
+#[derive(Debug)]
+pub struct GarbageReporter {
+  interval: Duration,
+
+  /// the number of bytes pending, and the first few of them
+  count: usize,
+  sample: Vec<u8>,
+
+  /// when the first pending byte was received
+  started: Option<Instant>,
+
+  last_report: Option<Instant>
+}
+
+impl GarbageReporter {
+  pub fn new(interval: Duration) -> Self {
+    GarbageReporter {
+      interval,
+      count: 0,
+      sample: Vec::with_capacity(GARBAGE_SAMPLE_LEN),
+      started: None,
+      last_report: None
+    }
+  }
+
+  pub fn push(&mut self, byte: u8) {
+    self.count += 1;
+    if self.sample.len() < GARBAGE_SAMPLE_LEN {
+      self.sample.push(byte);
+    }
+
+    self.started.get_or_insert_with(Instant::now);
+  }
+
+  /// Returns the pending count and sample if a report is due. `in_run` should
+  /// be true if the latest byte was garbage.
+  pub fn poll(&mut self, in_run: bool) -> Option<(usize, Vec<u8>)> {
+    let started = self.started?;
+    let now = Instant::now();
+
+    let throttled = self.last_report
+      .map(|last| now.duration_since(last) < self.interval)
+      .unwrap_or(false);
+    if throttled || (in_run && now.duration_since(started) < self.interval) {
+      return None;
+    }
+
+    self.started = None;
+    self.last_report = Some(now);
+
+    let sample = std::mem::replace(&mut self.sample, Vec::with_capacity(GARBAGE_SAMPLE_LEN));
+    Some((std::mem::take(&mut self.count), sample))
+  }
+}
@@ -0,0 +1,55 @@
+use std::ffi::OsStr;
+
+use crate::error::*;
+
+/// An advisory lock on a serial device, held until dropped.
+///
+/// Two processes reading the same sensor each see only part of its output, so
+/// both get a stream of corrupt frames; locking makes the second fail loudly
+/// instead. On Unix this is an exclusive `flock()` on the device node, which
+/// other tools (e.g. `flock(1)`, minicom) respect; elsewhere it's a no-op.
+#[derive(Debug)]
+pub struct DeviceLock {
+  #[cfg(unix)]
+  _file: std::fs::File
+}
+
+impl DeviceLock {
+  /// Locks the device at the given path, returning `Error::DeviceBusy` if
+  /// another process already holds the lock
+  #[cfg(unix)]
+  pub fn acquire<P: AsRef<OsStr>>(device: P) -> Result<DeviceLock> {
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    use crate::port::open_error;
+
+    let device = device.as_ref();
+
+    // nonblocking, so opening a modem-control tty doesn't wait for carrier
+    let file = OpenOptions::new()
+      .read(true)
+      .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+      .open(device)
+      .map_err(|e| open_error(device, e.into()))?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+      let e = io::Error::last_os_error();
+      return Err(match e.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Error::DeviceBusy(device.to_string_lossy().into_owned()),
+        _ => Error::SerialPortError(e.into())
+      });
+    }
+
+    debug!("locked device {:?}", device);
+
+    Ok(DeviceLock { _file: file })
+  }
+
+  #[cfg(not(unix))]
+  pub fn acquire<P: AsRef<OsStr>>(_device: P) -> Result<DeviceLock> {
+    Ok(DeviceLock {})
+  }
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::Record;
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::{Map, Number};
+
+use crate::error::*;
+
+/// How the binaries format log lines
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LogFormat {
+  /// env_logger's usual human-readable lines
+  Text,
+
+  /// One JSON object per line, with structured fields (e.g. `device`,
+  /// `frame`, `error_kind`) as top-level keys
+  Json
+}
+
+impl FromStr for LogFormat {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "text" => Ok(LogFormat::Text),
+      "json" => Ok(LogFormat::Json),
+      s => Err(Error::InvalidLogFormat(format!("'{}', expected text or json", s)))
+    }
+  }
+}
+
+/// Collects a record's key-values as JSON values
+struct Fields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs, 'a> VisitSource<'kvs> for Fields<'a> {
+  fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> std::result::Result<(), kv::Error> {
+    let json = if let Some(b) = value.to_bool() {
+      b.into()
+    } else if let Some(n) = value.to_i64() {
+      n.into()
+    } else if let Some(n) = value.to_u64() {
+      n.into()
+    } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+      n.into()
+    } else {
+      value.to_string().into()
+    };
+
+    self.0.insert(key.as_str().to_string(), json);
+    Ok(())
+  }
+}
+
+/// Formats a log record as a single line of JSON with `ts` (seconds since the
+/// epoch), `level`, `target`, `message`, and any structured fields
+pub fn format_json(record: &Record) -> String {
+  let mut object = Map::new();
+
+  let ts = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs_f64())
+    .unwrap_or_default();
+
+  if let Some(ts) = Number::from_f64(ts) {
+    object.insert("ts".into(), ts.into());
+  }
+
+  object.insert("level".into(), record.level().as_str().to_lowercase().into());
+  object.insert("target".into(), record.target().into());
+  object.insert("message".into(), record.args().to_string().into());
+
+  record.key_values().visit(&mut Fields(&mut object)).ok();
+
+  serde_json::Value::Object(object).to_string()
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command::*;
+use crate::error::*;
+use crate::response::*;
+use crate::{retry_send, RetryConfig};
+
+/// Summary statistics of a single measurement over a burst of readings
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SummaryStats {
+  pub mean: f32,
+  pub median: f32,
+
+  /// Population standard deviation
+  pub stddev: f32,
+
+  pub min: f32,
+  pub max: f32
+}
+
+impl SummaryStats {
+  /// Summarizes the given values, or returns None if there are none
+  pub fn from_values(values: &[f32]) -> Option<SummaryStats> {
+    if values.is_empty() {
+      return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let n = sorted.len();
+    let median = if n % 2 == 1 {
+      sorted[n / 2]
+    } else {
+      (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    };
+
+    let mean = sorted.iter().map(|v| *v as f64).sum::<f64>() / n as f64;
+    let variance = sorted.iter()
+      .map(|v| (*v as f64 - mean).powi(2))
+      .sum::<f64>() / n as f64;
+
+    Some(SummaryStats {
+      mean: mean as f32,
+      median,
+      stddev: variance.sqrt() as f32,
+      min: sorted[0],
+      max: sorted[n - 1]
+    })
+  }
+}
+
+/// A cumulative histogram of readings, as exported to Prometheus: each bucket
+/// counts readings less than or equal to its upper bound
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+  bounds: Vec<f32>,
+
+  /// non-cumulative counts per bound, plus one for readings above them all
+  counts: Vec<u64>,
+
+  sum: f64,
+  count: u64
+}
+
+impl Histogram {
+  /// Creates an empty histogram with the given bucket upper bounds, which are
+  /// sorted; duplicates and NaN are dropped
+  pub fn new(bounds: &[f32]) -> Self {
+    let mut bounds: Vec<f32> = bounds.iter().copied().filter(|b| !b.is_nan()).collect();
+    bounds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    bounds.dedup();
+
+    Histogram {
+      counts: vec![0; bounds.len() + 1],
+      bounds,
+      sum: 0.0,
+      count: 0
+    }
+  }
+
+  pub fn observe(&mut self, value: f32) {
+    let bucket = self.bounds.iter()
+      .position(|b| value <= *b)
+      .unwrap_or(self.bounds.len());
+
+    self.counts[bucket] += 1;
+    self.sum += value as f64;
+    self.count += 1;
+  }
+
+  /// Each bucket's upper bound and cumulative count, ending with
+  /// `f32::INFINITY` for the total
+  pub fn buckets(&self) -> Vec<(f32, u64)> {
+    let bounds = self.bounds.iter().copied().chain(std::iter::once(f32::INFINITY));
+
+    bounds
+      .zip(self.counts.iter().scan(0, |total, n| {
+        *total += n;
+        Some(*total)
+      }))
+      .collect()
+  }
+
+  /// The sum of all readings observed
+  pub fn sum(&self) -> f64 {
+    self.sum
+  }
+
+  /// The number of readings observed
+  pub fn count(&self) -> u64 {
+    self.count
+  }
+}
+
+/// The highest reading over a rolling window, e.g. the last hour, so a short
+/// spike between scrapes of a long working period isn't missed. Readings drop
+/// out once they're older than the window, so the peak decays back down to
+/// whatever's highest among the readings since.
+#[derive(Debug, Clone)]
+pub struct PeakHold {
+  window: Duration,
+
+  /// readings that could still become the peak as older ones expire: each is
+  /// newer and lower than the one before, so the first is the peak
+  candidates: VecDeque<(Instant, f32)>
+}
+
+impl PeakHold {
+  pub fn new(window: Duration) -> Self {
+    PeakHold {
+      window,
+      candidates: VecDeque::new()
+    }
+  }
+
+  pub fn observe(&mut self, value: f32) {
+    self.observe_at(value, Instant::now());
+  }
+
+  /// Adds a reading taken at `time`, which shouldn't be before that of the
+  /// previous reading
+  pub fn observe_at(&mut self, value: f32, time: Instant) {
+    if value.is_nan() {
+      return;
+    }
+
+    while self.candidates.back().map(|(_, v)| *v <= value).unwrap_or(false) {
+      self.candidates.pop_back();
+    }
+
+    self.candidates.push_back((time, value));
+    self.expire(time);
+  }
+
+  /// The highest reading within the window, or None if there's been none
+  pub fn peak(&mut self) -> Option<f32> {
+    self.peak_at(Instant::now())
+  }
+
+  /// The highest reading within the window ending at `now`
+  pub fn peak_at(&mut self, now: Instant) -> Option<f32> {
+    self.expire(now);
+    self.candidates.front().map(|(_, value)| *value)
+  }
+
+  fn expire(&mut self, now: Instant) {
+    while let Some((time, _)) = self.candidates.front() {
+      if now.saturating_duration_since(*time) < self.window {
+        break;
+      }
+
+      self.candidates.pop_front();
+    }
+  }
+}
+
+/// The result of `measure_n()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementSummary {
+  /// The readings summarized, in the order received
+  pub readings: Vec<QueryResponse>,
+
+  /// The number of samples requested; more than `readings.len()` if some
+  /// queries went unanswered
+  pub requested: usize,
+
+  pub pm25: SummaryStats,
+  pub pm10: SummaryStats
+}
+
+/// Queries the sensor `samples` times, `spacing` apart, and summarizes the
+/// readings, giving a more stable value than any single noisy sample. The
+/// sensor should already be awake and warmed up.
+///
+/// Unanswered queries are skipped; returns `Error::RetriesExceeded` only if
+/// none were answered (or `samples` is 0).
+pub fn measure_n(
+  command_tx: &Sender<Cmd>,
+  response_rx: &Receiver<Resp>,
+  samples: usize,
+  spacing: Duration,
+  retry: &RetryConfig
+) -> Result<MeasurementSummary> {
+  let mut readings = Vec::with_capacity(samples);
+  let mut last_error = None;
+
+  for i in 0..samples {
+    if i > 0 {
+      thread::sleep(spacing);
+    }
+
+    match retry_send(Query, command_tx, response_rx, retry) {
+      Ok((query, _)) => readings.push(query),
+      Err(e @ Error::RetriesExceeded { .. }) => {
+        warn!("sample #{} failed: {}", i + 1, e);
+        last_error = Some(e);
+      },
+      Err(e) => return Err(e)
+    }
+  }
+
+  let pm25: Vec<f32> = readings.iter().map(|r| r.pm25).collect();
+  let pm10: Vec<f32> = readings.iter().map(|r| r.pm10).collect();
+
+  match (SummaryStats::from_values(&pm25), SummaryStats::from_values(&pm10)) {
+    (Some(pm25), Some(pm10)) => Ok(MeasurementSummary {
+      readings,
+      requested: samples,
+      pm25,
+      pm10
+    }),
+    _ => Err(last_error.unwrap_or_else(|| Error::RetriesExceeded {
+      command: format!("{:?}", Query),
+      attempts: 0
+    }))
+  }
+}
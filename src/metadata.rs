@@ -0,0 +1,31 @@
+/// A human-readable name and location for a sensor, included in output so the
+/// data describes itself without relabeling downstream
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SensorMetadata {
+  /// e.g. `kitchen`
+  pub name: Option<String>,
+
+  /// free text, e.g. `north wall, 2m up`
+  pub location: Option<String>
+}
+
+impl SensorMetadata {
+  /// The name of each field, in the order `iter()` returns them
+  pub const FIELDS: [&'static str; 2] = ["name", "location"];
+
+  pub fn is_empty(&self) -> bool {
+    self.name.is_none() && self.location.is_none()
+  }
+
+  /// Each field's name and value, if set
+  pub fn iter(&self) -> impl Iterator<Item = (&'static str, Option<&str>)> {
+    let values = [self.name.as_deref(), self.location.as_deref()];
+
+    SensorMetadata::FIELDS.iter().copied().zip(values.to_vec())
+  }
+
+  /// The name and value of each field that's set
+  pub fn set(&self) -> impl Iterator<Item = (&'static str, &str)> {
+    self.iter().filter_map(|(field, value)| value.map(|value| (field, value)))
+  }
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::command::COMMAND_LEN;
+use crate::transport::Transport;
+use crate::response::Resp;
+use crate::util::build_frame;
+use crate::Frame;
+
+#[derive(Debug, Default)]
+struct MockState {
+  /// replies to send, one per command received; commands past the end go
+  /// unanswered
+  replies: VecDeque<Vec<u8>>,
+
+  /// bytes ready to be read
+  pending: VecDeque<u8>,
+
+  /// bytes of the command currently being written
+  partial: Vec<u8>,
+
+  commands: Vec<[u8; COMMAND_LEN]>,
+
+  timeout: Option<Duration>,
+  closed: bool
+}
+
+/// An in-memory `Transport` for unit testing code built on this crate without
+/// hardware: script the sensor's replies, open it with
+/// `open_sensor_transport()` (or `Supervisor::spawn_transport()`), and inspect
+/// the commands sent. Clones share the same state.
+///
+/// Each complete command written is answered with the next scripted reply, if
+/// any; `report()` sends bytes unprompted, e.g. actively reported readings.
+#[derive(Debug, Clone, Default)]
+pub struct MockSensorTransport {
+  state: Arc<(Mutex<MockState>, Condvar)>
+}
+
+impl MockSensorTransport {
+  pub fn new() -> Self {
+    MockSensorTransport::default()
+  }
+
+  /// Builds a reply frame from raw data bytes; see `build_frame()`
+  pub fn frame(id: u8, data: [u8; 6]) -> Frame {
+    build_frame(id, data)
+  }
+
+  /// Queues raw bytes to be sent in reply to the next unanswered command. An
+  /// empty reply leaves that command unanswered.
+  pub fn reply(&self, bytes: &[u8]) -> &Self {
+    self.with_state(|state| state.replies.push_back(bytes.to_vec()));
+    self
+  }
+
+  /// Queues a response to be sent in reply to the next unanswered command
+  pub fn respond(&self, resp: impl Into<Resp>) -> &Self {
+    self.reply(&resp.into().to_frame())
+  }
+
+  /// Makes raw bytes available to read immediately
+  pub fn report(&self, bytes: &[u8]) -> &Self {
+    self.with_state(|state| state.pending.extend(bytes));
+    self
+  }
+
+  /// Every complete command written so far, in order; compare against e.g.
+  /// `Query.to_cmd().bytes()`
+  pub fn commands(&self) -> Vec<[u8; COMMAND_LEN]> {
+    self.with_state(|state| state.commands.clone())
+  }
+
+  /// The number of scripted replies not yet sent
+  pub fn remaining_replies(&self) -> usize {
+    self.with_state(|state| state.replies.len())
+  }
+
+  /// Simulates the sensor being unplugged: once pending bytes are read, reads
+  /// return end of file and writes fail
+  pub fn close(&self) {
+    self.with_state(|state| state.closed = true);
+  }
+
+  fn with_state<T>(&self, f: impl FnOnce(&mut MockState) -> T) -> T {
+    let (state, condvar) = &*self.state;
+    let result = f(&mut state.lock().unwrap());
+    condvar.notify_all();
+
+    result
+  }
+}
+
+impl Read for MockSensorTransport {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let (state, condvar) = &*self.state;
+    let mut state = state.lock().unwrap();
+    let deadline = state.timeout.map(|t| Instant::now() + t);
+
+    while state.pending.is_empty() {
+      if state.closed {
+        return Ok(0);
+      }
+
+      state = match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "mock read timed out"));
+          }
+
+          condvar.wait_timeout(state, deadline - now).unwrap().0
+        },
+        None => condvar.wait(state).unwrap()
+      };
+    }
+
+    let n = buf.len().min(state.pending.len());
+    for (b, byte) in buf.iter_mut().zip(state.pending.drain(..n)) {
+      *b = byte;
+    }
+
+    Ok(n)
+  }
+}
+
+impl Write for MockSensorTransport {
+  fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+    self.with_state(|state| {
+      if state.closed {
+        return Err(io::Error::new(io::ErrorKind::BrokenPipe, "mock sensor closed"));
+      }
+
+      state.partial.extend_from_slice(bytes);
+      while state.partial.len() >= COMMAND_LEN {
+        let mut command = [0u8; COMMAND_LEN];
+        command.copy_from_slice(&state.partial[..COMMAND_LEN]);
+        state.partial.drain(..COMMAND_LEN);
+        state.commands.push(command);
+
+        if let Some(reply) = state.replies.pop_front() {
+          state.pending.extend(reply);
+        }
+      }
+
+      Ok(bytes.len())
+    })
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl Transport for MockSensorTransport {
+  fn try_clone(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    self.with_state(|state| state.timeout = Some(timeout));
+    Ok(())
+  }
+
+  fn clear_input(&self) -> io::Result<()> {
+    self.with_state(|state| state.pending.clear());
+    Ok(())
+  }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use crate::clock::Timestamp;
+use crate::error::*;
+use crate::response::QueryResponse;
+
+/// Register value for a measurement without a reading
+pub const MODBUS_NO_READING: u16 = 0xFFFF;
+
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+
+/// The most registers one request may read
+const MAX_REGISTERS: u16 = 125;
+
+/// Modbus exception codes
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+const GATEWAY_PATH_UNAVAILABLE: u8 = 0x0A;
+
+/// A value that can be mapped to a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusValue {
+  /// PM2.5 in tenths of µg/m³, as the sensor itself reports it
+  Pm25,
+
+  /// PM10 in tenths of µg/m³
+  Pm10,
+
+  Aqi,
+
+  /// 1 if the sensor is up, otherwise 0
+  Up,
+
+  /// seconds since the latest reading was received
+  Age,
+
+  /// non-fatal error count, e.g. invalid packets
+  Errors,
+
+  /// fatal error count, i.e. the sensor had to be reopened
+  FatalErrors,
+
+  /// the sensor's device id
+  Device
+}
+
+impl ModbusValue {
+  const ALL: &'static [ModbusValue] = &[
+    ModbusValue::Pm25, ModbusValue::Pm10, ModbusValue::Aqi, ModbusValue::Up, ModbusValue::Age,
+    ModbusValue::Errors, ModbusValue::FatalErrors, ModbusValue::Device
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      ModbusValue::Pm25 => "pm25",
+      ModbusValue::Pm10 => "pm10",
+      ModbusValue::Aqi => "aqi",
+      ModbusValue::Up => "up",
+      ModbusValue::Age => "age",
+      ModbusValue::Errors => "errors",
+      ModbusValue::FatalErrors => "fatal_errors",
+      ModbusValue::Device => "device"
+    }
+  }
+
+  /// The register value, saturating at 0xFFFE; values that depend on a
+  /// reading are `MODBUS_NO_READING` without one
+  pub fn read(&self, state: &ModbusState) -> u16 {
+    let saturate = |value: u64| value.min(MODBUS_NO_READING as u64 - 1) as u16;
+    let reading = |f: &dyn Fn(&Timestamp, &QueryResponse) -> u64| match &state.latest {
+      Some((time, r)) => saturate(f(time, r)),
+      None => MODBUS_NO_READING
+    };
+
+    match self {
+      ModbusValue::Pm25 => reading(&|_, r| (r.pm25 * 10.0).round() as u64),
+      ModbusValue::Pm10 => reading(&|_, r| (r.pm10 * 10.0).round() as u64),
+      ModbusValue::Aqi => reading(&|_, r| r.aqi() as u64),
+      ModbusValue::Age => reading(&|time, _| time.elapsed().as_secs()),
+      ModbusValue::Device => reading(&|_, r| r.device.0 as u64),
+      ModbusValue::Up => state.up as u16,
+      ModbusValue::Errors => saturate(state.errors as u64),
+      ModbusValue::FatalErrors => saturate(state.fatal_errors as u64)
+    }
+  }
+}
+
+impl FromStr for ModbusValue {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    ModbusValue::ALL.iter()
+      .find(|value| value.name() == s)
+      .copied()
+      .ok_or_else(|| Error::InvalidModbusRegisterMap(format!("unknown value '{}'", s)))
+  }
+}
+
+/// What a sensor's registers are read from
+#[derive(Debug, Clone, Default)]
+pub struct ModbusState {
+  pub latest: Option<(Timestamp, QueryResponse)>,
+  pub up: bool,
+  pub errors: usize,
+  pub fatal_errors: usize
+}
+
+/// Which value each register address holds, parsed from e.g.
+/// `0=pm25,1=pm10,2=aqi`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModbusRegisterMap {
+  registers: Vec<(u16, ModbusValue)>
+}
+
+impl ModbusRegisterMap {
+  pub fn get(&self, address: u16) -> Option<ModbusValue> {
+    self.registers.iter().find(|(a, _)| *a == address).map(|(_, value)| *value)
+  }
+}
+
+impl Default for ModbusRegisterMap {
+  /// Every value, in order from address 0
+  fn default() -> Self {
+    ModbusRegisterMap {
+      registers: ModbusValue::ALL.iter().enumerate().map(|(i, v)| (i as u16, *v)).collect()
+    }
+  }
+}
+
+impl FromStr for ModbusRegisterMap {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let mut registers: Vec<(u16, ModbusValue)> = Vec::new();
+    for entry in s.split(',').map(str::trim) {
+      let (address, value) = entry.split_once('=').ok_or_else(|| {
+        Error::InvalidModbusRegisterMap(format!("expected ADDRESS=VALUE, got '{}'", entry))
+      })?;
+
+      let address: u16 = address.trim().parse().map_err(|_| {
+        Error::InvalidModbusRegisterMap(format!("invalid address '{}'", address))
+      })?;
+
+      if registers.iter().any(|(a, _)| *a == address) {
+        return Err(Error::InvalidModbusRegisterMap(format!("address {} mapped twice", address)));
+      }
+
+      registers.push((address, value.trim().parse()?));
+    }
+
+    Ok(ModbusRegisterMap { registers })
+  }
+}
+
+impl fmt::Display for ModbusRegisterMap {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, (address, value)) in self.registers.iter().enumerate() {
+      if i > 0 {
+        f.write_str(",")?;
+      }
+
+      write!(f, "{}={}", address, value.name())?;
+    }
+
+    Ok(())
+  }
+}
+
+/// A Modbus TCP request: the MBAP header's fields and the PDU
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModbusRequest {
+  pub transaction_id: u16,
+  pub unit_id: u8,
+
+  /// the function code and its data
+  pub pdu: Vec<u8>
+}
+
+impl ModbusRequest {
+  /// Reads the next request from a connection, or None once it's closed
+  pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<ModbusRequest>> {
+    let mut header = [0u8; 7];
+    match reader.read_exact(&mut header) {
+      Ok(()) => (),
+      Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+      Err(e) => return Err(e)
+    }
+
+    let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+    let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+
+    // the length counts the unit id, and a PDU is at most 253 bytes
+    if protocol_id != 0 || !(2..=254).contains(&len) {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid Modbus TCP header"));
+    }
+
+    let mut pdu = vec![0u8; len - 1];
+    reader.read_exact(&mut pdu)?;
+
+    Ok(Some(ModbusRequest {
+      transaction_id: u16::from_be_bytes([header[0], header[1]]),
+      unit_id: header[6],
+      pdu
+    }))
+  }
+
+  /// The response to this request, reading registers from `map` and `state`,
+  /// or an exception if the unit is unknown (`state` is None), the function
+  /// isn't a register read, or any register in the range isn't mapped
+  pub fn respond(&self, map: &ModbusRegisterMap, state: Option<&ModbusState>) -> Vec<u8> {
+    let function = self.pdu[0];
+    let pdu = match (function, state) {
+      (READ_HOLDING_REGISTERS, Some(state)) | (READ_INPUT_REGISTERS, Some(state)) => {
+        self.read_registers(map, state)
+      },
+      (READ_HOLDING_REGISTERS, None) | (READ_INPUT_REGISTERS, None) => {
+        Err(GATEWAY_PATH_UNAVAILABLE)
+      },
+      _ => Err(ILLEGAL_FUNCTION)
+    };
+
+    let pdu = pdu.unwrap_or_else(|exception| vec![function | 0x80, exception]);
+
+    let mut adu = Vec::with_capacity(7 + pdu.len());
+    adu.extend_from_slice(&self.transaction_id.to_be_bytes());
+    adu.extend_from_slice(&0u16.to_be_bytes());
+    adu.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+    adu.push(self.unit_id);
+    adu.extend_from_slice(&pdu);
+    adu
+  }
+
+  fn read_registers(
+    &self,
+    map: &ModbusRegisterMap,
+    state: &ModbusState
+  ) -> std::result::Result<Vec<u8>, u8> {
+    if self.pdu.len() != 5 {
+      return Err(ILLEGAL_DATA_VALUE);
+    }
+
+    let start = u16::from_be_bytes([self.pdu[1], self.pdu[2]]);
+    let count = u16::from_be_bytes([self.pdu[3], self.pdu[4]]);
+    if count == 0 || count > MAX_REGISTERS {
+      return Err(ILLEGAL_DATA_VALUE);
+    }
+
+    let mut pdu = vec![self.pdu[0], (count * 2) as u8];
+    for offset in 0..count {
+      let address = start.checked_add(offset).ok_or(ILLEGAL_DATA_ADDRESS)?;
+      let value = map.get(address).ok_or(ILLEGAL_DATA_ADDRESS)?;
+      pdu.extend_from_slice(&value.read(state).to_be_bytes());
+    }
+
+    Ok(pdu)
+  }
+}
@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sniff::{Direction, SniffEvent, Sniffed};
+
+/// The link type of captured frames: LINKTYPE_USER0 (DLT_USER0), which
+/// Wireshark can map to an SDS011 dissector in its DLT_USER table
+pub const PCAP_LINKTYPE: u16 = 147;
+
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_EPB_FLAGS: u16 = 2;
+
+/// Writes captured serial traffic as pcapng, one packet per frame, with the
+/// decoded frame as a comment and its direction in the packet flags.
+///
+/// Timestamps have microsecond resolution, the pcapng default. Output isn't
+/// flushed until `flush()` or drop, so wrap files in a `BufWriter` freely.
+pub struct PcapngWriter<W: Write> {
+  out: W
+}
+
+impl<W: Write> PcapngWriter<W> {
+  /// Writes the section header and the single interface description
+  pub fn new(out: W) -> io::Result<Self> {
+    let mut writer = PcapngWriter { out };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+
+    // section length: unknown
+    header.extend_from_slice(&(-1i64).to_le_bytes());
+    writer.write_block(SECTION_HEADER, &header)?;
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&PCAP_LINKTYPE.to_le_bytes());
+    interface.extend_from_slice(&0u16.to_le_bytes());
+
+    // snap length: unlimited
+    interface.extend_from_slice(&0u32.to_le_bytes());
+    writer.write_block(INTERFACE_DESCRIPTION, &interface)?;
+
+    Ok(writer)
+  }
+
+  /// Writes a single captured frame
+  pub fn write_packet(
+    &mut self,
+    time: SystemTime,
+    data: &[u8],
+    direction: Option<Direction>,
+    comment: Option<&str>
+  ) -> io::Result<()> {
+    let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+
+    let mut body = Vec::new();
+
+    // interface id
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(micros as u32).to_le_bytes());
+
+    // captured and original length
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    push_padded(&mut body, data);
+
+    if let Some(comment) = comment {
+      push_option(&mut body, OPT_COMMENT, comment.as_bytes());
+    }
+
+    if let Some(direction) = direction {
+      // the low bits of epb_flags: 1 for inbound, 2 for outbound
+      let flags: u32 = match direction {
+        Direction::FromSensor => 1,
+        Direction::ToSensor => 2
+      };
+
+      push_option(&mut body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+    }
+
+    if comment.is_some() || direction.is_some() {
+      push_option(&mut body, OPT_END, &[]);
+    }
+
+    self.write_block(ENHANCED_PACKET, &body)
+  }
+
+  /// Writes a frame seen by a `SnifferMode`, with its decoded form as the
+  /// comment
+  pub fn write_sniffed(&mut self, sniffed: &Sniffed) -> io::Result<()> {
+    let direction = sniffed.event.direction();
+
+    match &sniffed.event {
+      SniffEvent::Response { frame, response } => {
+        let comment = format!("{:x?}", response);
+        self.write_packet(sniffed.time, frame, direction, Some(&comment))
+      },
+      SniffEvent::Command { frame, command } => {
+        let comment = format!("{:x?}", command);
+        self.write_packet(sniffed.time, frame, direction, Some(&comment))
+      },
+      SniffEvent::Invalid { frame, error } => {
+        let comment = format!("invalid: {}", error);
+        self.write_packet(sniffed.time, frame, direction, Some(&comment))
+      },
+      SniffEvent::Garbage(byte) => {
+        self.write_packet(sniffed.time, &[*byte], direction, Some("garbage"))
+      }
+    }
+  }
+
+  pub fn flush(&mut self) -> io::Result<()> {
+    self.out.flush()
+  }
+
+  pub fn into_inner(self) -> W {
+    self.out
+  }
+
+  /// Writes a block around the given body, which must be padded to 32 bits
+  fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+    // type and both copies of the length
+    let len = (body.len() + 12) as u32;
+
+    self.out.write_all(&block_type.to_le_bytes())?;
+    self.out.write_all(&len.to_le_bytes())?;
+    self.out.write_all(body)?;
+    self.out.write_all(&len.to_le_bytes())
+  }
+}
+
+/// Appends bytes, zero padded to 32 bits
+fn push_padded(buf: &mut Vec<u8>, bytes: &[u8]) {
+  buf.extend_from_slice(bytes);
+  buf.resize(buf.len() + (4 - bytes.len() % 4) % 4, 0);
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+  buf.extend_from_slice(&code.to_le_bytes());
+  buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+  push_padded(buf, value);
+}
@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::*;
+
+/// The Win32 device namespace prefix, required to open COM ports above 9
+const DEVICE_NAMESPACE: &str = r"\\.\";
+
+/// Normalizes a Windows serial port name, e.g. from the command line, to the
+/// form serialport expects.
+///
+/// serialport adds the `\\.\` device namespace prefix itself, so a name that
+/// already has it (as commonly suggested for `COM10` and up) would get it
+/// twice; it's stripped here. Surrounding whitespace, a trailing colon (as in
+/// `COM3:`), and lowercase `com` are also accepted. Any other name is passed
+/// through unchanged.
+///
+/// Available on all platforms for testing; `open_port()` only applies it on
+/// Windows.
+pub fn normalize_windows_port_name(name: &str) -> Cow<'_, str> {
+  let trimmed = name.trim();
+  let bare = trimmed.strip_prefix(DEVICE_NAMESPACE).unwrap_or(trimmed);
+  let bare = bare.strip_suffix(':').unwrap_or(bare);
+
+  let is_com = bare.len() > 3
+    && bare[..3].eq_ignore_ascii_case("com")
+    && bare[3..].bytes().all(|b| b.is_ascii_digit());
+
+  if is_com {
+    Cow::Owned(format!("COM{}", &bare[3..]))
+  } else if trimmed.len() != name.len() {
+    Cow::Owned(trimmed.to_string())
+  } else {
+    Cow::Borrowed(name)
+  }
+}
+
+/// Normalizes a port name for the current platform; see
+/// `normalize_windows_port_name()`
+pub fn normalize_port_name(device: &OsStr) -> Cow<'_, OsStr> {
+  if !cfg!(windows) {
+    return Cow::Borrowed(device);
+  }
+
+  match device.to_str().map(normalize_windows_port_name) {
+    Some(Cow::Owned(name)) => Cow::Owned(name.into()),
+    _ => Cow::Borrowed(device)
+  }
+}
+
+/// Maps a failure to open a port to a clearer error where possible.
+///
+/// serialport reports both missing and in-use Windows ports as `NoDevice`
+/// without the Win32 error code, so it's recovered from the thread's last
+/// error.
+#[cfg(windows)]
+pub(crate) fn open_error(device: &OsStr, error: serialport::Error) -> Error {
+  const ERROR_FILE_NOT_FOUND: i32 = 2;
+  const ERROR_PATH_NOT_FOUND: i32 = 3;
+  const ERROR_ACCESS_DENIED: i32 = 5;
+
+  let name = device.to_string_lossy().into_owned();
+  match std::io::Error::last_os_error().raw_os_error() {
+    Some(ERROR_FILE_NOT_FOUND) | Some(ERROR_PATH_NOT_FOUND) => missing(name),
+
+    // COM ports can only be opened by one process at a time
+    Some(ERROR_ACCESS_DENIED) => Error::DeviceBusy(name),
+
+    _ => Error::SerialPortError(error)
+  }
+}
+
+#[cfg(not(any(windows, target_arch = "wasm32")))]
+pub(crate) fn open_error(device: &OsStr, error: serialport::Error) -> Error {
+  use serialport::ErrorKind;
+  use std::io;
+
+  let path = device.to_string_lossy().into_owned();
+  match error.kind() {
+    ErrorKind::Io(io::ErrorKind::PermissionDenied) => Error::DeviceAccess {
+      hint: access_hint(device),
+      path
+    },
+    ErrorKind::NoDevice | ErrorKind::Io(io::ErrorKind::NotFound) => missing(path),
+    _ => Error::SerialPortError(error)
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn missing(path: String) -> Error {
+  let ports = serialport::available_ports()
+    .map(|ports| ports.into_iter().map(|p| p.port_name).collect())
+    .unwrap_or_default();
+
+  Error::DeviceMissing { path, hint: DeviceHint::AvailablePorts(ports) }
+}
+
+/// Suggests joining the group that owns the device, if it's one a user could
+/// reasonably join
+#[cfg(unix)]
+fn access_hint(device: &OsStr) -> DeviceHint {
+  use std::ffi::CStr;
+  use std::os::unix::fs::MetadataExt;
+
+  let gid = match std::fs::metadata(device) {
+    Ok(metadata) if metadata.gid() != 0 => metadata.gid(),
+    _ => return DeviceHint::UdevRule
+  };
+
+  let mut group: libc::group = unsafe { std::mem::zeroed() };
+  let mut found = std::ptr::null_mut();
+  let mut buf = vec![0 as libc::c_char; 1024];
+  let result = unsafe {
+    libc::getgrgid_r(gid, &mut group, buf.as_mut_ptr(), buf.len(), &mut found)
+  };
+
+  if result != 0 || found.is_null() {
+    return DeviceHint::UdevRule;
+  }
+
+  let name = unsafe { CStr::from_ptr(group.gr_name) };
+  DeviceHint::JoinGroup(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(unix, target_arch = "wasm32")))]
+fn access_hint(_device: &OsStr) -> DeviceHint {
+  DeviceHint::UdevRule
+}
+
+/// A likely fix for a device that couldn't be opened, carried by
+/// `Error::DeviceAccess` and `Error::DeviceMissing`. Displays as a suggestion
+/// to show the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceHint {
+  /// the device belongs to this group, e.g. `dialout`, which the user likely
+  /// isn't in
+  JoinGroup(String),
+
+  /// the device belongs to root or its group is unknown; a udev rule can
+  /// grant access instead
+  UdevRule,
+
+  /// nothing is at the path; these serial ports were found instead
+  AvailablePorts(Vec<String>)
+}
+
+impl fmt::Display for DeviceHint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DeviceHint::JoinGroup(group) => write!(
+        f,
+        "add your user to the {} group (e.g. `sudo usermod -aG {} $USER`) and log in again",
+        group, group
+      ),
+      DeviceHint::UdevRule => write!(
+        f,
+        "grant access with a udev rule, e.g. for the usual CH340 adapter: \
+        SUBSYSTEM==\"tty\", ATTRS{{idVendor}}==\"1a86\", ATTRS{{idProduct}}==\"7523\", MODE=\"0666\""
+      ),
+      DeviceHint::AvailablePorts(ports) if ports.is_empty() => write!(
+        f,
+        "no serial ports were found; check the adapter is plugged in"
+      ),
+      DeviceHint::AvailablePorts(ports) => write!(
+        f,
+        "check the adapter is plugged in; serial ports found: {}",
+        ports.join(", ")
+      )
+    }
+  }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::response::QueryResponse;
+
+/// How long the sensor needs after waking before its readings settle; the
+/// datasheet recommends at least 30 seconds of fan time
+pub const WARMUP: Duration = Duration::from_secs(30);
+
+/// Reasons to distrust a reading, so every consumer can tell suspect data from
+/// good data the same way. A reading with no flags set is good.
+///
+/// Only `saturated` can be told from the reading itself (see `of()`); the rest
+/// are set by whatever knows when the sensor woke, how the reading was
+/// filtered, or how old it is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ReadingQuality {
+  /// taken within `WARMUP` of the sensor waking
+  pub warmup: bool,
+
+  /// a value is pinned at `SATURATION_LIMIT`
+  pub saturated: bool,
+
+  /// replaced or smoothed by an outlier filter
+  pub outlier_filtered: bool,
+
+  /// older than the sensor's working period suggests it should be
+  pub stale: bool
+}
+
+impl ReadingQuality {
+  /// The name of each flag, in the order `flags()` returns them
+  pub const FLAGS: [&'static str; 4] = ["warmup", "saturated", "outlier_filtered", "stale"];
+
+  /// The quality of a reading judging only by its values
+  pub fn of(reading: &QueryResponse) -> Self {
+    ReadingQuality {
+      saturated: reading.saturated(),
+      ..ReadingQuality::default()
+    }
+  }
+
+  pub fn is_good(&self) -> bool {
+    *self == ReadingQuality::default()
+  }
+
+  /// Each flag's name and whether it's set
+  pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+    let set = [self.warmup, self.saturated, self.outlier_filtered, self.stale];
+
+    ReadingQuality::FLAGS.iter().copied().zip(set.to_vec())
+  }
+
+  /// The names of the flags that are set
+  pub fn flags(&self) -> Vec<&'static str> {
+    self.iter().filter(|(_, set)| *set).map(|(name, _)| name).collect()
+  }
+}
+
+/// The flags that are set, separated by `|`, or `good` if none are
+impl fmt::Display for ReadingQuality {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_good() {
+      write!(f, "good")
+    } else {
+      write!(f, "{}", self.flags().join("|"))
+    }
+  }
+}
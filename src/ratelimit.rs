@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorKind};
+
+/// The default window over which repeated errors of one kind are collapsed
+pub const DEFAULT_ERROR_WINDOW: Duration = Duration::from_secs(10);
+
+/// Collapses bursts of identical non-fatal errors, e.g. from a bad cable.
+///
+/// The first error of each kind in a window is passed through; the rest are
+/// counted, and reported as a single `Error::Suppressed` once the window ends.
+#[derive(Debug)]
+pub struct ErrorLimiter {
+  window: Duration,
+
+  /// per error kind: when its window started, and how many were suppressed
+  windows: HashMap<ErrorKind, (Instant, usize)>
+}
+
+impl Default for ErrorLimiter {
+  fn default() -> Self {
+    ErrorLimiter::new(DEFAULT_ERROR_WINDOW)
+  }
+}
+
+impl ErrorLimiter {
+  pub fn new(window: Duration) -> Self {
+    ErrorLimiter {
+      window,
+      windows: HashMap::new()
+    }
+  }
+
+  /// Returns the error if it should be reported now, or None if it was
+  /// suppressed. Call `flush()` first so summaries are reported in order.
+  pub fn push(&mut self, error: Error) -> Option<Error> {
+    let now = Instant::now();
+
+    match self.windows.get_mut(&error.kind()) {
+      Some((started, suppressed)) if now.duration_since(*started) < self.window => {
+        *suppressed += 1;
+        None
+      },
+      _ => {
+        self.windows.insert(error.kind(), (now, 0));
+        Some(error)
+      }
+    }
+  }
+
+  /// Ends any expired windows, returning a summary for each that suppressed
+  /// errors
+  pub fn flush(&mut self) -> Vec<Error> {
+    let now = Instant::now();
+    let window = self.window;
+    let mut summaries = Vec::new();
+
+    self.windows.retain(|kind, (started, suppressed)| {
+      let elapsed = now.duration_since(*started);
+      if elapsed < window {
+        return true;
+      }
+
+      if *suppressed > 0 {
+        summaries.push(Error::Suppressed {
+          kind: *kind,
+          count: *suppressed,
+          seconds: elapsed.as_secs()
+        });
+      }
+
+      false
+    });
+
+    summaries
+  }
+}
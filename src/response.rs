@@ -1,7 +1,10 @@
+use std::fmt;
+
 use bytes::buf::Buf;
 
 use crate::error::*;
 use crate::util::*;
+use crate::Frame;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Resp {
@@ -20,8 +23,142 @@ impl Resp {
   pub fn try_into_response<T: Response>(self) -> Result<T> {
     T::unpack_resp(self)
   }
+
+  /// Encodes this response as the frame the sensor would send, e.g. to build
+  /// fixtures for `MockSensorTransport`. Readings are rounded to the nearest
+  /// 0.1 µg/m³, and reserved bytes are zeroed.
+  pub fn to_frame(&self) -> Frame {
+    let setting = |sub_id: u8, query: bool, value: u8, device: DeviceId| {
+      let [device_hi, device_lo] = device.to_be_bytes();
+      let query = if query { 0x00 } else { 0x01 };
+      build_frame(0xC5, [sub_id, query, value, 0x00, device_hi, device_lo])
+    };
+
+    match self {
+      Resp::SetReportingMode(r) => setting(0x02, r.query, r.mode.as_byte(), r.device),
+      Resp::Query(r) => {
+        let [pm25_lo, pm25_hi] = ((r.pm25 * 10.0).round() as u16).to_le_bytes();
+        let [pm10_lo, pm10_hi] = ((r.pm10 * 10.0).round() as u16).to_le_bytes();
+        let [device_hi, device_lo] = r.device.to_be_bytes();
+        build_frame(0xC0, [pm25_lo, pm25_hi, pm10_lo, pm10_hi, device_hi, device_lo])
+      },
+      Resp::SetDeviceId(r) => {
+        let [device_hi, device_lo] = r.device.to_be_bytes();
+        build_frame(0xC5, [0x05, 0x00, 0x00, 0x00, device_hi, device_lo])
+      },
+      Resp::SetSleepWork(r) => setting(0x06, r.query, r.mode.as_byte(), r.device),
+      Resp::SetWorkingPeriod(r) => {
+        setting(0x08, r.query, r.working_period.as_byte(), r.device)
+      },
+      Resp::GetFirmwareVersion(r) => {
+        let [device_hi, device_lo] = r.device.to_be_bytes();
+        build_frame(0xC5, [0x07, r.year, r.month, r.day, device_hi, device_lo])
+      }
+    }
+  }
+
+  /// The id of the device that sent this response
+  pub fn device(&self) -> DeviceId {
+    match self {
+      Resp::SetReportingMode(r) => r.device,
+      Resp::Query(r) => r.device,
+      Resp::SetDeviceId(r) => r.device,
+      Resp::SetSleepWork(r) => r.device,
+      Resp::SetWorkingPeriod(r) => r.device,
+      Resp::GetFirmwareVersion(r) => r.device
+    }
+  }
+
+  /// The kind of this response, for filtering subscriptions
+  pub fn kind(&self) -> ResponseKind {
+    match self {
+      Resp::Query(_) => ResponseKind::Measurement,
+      _ => ResponseKind::Ack
+    }
+  }
+}
+
+/// Broad categories of responses; see `ResponseKindSet`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseKind {
+  /// A reading, whether actively reported or in reply to a query
+  Measurement,
+
+  /// A reply to any other command
+  Ack
 }
 
+impl ResponseKind {
+  fn bit(self) -> u8 {
+    match self {
+      ResponseKind::Measurement => 0b01,
+      ResponseKind::Ack => 0b10
+    }
+  }
+}
+
+/// A set of `ResponseKind`s, e.g. `ResponseKindSet::MEASUREMENTS`. Sets can be
+/// combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResponseKindSet(u8);
+
+impl ResponseKindSet {
+  pub const NONE: ResponseKindSet = ResponseKindSet(0);
+  pub const MEASUREMENTS: ResponseKindSet = ResponseKindSet(0b01);
+  pub const ACKS: ResponseKindSet = ResponseKindSet(0b10);
+  pub const ALL: ResponseKindSet = ResponseKindSet(0b11);
+
+  pub fn contains(self, kind: ResponseKind) -> bool {
+    self.0 & kind.bit() != 0
+  }
+
+  /// True if the given response is of a kind in this set
+  pub fn matches(self, resp: &Resp) -> bool {
+    self.contains(resp.kind())
+  }
+}
+
+impl Default for ResponseKindSet {
+  fn default() -> Self {
+    ResponseKindSet::ALL
+  }
+}
+
+impl From<ResponseKind> for ResponseKindSet {
+  fn from(kind: ResponseKind) -> Self {
+    ResponseKindSet(kind.bit())
+  }
+}
+
+impl std::ops::BitOr for ResponseKindSet {
+  type Output = ResponseKindSet;
+
+  fn bitor(self, other: ResponseKindSet) -> ResponseKindSet {
+    ResponseKindSet(self.0 | other.0)
+  }
+}
+
+macro_rules! impl_from_response {
+  ($($variant:ident($response:ident)),*) => {
+    $(
+      impl From<$response> for Resp {
+        fn from(r: $response) -> Self {
+          Resp::$variant(r)
+        }
+      }
+    )*
+  };
+}
+
+impl_from_response!(
+  SetReportingMode(SetReportingModeResponse),
+  Query(QueryResponse),
+  SetDeviceId(SetDeviceIdResponse),
+  SetSleepWork(SetSleepWorkResponse),
+  SetWorkingPeriod(SetWorkingPeriodResponse),
+  GetFirmwareVersion(GetFirmwareVersionResponse)
+);
+
 pub(crate) trait ResponseParser {
   fn parse(buf: &[u8]) -> Resp;
 }
@@ -37,7 +174,7 @@ pub trait Response : Sized {
 pub struct SetReportingModeResponse {
   pub query: bool,
   pub mode: ReportingMode,
-  pub device: u16
+  pub device: DeviceId
 }
 
 impl ResponseParser for SetReportingModeResponse {
@@ -46,7 +183,7 @@ impl ResponseParser for SetReportingModeResponse {
     let query = buf.get_u8() == 0x00;
     let mode = ReportingMode::from_byte(buf.get_u8());
     buf.advance(1);
-    let device = buf.get_u16();
+    let device = DeviceId(buf.get_u16());
 
     Resp::SetReportingMode(SetReportingModeResponse {
       query,
@@ -62,7 +199,7 @@ impl Response for SetReportingModeResponse {
       Resp::SetReportingMode(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "SetReportingModeResponse".into()
+        target: "SetReportingModeResponse"
       })
     }
   }
@@ -70,14 +207,36 @@ impl Response for SetReportingModeResponse {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct QueryResponse {
-  // PM2.5 reading in micrograms per cubic meter
+  /// PM2.5 reading in micrograms per cubic meter
   pub pm25: f32,
 
-  // PM10 reading in micrograms per cubic meter
+  /// PM10 reading in micrograms per cubic meter
   pub pm10: f32,
 
-  // 2-byte device ID
-  pub device: u16
+  /// 2-byte device ID
+  pub device: DeviceId
+}
+
+/// The largest value the sensor reports, in micrograms per cubic meter. A
+/// reading at this value means the sensor is saturated, e.g. in heavy smoke,
+/// and the true concentration is higher.
+pub const SATURATION_LIMIT: f32 = 999.9;
+
+impl QueryResponse {
+  /// Whether the PM2.5 value is pinned at `SATURATION_LIMIT`
+  pub fn pm25_saturated(&self) -> bool {
+    self.pm25 >= SATURATION_LIMIT
+  }
+
+  /// Whether the PM10 value is pinned at `SATURATION_LIMIT`
+  pub fn pm10_saturated(&self) -> bool {
+    self.pm10 >= SATURATION_LIMIT
+  }
+
+  /// Whether either value is saturated
+  pub fn saturated(&self) -> bool {
+    self.pm25_saturated() || self.pm10_saturated()
+  }
 }
 
 impl ResponseParser for QueryResponse {
@@ -87,7 +246,7 @@ impl ResponseParser for QueryResponse {
     Resp::Query(QueryResponse {
       pm25: buf.get_u16_le() as f32 / 10f32,
       pm10: buf.get_u16_le() as f32 / 10f32,
-      device: buf.get_u16(),
+      device: DeviceId(buf.get_u16()),
     })
   }
 }
@@ -98,7 +257,7 @@ impl Response for QueryResponse {
       Resp::Query(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "QueryResponse".into()
+        target: "QueryResponse"
       })
     }
   }
@@ -106,16 +265,18 @@ impl Response for QueryResponse {
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct SetDeviceIdResponse {
-  // 2-byte device ID
-  device: u16
+  /// the new device id, as echoed by the sensor
+  pub device: DeviceId
 }
 
 impl ResponseParser for SetDeviceIdResponse {
   fn parse(mut buf: &[u8]) -> Resp {
-    buf.advance(6); // bytes 3-5 are reserved
+    // bytes 3-5 are reserved; some firmware revisions don't zero them (e.g. in
+    // replies to a broadcast set), so they're ignored entirely
+    buf.advance(6);
 
     Resp::SetDeviceId(SetDeviceIdResponse {
-      device: buf.get_u16()
+      device: DeviceId(buf.get_u16())
     })
   }
 }
@@ -126,7 +287,7 @@ impl Response for SetDeviceIdResponse {
       Resp::SetDeviceId(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "SetDeviceIdResponse".into()
+        target: "SetDeviceIdResponse"
       })
     }
   }
@@ -136,7 +297,7 @@ impl Response for SetDeviceIdResponse {
 pub struct SetSleepWorkResponse {
   pub query: bool,
   pub mode: WorkMode,
-  pub device: u16
+  pub device: DeviceId
 }
 
 impl ResponseParser for SetSleepWorkResponse {
@@ -145,7 +306,7 @@ impl ResponseParser for SetSleepWorkResponse {
     let query = buf.get_u8() == 0x00;
     let mode = WorkMode::from_byte(buf.get_u8());
     buf.advance(1);
-    let device = buf.get_u16();
+    let device = DeviceId(buf.get_u16());
 
     Resp::SetSleepWork(SetSleepWorkResponse {
       query,
@@ -161,7 +322,7 @@ impl Response for SetSleepWorkResponse {
       Resp::SetSleepWork(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "SetSleepWorkResponse".into()
+        target: "SetSleepWorkResponse"
       })
     }
   }
@@ -172,7 +333,7 @@ pub struct SetWorkingPeriodResponse {
   /// if true, queries the current state; if false, sets the working period
   pub query: bool,
   pub working_period: WorkingPeriod,
-  pub device: u16
+  pub device: DeviceId
 }
 
 impl ResponseParser for SetWorkingPeriodResponse {
@@ -181,7 +342,7 @@ impl ResponseParser for SetWorkingPeriodResponse {
     let query = buf.get_u8() == 0x00;
     let working_period = WorkingPeriod::from_byte(buf.get_u8());
     buf.advance(1);
-    let device = buf.get_u16();
+    let device = DeviceId(buf.get_u16());
 
     Resp::SetWorkingPeriod(SetWorkingPeriodResponse {
       query,
@@ -197,7 +358,7 @@ impl Response for SetWorkingPeriodResponse {
       Resp::SetWorkingPeriod(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "SetWorkingPeriodResponse".into()
+        target: "SetWorkingPeriodResponse"
       })
     }
   }
@@ -209,7 +370,32 @@ pub struct GetFirmwareVersionResponse {
   pub year: u8,
   pub month: u8,
   pub day: u8,
-  pub device: u16
+  pub device: DeviceId
+}
+
+impl GetFirmwareVersionResponse {
+  pub fn date(&self) -> FirmwareDate {
+    FirmwareDate {
+      year: 2000 + self.year as u16,
+      month: self.month,
+      day: self.day
+    }
+  }
+}
+
+/// The date of the sensor's firmware, its only version number. Displayed as
+/// e.g. `2018-11-16`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct FirmwareDate {
+  pub year: u16,
+  pub month: u8,
+  pub day: u8
+}
+
+impl fmt::Display for FirmwareDate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+  }
 }
 
 impl ResponseParser for GetFirmwareVersionResponse {
@@ -220,7 +406,7 @@ impl ResponseParser for GetFirmwareVersionResponse {
       year: buf.get_u8(),
       month: buf.get_u8(),
       day: buf.get_u8(),
-      device: buf.get_u16()
+      device: DeviceId(buf.get_u16())
     })
   }
 }
@@ -231,7 +417,7 @@ impl Response for GetFirmwareVersionResponse {
       Resp::GetFirmwareVersion(r) => Ok(r),
       resp => Err(Error::InvalidResponseConversion {
         resp,
-        target: "GetFirmwareVersionResponse".into()
+        target: "GetFirmwareVersionResponse"
       })
     }
   }
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::command::*;
+use crate::error::*;
+use crate::response::Resp;
+use crate::util::*;
+use crate::{retry_send, RetryConfig};
+
+/// A local time of day, to the minute
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+  pub hour: u8,
+  pub minute: u8
+}
+
+impl TimeOfDay {
+  pub fn new(hour: u8, minute: u8) -> Result<TimeOfDay> {
+    if hour > 23 || minute > 59 {
+      return Err(Error::InvalidSchedule(format!("invalid time {}:{:02}", hour, minute)));
+    }
+
+    Ok(TimeOfDay { hour, minute })
+  }
+
+  /// Minutes since midnight
+  pub fn minutes(&self) -> u32 {
+    self.hour as u32 * 60 + self.minute as u32
+  }
+
+  /// Minutes from this time until `other`, wrapping past midnight; 24 hours if
+  /// the two are equal
+  pub fn minutes_until(&self, other: TimeOfDay) -> u32 {
+    match (other.minutes() + 24 * 60 - self.minutes()) % (24 * 60) {
+      0 => 24 * 60,
+      n => n
+    }
+  }
+}
+
+impl fmt::Display for TimeOfDay {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:02}:{:02}", self.hour, self.minute)
+  }
+}
+
+impl FromStr for TimeOfDay {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || Error::InvalidSchedule(format!("invalid time '{}', expected HH:MM", s));
+
+    let mut parts = s.splitn(2, ':');
+    let hour = parts.next().and_then(|h| h.parse().ok()).ok_or_else(invalid)?;
+    let minute = parts.next().and_then(|m| m.parse().ok()).ok_or_else(invalid)?;
+
+    TimeOfDay::new(hour, minute)
+  }
+}
+
+/// Sensor settings that take effect at a time of day, written as
+/// `HH:MM=PERIOD[/MODE]`, e.g. `07:00=0` or `22:00=30/active`. The reporting
+/// mode defaults to active.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScheduleRule {
+  pub start: TimeOfDay,
+  pub working_period: WorkingPeriod,
+  pub reporting_mode: ReportingMode
+}
+
+impl ScheduleRule {
+  /// Configures the sensor with this rule's working period and reporting mode
+  pub fn apply(
+    &self,
+    command_tx: &Sender<Cmd>,
+    response_rx: &Receiver<Resp>,
+    retry: &RetryConfig
+  ) -> Result<()> {
+    retry_send(SetWorkingPeriod {
+      query: false,
+      working_period: self.working_period
+    }, command_tx, response_rx, retry)?;
+
+    retry_send(SetReportingMode {
+      query: false,
+      mode: self.reporting_mode
+    }, command_tx, response_rx, retry)?;
+
+    Ok(())
+  }
+}
+
+impl fmt::Display for ScheduleRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mode = match self.reporting_mode {
+      ReportingMode::Active => "active",
+      ReportingMode::Query => "query"
+    };
+
+    write!(f, "{}={}/{}", self.start, self.working_period.as_byte(), mode)
+  }
+}
+
+impl FromStr for ScheduleRule {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let mut parts = s.splitn(2, '=');
+    let start = parts.next().unwrap_or_default().trim().parse()?;
+    let settings = parts.next()
+      .ok_or_else(|| Error::InvalidSchedule(format!(
+        "invalid rule '{}', expected HH:MM=PERIOD[/MODE]", s
+      )))?;
+
+    let mut settings = settings.splitn(2, '/');
+    let working_period = settings.next().unwrap_or_default().trim().parse()?;
+    let reporting_mode = match settings.next() {
+      Some(mode) => mode.trim().parse()?,
+      None => ReportingMode::Active
+    };
+
+    Ok(ScheduleRule { start, working_period, reporting_mode })
+  }
+}
+
+/// A set of time-of-day rules, e.g. continuous readings during the day and a
+/// 30 minute working period at night. Each rule applies from its start time
+/// until the next rule's; the last rule of the day wraps past midnight.
+///
+/// Parsed from rules separated by commas or whitespace, e.g.
+/// `07:00=0,22:00=30`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+  /// sorted by start time, never empty
+  rules: Vec<ScheduleRule>
+}
+
+impl Schedule {
+  pub fn new(mut rules: Vec<ScheduleRule>) -> Result<Schedule> {
+    if rules.is_empty() {
+      return Err(Error::InvalidSchedule("schedule has no rules".into()));
+    }
+
+    rules.sort_by_key(|r| r.start);
+
+    if let Some(pair) = rules.windows(2).find(|pair| pair[0].start == pair[1].start) {
+      return Err(Error::InvalidSchedule(format!(
+        "multiple rules start at {}", pair[0].start
+      )));
+    }
+
+    Ok(Schedule { rules })
+  }
+
+  pub fn rules(&self) -> &[ScheduleRule] {
+    &self.rules
+  }
+
+  /// The rule in effect at the given time
+  pub fn rule_at(&self, time: TimeOfDay) -> &ScheduleRule {
+    self.rules.iter()
+      .rev()
+      .find(|r| r.start <= time)
+      .unwrap_or_else(|| self.rules.last().unwrap())
+  }
+
+  /// The next rule to take effect after the given time
+  pub fn next_rule(&self, time: TimeOfDay) -> &ScheduleRule {
+    self.rules.iter()
+      .find(|r| r.start > time)
+      .unwrap_or_else(|| self.rules.first().unwrap())
+  }
+}
+
+impl fmt::Display for Schedule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let rules: Vec<String> = self.rules.iter().map(|r| r.to_string()).collect();
+    write!(f, "{}", rules.join(","))
+  }
+}
+
+impl FromStr for Schedule {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let rules = s.split(|c: char| c == ',' || c.is_whitespace())
+      .filter(|r| !r.is_empty())
+      .map(str::parse)
+      .collect::<Result<Vec<ScheduleRule>>>()?;
+
+    Schedule::new(rules)
+  }
+}
@@ -0,0 +1,129 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::ffi::OsStr;
+use std::io::{BufReader, Bytes, Read};
+use std::time::SystemTime;
+
+#[cfg(not(target_arch = "wasm32"))]
+use serialport::SerialPort;
+
+use crate::command::{CommandFrame, DecodedCommand};
+use crate::error::*;
+use crate::response::Resp;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::open_port;
+use crate::{parse_command_frame, parse_frame, Frame, PacketReader, ReadEvent};
+
+/// Which way a frame was travelling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  /// A command from a host
+  ToSensor,
+
+  /// A response or reading from the sensor
+  FromSensor
+}
+
+/// Something seen on the bus by a `SnifferMode`
+#[derive(Debug)]
+pub enum SniffEvent {
+  Response {
+    frame: Frame,
+    response: Resp
+  },
+
+  Command {
+    frame: CommandFrame,
+    command: DecodedCommand
+  },
+
+  /// A complete frame in either direction that failed to parse
+  Invalid {
+    frame: Vec<u8>,
+    error: Error
+  },
+
+  /// A byte received outside of any frame
+  Garbage(u8)
+}
+
+impl SniffEvent {
+  /// The direction of a frame, or None for garbage
+  pub fn direction(&self) -> Option<Direction> {
+    match self {
+      SniffEvent::Response { .. } => Some(Direction::FromSensor),
+      SniffEvent::Command { .. } => Some(Direction::ToSensor),
+      SniffEvent::Invalid { frame, .. } if frame.get(1) == Some(&0xB4) => {
+        Some(Direction::ToSensor)
+      },
+      SniffEvent::Invalid { .. } => Some(Direction::FromSensor),
+      SniffEvent::Garbage(_) => None
+    }
+  }
+}
+
+/// A `SniffEvent` and when it was read
+#[derive(Debug)]
+pub struct Sniffed {
+  pub time: SystemTime,
+  pub event: SniffEvent
+}
+
+/// Passively decodes the traffic on a sensor's serial line in both directions,
+/// e.g. to debug third-party software talking to the same sensor. Only ever
+/// holds a reader, so it can't write to the bus.
+///
+/// Iterates over each frame (or garbage byte) as it's read; read errors are
+/// returned as `Error::ReadError`, after which iteration may continue, e.g. on
+/// a timeout while the bus is idle.
+pub struct SnifferMode<R> {
+  bytes: Bytes<BufReader<R>>,
+  reader: PacketReader
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SnifferMode<Box<dyn SerialPort>> {
+  /// Opens the serial port at `device` for sniffing. It isn't locked (see
+  /// `DeviceLock`), since the point is to share it with other software.
+  pub fn open<P: AsRef<OsStr>>(device: P) -> Result<Self> {
+    Ok(SnifferMode::new(open_port(device)?))
+  }
+}
+
+impl<R: Read> SnifferMode<R> {
+  pub fn new(reader: R) -> Self {
+    SnifferMode {
+      bytes: BufReader::new(reader).bytes(),
+      reader: PacketReader::default()
+    }
+  }
+}
+
+impl<R: Read> Iterator for SnifferMode<R> {
+  type Item = Result<Sniffed>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    for byte in &mut self.bytes {
+      let byte = match byte {
+        Ok(byte) => byte,
+        Err(e) => return Some(Err(Error::ReadError(e)))
+      };
+
+      let event = match self.reader.push(byte) {
+        Some(ReadEvent::Packet(frame)) => match parse_frame(&frame) {
+          Ok(response) => SniffEvent::Response { frame, response },
+          Err(error) => SniffEvent::Invalid { frame: frame.to_vec(), error }
+        },
+        Some(ReadEvent::Command(frame)) => match parse_command_frame(&frame) {
+          Ok(command) => SniffEvent::Command { frame, command },
+          Err(error) => SniffEvent::Invalid { frame: frame.to_vec(), error }
+        },
+        Some(ReadEvent::Garbage(byte)) => SniffEvent::Garbage(byte),
+        None => continue
+      };
+
+      return Some(Ok(Sniffed { time: SystemTime::now(), event }));
+    }
+
+    None
+  }
+}
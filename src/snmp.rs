@@ -0,0 +1,356 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::*;
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const COUNTER32: u8 = 0x41;
+const GAUGE32: u8 = 0x42;
+const NO_SUCH_OBJECT: u8 = 0x80;
+const NO_SUCH_INSTANCE: u8 = 0x81;
+const END_OF_MIB_VIEW: u8 = 0x82;
+
+const GET_REQUEST: u8 = 0xA0;
+const GET_NEXT_REQUEST: u8 = 0xA1;
+const RESPONSE: u8 = 0xA2;
+const SET_REQUEST: u8 = 0xA3;
+const GET_BULK_REQUEST: u8 = 0xA5;
+
+/// The version field of an SNMPv2c message
+const VERSION_2C: i64 = 1;
+
+/// error-status for a set of a read-only object
+const NOT_WRITABLE: i64 = 17;
+
+/// The most variable bindings returned for a GetBulk, to keep the response
+/// within a single datagram
+const MAX_BULK_BINDINGS: usize = 64;
+
+/// An SNMP object identifier, e.g. `1.3.6.1.4.1.32473`. Comparison is
+/// lexicographic by arc, which is the order GetNext walks in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid(pub Vec<u32>);
+
+impl Oid {
+  /// This OID with `arcs` appended
+  pub fn child(&self, arcs: &[u32]) -> Oid {
+    let mut oid = self.0.clone();
+    oid.extend_from_slice(arcs);
+    Oid(oid)
+  }
+}
+
+impl FromStr for Oid {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let arcs = s.trim_start_matches('.')
+      .split('.')
+      .map(|arc| arc.parse::<u32>())
+      .collect::<std::result::Result<Vec<_>, _>>()
+      .map_err(|_| Error::InvalidOid(s.to_string()))?;
+
+    if arcs.len() < 2 || arcs[0] > 2 {
+      return Err(Error::InvalidOid(s.to_string()));
+    }
+
+    Ok(Oid(arcs))
+  }
+}
+
+impl fmt::Display for Oid {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let arcs: Vec<String> = self.0.iter().map(|arc| arc.to_string()).collect();
+    f.write_str(&arcs.join("."))
+  }
+}
+
+/// A variable binding's value
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnmpValue {
+  Integer(i64),
+  OctetString(Vec<u8>),
+  Counter32(u32),
+  Gauge32(u32),
+  Null,
+  NoSuchObject,
+  NoSuchInstance,
+  EndOfMibView
+}
+
+/// The kind of an SNMP request PDU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnmpRequestKind {
+  Get,
+  GetNext,
+  GetBulk {
+    non_repeaters: usize,
+    max_repetitions: usize
+  },
+  Set
+}
+
+/// An SNMPv2c request, parsed as far as a read-only agent needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnmpRequest {
+  pub community: Vec<u8>,
+  pub kind: SnmpRequestKind,
+  pub request_id: i64,
+
+  /// the requested OIDs; their values are ignored
+  pub oids: Vec<Oid>
+}
+
+impl SnmpRequest {
+  /// Parses a datagram, or returns None if it isn't an SNMPv2c request
+  pub fn parse(datagram: &[u8]) -> Option<SnmpRequest> {
+    let (message, _) = read_tlv(datagram, SEQUENCE)?;
+    let (version, rest) = read_tlv(message, INTEGER)?;
+    if decode_integer(version)? != VERSION_2C {
+      return None;
+    }
+
+    let (community, rest) = read_tlv(rest, OCTET_STRING)?;
+    let (tag, pdu, _) = read_any(rest)?;
+
+    let (request_id, rest) = read_tlv(pdu, INTEGER)?;
+    let (field1, rest) = read_tlv(rest, INTEGER)?;
+    let (field2, rest) = read_tlv(rest, INTEGER)?;
+    let (mut bindings, _) = read_tlv(rest, SEQUENCE)?;
+
+    let kind = match tag {
+      GET_REQUEST => SnmpRequestKind::Get,
+      GET_NEXT_REQUEST => SnmpRequestKind::GetNext,
+      SET_REQUEST => SnmpRequestKind::Set,
+      GET_BULK_REQUEST => SnmpRequestKind::GetBulk {
+        non_repeaters: decode_integer(field1)?.max(0) as usize,
+        max_repetitions: decode_integer(field2)?.max(0) as usize
+      },
+      _ => return None
+    };
+
+    let mut oids = Vec::new();
+    while !bindings.is_empty() {
+      let (binding, rest) = read_tlv(bindings, SEQUENCE)?;
+      let (oid, _) = read_tlv(binding, OBJECT_IDENTIFIER)?;
+      oids.push(decode_oid(oid)?);
+      bindings = rest;
+    }
+
+    Some(SnmpRequest {
+      community: community.to_vec(),
+      kind,
+      request_id: decode_integer(request_id)?,
+      oids
+    })
+  }
+
+  /// The response from `mib`, a read-only set of objects sorted by OID
+  pub fn respond(&self, mib: &[(Oid, SnmpValue)]) -> Vec<u8> {
+    let next = |oid: &Oid| match mib.iter().find(|(o, _)| o > oid) {
+      Some((o, value)) => (o.clone(), value.clone()),
+      None => (oid.clone(), SnmpValue::EndOfMibView)
+    };
+
+    let mut error_status = 0;
+    let mut error_index = 0;
+    let bindings: Vec<(Oid, SnmpValue)> = match self.kind {
+      SnmpRequestKind::Get => self.oids.iter()
+        .map(|oid| {
+          // if there's another instance of the object, it's just this one missing
+          let parent = &oid.0[..oid.0.len() - 1];
+          let value = match mib.iter().find(|(o, _)| o == oid) {
+            Some((_, value)) => value.clone(),
+            None if mib.iter().any(|(o, _)| o.0.starts_with(parent)) => {
+              SnmpValue::NoSuchInstance
+            },
+            None => SnmpValue::NoSuchObject
+          };
+
+          (oid.clone(), value)
+        })
+        .collect(),
+      SnmpRequestKind::GetNext => self.oids.iter().map(next).collect(),
+      SnmpRequestKind::GetBulk { non_repeaters, max_repetitions } => {
+        let non_repeaters = non_repeaters.min(self.oids.len());
+        let (singles, repeated) = self.oids.split_at(non_repeaters);
+
+        let mut bindings: Vec<_> = singles.iter().map(next).collect();
+        let mut cursors = repeated.to_vec();
+        for _ in 0..max_repetitions {
+          if cursors.is_empty() || bindings.len() + cursors.len() > MAX_BULK_BINDINGS {
+            break;
+          }
+
+          for cursor in cursors.iter_mut() {
+            let (oid, value) = next(cursor);
+            *cursor = oid.clone();
+            bindings.push((oid, value));
+          }
+
+          let last = &bindings[bindings.len() - cursors.len()..];
+          if last.iter().all(|(_, value)| *value == SnmpValue::EndOfMibView) {
+            break;
+          }
+        }
+
+        bindings
+      },
+      SnmpRequestKind::Set => {
+        error_status = NOT_WRITABLE;
+        error_index = 1;
+        self.oids.iter().map(|oid| (oid.clone(), SnmpValue::Null)).collect()
+      }
+    };
+
+    let bindings: Vec<u8> = bindings.iter()
+      .flat_map(|(oid, value)| {
+        tlv(SEQUENCE, &[tlv(OBJECT_IDENTIFIER, &encode_oid(oid)), encode_value(value)].concat())
+      })
+      .collect();
+
+    let pdu = [
+      tlv(INTEGER, &encode_integer(self.request_id)),
+      tlv(INTEGER, &encode_integer(error_status)),
+      tlv(INTEGER, &encode_integer(error_index)),
+      tlv(SEQUENCE, &bindings)
+    ].concat();
+
+    let message = [
+      tlv(INTEGER, &encode_integer(VERSION_2C)),
+      tlv(OCTET_STRING, &self.community),
+      tlv(RESPONSE, &pdu)
+    ].concat();
+
+    tlv(SEQUENCE, &message)
+  }
+}
+
+/// Reads a BER value with the given tag, returning it and the bytes after it
+fn read_tlv(bytes: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+  match read_any(bytes)? {
+    (t, value, rest) if t == tag => Some((value, rest)),
+    _ => None
+  }
+}
+
+/// Reads a BER value with any tag
+fn read_any(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+  let (&tag, rest) = bytes.split_first()?;
+  let (&first, rest) = rest.split_first()?;
+
+  let (len, rest) = match first {
+    0..=0x7F => (first as usize, rest),
+    0x81..=0x84 => {
+      let count = (first & 0x7F) as usize;
+      if rest.len() < count {
+        return None;
+      }
+
+      let (len, rest) = rest.split_at(count);
+      (len.iter().fold(0usize, |len, &b| len << 8 | b as usize), rest)
+    },
+    _ => return None
+  };
+
+  if rest.len() < len {
+    return None;
+  }
+
+  let (value, rest) = rest.split_at(len);
+  Some((tag, value, rest))
+}
+
+fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+  let mut out = vec![tag];
+  let len = value.len();
+  match len {
+    0..=0x7F => out.push(len as u8),
+    0x80..=0xFF => out.extend_from_slice(&[0x81, len as u8]),
+    _ => {
+      out.push(0x82);
+      out.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+  }
+
+  out.extend_from_slice(value);
+  out
+}
+
+fn decode_integer(bytes: &[u8]) -> Option<i64> {
+  if bytes.is_empty() || bytes.len() > 8 {
+    return None;
+  }
+
+  // sign-extend from the first byte
+  let initial = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+  Some(bytes.iter().fold(initial, |value, &b| value << 8 | b as i64))
+}
+
+/// Two's complement, big-endian, in as few bytes as keep the sign
+fn encode_integer(value: i64) -> Vec<u8> {
+  let bytes = value.to_be_bytes();
+  let mut start = 0;
+  while start < 7 {
+    let redundant = (bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0)
+      || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0);
+    if !redundant {
+      break;
+    }
+
+    start += 1;
+  }
+
+  bytes[start..].to_vec()
+}
+
+fn decode_oid(bytes: &[u8]) -> Option<Oid> {
+  let (&first, rest) = bytes.split_first()?;
+  let mut arcs = vec![(first / 40).min(2) as u32, (first - 40 * (first / 40).min(2)) as u32];
+
+  let mut arc = 0u32;
+  for &b in rest {
+    arc = arc.checked_mul(128)? | (b & 0x7F) as u32;
+    if b & 0x80 == 0 {
+      arcs.push(arc);
+      arc = 0;
+    }
+  }
+
+  Some(Oid(arcs))
+}
+
+fn encode_oid(oid: &Oid) -> Vec<u8> {
+  let arcs = &oid.0;
+  let mut out = vec![(arcs[0] * 40 + arcs.get(1).copied().unwrap_or(0)) as u8];
+
+  for &arc in arcs.iter().skip(2) {
+    let mut groups = vec![(arc & 0x7F) as u8];
+    let mut rest = arc >> 7;
+    while rest > 0 {
+      groups.push((rest & 0x7F) as u8 | 0x80);
+      rest >>= 7;
+    }
+
+    out.extend(groups.iter().rev());
+  }
+
+  out
+}
+
+fn encode_value(value: &SnmpValue) -> Vec<u8> {
+  match value {
+    SnmpValue::Integer(i) => tlv(INTEGER, &encode_integer(*i)),
+    SnmpValue::OctetString(s) => tlv(OCTET_STRING, s),
+    SnmpValue::Counter32(c) => tlv(COUNTER32, &encode_integer(*c as i64)),
+    SnmpValue::Gauge32(g) => tlv(GAUGE32, &encode_integer(*g as i64)),
+    SnmpValue::Null => tlv(NULL, &[]),
+    SnmpValue::NoSuchObject => tlv(NO_SUCH_OBJECT, &[]),
+    SnmpValue::NoSuchInstance => tlv(NO_SUCH_INSTANCE, &[]),
+    SnmpValue::EndOfMibView => tlv(END_OF_MIB_VIEW, &[])
+  }
+}
@@ -0,0 +1,788 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::clock::Timestamp;
+use crate::command::Cmd;
+#[cfg(feature = "async")]
+use crate::command::Command;
+use crate::error::*;
+use crate::response::{QueryResponse, Resp, ResponseKindSet};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::open_sensor_channels_with_options;
+use crate::{open_transport_channels, ControlMessage, LinkStats, SensorOptions, Transport};
+#[cfg(feature = "async")]
+use crate::{response::Response, retry_send_async, RetryConfig};
+
+/// What a `Supervisor` does when its sensor hits a fatal error
+#[derive(Debug, Clone, Copy)]
+pub enum RestartPolicy {
+  /// Stop supervising after the first fatal error
+  Never,
+
+  /// Reopen the sensor after waiting `delay`, up to `max_restarts` times in a
+  /// row (or forever, if `None`). The count resets once a reopened sensor
+  /// sends a valid response.
+  Restart {
+    delay: Duration,
+    max_restarts: Option<usize>
+  }
+}
+
+pub struct SupervisorConfig {
+  /// What to do after a fatal error
+  pub restart: RestartPolicy,
+
+  /// The time to wait between each check for responses and commands
+  pub sleep: Duration,
+
+  /// Read path options used each time the sensor is opened
+  pub sensor: SensorOptions,
+
+  /// If set, the sensor is considered stalled (and `ControlMessage::Stalled`
+  /// is sent) if nothing is received from it for this long. Should be well
+  /// over the sensor's working period.
+  pub stall_timeout: Option<Duration>,
+
+  /// If set, `ControlMessage::Backlog` is sent (and a warning logged) when more
+  /// than this many responses from the sensor are waiting to be handled at
+  /// once, e.g. because a setup function or lock holder is blocking the
+  /// supervisor
+  pub backlog_watermark: Option<usize>,
+
+  /// The number of recent lifecycle events and errors to keep for
+  /// `Supervisor::recent_events()`; 0 for none
+  pub event_history: usize
+}
+
+impl Default for SupervisorConfig {
+  fn default() -> Self {
+    SupervisorConfig {
+      restart: RestartPolicy::Never,
+      sleep: Duration::from_millis(100),
+      sensor: SensorOptions::default(),
+      stall_timeout: None,
+      backlog_watermark: None,
+      event_history: 0
+    }
+  }
+}
+
+/// Counters describing a supervised sensor
+#[derive(Debug, Clone, Default)]
+pub struct SupervisorStats {
+  /// Non-fatal errors, e.g. invalid packets
+  pub errors: usize,
+
+  /// Fatal errors, i.e. the sensor had to be (or would have had to be)
+  /// reopened
+  pub fatal_errors: usize,
+
+  /// Number of times the sensor was successfully reopened
+  pub restarts: usize,
+
+  /// Fatal errors that were the read or write thread panicking
+  pub panics: usize,
+
+  /// False once the supervisor has given up on the sensor
+  pub running: bool,
+
+  /// True while the sensor is open and hasn't stalled
+  pub up: bool,
+
+  /// Receivers from `subscribe()` and `events()` not yet known to be dropped
+  pub subscribers: usize,
+  pub listeners: usize
+}
+
+/// Run against a freshly opened sensor before it's handed over to the
+/// supervisor, e.g. to configure its working period. Responses received here
+/// aren't passed on to subscribers.
+pub type SetupFn = dyn Fn(&Sender<Cmd>, &Receiver<Resp>) -> Result<()> + Send;
+
+/// Opens the sensor with the given options, returning its channels
+type OpenFn = dyn Fn(&SensorOptions) -> Result<Channels> + Send;
+
+/// Where a subscriber's responses are sent
+enum Subscriber {
+  Sync(Sender<Resp>),
+
+  #[cfg(feature = "async")]
+  Async(tokio::sync::mpsc::UnboundedSender<Resp>)
+}
+
+impl Subscriber {
+  /// Passes on a response, returning false once the receiver is dropped
+  fn send(&self, response: Resp) -> bool {
+    match self {
+      Subscriber::Sync(tx) => tx.send(response).is_ok(),
+
+      #[cfg(feature = "async")]
+      Subscriber::Async(tx) => tx.send(response).is_ok()
+    }
+  }
+}
+
+/// The latest reading and when it was received
+type Latest = Option<(Timestamp, QueryResponse)>;
+
+/// The latest reading, with a version bumped each time it changes so watchers
+/// can tell whether they've seen it
+#[derive(Default)]
+struct Versioned {
+  version: u64,
+  reading: Latest,
+  closed: bool
+}
+
+/// The latest reading as shared with watchers
+struct LatestReading {
+  current: Mutex<Versioned>,
+  changed: Condvar,
+
+  /// taken when closed, so async watchers see the channel close
+  #[cfg(feature = "async")]
+  tx: Mutex<Option<tokio::sync::watch::Sender<Latest>>>,
+
+  /// tokio 0.2's senders can't create receivers, so this one is cloned for
+  /// each async watcher
+  #[cfg(feature = "async")]
+  rx: tokio::sync::watch::Receiver<Latest>
+}
+
+impl Default for LatestReading {
+  fn default() -> Self {
+    #[cfg(feature = "async")]
+    let (tx, rx) = tokio::sync::watch::channel(None);
+
+    LatestReading {
+      current: Mutex::default(),
+      changed: Condvar::new(),
+
+      #[cfg(feature = "async")]
+      tx: Mutex::new(Some(tx)),
+
+      #[cfg(feature = "async")]
+      rx
+    }
+  }
+}
+
+impl LatestReading {
+  fn get(&self) -> Latest {
+    self.current.lock().unwrap().reading.clone()
+  }
+
+  fn set(&self, reading: Latest) {
+    {
+      let mut current = self.current.lock().unwrap();
+      if reading.is_none() && current.reading.is_none() {
+        return;
+      }
+
+      current.version += 1;
+      current.reading = reading.clone();
+    }
+
+    #[cfg(feature = "async")]
+    if let Some(tx) = &*self.tx.lock().unwrap() {
+      // `rx` is never dropped, so this can't fail
+      tx.broadcast(reading).ok();
+    }
+
+    self.changed.notify_all();
+  }
+
+  fn close(&self) {
+    self.current.lock().unwrap().closed = true;
+
+    #[cfg(feature = "async")]
+    self.tx.lock().unwrap().take();
+
+    self.changed.notify_all();
+  }
+}
+
+/// Observes a supervisor's latest reading, see `Supervisor::watch()`. Any
+/// number of watchers can be created, and each only sees the most recent
+/// value: readings that arrive while it isn't looking are skipped, not queued.
+#[derive(Clone)]
+pub struct LatestWatch {
+  latest: Arc<LatestReading>,
+  seen: u64
+}
+
+impl LatestWatch {
+  /// The latest reading and when it was received, if any, marking it seen.
+  /// Cleared on fatal errors, like `Supervisor::latest()`.
+  pub fn latest(&mut self) -> Option<(Timestamp, QueryResponse)> {
+    let current = self.latest.current.lock().unwrap();
+    self.seen = current.version;
+
+    current.reading.clone()
+  }
+
+  /// True if the reading has changed (or been cleared) since it was last seen
+  pub fn has_changed(&self) -> bool {
+    self.latest.current.lock().unwrap().version != self.seen
+  }
+
+  /// Waits up to `timeout` for the reading to change since it was last seen,
+  /// returning whether it did. Returns false straight away once the
+  /// supervisor has given up.
+  pub fn wait(&self, timeout: Duration) -> bool {
+    let current = self.latest.current.lock().unwrap();
+    let (current, _) = self.latest.changed
+      .wait_timeout_while(current, timeout, |c| c.version == self.seen && !c.closed)
+      .unwrap();
+
+    current.version != self.seen
+  }
+
+  /// True once the supervisor has given up, after which the reading never
+  /// changes
+  pub fn is_closed(&self) -> bool {
+    self.latest.current.lock().unwrap().closed
+  }
+}
+
+/// A lifecycle event or error from a supervised sensor, kept for diagnosis
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupervisorEvent {
+  pub time: Timestamp,
+
+  /// e.g. `reconnecting` or `fatal_error`
+  pub kind: &'static str,
+
+  /// what happened, e.g. the error message
+  pub message: String
+}
+
+#[derive(Default)]
+struct State {
+  latest: Arc<LatestReading>,
+  stats: SupervisorStats,
+  subscribers: Vec<(ResponseKindSet, Subscriber)>,
+  listeners: Vec<Sender<ControlMessage>>,
+
+  /// the most recent events, up to `history_len`
+  history: VecDeque<SupervisorEvent>,
+  history_len: usize
+}
+
+impl State {
+  /// Keeps an event for `Supervisor::recent_events()`, dropping the oldest if
+  /// there are too many
+  fn record(&mut self, event: &ControlMessage) {
+    if self.history_len == 0 {
+      return;
+    }
+
+    if self.history.len() >= self.history_len {
+      self.history.pop_front();
+    }
+
+    let (kind, message) = describe(event);
+    self.history.push_back(SupervisorEvent {
+      time: Timestamp::now(),
+      kind,
+      message
+    });
+  }
+
+  /// Passes a lifecycle event on to all listeners
+  fn notify(&mut self, event: ControlMessage) {
+    self.record(&event);
+    self.listeners.retain(|tx| match event.try_clone() {
+      Some(event) => tx.send(event).is_ok(),
+      None => true
+    });
+  }
+}
+
+/// Keeps a sensor open on a background thread: tracks its latest reading and
+/// error counts, passes responses on to any number of subscribers, and reopens
+/// it after fatal errors according to its `RestartPolicy`.
+#[derive(Clone)]
+pub struct Supervisor {
+  state: Arc<Mutex<State>>,
+  command_tx: Sender<Cmd>,
+  link_stats: LinkStats
+}
+
+impl Supervisor {
+  /// Opens the sensor at `device`, runs `setup` against it, and starts
+  /// supervising it. Errors opening or setting up the sensor the first time are
+  /// returned here rather than retried.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn spawn<P, F>(device: P, config: SupervisorConfig, setup: F) -> Result<Supervisor>
+  where
+    P: Into<OsString>,
+    F: Fn(&Sender<Cmd>, &Receiver<Resp>) -> Result<()> + Send + 'static
+  {
+    let device = device.into();
+    let path = device.clone();
+    let open = move |options: &SensorOptions| {
+      open_sensor_channels_with_options(&path, options.clone())
+    };
+
+    Supervisor::start(device, Box::new(open), config, Box::new(setup))
+  }
+
+  /// Supervises a sensor over any `Transport`, e.g. a TCP connection to a
+  /// serial bridge. `open` is called to connect initially and again for each
+  /// restart; `name` identifies the sensor in logs. See `spawn()`.
+  pub fn spawn_transport<N, O, T, F>(
+    name: N,
+    open: O,
+    config: SupervisorConfig,
+    setup: F
+  ) -> Result<Supervisor>
+  where
+    N: Into<String>,
+    O: Fn() -> Result<T> + Send + 'static,
+    T: Transport,
+    F: Fn(&Sender<Cmd>, &Receiver<Resp>) -> Result<()> + Send + 'static
+  {
+    let name = name.into();
+    let transport_name = name.clone();
+    let open = move |options: &SensorOptions| {
+      open_transport_channels(&transport_name, open()?, options.clone())
+    };
+
+    Supervisor::start(name.into(), Box::new(open), config, Box::new(setup))
+  }
+
+  fn start(
+    device: OsString,
+    open: Box<OpenFn>,
+    config: SupervisorConfig,
+    setup: Box<SetupFn>
+  ) -> Result<Supervisor> {
+    let (sensor_tx, sensor_rx, control_rx) = open(&config.sensor)?;
+    setup(&sensor_tx, &sensor_rx)?;
+
+    let state = Arc::new(Mutex::new(State::default()));
+    {
+      let mut state = state.lock().unwrap();
+      state.stats.running = true;
+      state.stats.up = true;
+      state.history_len = config.event_history;
+    }
+
+    let (command_tx, command_rx) = channel();
+    let supervisor = Supervisor {
+      state,
+      command_tx,
+      link_stats: config.sensor.stats.clone()
+    };
+
+    let thread_state = Arc::clone(&supervisor.state);
+    thread::spawn(move || {
+      supervise(
+        device,
+        config,
+        open,
+        setup,
+        thread_state,
+        command_rx,
+        (sensor_tx, sensor_rx, control_rx)
+      );
+    });
+
+    Ok(supervisor)
+  }
+
+  /// Returns a new receiver for every response from the sensor. Its sender is
+  /// dropped (i.e. iteration ends) once the supervisor gives up.
+  pub fn subscribe(&self) -> Receiver<Resp> {
+    self.subscription().responses()
+  }
+
+  /// Starts configuring a new subscriber, e.g. to receive only measurements:
+  /// `supervisor.subscription().filter(ResponseKindSet::MEASUREMENTS).responses()`
+  pub fn subscription(&self) -> Subscription<'_> {
+    Subscription {
+      supervisor: self,
+      kinds: ResponseKindSet::ALL
+    }
+  }
+
+  /// Sends a command and waits for its reply without blocking a thread. The
+  /// reply is awaited on a new subscription to every kind of response, so
+  /// unlike sending on a subscription filtered for other uses, e.g. to
+  /// `MEASUREMENTS`, it can't be filtered out.
+  #[cfg(feature = "async")]
+  pub async fn send_async<T: Response>(
+    &self,
+    command: impl Command<ResponseType = T>,
+    config: &RetryConfig
+  ) -> Result<T> {
+    let mut responses = self.subscription().async_responses();
+    let (reply, _) = retry_send_async(command, &self.command_tx, &mut responses, config).await?;
+
+    Ok(reply)
+  }
+
+  /// Returns a new receiver for the sensor's lifecycle events: `Reconnecting`,
+  /// `Reconnected`, `Stalled`, `Resynced`, `Garbage` and `Backlog` (if
+  /// enabled), and finally `Closed` once the supervisor gives up. Errors are
+  /// counted in `stats()` instead.
+  pub fn events(&self) -> Receiver<ControlMessage> {
+    let (tx, rx) = channel();
+
+    let mut state = self.state.lock().unwrap();
+    if state.stats.running {
+      state.listeners.push(tx);
+    }
+
+    rx
+  }
+
+  /// Returns a sender for commands to the sensor, which remains valid across
+  /// restarts
+  pub fn commands(&self) -> Sender<Cmd> {
+    self.command_tx.clone()
+  }
+
+  /// The latest reading and when it was received, if any. Cleared on fatal
+  /// errors so stale readings aren't reported.
+  pub fn latest(&self) -> Option<(Timestamp, QueryResponse)> {
+    self.state.lock().unwrap().latest.get()
+  }
+
+  /// Returns a new watcher of the latest reading, which can cheaply check for
+  /// or wait on a newer one. The current reading, if any, counts as unseen.
+  pub fn watch(&self) -> LatestWatch {
+    LatestWatch {
+      latest: Arc::clone(&self.state.lock().unwrap().latest),
+      seen: 0
+    }
+  }
+
+  /// Like `watch()`, but a `tokio::sync::watch` receiver for async consumers.
+  /// It's closed once the supervisor gives up.
+  #[cfg(feature = "async")]
+  pub fn watch_async(&self) -> tokio::sync::watch::Receiver<Option<(Timestamp, QueryResponse)>> {
+    self.state.lock().unwrap().latest.rx.clone()
+  }
+
+  /// The most recent lifecycle events and errors, oldest first; empty unless
+  /// `SupervisorConfig::event_history` is set
+  pub fn recent_events(&self) -> Vec<SupervisorEvent> {
+    self.state.lock().unwrap().history.iter().cloned().collect()
+  }
+
+  pub fn stats(&self) -> SupervisorStats {
+    let state = self.state.lock().unwrap();
+
+    SupervisorStats {
+      subscribers: state.subscribers.len(),
+      listeners: state.listeners.len(),
+      ..state.stats.clone()
+    }
+  }
+
+  /// Byte stream counters, accumulated across restarts
+  pub fn link_stats(&self) -> &LinkStats {
+    &self.link_stats
+  }
+}
+
+/// A subscriber being configured; see `Supervisor::subscription()`. Each
+/// subscriber gets its own channel, so e.g. a reading cache and a command sender
+/// never consume each other's responses.
+pub struct Subscription<'a> {
+  supervisor: &'a Supervisor,
+  kinds: ResponseKindSet
+}
+
+impl<'a> Subscription<'a> {
+  /// Only passes on responses of the given kinds; all by default. For only
+  /// lifecycle events, see `Supervisor::events()`.
+  pub fn filter(mut self, kinds: ResponseKindSet) -> Self {
+    self.kinds = kinds;
+    self
+  }
+
+  /// Registers the subscriber, returning its receiver. Its sender is dropped
+  /// (i.e. iteration ends) once the supervisor gives up.
+  pub fn responses(self) -> Receiver<Resp> {
+    let (tx, rx) = channel();
+    self.register(Subscriber::Sync(tx));
+
+    rx
+  }
+
+  /// Like `responses()`, but for async consumers, e.g. of
+  /// `retry_send_async()`. The receiver's `recv()` returns None once the
+  /// supervisor gives up.
+  #[cfg(feature = "async")]
+  pub fn async_responses(self) -> tokio::sync::mpsc::UnboundedReceiver<Resp> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    self.register(Subscriber::Async(tx));
+
+    rx
+  }
+
+  fn register(self, subscriber: Subscriber) {
+    let mut state = self.supervisor.state.lock().unwrap();
+    if state.stats.running {
+      state.subscribers.push((self.kinds, subscriber));
+    }
+  }
+}
+
+type Channels = (Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>);
+
+/// How long to wait for a lost sensor's threads to stop, releasing its port and
+/// lock, before reopening it
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Waits for the read and write threads behind `control_rx` to stop, i.e. to
+/// drop their senders, discarding anything else they send
+fn wait_closed(device: &OsString, control_rx: &Receiver<ControlMessage>) {
+  let deadline = Instant::now() + CLOSE_TIMEOUT;
+  loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match control_rx.recv_timeout(remaining) {
+      Ok(message) => debug!("discarding {:?} from lost sensor {:?}", message, device),
+      Err(RecvTimeoutError::Disconnected) => return,
+      Err(RecvTimeoutError::Timeout) => {
+        warn!("sensor {:?} is still open after {:?}; reopening anyway", device, CLOSE_TIMEOUT);
+        return;
+      }
+    }
+  }
+}
+
+/// Reopens the sensor according to `config.restart`, returning None once out
+/// of attempts
+fn reopen(
+  device: &OsString,
+  open: &OpenFn,
+  config: &SupervisorConfig,
+  attempts: &mut usize,
+  setup: &SetupFn,
+  state: &Mutex<State>
+) -> Option<Channels> {
+  loop {
+    let (delay, max_restarts) = match config.restart {
+      RestartPolicy::Never => return None,
+      RestartPolicy::Restart { delay, max_restarts } => (delay, max_restarts)
+    };
+
+    if max_restarts.map(|max| *attempts >= max).unwrap_or(false) {
+      error!("giving up on sensor {:?} after {} restarts", device, attempts);
+      return None;
+    }
+
+    thread::sleep(delay);
+    *attempts += 1;
+
+    state.lock().unwrap().notify(ControlMessage::Reconnecting { attempt: *attempts });
+
+    info!("reopening sensor {:?}, attempt #{}", device, attempts);
+    let result = open(&config.sensor)
+      .and_then(|(tx, rx, control)| setup(&tx, &rx).map(|_| (tx, rx, control)));
+
+    match result {
+      Ok(channels) => {
+        let mut state = state.lock().unwrap();
+        state.stats.up = true;
+        state.stats.restarts += 1;
+        state.notify(ControlMessage::Reconnected);
+
+        return Some(channels);
+      },
+      Err(e) => {
+        error!("error reopening sensor {:?}: {}", device, e);
+        let mut state = state.lock().unwrap();
+        state.stats.fatal_errors += 1;
+        state.record(&ControlMessage::FatalError(e));
+      }
+    }
+  }
+}
+
+fn supervise(
+  device: OsString,
+  config: SupervisorConfig,
+  open: Box<OpenFn>,
+  setup: Box<SetupFn>,
+  state: Arc<Mutex<State>>,
+  command_rx: Receiver<Cmd>,
+  channels: Channels
+) {
+  let (mut sensor_tx, mut sensor_rx, mut control_rx) = channels;
+  let mut attempts = 0;
+
+  // when anything was last received, and whether that was too long ago
+  let mut last_received = Instant::now();
+  let mut stalled = false;
+
+  // whether the last pass was over the backlog watermark
+  let mut backlogged = false;
+
+  debug!("started supervisor for {:?}", device);
+
+  'outer: loop {
+    for cmd in command_rx.try_iter() {
+      sensor_tx.send(cmd).ok();
+    }
+
+    let mut depth = 0;
+    for response in sensor_rx.try_iter() {
+      depth += 1;
+      attempts = 0;
+      last_received = Instant::now();
+
+      let mut state = state.lock().unwrap();
+      if stalled {
+        info!("sensor {:?} is responding again", device);
+        stalled = false;
+        state.stats.up = true;
+      }
+
+      if let Resp::Query(q) = &response {
+        state.latest.set(Some((Timestamp::now(), q.clone())));
+      }
+
+      state.subscribers.retain(|(kinds, tx)| {
+        !kinds.matches(&response) || tx.send(response.clone())
+      });
+    }
+
+    if let Some(watermark) = config.backlog_watermark {
+      if depth > watermark && !backlogged {
+        warn!(
+          device = device.to_string_lossy().as_ref();
+          "{} responses were waiting to be handled (watermark {})", depth, watermark
+        );
+        state.lock().unwrap().notify(ControlMessage::Backlog { depth });
+      }
+
+      backlogged = depth > watermark;
+    }
+
+    let timed_out = config.stall_timeout
+      .map(|timeout| last_received.elapsed() >= timeout)
+      .unwrap_or(false);
+    if timed_out && !stalled {
+      warn!("sensor {:?} has stalled", device);
+      stalled = true;
+
+      let mut state = state.lock().unwrap();
+      state.stats.up = false;
+      state.notify(ControlMessage::Stalled);
+    }
+
+    for message in control_rx.try_iter() {
+      match message {
+        ControlMessage::Error(e) => {
+          warn!(
+            device = device.to_string_lossy().as_ref(),
+            error_kind = e.kind().as_str();
+            "sensor warning: {}", e
+          );
+          let mut state = state.lock().unwrap();
+          state.stats.errors += e.count();
+          state.record(&ControlMessage::Error(e));
+          continue;
+        },
+        ControlMessage::FatalError(e) => {
+          error!(
+            device = device.to_string_lossy().as_ref(),
+            error_kind = e.kind().as_str();
+            "sensor fatal error: {:?}", e
+          );
+          let mut state = state.lock().unwrap();
+          state.stats.fatal_errors += 1;
+          if let Error::ThreadPanic { .. } = e {
+            state.stats.panics += 1;
+          }
+
+          state.record(&ControlMessage::FatalError(e));
+        },
+        ControlMessage::Closed => {
+          warn!("sensor {:?} closed unexpectedly", device);
+          state.lock().unwrap().record(&ControlMessage::Closed);
+        },
+
+        // reopening is reported as `Reconnected` instead
+        ControlMessage::Opened => continue,
+
+        ControlMessage::Garbage { count, sample } => {
+          warn!(
+            device = device.to_string_lossy().as_ref();
+            "received {} garbage bytes, e.g. {:x?}; check wiring", count, sample
+          );
+
+          state.lock().unwrap().notify(ControlMessage::Garbage { count, sample });
+          continue;
+        },
+
+        message => {
+          state.lock().unwrap().notify(message);
+          continue;
+        }
+      }
+
+      // the sensor was lost
+      {
+        let mut state = state.lock().unwrap();
+        state.stats.up = false;
+
+        // clear the reading so nobody reports misleading data
+        state.latest.set(None);
+      }
+
+      wait_closed(&device, &control_rx);
+      match reopen(&device, &*open, &config, &mut attempts, &*setup, &state) {
+        Some(channels) => {
+          let (tx, rx, control) = channels;
+          sensor_tx = tx;
+          sensor_rx = rx;
+          control_rx = control;
+
+          last_received = Instant::now();
+          stalled = false;
+          continue 'outer;
+        },
+        None => break 'outer
+      }
+    }
+
+    thread::sleep(config.sleep);
+  }
+
+  let mut state = state.lock().unwrap();
+  state.stats.running = false;
+  state.latest.close();
+  state.notify(ControlMessage::Closed);
+  state.subscribers.clear();
+  state.listeners.clear();
+}
+
+/// An event's kind and description, for `SupervisorEvent`
+fn describe(event: &ControlMessage) -> (&'static str, String) {
+  match event {
+    ControlMessage::Error(e) => ("error", e.to_string()),
+    ControlMessage::FatalError(e) => ("fatal_error", e.to_string()),
+    ControlMessage::Opened => ("opened", "sensor opened".to_string()),
+    ControlMessage::Reconnecting { attempt } => {
+      ("reconnecting", format!("reopening sensor, attempt #{}", attempt))
+    },
+    ControlMessage::Reconnected => ("reconnected", "sensor reopened".to_string()),
+    ControlMessage::Stalled => ("stalled", "nothing received from sensor".to_string()),
+    ControlMessage::Resynced => ("resynced", "valid packet received after garbage".to_string()),
+    ControlMessage::Garbage { count, sample } => {
+      ("garbage", format!("{} garbage bytes, e.g. {:x?}", count, sample))
+    },
+    ControlMessage::Backlog { depth } => ("backlog", format!("{} responses waiting", depth)),
+    ControlMessage::Closed => ("closed", "sensor closed".to_string())
+  }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use serialport::{ClearBuffer, SerialPort};
+
+/// How long a read may block before the sensor is considered lost; longer than
+/// the worst-case working period
+pub const READ_TIMEOUT: Duration = Duration::from_secs(60 * 31);
+
+/// A bidirectional byte stream to a sensor, e.g. a serial port or a TCP
+/// connection to a serial bridge. The read and write threads each get their own
+/// handle from `try_clone()`, so either may block without stalling the other.
+pub trait Transport: Read + Write + Send + 'static {
+  /// Returns another handle to the same connection
+  fn try_clone(&self) -> io::Result<Self> where Self: Sized;
+
+  /// Sets how long a read may block before failing
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+  /// Discards bytes received but not yet read, for `Cmd::flush_input()`. Does
+  /// nothing by default.
+  fn clear_input(&self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for Box<dyn SerialPort> {
+  fn try_clone(&self) -> io::Result<Self> {
+    SerialPort::try_clone(&**self).map_err(io::Error::from)
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    SerialPort::set_timeout(&mut **self, timeout).map_err(io::Error::from)
+  }
+
+  fn clear_input(&self) -> io::Result<()> {
+    self.clear(ClearBuffer::Input).map_err(io::Error::from)
+  }
+}
+
+/// e.g. for a sensor behind `ser2net` or a similar serial-to-TCP bridge
+impl Transport for TcpStream {
+  fn try_clone(&self) -> io::Result<Self> {
+    TcpStream::try_clone(self)
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    self.set_read_timeout(Some(timeout))
+  }
+}
+
+/// e.g. one end of a `TTYPort::pair()` for testing against a simulated sensor
+#[cfg(unix)]
+impl Transport for serialport::posix::TTYPort {
+  fn try_clone(&self) -> io::Result<Self> {
+    self.try_clone_native().map_err(io::Error::from)
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    SerialPort::set_timeout(self, timeout).map_err(io::Error::from)
+  }
+
+  fn clear_input(&self) -> io::Result<()> {
+    self.clear(ClearBuffer::Input).map_err(io::Error::from)
+  }
+}
@@ -1,7 +1,10 @@
 use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 
 use crate::error::*;
+use crate::{Frame, FRAME_LEN};
 
 /// Computes a checksum for the given bytes.
 ///
@@ -13,6 +16,150 @@ pub fn checksum(bytes: &[u8]) -> u8 {
   sum.to_le_bytes()[0]
 }
 
+/// Builds a reply frame with the given command id (0xC0 for readings, 0xC5 for
+/// everything else) and data bytes, adding the head, checksum, and tail
+pub fn build_frame(id: u8, data: [u8; 6]) -> Frame {
+  let mut frame = [0u8; FRAME_LEN];
+  frame[0] = 0xAA;
+  frame[1] = id;
+  frame[2..8].copy_from_slice(&data);
+  frame[8] = checksum(&data);
+  frame[9] = 0xAB;
+
+  frame
+}
+
+/// Formats bytes as space-separated uppercase hex, e.g. `AA C0 ...`
+pub fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter()
+    .map(|b| format!("{:02X}", b))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Parses hex bytes separated by whitespace or commas, each optionally
+/// prefixed with `0x`; unseparated strings like `AAB406` are also accepted.
+pub fn parse_hex(s: &str) -> Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+
+  for token in s.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+    let token = token.trim_start_matches("0x").trim_start_matches("0X");
+    if token.len() % 2 != 0 {
+      return Err(Error::InvalidHex(format!("'{}': odd number of digits", token)));
+    }
+
+    for i in (0..token.len()).step_by(2) {
+      let byte = token.get(i..i + 2)
+        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| Error::InvalidHex(format!("'{}'", token)))?;
+
+      bytes.push(byte);
+    }
+  }
+
+  Ok(bytes)
+}
+
+/// A sensor's 2-byte device id. Displayed as hex, e.g. `0xa1b2`; parsed from
+/// either hex with a `0x` prefix or decimal.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct DeviceId(pub u16);
+
+impl DeviceId {
+  pub fn to_be_bytes(self) -> [u8; 2] {
+    self.0.to_be_bytes()
+  }
+}
+
+impl From<u16> for DeviceId {
+  fn from(id: u16) -> Self {
+    DeviceId(id)
+  }
+}
+
+impl From<DeviceId> for u16 {
+  fn from(id: DeviceId) -> Self {
+    id.0
+  }
+}
+
+impl fmt::Display for DeviceId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "0x{:04x}", self.0)
+  }
+}
+
+impl fmt::LowerHex for DeviceId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::LowerHex::fmt(&self.0, f)
+  }
+}
+
+impl fmt::UpperHex for DeviceId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::UpperHex::fmt(&self.0, f)
+  }
+}
+
+impl FromStr for DeviceId {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+      Some(hex) => u16::from_str_radix(hex, 16),
+      None => s.parse()
+    };
+
+    parsed
+      .map(DeviceId)
+      .map_err(|e| Error::InvalidDeviceId(format!("'{}': {}", s, e)))
+  }
+}
+
+/// Serialized as a hex string; deserialized from a string or an integer
+#[cfg(feature = "serde")]
+impl serde::Serialize for DeviceId {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DeviceId {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>
+  {
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+      type Value = DeviceId;
+
+      fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a device id, e.g. 0xa1b2 or 41394")
+      }
+
+      fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<DeviceId, E> {
+        u16::try_from(v)
+          .map(DeviceId)
+          .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+      }
+
+      fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<DeviceId, E> {
+        u16::try_from(v)
+          .map(DeviceId)
+          .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+      }
+
+      fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<DeviceId, E> {
+        v.parse().map_err(E::custom)
+      }
+    }
+
+    deserializer.deserialize_any(Visitor)
+  }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum WorkMode {
   Sleep,
@@ -71,6 +218,24 @@ impl WorkingPeriod {
       WorkingPeriod::Periodic(n) => *n
     }
   }
+
+  /// The interval between actively reported readings: one second when
+  /// continuous, otherwise the period in minutes
+  pub fn as_duration(&self) -> Duration {
+    match self {
+      WorkingPeriod::Continuous => Duration::from_secs(1),
+      WorkingPeriod::Periodic(n) => Duration::from_secs(*n as u64 * 60)
+    }
+  }
+
+  /// Converts a duration to the nearest working period in whole minutes; under
+  /// 30 seconds rounds to continuous. Returns
+  /// `Error::WorkingPeriodOutOfRange` past 30 minutes.
+  pub fn from_duration(duration: Duration) -> Result<Self> {
+    let minutes = (duration.as_secs() + 30) / 60;
+
+    WorkingPeriod::try_from(usize::try_from(minutes).unwrap_or(usize::MAX))
+  }
 }
 
 impl TryFrom<usize> for WorkingPeriod {
@@ -80,25 +245,80 @@ impl TryFrom<usize> for WorkingPeriod {
     match value {
       0 => Ok(WorkingPeriod::Continuous),
       1..=30 => Ok(WorkingPeriod::Periodic(value as u8)),
-      _ => Err(Error::InvalidWorkingPeriod {
-        period: value.to_string(),
-        reason: "value out of range (0 <= n <= 30)".into()
-      })
+      _ => Err(Error::WorkingPeriodOutOfRange(value))
     }
   }
 }
 
+/// Parses a number of minutes, optionally with a unit, e.g. `5`, `5m`, `5min`,
+/// or `300s`; seconds are rounded as in `from_duration()`
 impl FromStr for WorkingPeriod {
   type Err = Error;
 
   fn from_str(s: &str) -> Result<Self> {
-    let value = s.parse::<usize>()
-      .map_err(|e| Error::InvalidWorkingPeriod {
+    let trimmed = s.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit()).unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let value = number.parse::<u64>()
+      .map_err(|source| Error::InvalidWorkingPeriod {
         period: s.into(),
-        reason: format!("could not parse int: {}", e)
+        source
       })?;
 
-    WorkingPeriod::try_from(value)
+    match unit.trim() {
+      "" | "m" | "min" => WorkingPeriod::try_from(usize::try_from(value).unwrap_or(usize::MAX)),
+      "s" | "sec" => WorkingPeriod::from_duration(Duration::from_secs(value)),
+      unit => Err(Error::InvalidWorkingPeriodUnit {
+        period: s.into(),
+        unit: unit.into()
+      })
+    }
+  }
+}
+
+/// Serialized as a number of minutes; deserialized from minutes or a string
+/// as accepted by `from_str()`
+#[cfg(feature = "serde")]
+impl serde::Serialize for WorkingPeriod {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_u8(self.as_byte())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for WorkingPeriod {
+  fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>
+  {
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+      type Value = WorkingPeriod;
+
+      fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a working period, e.g. 5 or \"5m\"")
+      }
+
+      fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<WorkingPeriod, E> {
+        usize::try_from(v)
+          .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+          .and_then(|v| WorkingPeriod::try_from(v).map_err(E::custom))
+      }
+
+      fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<WorkingPeriod, E> {
+        usize::try_from(v)
+          .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+          .and_then(|v| WorkingPeriod::try_from(v).map_err(E::custom))
+      }
+
+      fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<WorkingPeriod, E> {
+        v.parse().map_err(E::custom)
+      }
+    }
+
+    deserializer.deserialize_any(Visitor)
   }
 }
 
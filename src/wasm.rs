@@ -0,0 +1,157 @@
+//! JavaScript bindings for decoding hex dumps and computing AQI in a browser,
+//! e.g. for a web UI or debugging docs. Build for `wasm32-unknown-unknown` and
+//! run the result through `wasm-bindgen`; see the README.
+
+use wasm_bindgen::prelude::*;
+
+use crate::aqi;
+use crate::response::Resp;
+use crate::util::{parse_hex, to_hex};
+use crate::{parse_command_frame, parse_frame, PacketReader, ReadEvent};
+
+/// One frame (or run of stray bytes) found in a hex dump
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedFrame {
+  kind: &'static str,
+  hex: String,
+  description: String,
+  device: Option<String>,
+  pm25: Option<f32>,
+  pm10: Option<f32>,
+  aqi: Option<u16>
+}
+
+impl DecodedFrame {
+  fn new(kind: &'static str, bytes: &[u8], description: String) -> Self {
+    DecodedFrame {
+      kind,
+      hex: to_hex(bytes),
+      description,
+      device: None,
+      pm25: None,
+      pm10: None,
+      aqi: None
+    }
+  }
+
+  fn response(frame: &[u8], resp: Resp) -> Self {
+    let mut decoded = DecodedFrame::new("response", frame, format!("{:x?}", resp));
+    decoded.device = Some(resp.device().to_string());
+
+    if let Resp::Query(r) = resp {
+      decoded.kind = "reading";
+      decoded.pm25 = Some(r.pm25);
+      decoded.pm10 = Some(r.pm10);
+      decoded.aqi = Some(r.aqi());
+    }
+
+    decoded
+  }
+}
+
+#[wasm_bindgen]
+impl DecodedFrame {
+  /// `reading`, `response` (to any other command), `command` (sent to the
+  /// sensor), `invalid`, `garbage` (bytes outside of any frame), or
+  /// `incomplete` (a frame cut off at the end of the dump)
+  #[wasm_bindgen(getter)]
+  pub fn kind(&self) -> String {
+    self.kind.to_string()
+  }
+
+  /// The frame's bytes, e.g. `AA C0 ...`
+  #[wasm_bindgen(getter)]
+  pub fn hex(&self) -> String {
+    self.hex.clone()
+  }
+
+  /// The decoded frame, or why it couldn't be decoded
+  #[wasm_bindgen(getter)]
+  pub fn description(&self) -> String {
+    self.description.clone()
+  }
+
+  /// The sensor's id, e.g. `0xa1b2`, for responses
+  #[wasm_bindgen(getter)]
+  pub fn device(&self) -> Option<String> {
+    self.device.clone()
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn pm25(&self) -> Option<f32> {
+    self.pm25
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn pm10(&self) -> Option<f32> {
+    self.pm10
+  }
+
+  #[wasm_bindgen(getter)]
+  pub fn aqi(&self) -> Option<u16> {
+    self.aqi
+  }
+}
+
+/// Decodes every frame in a hex dump, e.g. copied from a serial monitor or
+/// a logic analyzer, in both directions. Throws if the hex is malformed.
+#[wasm_bindgen(js_name = decodeHex)]
+pub fn decode_hex(hex: &str) -> Result<Vec<DecodedFrame>, String> {
+  let bytes = parse_hex(hex).map_err(|e| e.to_string())?;
+
+  let mut reader = PacketReader::default();
+  let mut frames = Vec::new();
+  let mut garbage = Vec::new();
+  let mut pending = Vec::new();
+  for byte in bytes {
+    pending.push(byte);
+    let decoded = match reader.push(byte) {
+      None => continue,
+      Some(ReadEvent::Garbage(byte)) => {
+        garbage.push(byte);
+        pending.clear();
+        continue;
+      },
+      Some(ReadEvent::Packet(frame)) => match parse_frame(&frame) {
+        Ok(resp) => DecodedFrame::response(&frame, resp),
+        Err(e) => DecodedFrame::new("invalid", &frame, e.to_string())
+      },
+      Some(ReadEvent::Command(frame)) => match parse_command_frame(&frame) {
+        Ok(command) => DecodedFrame::new("command", &frame, format!("{:x?}", command)),
+        Err(e) => DecodedFrame::new("invalid", &frame, e.to_string())
+      }
+    };
+
+    if !garbage.is_empty() {
+      frames.push(DecodedFrame::new("garbage", &garbage, String::new()));
+      garbage.clear();
+    }
+
+    frames.push(decoded);
+    pending.clear();
+  }
+
+  if !garbage.is_empty() {
+    frames.push(DecodedFrame::new("garbage", &garbage, String::new()));
+  }
+
+  // a frame cut off at the end of the dump
+  if !pending.is_empty() {
+    frames.push(DecodedFrame::new("incomplete", &pending, String::new()));
+  }
+
+  Ok(frames)
+}
+
+/// The AQI for a PM2.5 concentration in µg/m³
+#[wasm_bindgen(js_name = pm25Aqi)]
+pub fn pm25_aqi(concentration: f32) -> u16 {
+  aqi::pm25_aqi(concentration)
+}
+
+/// The AQI for a PM10 concentration in µg/m³
+#[wasm_bindgen(js_name = pm10Aqi)]
+pub fn pm10_aqi(concentration: f32) -> u16 {
+  aqi::pm10_aqi(concentration)
+}
@@ -0,0 +1,35 @@
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use sds011_exporter::Broadcast;
+
+#[test]
+fn every_subscriber_sees_every_message() {
+  let (tx, rx) = channel();
+  let broadcast = Broadcast::spawn(rx);
+
+  let first = broadcast.subscribe();
+  let second = broadcast.subscribe();
+  assert_eq!(broadcast.subscribers(), 2);
+
+  tx.send(1).unwrap();
+  tx.send(2).unwrap();
+  drop(tx);
+
+  assert_eq!(first.iter().collect::<Vec<_>>(), vec![1, 2]);
+  assert_eq!(second.iter().collect::<Vec<_>>(), vec![1, 2]);
+  assert_eq!(broadcast.subscribers(), 0);
+}
+
+#[test]
+fn dropped_subscribers_are_pruned() {
+  let (tx, rx) = channel();
+  let broadcast = Broadcast::spawn(rx);
+
+  let kept = broadcast.subscribe();
+  drop(broadcast.subscribe());
+
+  tx.send("reading").unwrap();
+  assert_eq!(kept.recv_timeout(Duration::from_secs(1)), Ok("reading"));
+  assert_eq!(broadcast.subscribers(), 1);
+}
@@ -0,0 +1,23 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use sds011_exporter::clock::Timestamp;
+
+#[test]
+fn wall_clock_time_is_taken_from_the_current_clock() {
+  let earlier = Timestamp::from(Instant::now() - Duration::from_secs(60));
+
+  let age = SystemTime::now().duration_since(earlier.to_system_time()).unwrap();
+  assert!(age >= Duration::from_secs(60) && age < Duration::from_secs(61));
+  assert!(earlier.elapsed() >= Duration::from_secs(60));
+}
+
+#[test]
+fn durations_never_go_negative() {
+  let earlier = Timestamp::now();
+  let later = Timestamp::from(Instant::now() + Duration::from_secs(5));
+
+  assert!(later > earlier);
+  assert!(later.duration_since(earlier) >= Duration::from_secs(4));
+  assert_eq!(earlier.duration_since(later), Duration::from_secs(0));
+  assert_eq!(later.elapsed(), Duration::from_secs(0));
+}
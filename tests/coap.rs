@@ -0,0 +1,91 @@
+#![cfg(feature = "coap")]
+
+use sds011_exporter::clock::Timestamp;
+use sds011_exporter::response::QueryResponse;
+use sds011_exporter::util::DeviceId;
+use sds011_exporter::{CborWriter, CoapCode, CoapMessage, CoapType};
+
+fn get(kind: CoapType, path: &[&str]) -> CoapMessage {
+  CoapMessage {
+    kind,
+    code: CoapCode::GET,
+    message_id: 0x1234,
+    token: vec![0xAB, 0xCD],
+    path: path.iter().map(|s| s.to_string()).collect(),
+    payload: Vec::new()
+  }
+}
+
+#[test]
+fn parses_a_get_request() {
+  // GET coap://host/sensors/pm, as sent by libcoap
+  let datagram = [
+    0x42, 0x01, 0x12, 0x34, 0xAB, 0xCD,
+    0xB7, b's', b'e', b'n', b's', b'o', b'r', b's',
+    0x02, b'p', b'm'
+  ];
+
+  let request = CoapMessage::parse(&datagram).unwrap();
+  assert_eq!(request, get(CoapType::Confirmable, &["sensors", "pm"]));
+  assert_eq!(request.to_bytes(), datagram);
+}
+
+#[test]
+fn skips_other_options() {
+  // Uri-Host (3) then Uri-Path (11), then Accept (17) with an extended delta
+  let datagram = [
+    0x50, 0x01, 0x00, 0x01,
+    0x34, b'h', b'o', b's', b't',
+    0x82, b'p', b'm',
+    0xD1, 0x00, 0x3C
+  ];
+
+  let request = CoapMessage::parse(&datagram).unwrap();
+  assert_eq!(request.kind, CoapType::NonConfirmable);
+  assert_eq!(request.path, vec!["pm"]);
+}
+
+#[test]
+fn rejects_invalid_messages() {
+  // too short, wrong version, token too long, and a marker with no payload
+  let datagrams: &[&[u8]] = &[
+    &[0x40, 0x01],
+    &[0x80, 0x01, 0x00, 0x00],
+    &[0x49, 0x01, 0x00, 0x00],
+    &[0x40, 0x01, 0x00, 0x00, 0xFF]
+  ];
+
+  for datagram in datagrams {
+    assert_eq!(CoapMessage::parse(datagram), None);
+  }
+}
+
+#[test]
+fn confirmable_requests_get_piggybacked_responses() {
+  let response = get(CoapType::Confirmable, &["pm"]).response(CoapCode::CONTENT, 7, &[0xF6]);
+  assert_eq!(response.to_bytes(), vec![0x62, 0x45, 0x12, 0x34, 0xAB, 0xCD, 0xC1, 60, 0xFF, 0xF6]);
+
+  let response = get(CoapType::NonConfirmable, &["pm"]).response(CoapCode::NOT_FOUND, 7, &[]);
+  assert_eq!(response.to_bytes(), vec![0x52, 0x84, 0x00, 0x07, 0xAB, 0xCD]);
+}
+
+#[test]
+fn encodes_readings_as_cbor() {
+  let mut cbor = CborWriter::new();
+  cbor.reading(None);
+  assert_eq!(cbor.into_bytes(), vec![0xF6]);
+
+  let reading = QueryResponse { pm25: 1.5, pm10: 300.0, device: DeviceId(0xA1B2) };
+  let mut cbor = CborWriter::new();
+  cbor.reading(Some((Timestamp::now(), reading)));
+  let bytes = cbor.into_bytes();
+
+  // {"t": <4-byte uint>, "pm25": 1.5, "pm10": 300.0, "aqi": 173}
+  assert_eq!(&bytes[..3], &[0xA4, 0x61, b't']);
+  assert_eq!(bytes[3], 0x1A);
+  assert_eq!(&bytes[8..], &[
+    0x64, b'p', b'm', b'2', b'5', 0xFA, 0x3F, 0xC0, 0x00, 0x00,
+    0x64, b'p', b'm', b'1', b'0', 0xFA, 0x43, 0x96, 0x00, 0x00,
+    0x63, b'a', b'q', b'i', 0x18, 0xAD
+  ][..]);
+}
@@ -0,0 +1,62 @@
+use sds011_exporter::command::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  parse_command_frame, Error, FrameError, MockSensorTransport, PacketReader, ReadEvent
+};
+
+fn read_all(bytes: &[u8]) -> Vec<ReadEvent> {
+  let mut reader = PacketReader::default();
+  bytes.iter().filter_map(|b| reader.push(*b)).collect()
+}
+
+fn commands() -> Vec<Req> {
+  vec![
+    Req::SetReportingMode(SetReportingMode { query: false, mode: ReportingMode::Query }),
+    Req::Query(Query),
+    Req::SetDeviceId(SetDeviceId { id: DeviceId(0xA001) }),
+    Req::SetSleepWork(SetSleepWork { query: true, mode: WorkMode::Work }),
+    Req::SetWorkingPeriod(SetWorkingPeriod {
+      query: false,
+      working_period: WorkingPeriod::Periodic(5)
+    }),
+    Req::GetFirmwareVersion(GetFirmwareVersion)
+  ]
+}
+
+#[test]
+fn command_frames_decode_back() {
+  for command in commands() {
+    let cmd = command.to_cmd();
+
+    match read_all(cmd.bytes()).as_slice() {
+      [ReadEvent::Command(frame)] => {
+        let decoded = parse_command_frame(frame).unwrap();
+        assert_eq!(decoded, DecodedCommand { command, target: DeviceId(0xFFFF) });
+      },
+      other => panic!("expected one command frame for {:?}, got {:?}", command, other)
+    }
+  }
+}
+
+#[test]
+fn both_directions_decode_cleanly() {
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  let stream = [Query.to_cmd().bytes(), &reply[..]].concat();
+
+  match read_all(&stream).as_slice() {
+    [ReadEvent::Command(_), ReadEvent::Packet(packet)] => assert_eq!(packet, &reply),
+    other => panic!("expected a command then a packet, got {:?}", other)
+  }
+}
+
+#[test]
+fn corrupt_command_frames_are_rejected() {
+  let mut frame = [0u8; COMMAND_LEN];
+  frame.copy_from_slice(Query.to_cmd().bytes());
+  frame[17] = frame[17].wrapping_add(1);
+
+  match parse_command_frame(&frame) {
+    Err(Error::PacketError(FrameError::InvalidCommandChecksum { .. })) => (),
+    other => panic!("expected a checksum error, got {:?}", other)
+  }
+}
@@ -0,0 +1,280 @@
+//! Fixtures shared by the integration tests: retry configs, readings, a
+//! channel-level mock sensor, and a byte-level sensor simulator with scriptable
+//! fault injection, connected to the library's real read and write threads via
+//! `open_sensor_stream()`.
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use sds011_exporter::command::Cmd;
+use sds011_exporter::response::{GetFirmwareVersionResponse, QueryResponse, Resp};
+use sds011_exporter::util::DeviceId;
+use sds011_exporter::{
+  checksum, open_sensor_stream, ControlMessage, RetryConfig, SensorOptions
+};
+
+/// Device id reported by the simulator and used in fixture responses
+pub const DEVICE: DeviceId = DeviceId(0xa1b2);
+
+/// A retry config with short timeouts and almost no sleep between attempts
+pub fn config(retries: usize) -> RetryConfig {
+  config_with_timeout(retries, Duration::from_millis(50))
+}
+
+/// Like `config()`, but for transports slower than a channel, e.g. TCP
+pub fn config_with_timeout(retries: usize, timeout: Duration) -> RetryConfig {
+  RetryConfig::builder()
+    .retries(retries)
+    .timeout(timeout)
+    .sleep(Duration::from_millis(1))
+    .build()
+}
+
+/// A reading from `DEVICE`
+pub fn reading(pm25: f32, pm10: f32) -> QueryResponse {
+  QueryResponse { pm25, pm10, device: DEVICE }
+}
+
+/// A firmware version from `DEVICE`
+pub fn firmware() -> GetFirmwareVersionResponse {
+  GetFirmwareVersionResponse { year: 18, month: 11, day: 16, device: DEVICE }
+}
+
+/// Waits briefly for mocks to count any in-flight commands
+pub fn settle() {
+  thread::sleep(Duration::from_millis(50));
+}
+
+/// Starts a thread that ignores the first `ignore` commands, then answers
+/// each one by passing `responses` to `reply`; returns the command sender and
+/// a count of commands received
+pub fn mock_sensor<F>(
+  ignore: usize,
+  responses: Vec<Resp>,
+  reply: F
+) -> (Sender<Cmd>, Arc<AtomicUsize>)
+where
+  F: Fn(Resp) + Send + 'static
+{
+  let (command_tx, command_rx) = channel::<Cmd>();
+  let received = Arc::new(AtomicUsize::new(0));
+
+  let counter = Arc::clone(&received);
+  thread::spawn(move || {
+    for _ in command_rx {
+      if counter.fetch_add(1, Ordering::SeqCst) >= ignore {
+        for response in &responses {
+          reply(response.clone());
+        }
+      }
+    }
+  });
+
+  (command_tx, received)
+}
+
+/// Length of a command frame: head, id, 15 data bytes, checksum, tail
+const COMMAND_LEN: usize = 19;
+
+/// What goes wrong with the reply to one command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+  /// The reply is sent normally
+  None,
+
+  /// No reply is sent at all
+  Silence,
+
+  /// The first `n` bytes of the reply are lost
+  DropBytes(usize),
+
+  /// The reply has an invalid checksum
+  CorruptChecksum,
+
+  /// The reply is sent twice, as some firmware does for acks
+  DuplicateAck,
+
+  /// `n` garbage bytes (never 0xAA) precede the reply
+  Garbage(usize),
+
+  /// From this command on, queries return the previous reading forever
+  Stuck
+}
+
+#[derive(Debug, Default)]
+struct State {
+  /// one fault per command, in order; commands past the end are unaffected
+  script: VecDeque<Fault>,
+
+  commands: usize,
+  queries: u16,
+  stuck: bool,
+
+  reporting_mode: u8,
+  work_mode: u8,
+  working_period: u8
+}
+
+/// Shared view of a running simulator
+#[derive(Clone)]
+pub struct Simulator {
+  state: Arc<Mutex<State>>
+}
+
+impl Simulator {
+  /// The number of complete commands received so far
+  pub fn commands(&self) -> usize {
+    self.state.lock().unwrap().commands
+  }
+
+  /// Appends faults to the script
+  pub fn script(&self, faults: &[Fault]) {
+    self.state.lock().unwrap().script.extend(faults);
+  }
+}
+
+/// Starts a simulated sensor, returning the same channels as
+/// `open_sensor_channels()` plus a handle to the simulator
+pub fn simulate(
+  options: SensorOptions,
+  script: &[Fault]
+) -> (Sender<Cmd>, Receiver<Resp>, Receiver<ControlMessage>, Simulator) {
+  let state = Arc::new(Mutex::new(State {
+    script: script.iter().cloned().collect(),
+    work_mode: 1,
+    ..State::default()
+  }));
+
+  let (bytes_tx, bytes_rx) = channel();
+  let reader = SimReader { rx: bytes_rx, pending: VecDeque::new() };
+  let writer = SimWriter { state: Arc::clone(&state), tx: bytes_tx, buf: Vec::new() };
+
+  let (command_tx, command_rx) = channel();
+  let (response_tx, response_rx) = channel();
+  let (control_tx, control_rx) = channel();
+  open_sensor_stream("sim", reader, writer, options, command_rx, response_tx, control_tx);
+
+  (command_tx, response_rx, control_rx, Simulator { state })
+}
+
+/// Builds a 10 byte reply frame around the given 6 data bytes
+fn frame(id: u8, data: [u8; 6]) -> Vec<u8> {
+  let mut frame = vec![0xAA, id];
+  frame.extend_from_slice(&data);
+  frame.push(checksum(&data));
+  frame.push(0xAB);
+
+  frame
+}
+
+fn reply(state: &mut State, command: &[u8]) -> Vec<u8> {
+  let [device_hi, device_lo] = DEVICE.to_be_bytes();
+  let (sub_id, set, value) = (command[2], command[3] == 0x01, command[4]);
+
+  let setting = match sub_id {
+    0x02 => Some(&mut state.reporting_mode),
+    0x06 => Some(&mut state.work_mode),
+    0x08 => Some(&mut state.working_period),
+    _ => None
+  };
+
+  if let Some(setting) = setting {
+    if set {
+      *setting = value;
+    }
+
+    return frame(0xC5, [sub_id, command[3], *setting, 0x00, device_hi, device_lo]);
+  }
+
+  match sub_id {
+    0x04 => {
+      if !state.stuck || state.queries == 0 {
+        state.queries += 1;
+      }
+
+      // readings climb by 0.1 per query, unless stuck
+      let [pm25_lo, pm25_hi] = (10 + state.queries).to_le_bytes();
+      let [pm10_lo, pm10_hi] = (20 + state.queries).to_le_bytes();
+      frame(0xC0, [pm25_lo, pm25_hi, pm10_lo, pm10_hi, device_hi, device_lo])
+    },
+    0x05 => frame(0xC5, [0x05, 0x00, 0x00, 0x00, command[13], command[14]]),
+    0x07 => frame(0xC5, [0x07, 18, 11, 16, device_hi, device_lo]),
+    _ => Vec::new()
+  }
+}
+
+/// Receives commands, answering each according to the script
+struct SimWriter {
+  state: Arc<Mutex<State>>,
+  tx: Sender<Vec<u8>>,
+  buf: Vec<u8>
+}
+
+impl Write for SimWriter {
+  fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+    self.buf.extend_from_slice(bytes);
+
+    while self.buf.len() >= COMMAND_LEN {
+      let command: Vec<u8> = self.buf.drain(..COMMAND_LEN).collect();
+
+      let mut state = self.state.lock().unwrap();
+      state.commands += 1;
+
+      let fault = state.script.pop_front().unwrap_or(Fault::None);
+      if fault == Fault::Stuck {
+        state.stuck = true;
+      }
+
+      let mut bytes = reply(&mut state, &command);
+      match fault {
+        Fault::Silence => continue,
+        Fault::DropBytes(n) => {
+          bytes.drain(..n.min(bytes.len()));
+        },
+        Fault::CorruptChecksum => bytes[8] = bytes[8].wrapping_add(1),
+        Fault::DuplicateAck => bytes.extend(bytes.clone()),
+        Fault::Garbage(n) => bytes.splice(..0, vec![0x42; n]).for_each(drop),
+        Fault::None | Fault::Stuck => ()
+      }
+
+      self.tx.send(bytes).ok();
+    }
+
+    Ok(bytes.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Yields reply bytes as the writer produces them, blocking in between
+struct SimReader {
+  rx: Receiver<Vec<u8>>,
+  pending: VecDeque<u8>
+}
+
+impl Read for SimReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.pending.is_empty() {
+      match self.rx.recv() {
+        Ok(bytes) => self.pending.extend(bytes),
+        Err(_) => return Ok(0)
+      }
+    }
+
+    let n = buf.len().min(self.pending.len());
+    for (b, byte) in buf.iter_mut().zip(self.pending.drain(..n)) {
+      *b = byte;
+    }
+
+    Ok(n)
+  }
+}
@@ -0,0 +1,70 @@
+use sds011_exporter::companion::*;
+
+#[test]
+fn sht3x_crc_matches_datasheet() {
+  assert_eq!(sht3x_crc(&[0xBE, 0xEF]), 0x92);
+}
+
+#[test]
+fn sht3x_readings_are_decoded() {
+  // 0x6666 is 25°C, 0x8000 is 50% RH
+  let data = [0x66, 0x66, sht3x_crc(&[0x66, 0x66]), 0x80, 0x00, sht3x_crc(&[0x80, 0x00])];
+  let climate = decode_sht3x(&data).unwrap();
+
+  assert!((climate.temperature - 25.0).abs() < 0.01);
+  assert!((climate.humidity - 50.0).abs() < 0.01);
+}
+
+#[test]
+fn sht3x_checksum_mismatches_are_rejected() {
+  let data = [0x66, 0x66, 0x00, 0x80, 0x00, sht3x_crc(&[0x80, 0x00])];
+  assert!(decode_sht3x(&data).is_err());
+}
+
+/// Calibration with the datasheet's temperature example: T1=27504, T2=26435,
+/// T3=-1000
+fn bme280_calibration(h1: u8, h2: i16) -> Bme280Calibration {
+  let mut low = [0; 26];
+  low[0..2].copy_from_slice(&27504u16.to_le_bytes());
+  low[2..4].copy_from_slice(&26435i16.to_le_bytes());
+  low[4..6].copy_from_slice(&(-1000i16).to_le_bytes());
+  low[25] = h1;
+
+  let mut high = [0; 7];
+  high[0..2].copy_from_slice(&h2.to_le_bytes());
+  high[3] = 0x14;
+  high[4] = 0x0B;
+  high[6] = 30;
+
+  Bme280Calibration::from_registers(&low, &high)
+}
+
+/// Data registers with adc_T=519888 and the given raw humidity
+fn bme280_data(adc_h: u16) -> [u8; 8] {
+  let adc_t: u32 = 519888;
+  let [h_msb, h_lsb] = adc_h.to_be_bytes();
+
+  [0, 0, 0, (adc_t >> 12) as u8, (adc_t >> 4) as u8, (adc_t << 4) as u8, h_msb, h_lsb]
+}
+
+#[test]
+fn bme280_temperature_matches_datasheet() {
+  let climate = bme280_calibration(75, 360).compensate(&bme280_data(30000));
+  assert!((climate.temperature - 25.08).abs() < 0.01, "{:?}", climate);
+}
+
+#[test]
+fn bme280_humidity_is_clamped() {
+  let calibration = bme280_calibration(0, i16::MAX);
+  assert_eq!(calibration.compensate(&bme280_data(u16::MAX)).humidity, 100.0);
+
+  let calibration = bme280_calibration(75, 360);
+  assert_eq!(calibration.compensate(&bme280_data(0)).humidity, 0.0);
+}
+
+#[test]
+fn companion_kinds_are_parsed() {
+  assert_eq!("sht31".parse::<CompanionKind>().unwrap(), CompanionKind::Sht3x);
+  assert_eq!("BME280".parse::<CompanionKind>().unwrap(), CompanionKind::Bme280);
+  assert!("bmp280".parse::<CompanionKind>().is_err());
+}
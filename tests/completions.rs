@@ -0,0 +1,82 @@
+#![cfg(all(unix, feature = "cli"))]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+const SDS011: &str = env!("CARGO_BIN_EXE_sds011");
+
+fn sds011(args: &[&str]) -> String {
+  let output = Command::new(SDS011).args(args).output().unwrap();
+  assert!(output.status.success(), "{:?} failed: {:?}", args, output);
+
+  String::from_utf8(output.stdout).unwrap()
+}
+
+/// A stand-in `sds011` that reports two serial ports, placed in its own
+/// directory so it can go first on the `PATH` the scripts see
+fn fake_sds011() -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("sds011-completions-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+
+  let path = dir.join("sds011");
+  fs::write(&path, "#!/bin/sh\necho /dev/ttyUSB0\necho /dev/ttyUSB1\n").unwrap();
+  fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+  dir
+}
+
+/// Sources the bash script and completes `words`, returning the candidates
+fn complete_bash(script: &str, words: &[&str]) -> Vec<String> {
+  let dir = fake_sds011();
+  let script_path = dir.join("sds011.bash");
+  fs::write(&script_path, script).unwrap();
+
+  let words = words.iter().map(|w| format!("'{}'", w)).collect::<Vec<_>>().join(" ");
+  let output = Command::new("bash")
+    .arg("-c")
+    .arg(format!(
+      concat!(
+        "source {}; COMP_WORDS=({}); COMP_CWORD=$((${{#COMP_WORDS[@]}} - 1)); ",
+        "_sds011_devices; printf '%s\\n' \"${{COMPREPLY[@]}}\""
+      ),
+      script_path.display(),
+      words
+    ))
+    .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap()))
+    .output()
+    .unwrap();
+  assert!(output.status.success(), "{:?}", output);
+
+  String::from_utf8(output.stdout).unwrap().lines().map(String::from).collect()
+}
+
+#[test]
+fn listing_devices_succeeds() {
+  sds011(&["completions", "--devices"]);
+}
+
+#[test]
+fn bash_completes_devices_in_place_of_placeholders() {
+  let script = sds011(&["completions", "bash"]);
+  assert!(script.contains("complete -F _sds011_devices"));
+
+  let candidates = complete_bash(&script, &["sds011", "tool", ""]);
+  assert!(candidates.contains(&"info".to_string()), "{:?}", candidates);
+  assert!(candidates.contains(&"/dev/ttyUSB0".to_string()), "{:?}", candidates);
+  assert!(!candidates.iter().any(|c| c.starts_with('<')), "{:?}", candidates);
+
+  let candidates = complete_bash(&script, &["sds011", "watch", "/dev/ttyUSB1", "/dev/tty"]);
+  assert_eq!(candidates, vec!["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+}
+
+#[test]
+fn zsh_and_fish_scripts_keep_clap_output_intact() {
+  let zsh = sds011(&["completions", "zsh"]);
+  assert!(zsh.contains("_sds011 \"$@\""));
+  assert!(zsh.contains("functions[_sds011_clap]=$functions[_sds011]"));
+
+  let fish = sds011(&["completions", "fish"]);
+  assert!(fish.contains("sds011 completions --devices"));
+}
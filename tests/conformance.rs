@@ -0,0 +1,102 @@
+use sds011_exporter::response::{QueryResponse, Resp};
+use sds011_exporter::util::DeviceId;
+use sds011_exporter::{parse_packet, Error};
+
+/// Captured and reference frames with their expected parse results
+const CORPUS: &str = include_str!("corpus/frames.txt");
+
+struct Case {
+  line: usize,
+  description: String,
+  frame: Vec<u8>,
+  expected: String
+}
+
+fn cases() -> Vec<Case> {
+  let mut cases = Vec::new();
+  let mut description = String::new();
+
+  for (i, line) in CORPUS.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() {
+      continue;
+    }
+
+    if let Some(comment) = line.strip_prefix('#') {
+      description = comment.trim().to_string();
+      continue;
+    }
+
+    let mut parts = line.splitn(2, "=>");
+    let hex = parts.next().unwrap();
+    let expected = parts.next()
+      .unwrap_or_else(|| panic!("frames.txt:{}: missing '=>'", i + 1))
+      .trim()
+      .to_string();
+
+    let frame = hex.split_whitespace()
+      .map(|b| u8::from_str_radix(b, 16)
+        .unwrap_or_else(|e| panic!("frames.txt:{}: invalid byte {:?}: {}", i + 1, b, e)))
+      .collect();
+
+    cases.push(Case { line: i + 1, description: description.clone(), frame, expected });
+  }
+
+  cases
+}
+
+#[test]
+fn corpus_is_not_empty() {
+  assert!(cases().len() >= 20);
+}
+
+#[test]
+fn parse_packet_conforms_to_corpus() {
+  let mut failures = Vec::new();
+
+  for case in cases() {
+    let actual = match parse_packet(&case.frame) {
+      Ok(resp) => format!("{:?}", resp),
+      Err(Error::PacketError(_)) => "error".to_string(),
+      Err(e) => format!("unexpected error: {}", e)
+    };
+
+    if actual != case.expected {
+      failures.push(format!(
+        "frames.txt:{} ({}):\n  expected: {}\n    actual: {}",
+        case.line, case.description, case.expected, actual
+      ));
+    }
+  }
+
+  assert!(failures.is_empty(), "{} frame(s) failed:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn encoded_responses_parse_back() {
+  for case in cases() {
+    if let Ok(resp) = parse_packet(&case.frame) {
+      let encoded = resp.to_frame();
+      assert_eq!(
+        parse_packet(&encoded).ok().as_ref(), Some(&resp),
+        "frames.txt:{} ({}): re-encoded as {:x?}", case.line, case.description, encoded
+      );
+    }
+  }
+}
+
+#[test]
+fn saturated_readings_are_detected() {
+  let saturated = Resp::from(QueryResponse { pm25: 999.9, pm10: 120.0, device: DeviceId(1) });
+
+  match parse_packet(&saturated.to_frame()) {
+    Ok(Resp::Query(r)) => {
+      assert!(r.saturated());
+      assert!(r.pm25_saturated());
+      assert!(!r.pm10_saturated());
+    },
+    other => panic!("expected a reading, got {:?}", other)
+  }
+
+  assert!(!QueryResponse { pm25: 999.8, pm10: 0.0, device: DeviceId(1) }.saturated());
+}
@@ -0,0 +1,25 @@
+use sds011_exporter::{DeviceId, Error};
+
+#[test]
+fn parses_hex_and_decimal() {
+  for s in &["0xa1b2", "0XA1B2", "0xA1b2", "41394"] {
+    assert_eq!(s.parse::<DeviceId>().unwrap(), DeviceId(0xa1b2), "s = {:?}", s);
+  }
+}
+
+#[test]
+fn rejects_invalid_ids() {
+  for s in &["", "0x", "a1b2", "0x1ffff", "65536", "-1"] {
+    match s.parse::<DeviceId>() {
+      Err(Error::InvalidDeviceId(_)) => (),
+      other => panic!("expected an error for {:?}, got {:?}", s, other)
+    }
+  }
+}
+
+#[test]
+fn displays_as_padded_hex() {
+  assert_eq!(DeviceId(0xa1b2).to_string(), "0xa1b2");
+  assert_eq!(DeviceId(0x1).to_string(), "0x0001");
+  assert_eq!(DeviceId(0x1).to_string().parse::<DeviceId>().unwrap(), DeviceId(0x1));
+}
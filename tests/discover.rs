@@ -0,0 +1,41 @@
+mod common;
+
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{probe_transport, DeviceSelector, Error, MockSensorTransport};
+
+use common::{config, firmware, DEVICE};
+
+#[test]
+fn probes_report_the_device_id() {
+  let mut mock = MockSensorTransport::new();
+  mock.respond(firmware());
+
+  assert_eq!(probe_transport(&mut mock, &config(2)).unwrap(), DEVICE);
+}
+
+/// e.g. an actively reported reading arriving before the reply
+#[test]
+fn probes_take_the_id_from_any_frame() {
+  let mut mock = MockSensorTransport::new();
+  mock.reply(&[]);
+  mock.respond(QueryResponse { pm25: 1.0, pm10: 2.0, device: DeviceId(0xBEEF) });
+
+  assert_eq!(probe_transport(&mut mock, &config(2)).unwrap(), DeviceId(0xBEEF));
+}
+
+#[test]
+fn silent_ports_fail_the_probe() {
+  let mut mock = MockSensorTransport::new();
+
+  match probe_transport(&mut mock, &config(2)) {
+    Err(Error::RetriesExceeded { attempts: 2, .. }) => (),
+    other => panic!("expected retries exceeded, got {:?}", other)
+  }
+}
+
+#[test]
+fn selectors_describe_themselves() {
+  assert_eq!(DeviceSelector::DeviceId(DeviceId(0xA1B2)).to_string(), "device id 0xa1b2");
+  assert_eq!(DeviceSelector::UsbSerial("A9X1".into()).to_string(), "USB serial number A9X1");
+}
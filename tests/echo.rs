@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use sds011_exporter::command::*;
+use sds011_exporter::{
+  open_transport_channels, retry_send, Echo, EchoFilter, LinkStats, MockSensorTransport,
+  RetryConfig, SensorOptions, WrittenCommands
+};
+
+fn feed(filter: &mut EchoFilter, bytes: &[u8]) -> (Vec<u8>, usize) {
+  let mut passed = Vec::new();
+  let mut echoes = 0;
+
+  for &byte in bytes {
+    match filter.push(byte) {
+      Echo::Bytes(bytes) => passed.extend_from_slice(bytes),
+      Echo::Complete => echoes += 1,
+      Echo::Partial => ()
+    }
+  }
+
+  (passed, echoes)
+}
+
+#[test]
+fn echoed_commands_are_stripped() {
+  let written = WrittenCommands::new();
+  let mut filter = EchoFilter::new(written.clone(), Duration::from_secs(1));
+
+  let command = Query.to_cmd();
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  written.push(command.bytes());
+
+  let (passed, echoes) = feed(&mut filter, &[command.bytes(), &reply[..]].concat());
+  assert_eq!((passed, echoes), (reply.to_vec(), 1));
+  assert!(written.is_empty());
+}
+
+#[test]
+fn mismatched_bytes_are_released() {
+  let written = WrittenCommands::new();
+  let mut filter = EchoFilter::new(written.clone(), Duration::from_secs(1));
+
+  // the sensor's reply shares the echo's first byte
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  written.push(Query.to_cmd().bytes());
+
+  assert_eq!(feed(&mut filter, &reply), (reply.to_vec(), 0));
+  assert_eq!(written.len(), 1);
+}
+
+#[test]
+fn unechoed_commands_expire() {
+  let written = WrittenCommands::new();
+  let mut filter = EchoFilter::new(written.clone(), Duration::from_millis(10));
+  written.push(Query.to_cmd().bytes());
+
+  std::thread::sleep(Duration::from_millis(20));
+  assert_eq!(feed(&mut filter, &[0xAA]), (vec![0xAA], 0));
+  assert!(written.is_empty());
+}
+
+#[test]
+fn writes_are_confirmed_by_their_echo() {
+  let mock = MockSensorTransport::new();
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  mock.reply(&[Query.to_cmd().bytes(), &reply[..]].concat());
+
+  let stats = LinkStats::new();
+  let options = SensorOptions::builder().local_echo(true).stats(stats.clone()).build();
+  let (tx, rx, _control) = open_transport_channels("mock", mock, options).unwrap();
+
+  let config = RetryConfig::builder().retries(0).timeout(Duration::from_secs(1)).build();
+  let (reading, _) = retry_send(Query, &tx, &rx, &config).unwrap();
+
+  assert_eq!(reading.pm25, 10.0);
+  assert_eq!((stats.echoes(), stats.invalid_packets()), (1, 0));
+}
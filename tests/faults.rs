@@ -0,0 +1,198 @@
+mod common;
+
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  measure_n, retry_send, with_reporting_paused, ControlMessage, Error, LinkStats, SensorOptions,
+  DEFAULT_DEDUP_WINDOW
+};
+
+use common::{config, settle, simulate, Fault};
+
+fn set_period() -> SetWorkingPeriod {
+  SetWorkingPeriod {
+    query: false,
+    working_period: WorkingPeriod::Periodic(5)
+  }
+}
+
+#[test]
+fn silence_is_retried() {
+  let (tx, rx, _control, sim) = simulate(SensorOptions::default(), &[Fault::Silence, Fault::Silence]);
+
+  let (response, _) = retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+
+  assert_eq!(response.working_period, WorkingPeriod::Periodic(5));
+  assert_eq!(sim.commands(), 3);
+}
+
+#[test]
+fn long_silence_exhausts_retries() {
+  let (tx, rx, _control, sim) = simulate(SensorOptions::default(), &[Fault::Silence; 5]);
+
+  match retry_send(set_period(), &tx, &rx, &config(3)) {
+    Err(Error::RetriesExceeded { .. }) => (),
+    other => panic!("expected RetriesExceeded, got {:?}", other)
+  }
+
+  settle();
+  assert_eq!(sim.commands(), 3);
+}
+
+#[test]
+fn corrupt_checksum_is_reported_and_retried() {
+  let (tx, rx, control, sim) = simulate(SensorOptions::default(), &[Fault::CorruptChecksum]);
+
+  retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+  assert_eq!(sim.commands(), 2);
+
+  assert!(matches!(control.try_recv(), Ok(ControlMessage::Opened)));
+  match control.try_recv() {
+    Ok(ControlMessage::Error(Error::PacketError(_))) => (),
+    other => panic!("expected a packet error, got {:?}", other)
+  }
+
+  // the retried reply was valid
+  assert!(matches!(control.try_recv(), Ok(ControlMessage::Resynced)));
+}
+
+#[test]
+fn recent_invalid_frames_are_kept() {
+  let stats = LinkStats::with_invalid_frames(1);
+  let options = SensorOptions::builder().stats(stats.clone()).build();
+  let (tx, rx, _control, _sim) = simulate(
+    options,
+    &[Fault::CorruptChecksum, Fault::None, Fault::CorruptChecksum]
+  );
+
+  retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+  retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+  settle();
+
+  assert_eq!(stats.invalid_packets(), 2);
+
+  let frames = stats.invalid_frames();
+  assert_eq!(frames.len(), 1);
+  assert!(frames[0].error.contains("invalid checksum"), "error = {}", frames[0].error);
+  assert_eq!(frames[0].frame[0], 0xAA);
+}
+
+#[test]
+fn resyncs_after_dropped_bytes() {
+  let (tx, rx, _control, sim) = simulate(SensorOptions::default(), &[Fault::DropBytes(3)]);
+
+  retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+  assert_eq!(sim.commands(), 2);
+
+  // later frames are unaffected by the partial one
+  retry_send(Query, &tx, &rx, &config(1)).unwrap();
+}
+
+#[test]
+fn skips_garbage_before_reply() {
+  let (tx, rx, _control, sim) = simulate(SensorOptions::default(), &[Fault::Garbage(7)]);
+
+  retry_send(set_period(), &tx, &rx, &config(3)).unwrap();
+
+  assert_eq!(sim.commands(), 1);
+}
+
+#[test]
+fn garbage_is_counted_and_reported() {
+  let stats = LinkStats::new();
+  let options = SensorOptions::builder()
+    .garbage_report_interval(Duration::from_secs(60))
+    .stats(stats.clone())
+    .build();
+  let (tx, rx, control, _sim) = simulate(options, &[Fault::Garbage(7), Fault::Garbage(3)]);
+
+  retry_send(set_period(), &tx, &rx, &config(1)).unwrap();
+  retry_send(set_period(), &tx, &rx, &config(1)).unwrap();
+  settle();
+
+  assert_eq!(stats.garbage_bytes(), 10);
+  assert_eq!(stats.packets(), 2);
+  assert_eq!(stats.commands(), 2);
+  assert!(stats.last_packet().is_some());
+
+  // the second burst is held back until the interval passes
+  let reports: Vec<_> = control.try_iter()
+    .filter_map(|m| match m {
+      ControlMessage::Garbage { count, sample } => Some((count, sample)),
+      _ => None
+    })
+    .collect();
+  assert_eq!(reports, vec![(7, vec![0x42; 7])]);
+}
+
+#[test]
+fn duplicate_acks_pass_through_by_default() {
+  let (tx, rx, _control, _sim) = simulate(SensorOptions::default(), &[Fault::DuplicateAck]);
+
+  retry_send(set_period(), &tx, &rx, &config(1)).unwrap();
+  settle();
+
+  assert_eq!(rx.try_iter().count(), 1);
+}
+
+#[test]
+fn duplicate_acks_are_dropped_with_dedup() {
+  let options = SensorOptions::builder().dedup_window(DEFAULT_DEDUP_WINDOW).build();
+  let (tx, rx, _control, _sim) = simulate(options, &[Fault::DuplicateAck]);
+
+  retry_send(set_period(), &tx, &rx, &config(1)).unwrap();
+  settle();
+
+  assert_eq!(rx.try_iter().count(), 0);
+}
+
+#[test]
+fn stuck_values_have_no_spread() {
+  let (tx, rx, _control, _sim) = simulate(SensorOptions::default(), &[]);
+  let summary = measure_n(&tx, &rx, 3, Duration::from_millis(1), &config(1)).unwrap();
+  assert!(summary.pm25.stddev > 0.0);
+
+  let (tx, rx, _control, _sim) = simulate(SensorOptions::default(), &[Fault::Stuck]);
+  let summary = measure_n(&tx, &rx, 3, Duration::from_millis(1), &config(1)).unwrap();
+  assert_eq!(summary.pm25.stddev, 0.0);
+  assert_eq!(summary.pm25.min, summary.pm25.max);
+}
+
+fn reporting_mode(tx: &Sender<Cmd>, rx: &Receiver<Resp>) -> ReportingMode {
+  let (r, _) = retry_send(SetReportingMode {
+    query: true,
+    mode: ReportingMode::Active
+  }, tx, rx, &config(3)).unwrap();
+
+  r.mode
+}
+
+#[test]
+fn reporting_is_paused_and_restored() {
+  let (tx, rx, _control, _sim) = simulate(SensorOptions::default(), &[]);
+  assert_eq!(reporting_mode(&tx, &rx), ReportingMode::Active);
+
+  let during = with_reporting_paused(&tx, &rx, &config(3), |tx, rx| {
+    retry_send(Query, tx, rx, &config(3))?;
+    Ok(reporting_mode(tx, rx))
+  }).unwrap();
+
+  assert_eq!(during, ReportingMode::Query);
+  assert_eq!(reporting_mode(&tx, &rx), ReportingMode::Active);
+}
+
+#[test]
+fn reporting_is_restored_after_failure() {
+  let (tx, rx, _control, _sim) = simulate(SensorOptions::default(), &[]);
+
+  let result: Result<(), Error> = with_reporting_paused(&tx, &rx, &config(3), |_, _| {
+    Err(Error::InvalidReportingMode("test".into()))
+  });
+
+  assert!(matches!(result, Err(Error::InvalidReportingMode(_))));
+  assert_eq!(reporting_mode(&tx, &rx), ReportingMode::Active);
+}
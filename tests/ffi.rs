@@ -0,0 +1,85 @@
+#![cfg(feature = "ffi")]
+
+use sds011_exporter::ffi::*;
+use sds011_exporter::response::{QueryResponse, Resp};
+use sds011_exporter::util::DeviceId;
+use sds011_exporter::{build_frame, pm25_aqi, Command, Query, SetWorkingPeriod, WorkingPeriod};
+
+fn decode(frame: &[u8]) -> (i32, Option<Sds011Response>) {
+  let mut out = std::mem::MaybeUninit::<Sds011Response>::uninit();
+  let status = unsafe { sds011_decode_frame(frame.as_ptr(), frame.len(), out.as_mut_ptr()) };
+  if status == SDS011_OK {
+    (status, Some(unsafe { out.assume_init() }))
+  } else {
+    (status, None)
+  }
+}
+
+#[test]
+fn decodes_a_reading() {
+  let frame = Resp::Query(QueryResponse { pm25: 12.3, pm10: 45.6, device: DeviceId(0xa1b2) })
+    .to_frame();
+
+  let (status, resp) = decode(&frame);
+  let resp = resp.unwrap();
+  assert_eq!(status, SDS011_OK);
+  assert_eq!(resp.kind, Sds011ResponseKind::Query);
+  assert_eq!(resp.device, 0xa1b2);
+  assert!((resp.pm25 - 12.3).abs() < 0.01);
+  assert!((resp.pm10 - 45.6).abs() < 0.01);
+}
+
+#[test]
+fn decodes_a_firmware_version() {
+  let frame = build_frame(0xC5, [0x07, 18, 11, 16, 0xa1, 0xb2]);
+
+  let resp = decode(&frame).1.unwrap();
+  assert_eq!(resp.kind, Sds011ResponseKind::GetFirmwareVersion);
+  assert_eq!((resp.year, resp.month, resp.day), (18, 11, 16));
+  assert_eq!(resp.pm25, 0.0);
+}
+
+#[test]
+fn reports_invalid_frames() {
+  let mut frame = build_frame(0xC0, [1, 2, 3, 4, 5, 6]);
+  assert_eq!(decode(&frame[..9]).0, SDS011_ERR_LENGTH);
+
+  frame[8] ^= 0xFF;
+  assert_eq!(decode(&frame).0, SDS011_ERR_CHECKSUM);
+
+  let frame = build_frame(0xC5, [0x42, 0, 0, 0, 0, 0]);
+  assert_eq!(decode(&frame).0, SDS011_ERR_UNKNOWN_COMMAND);
+
+  let status = unsafe { sds011_decode_frame(std::ptr::null(), 10, std::ptr::null_mut()) };
+  assert_eq!(status, SDS011_ERR_NULL);
+}
+
+#[test]
+fn encodes_commands() {
+  let mut out = [0u8; SDS011_COMMAND_LEN];
+  let len = unsafe { sds011_encode_query(out.as_mut_ptr(), out.len()) };
+  assert_eq!(len, SDS011_COMMAND_LEN as i32);
+  assert_eq!(&out[..], Query.to_cmd().bytes());
+
+  let len = unsafe { sds011_encode_set_working_period(false, 5, out.as_mut_ptr(), out.len()) };
+  assert_eq!(len, SDS011_COMMAND_LEN as i32);
+
+  let expected = SetWorkingPeriod { query: false, working_period: WorkingPeriod::Periodic(5) };
+  assert_eq!(&out[..], expected.to_cmd().bytes());
+}
+
+#[test]
+fn rejects_invalid_encode_arguments() {
+  let mut out = [0u8; SDS011_COMMAND_LEN];
+  let status = unsafe { sds011_encode_set_working_period(false, 31, out.as_mut_ptr(), out.len()) };
+  assert_eq!(status, SDS011_ERR_INVALID_ARGUMENT);
+
+  let status = unsafe { sds011_encode_get_firmware_version(out.as_mut_ptr(), out.len() - 1) };
+  assert_eq!(status, SDS011_ERR_LENGTH);
+}
+
+#[test]
+fn computes_aqi() {
+  assert_eq!(sds011_pm25_aqi(35.4), pm25_aqi(35.4));
+  assert_eq!(sds011_pm10_aqi(300.0), 173);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use sds011_exporter::humidity::*;
+
+use common::reading;
+
+#[test]
+fn no_correction_leaves_readings_alone() {
+  let corrected = HumidityCorrection::None.correct(&reading(12.0, 24.0), 90.0);
+  assert_eq!(corrected, reading(12.0, 24.0));
+}
+
+#[test]
+fn kohler_growth_follows_kappa() {
+  let model = HumidityCorrection::Kohler { kappa: 0.33 };
+
+  // 1 + (0.33 / 1.65) / (1 / 0.5 - 1)
+  assert!((model.growth_factor(50.0) - 1.2).abs() < 1e-4);
+  assert_eq!(model.growth_factor(0.0), 1.0);
+
+  let corrected = model.correct(&reading(12.0, 24.0), 50.0);
+  assert!((corrected.pm25 - 10.0).abs() < 1e-4);
+  assert!((corrected.pm10 - 20.0).abs() < 1e-4);
+}
+
+#[test]
+fn humidity_is_clamped() {
+  let model = HumidityCorrection::Kohler { kappa: DEFAULT_KAPPA };
+  assert_eq!(model.growth_factor(100.0), model.growth_factor(MAX_CORRECTED_HUMIDITY));
+  assert!(model.growth_factor(100.0).is_finite());
+}
+
+#[test]
+fn piecewise_growth_is_interpolated() {
+  let model = HumidityCorrection::Piecewise;
+
+  assert_eq!(model.growth_factor(30.0), 1.0);
+  assert_eq!(model.growth_factor(50.0), 1.0);
+  assert!((model.growth_factor(75.0) - 1.25).abs() < 1e-4);
+  assert_eq!(model.growth_factor(100.0), model.growth_factor(MAX_CORRECTED_HUMIDITY));
+}
+
+#[test]
+fn saturated_values_are_not_corrected() {
+  let corrected = HumidityCorrection::Piecewise.correct(&reading(999.9, 180.0), 90.0);
+  assert_eq!(corrected.pm25, 999.9);
+  assert!((corrected.pm10 - 100.0).abs() < 1e-4);
+}
+
+#[test]
+fn models_round_trip_through_strings() {
+  for s in &["none", "kohler:0.4", "kohler:0.25", "piecewise"] {
+    let model: HumidityCorrection = s.parse().unwrap();
+    assert_eq!(&model.to_string(), s);
+  }
+
+  assert_eq!("kohler".parse::<HumidityCorrection>().unwrap(), HumidityCorrection::Kohler { kappa: DEFAULT_KAPPA });
+  assert!("kohler:-1".parse::<HumidityCorrection>().is_err());
+  assert!("piecewise:2".parse::<HumidityCorrection>().is_err());
+  assert!("linear".parse::<HumidityCorrection>().is_err());
+}
@@ -0,0 +1,43 @@
+#![cfg(unix)]
+
+use std::fs::File;
+
+use sds011_exporter::{DeviceHint, DeviceLock, Error};
+
+#[test]
+fn second_lock_is_busy() {
+  let path = std::env::temp_dir().join(format!("sds011-lock-{}", std::process::id()));
+  File::create(&path).unwrap();
+
+  let lock = DeviceLock::acquire(&path).unwrap();
+  match DeviceLock::acquire(&path) {
+    Err(Error::DeviceBusy(_)) => (),
+    other => panic!("expected DeviceBusy, got {:?}", other)
+  }
+
+  drop(lock);
+  assert!(DeviceLock::acquire(&path).is_ok());
+
+  std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn missing_devices_are_reported_with_a_hint() {
+  let path = std::env::temp_dir().join(format!("sds011-missing-{}", std::process::id()));
+
+  match DeviceLock::acquire(&path) {
+    Err(Error::DeviceMissing { path: missing, hint: DeviceHint::AvailablePorts(_) }) => {
+      assert_eq!(missing, path.to_string_lossy());
+    },
+    other => panic!("expected DeviceMissing, got {:?}", other)
+  }
+}
+
+#[test]
+fn hints_suggest_a_fix() {
+  let hint = DeviceHint::JoinGroup("dialout".to_string()).to_string();
+  assert!(hint.contains("usermod -aG dialout"), "{}", hint);
+
+  let hint = DeviceHint::AvailablePorts(vec!["/dev/ttyUSB1".to_string()]).to_string();
+  assert!(hint.contains("/dev/ttyUSB1"), "{}", hint);
+}
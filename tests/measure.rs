@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use sds011_exporter::{Histogram, PeakHold, SummaryStats};
+
+#[test]
+fn summarizes_odd_count() {
+  let stats = SummaryStats::from_values(&[4.0, 1.0, 3.0, 2.0, 10.0]).unwrap();
+
+  assert_eq!(stats.mean, 4.0);
+  assert_eq!(stats.median, 3.0);
+  assert_eq!(stats.min, 1.0);
+  assert_eq!(stats.max, 10.0);
+  assert!((stats.stddev - 3.162).abs() < 0.001, "stddev = {}", stats.stddev);
+}
+
+#[test]
+fn median_of_even_count_is_midpoint() {
+  let stats = SummaryStats::from_values(&[1.0, 4.0, 2.0, 3.0]).unwrap();
+
+  assert_eq!(stats.median, 2.5);
+}
+
+#[test]
+fn single_value_has_no_spread() {
+  let stats = SummaryStats::from_values(&[7.5]).unwrap();
+
+  assert_eq!(stats.mean, 7.5);
+  assert_eq!(stats.median, 7.5);
+  assert_eq!(stats.stddev, 0.0);
+}
+
+#[test]
+fn no_values() {
+  assert_eq!(SummaryStats::from_values(&[]), None);
+}
+
+#[test]
+fn histogram_buckets_are_cumulative() {
+  let mut histogram = Histogram::new(&[25.0, 5.0, 10.0, 10.0]);
+  for value in &[1.0, 5.0, 7.5, 30.0, 12.0] {
+    histogram.observe(*value);
+  }
+
+  assert_eq!(
+    histogram.buckets(),
+    vec![(5.0, 2), (10.0, 3), (25.0, 4), (f32::INFINITY, 5)]
+  );
+  assert_eq!(histogram.count(), 5);
+  assert_eq!(histogram.sum(), 55.5);
+}
+
+#[test]
+fn peak_holds_for_the_window() {
+  let start = Instant::now();
+  let mut peak = PeakHold::new(Duration::from_secs(60));
+  assert_eq!(peak.peak_at(start), None);
+
+  peak.observe_at(5.0, start);
+  peak.observe_at(40.0, start + Duration::from_secs(10));
+  peak.observe_at(12.0, start + Duration::from_secs(20));
+  peak.observe_at(8.0, start + Duration::from_secs(30));
+
+  assert_eq!(peak.peak_at(start + Duration::from_secs(30)), Some(40.0));
+  assert_eq!(peak.peak_at(start + Duration::from_secs(69)), Some(40.0));
+
+  // the spike expires and the peak decays to the highest reading since
+  assert_eq!(peak.peak_at(start + Duration::from_secs(70)), Some(12.0));
+  assert_eq!(peak.peak_at(start + Duration::from_secs(85)), Some(8.0));
+  assert_eq!(peak.peak_at(start + Duration::from_secs(90)), None);
+}
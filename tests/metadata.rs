@@ -0,0 +1,22 @@
+use sds011_exporter::metadata::SensorMetadata;
+
+#[test]
+fn only_set_fields_are_listed() {
+  let metadata = SensorMetadata {
+    name: None,
+    location: Some("north wall".into())
+  };
+
+  assert!(!metadata.is_empty());
+  assert_eq!(metadata.set().collect::<Vec<_>>(), vec![("location", "north wall")]);
+  assert_eq!(metadata.iter().count(), SensorMetadata::FIELDS.len());
+}
+
+#[test]
+fn default_metadata_is_empty() {
+  let metadata = SensorMetadata::default();
+
+  assert!(metadata.is_empty());
+  assert_eq!(metadata.set().count(), 0);
+  assert_eq!(metadata.iter().collect::<Vec<_>>(), vec![("name", None), ("location", None)]);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::time::Duration;
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::util::*;
+use sds011_exporter::{
+  fetch_info, open_transport_channels, retry_send, set_device_id, ControlMessage, Error,
+  MockSensorTransport, SensorInfo, SensorOptions
+};
+
+use common::config;
+
+#[test]
+fn answers_with_scripted_replies() {
+  let mock = MockSensorTransport::new();
+  mock.reply(&MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]));
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock.clone(), SensorOptions::default()).unwrap();
+  let (reading, _) = retry_send(Query, &tx, &rx, &config(2)).unwrap();
+
+  assert_eq!((reading.pm25, reading.pm10), (10.0, 20.0));
+  assert_eq!(mock.commands(), vec![Query.to_cmd().bytes()]);
+}
+
+#[test]
+fn unanswered_commands_are_retried() {
+  let mock = MockSensorTransport::new();
+  mock.reply(&[])
+    .reply(&MockSensorTransport::frame(0xC5, [0x08, 0x01, 0x05, 0x00, 0xA1, 0xB2]));
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock.clone(), SensorOptions::default()).unwrap();
+  let (response, _) = retry_send(SetWorkingPeriod {
+    query: false,
+    working_period: WorkingPeriod::Periodic(5)
+  }, &tx, &rx, &config(2)).unwrap();
+
+  assert_eq!(response.working_period, WorkingPeriod::Periodic(5));
+  assert_eq!(mock.commands().len(), 2);
+  assert_eq!(mock.remaining_replies(), 0);
+}
+
+#[test]
+fn reports_arrive_unprompted() {
+  let mock = MockSensorTransport::new();
+  let (_tx, rx, _control) =
+    open_transport_channels("mock", mock.clone(), SensorOptions::default()).unwrap();
+
+  mock.report(&MockSensorTransport::frame(0xC0, [0x0A, 0x00, 0x14, 0x00, 0xA1, 0xB2]));
+
+  match rx.recv_timeout(Duration::from_secs(1)) {
+    Ok(Resp::Query(q)) => assert_eq!(q.pm25, 1.0),
+    other => panic!("expected a reading, got {:?}", other)
+  }
+}
+
+#[test]
+fn closing_ends_the_read_thread() {
+  let mock = MockSensorTransport::new();
+  let (_tx, _rx, control) =
+    open_transport_channels("mock", mock.clone(), SensorOptions::default()).unwrap();
+
+  mock.close();
+
+  let closed = control.iter()
+    .take(2)
+    .any(|m| matches!(m, ControlMessage::Closed));
+  assert!(closed);
+}
+
+#[test]
+fn responds_with_constructed_responses() {
+  let mock = MockSensorTransport::new();
+  mock.respond(GetFirmwareVersionResponse { year: 18, month: 11, day: 16, device: DeviceId(0xA1B2) });
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock, SensorOptions::default()).unwrap();
+  let (firmware, _) = retry_send(GetFirmwareVersion, &tx, &rx, &config(2)).unwrap();
+
+  assert_eq!((firmware.year, firmware.device), (18, DeviceId(0xA1B2)));
+}
+
+#[test]
+fn device_id_changes_are_verified() {
+  let mock = MockSensorTransport::new();
+  mock.reply(&MockSensorTransport::frame(0xC5, [0x05, 0x00, 0x00, 0x00, 0xA0, 0x01]))
+    .reply(&MockSensorTransport::frame(0xC5, [0x05, 0x00, 0x00, 0x00, 0xA0, 0x01]));
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock, SensorOptions::default()).unwrap();
+
+  let (response, _) = set_device_id(DeviceId(0xA001), &tx, &rx, &config(2)).unwrap();
+  assert_eq!(response.device, DeviceId(0xA001));
+
+  match set_device_id(DeviceId(0xA002), &tx, &rx, &config(2)) {
+    Err(Error::DeviceIdMismatch { requested, reported })
+      if requested == DeviceId(0xA002) && reported == DeviceId(0xA001) => (),
+    other => panic!("expected a mismatch, got {:?}", other)
+  }
+}
+
+/// Scripts replies to `fetch_info()`'s queries, the last of them from `last`
+fn script_info(mock: &MockSensorTransport, last: DeviceId) {
+  let device = DeviceId(0xA1B2);
+
+  mock.respond(GetFirmwareVersionResponse { year: 18, month: 11, day: 16, device })
+    .respond(SetReportingModeResponse { query: true, mode: ReportingMode::Query, device })
+    .respond(SetWorkingPeriodResponse {
+      query: true,
+      working_period: WorkingPeriod::Continuous,
+      device
+    })
+    .respond(SetSleepWorkResponse { query: true, mode: WorkMode::Work, device: last });
+}
+
+#[test]
+fn info_is_fetched() {
+  let mock = MockSensorTransport::new();
+  script_info(&mock, DeviceId(0xA1B2));
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock, SensorOptions::default()).unwrap();
+  let info = fetch_info(&tx, &rx, &config(2)).unwrap();
+
+  assert_eq!(info, SensorInfo {
+    device_id: DeviceId(0xA1B2),
+    firmware_date: FirmwareDate { year: 2018, month: 11, day: 16 },
+    work_mode: WorkMode::Work,
+    reporting_mode: ReportingMode::Query,
+    working_period: WorkingPeriod::Continuous
+  });
+  assert_eq!(info.firmware_date.to_string(), "2018-11-16");
+}
+
+#[test]
+fn info_from_another_device_is_rejected() {
+  let mock = MockSensorTransport::new();
+  script_info(&mock, DeviceId(0xBEEF));
+
+  let (tx, rx, _control) =
+    open_transport_channels("mock", mock, SensorOptions::default()).unwrap();
+
+  match fetch_info(&tx, &rx, &config(2)) {
+    Err(Error::InconsistentDevice { expected, reported, .. })
+      if expected == DeviceId(0xA1B2) && reported == DeviceId(0xBEEF) => (),
+    other => panic!("expected an inconsistent device, got {:?}", other)
+  }
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "modbus")]
+
+use std::io::Cursor;
+
+use sds011_exporter::clock::Timestamp;
+use sds011_exporter::response::QueryResponse;
+use sds011_exporter::util::DeviceId;
+use sds011_exporter::{
+  ModbusRegisterMap, ModbusRequest, ModbusState, ModbusValue, MODBUS_NO_READING
+};
+
+fn state() -> ModbusState {
+  ModbusState {
+    latest: Some((
+      Timestamp::now(),
+      QueryResponse { pm25: 12.3, pm10: 45.6, device: DeviceId(0xA1B2) }
+    )),
+    up: true,
+    errors: 70_000,
+    fatal_errors: 2
+  }
+}
+
+/// A read of `count` registers from `start`, as framed by a Modbus TCP client
+fn read_request(function: u8, start: u16, count: u16) -> Vec<u8> {
+  let mut adu = vec![0x00, 0x07, 0x00, 0x00, 0x00, 0x06, 0x01, function];
+  adu.extend_from_slice(&start.to_be_bytes());
+  adu.extend_from_slice(&count.to_be_bytes());
+  adu
+}
+
+fn respond(adu: &[u8], map: &ModbusRegisterMap, state: Option<&ModbusState>) -> Vec<u8> {
+  let request = ModbusRequest::read_from(&mut Cursor::new(adu)).unwrap().unwrap();
+  request.respond(map, state)
+}
+
+#[test]
+fn parses_register_maps() {
+  let map: ModbusRegisterMap = "10=pm25, 11=pm10,20=up".parse().unwrap();
+  assert_eq!(map.get(10), Some(ModbusValue::Pm25));
+  assert_eq!(map.get(20), Some(ModbusValue::Up));
+  assert_eq!(map.get(0), None);
+  assert_eq!(map.to_string(), "10=pm25,11=pm10,20=up");
+
+  for invalid in &["0=pm25,0=pm10", "0=pm1", "pm25", "-1=pm25"] {
+    assert!(invalid.parse::<ModbusRegisterMap>().is_err(), "{}", invalid);
+  }
+
+  let default = ModbusRegisterMap::default().to_string();
+  assert_eq!(default, "0=pm25,1=pm10,2=aqi,3=up,4=age,5=errors,6=fatal_errors,7=device");
+}
+
+#[test]
+fn reads_registers() {
+  let map = ModbusRegisterMap::default();
+  let response = respond(&read_request(0x03, 0, 8), &map, Some(&state()));
+
+  assert_eq!(&response[..9], &[0x00, 0x07, 0x00, 0x00, 0x00, 19, 0x01, 0x03, 16]);
+
+  let registers: Vec<u16> = response[9..].chunks(2)
+    .map(|r| u16::from_be_bytes([r[0], r[1]]))
+    .collect();
+  assert_eq!(registers, vec![123, 456, 57, 1, 0, 0xFFFE, 2, 0xA1B2]);
+
+  // input registers hold the same values
+  let response = respond(&read_request(0x04, 1, 1), &map, Some(&state()));
+  assert_eq!(&response[7..], &[0x04, 2, 0x01, 0xC8]);
+}
+
+#[test]
+fn readings_are_flagged_missing() {
+  let state = ModbusState { latest: None, ..state() };
+  assert_eq!(ModbusValue::Pm25.read(&state), MODBUS_NO_READING);
+  assert_eq!(ModbusValue::Age.read(&state), MODBUS_NO_READING);
+  assert_eq!(ModbusValue::Up.read(&state), 1);
+}
+
+#[test]
+fn errors_are_exceptions() {
+  let map: ModbusRegisterMap = "0=pm25,1=pm10".parse().unwrap();
+  let state = state();
+
+  // unmapped address, too many registers, unsupported function, unknown unit
+  let cases = [
+    (read_request(0x03, 1, 2), Some(&state), [0x83, 0x02]),
+    (read_request(0x03, 0, 126), Some(&state), [0x83, 0x03]),
+    (read_request(0x06, 0, 1), Some(&state), [0x86, 0x01]),
+    (read_request(0x03, 0, 1), None, [0x83, 0x0A])
+  ];
+
+  for (request, state, exception) in &cases {
+    let response = respond(request, &map, *state);
+    assert_eq!(&response[4..6], &[0x00, 0x03]);
+    assert_eq!(&response[7..], exception);
+  }
+}
+
+#[test]
+fn reads_requests_until_closed() {
+  let mut stream = Cursor::new(read_request(0x03, 0, 1));
+  assert!(ModbusRequest::read_from(&mut stream).unwrap().is_some());
+  assert_eq!(ModbusRequest::read_from(&mut stream).unwrap(), None);
+
+  // a protocol id other than 0 isn't Modbus
+  let mut invalid = read_request(0x03, 0, 1);
+  invalid[3] = 1;
+  assert!(ModbusRequest::read_from(&mut Cursor::new(invalid)).is_err());
+}
@@ -0,0 +1,68 @@
+use std::convert::TryInto;
+use std::io::Cursor;
+use std::time::{Duration, UNIX_EPOCH};
+
+use sds011_exporter::command::*;
+use sds011_exporter::{Direction, MockSensorTransport, PcapngWriter, SnifferMode, PCAP_LINKTYPE};
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Splits a capture into (block type, body) pairs, checking both lengths agree
+fn blocks(capture: &[u8]) -> Vec<(u32, &[u8])> {
+  let mut blocks = Vec::new();
+  let mut offset = 0;
+
+  while offset < capture.len() {
+    let len = u32_at(capture, offset + 4) as usize;
+    assert_eq!(len % 4, 0, "block at {} isn't padded", offset);
+    assert_eq!(u32_at(capture, offset + len - 4) as usize, len);
+
+    blocks.push((u32_at(capture, offset), &capture[offset + 8..offset + len - 4]));
+    offset += len;
+  }
+
+  blocks
+}
+
+#[test]
+fn captures_are_valid_pcapng() {
+  let time = UNIX_EPOCH + Duration::from_micros(1_600_000_000_123_456);
+  let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+  writer.write_packet(time, &[0xAA, 0xC0, 0x01], Some(Direction::FromSensor), Some("hi")).unwrap();
+
+  let capture = writer.into_inner();
+  let blocks = blocks(&capture);
+  assert_eq!(blocks.iter().map(|(t, _)| *t).collect::<Vec<_>>(), vec![0x0A0D0D0A, 1, 6]);
+
+  let (_, interface) = blocks[1];
+  assert_eq!(u16::from_le_bytes([interface[0], interface[1]]), PCAP_LINKTYPE);
+
+  let (_, packet) = blocks[2];
+  let micros = (u64::from(u32_at(packet, 4)) << 32) | u64::from(u32_at(packet, 8));
+  assert_eq!(micros, 1_600_000_000_123_456);
+  assert_eq!((u32_at(packet, 12), &packet[20..23]), (3, &[0xAA, 0xC0, 0x01][..]));
+
+  // comment, then flags, then the end of options
+  assert_eq!(&packet[24..30], &[1, 0, 2, 0, b'h', b'i']);
+  assert_eq!(&packet[32..40], &[2, 0, 4, 0, 1, 0, 0, 0]);
+  assert_eq!(&packet[40..], &[0, 0, 0, 0]);
+}
+
+#[test]
+fn sniffed_frames_are_written_with_direction() {
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  let capture = [Query.to_cmd().bytes(), &reply[..]].concat();
+
+  let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+  for sniffed in SnifferMode::new(Cursor::new(capture)) {
+    writer.write_sniffed(&sniffed.unwrap()).unwrap();
+  }
+
+  let capture = writer.into_inner();
+  let packets: Vec<_> = blocks(&capture).into_iter().filter(|(t, _)| *t == 6).collect();
+  assert_eq!(packets.len(), 2);
+  assert_eq!(u32_at(packets[0].1, 12), 19);
+  assert_eq!(u32_at(packets[1].1, 12), 10);
+}
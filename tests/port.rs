@@ -0,0 +1,17 @@
+use sds011_exporter::normalize_windows_port_name;
+
+#[test]
+fn accepts_com_port_spellings() {
+  for name in &["COM12", "com12", "COM12:", r"\\.\COM12", r"\\.\com12", " COM12 "] {
+    assert_eq!(normalize_windows_port_name(name), "COM12", "name = {:?}", name);
+  }
+
+  assert_eq!(normalize_windows_port_name("COM3"), "COM3");
+}
+
+#[test]
+fn passes_other_names_through() {
+  for name in &["/dev/ttyUSB0", r"\\.\CNCA0", "COM", "COMx1", "my-port"] {
+    assert_eq!(normalize_windows_port_name(name), *name);
+  }
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use sds011_exporter::quality::ReadingQuality;
+
+use common::reading;
+
+#[test]
+fn readings_are_good_unless_saturated() {
+  assert!(ReadingQuality::of(&reading(12.3, 45.6)).is_good());
+
+  let quality = ReadingQuality::of(&reading(12.3, 999.9));
+  assert!(quality.saturated);
+  assert!(!quality.is_good());
+}
+
+#[test]
+fn flags_are_named_in_order() {
+  let quality = ReadingQuality { warmup: true, stale: true, ..ReadingQuality::default() };
+
+  assert_eq!(quality.flags(), vec!["warmup", "stale"]);
+  assert_eq!(quality.to_string(), "warmup|stale");
+  assert_eq!(ReadingQuality::default().to_string(), "good");
+  assert_eq!(quality.iter().count(), ReadingQuality::FLAGS.len());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use sds011_exporter::response::*;
+use sds011_exporter::{
+  open_transport_channels, LinkStats, MockSensorTransport, ReadingDeduplicator, SensorOptions
+};
+
+use common::reading;
+
+#[test]
+fn repeats_within_the_window_are_duplicates() {
+  let mut dedup = ReadingDeduplicator::new(Duration::from_millis(50));
+
+  assert!(!dedup.is_duplicate(&reading(1.0, 2.0)));
+  assert!(dedup.is_duplicate(&reading(1.0, 2.0)));
+  assert!(!dedup.is_duplicate(&reading(1.5, 2.0)));
+
+  thread::sleep(Duration::from_millis(60));
+  assert!(!dedup.is_duplicate(&reading(1.5, 2.0)));
+}
+
+#[test]
+fn duplicate_readings_are_dropped_and_counted() {
+  let mock = MockSensorTransport::new();
+  let stats = LinkStats::new();
+  let options = SensorOptions::builder()
+    .reading_dedup_window(Duration::from_secs(5))
+    .stats(stats.clone())
+    .build();
+  let (_tx, rx, _control) = open_transport_channels("mock", mock.clone(), options).unwrap();
+
+  let frame = |pm25| Resp::from(reading(pm25, 2.0)).to_frame();
+  mock.report(&[frame(1.0), frame(1.0), frame(3.0)].concat());
+
+  let timeout = Duration::from_secs(1);
+  assert_eq!(rx.recv_timeout(timeout).unwrap(), Resp::from(reading(1.0, 2.0)));
+  assert_eq!(rx.recv_timeout(timeout).unwrap(), Resp::from(reading(3.0, 2.0)));
+  assert_eq!(stats.duplicate_readings(), 1);
+}
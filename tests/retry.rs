@@ -0,0 +1,128 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::{retry_send, Error, RetryConfig};
+
+use common::{config, firmware, reading, settle, DEVICE};
+
+/// A mock sensor answering over a plain channel
+fn mock_sensor(
+  ignore: usize,
+  responses: Vec<Resp>
+) -> (Sender<Cmd>, Receiver<Resp>, Arc<AtomicUsize>) {
+  let (response_tx, response_rx) = channel();
+  let (command_tx, received) = common::mock_sensor(ignore, responses, move |response| {
+    response_tx.send(response).ok();
+  });
+
+  (command_tx, response_rx, received)
+}
+
+#[test]
+fn responds_first_attempt() {
+  let (tx, rx, received) = mock_sensor(0, vec![reading(1.5, 3.0).into()]);
+
+  let (response, other) = retry_send(Query, &tx, &rx, &config(5)).unwrap();
+  settle();
+
+  assert_eq!(response.pm25, 1.5);
+  assert!(other.is_empty());
+  assert_eq!(received.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn retries_until_response() {
+  let (tx, rx, received) = mock_sensor(2, vec![reading(1.5, 3.0).into()]);
+
+  retry_send(Query, &tx, &rx, &config(3)).unwrap();
+  settle();
+
+  assert_eq!(received.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn honors_configured_retries() {
+  for retries in &[1, 2, 4, 5, 7] {
+    let (tx, rx, received) = mock_sensor(usize::MAX, vec![]);
+
+    match retry_send(Query, &tx, &rx, &config(*retries)) {
+      Err(Error::RetriesExceeded { attempts, .. }) => assert_eq!(attempts, *retries),
+      other => panic!("expected RetriesExceeded, got {:?}", other)
+    }
+
+    settle();
+    assert_eq!(received.load(Ordering::SeqCst), *retries, "retries = {}", retries);
+  }
+}
+
+#[test]
+fn zero_retries_still_attempts_once() {
+  let (tx, rx, received) = mock_sensor(0, vec![reading(1.5, 3.0).into()]);
+
+  retry_send(Query, &tx, &rx, &config(0)).unwrap();
+  settle();
+
+  assert_eq!(received.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn gives_up_at_deadline() {
+  let (tx, rx, received) = mock_sensor(usize::MAX, vec![]);
+  let config = RetryConfig::builder()
+    .retries(100)
+    .timeout(Duration::from_millis(30))
+    .sleep(Duration::from_millis(1))
+    .deadline(Duration::from_millis(75))
+    .build();
+
+  let start = Instant::now();
+  assert!(retry_send(Query, &tx, &rx, &config).is_err());
+  let elapsed = start.elapsed();
+  settle();
+
+  assert!(elapsed < Duration::from_millis(500), "took {:?}", elapsed);
+  assert_eq!(received.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn collects_other_responses() {
+  let (tx, rx, _) = mock_sensor(0, vec![firmware().into(), reading(1.5, 3.0).into()]);
+
+  let (response, other) = retry_send(Query, &tx, &rx, &config(1)).unwrap();
+
+  assert_eq!(response.device, DEVICE);
+  assert_eq!(other, vec![Resp::from(firmware())]);
+}
+
+#[test]
+fn flush_discards_stale_responses() {
+  let (tx, command_rx) = channel::<Cmd>();
+  let (response_tx, rx) = channel();
+
+  let stale = Resp::from(reading(1.5, 3.0));
+  response_tx.send(stale.clone()).unwrap();
+
+  thread::spawn(move || {
+    for _ in command_rx {
+      response_tx.send(reading(2.5, 5.0).into()).ok();
+    }
+  });
+
+  let config = RetryConfig::builder()
+    .timeout(Duration::from_millis(30))
+    .sleep(Duration::from_millis(1))
+    .flush(true)
+    .build();
+
+  let (response, other) = retry_send(Query, &tx, &rx, &config).unwrap();
+
+  assert_eq!(response.pm25, 2.5);
+  assert_eq!(other, vec![stale]);
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::{retry_send_async, Error};
+
+use common::{config, reading};
+
+/// A mock sensor answering over a tokio channel, as `async_responses()` does
+fn mock_sensor(
+  ignore: usize,
+  responses: Vec<Resp>
+) -> (Sender<Cmd>, UnboundedReceiver<Resp>, Arc<AtomicUsize>) {
+  let (response_tx, response_rx) = unbounded_channel();
+  let (command_tx, received) = common::mock_sensor(ignore, responses, move |response| {
+    response_tx.send(response).ok();
+  });
+
+  (command_tx, response_rx, received)
+}
+
+#[tokio::test]
+async fn retries_until_response() {
+  let (tx, mut rx, received) = mock_sensor(2, vec![reading(1.5, 3.0).into()]);
+
+  let (response, other) = retry_send_async(Query, &tx, &mut rx, &config(3)).await.unwrap();
+
+  assert_eq!(response.pm25, 1.5);
+  assert!(other.is_empty());
+  assert_eq!(received.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn honors_configured_retries() {
+  let (tx, mut rx, received) = mock_sensor(usize::MAX, vec![]);
+
+  match retry_send_async(Query, &tx, &mut rx, &config(4)).await {
+    Err(Error::RetriesExceeded { attempts, .. }) => assert_eq!(attempts, 4),
+    other => panic!("expected RetriesExceeded, got {:?}", other)
+  }
+
+  tokio::time::delay_for(Duration::from_millis(20)).await;
+  assert_eq!(received.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn fails_fast_once_responses_end() {
+  let (tx, _command_rx) = channel::<Cmd>();
+  let (response_tx, mut rx) = unbounded_channel::<Resp>();
+  drop(response_tx);
+
+  let start = Instant::now();
+  match retry_send_async(Query, &tx, &mut rx, &config(5)).await {
+    Err(Error::ChannelClosed(_)) => (),
+    other => panic!("expected ChannelClosed, got {:?}", other)
+  }
+
+  assert!(start.elapsed() < Duration::from_millis(30));
+}
+
+#[tokio::test]
+async fn can_be_cancelled_by_select() {
+  let (tx, mut rx, _) = mock_sensor(usize::MAX, vec![]);
+  let config = config(100);
+
+  let result = tokio::select! {
+    r = retry_send_async(Query, &tx, &mut rx, &config) => Some(r),
+    _ = tokio::time::delay_for(Duration::from_millis(50)) => None
+  };
+
+  assert!(result.is_none());
+}
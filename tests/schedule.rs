@@ -0,0 +1,48 @@
+use sds011_exporter::{ReportingMode, Schedule, TimeOfDay, WorkingPeriod};
+
+fn at(hour: u8, minute: u8) -> TimeOfDay {
+  TimeOfDay::new(hour, minute).unwrap()
+}
+
+#[test]
+fn parses_rules() {
+  let schedule: Schedule = "22:00=30/query, 07:00=0".parse().unwrap();
+
+  let rules = schedule.rules();
+  assert_eq!(rules.len(), 2);
+  assert_eq!(rules[0].start, at(7, 0));
+  assert_eq!(rules[0].working_period, WorkingPeriod::Continuous);
+  assert_eq!(rules[0].reporting_mode, ReportingMode::Active);
+  assert_eq!(rules[1].working_period, WorkingPeriod::Periodic(30));
+  assert_eq!(rules[1].reporting_mode, ReportingMode::Query);
+
+  assert_eq!(schedule.to_string(), "07:00=0/active,22:00=30/query");
+}
+
+#[test]
+fn rejects_invalid_rules() {
+  for s in &["", "7=0", "25:00=0", "07:00", "07:00=31", "07:00=0/sometimes", "07:00=0,07:00=1"] {
+    assert!(s.parse::<Schedule>().is_err(), "{:?} should not parse", s);
+  }
+}
+
+#[test]
+fn rules_wrap_past_midnight() {
+  let schedule: Schedule = "07:00=0,22:00=30".parse().unwrap();
+
+  assert_eq!(schedule.rule_at(at(12, 0)).start, at(7, 0));
+  assert_eq!(schedule.rule_at(at(23, 0)).start, at(22, 0));
+  assert_eq!(schedule.rule_at(at(3, 0)).start, at(22, 0));
+  assert_eq!(schedule.rule_at(at(7, 0)).start, at(7, 0));
+
+  assert_eq!(schedule.next_rule(at(3, 0)).start, at(7, 0));
+  assert_eq!(schedule.next_rule(at(23, 0)).start, at(7, 0));
+  assert_eq!(schedule.next_rule(at(7, 0)).start, at(22, 0));
+}
+
+#[test]
+fn minutes_until_wraps() {
+  assert_eq!(at(21, 30).minutes_until(at(22, 0)), 30);
+  assert_eq!(at(23, 0).minutes_until(at(7, 0)), 8 * 60);
+  assert_eq!(at(7, 0).minutes_until(at(7, 0)), 24 * 60);
+}
@@ -0,0 +1,47 @@
+use std::io::Cursor;
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+use sds011_exporter::{Direction, MockSensorTransport, SniffEvent, SnifferMode};
+
+#[test]
+fn both_directions_are_decoded() {
+  let reply = MockSensorTransport::frame(0xC0, [0x64, 0x00, 0xC8, 0x00, 0xA1, 0xB2]);
+  let capture = [Query.to_cmd().bytes(), &reply[..], &[0x42]].concat();
+
+  let events: Vec<_> = SnifferMode::new(Cursor::new(capture))
+    .map(|sniffed| sniffed.unwrap().event)
+    .collect();
+
+  let directions: Vec<_> = events.iter().map(SniffEvent::direction).collect();
+  assert_eq!(directions, vec![Some(Direction::ToSensor), Some(Direction::FromSensor), None]);
+
+  match &events[..] {
+    [
+      SniffEvent::Command { command, .. },
+      SniffEvent::Response { response: Resp::Query(q), .. },
+      SniffEvent::Garbage(0x42)
+    ] => {
+      assert_eq!(command.command, Req::Query(Query));
+      assert_eq!(q.pm25, 10.0);
+    },
+    other => panic!("unexpected events: {:?}", other)
+  }
+}
+
+#[test]
+fn corrupt_frames_keep_their_direction() {
+  let mut command = Query.to_cmd().bytes().to_vec();
+  command[17] ^= 0xFF;
+
+  let events: Vec<_> = SnifferMode::new(Cursor::new(command))
+    .map(|sniffed| sniffed.unwrap().event)
+    .collect();
+
+  match &events[..] {
+    [event @ SniffEvent::Invalid { .. }] => {
+      assert_eq!(event.direction(), Some(Direction::ToSensor))
+    },
+    other => panic!("unexpected events: {:?}", other)
+  }
+}
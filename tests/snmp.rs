@@ -0,0 +1,152 @@
+#![cfg(feature = "snmp")]
+
+use sds011_exporter::{Oid, SnmpRequest, SnmpRequestKind, SnmpValue};
+
+const PUBLIC: &[u8] = b"public";
+
+/// Reads a short-form BER value, returning its tag, value, and what follows
+fn tlv(bytes: &[u8]) -> (u8, &[u8], &[u8]) {
+  let len = bytes[1] as usize;
+  assert!(len < 0x80, "long form length in test data");
+  (bytes[0], &bytes[2..2 + len], &bytes[2 + len..])
+}
+
+/// A variable binding's encoded OID, value tag, and value
+type Binding = (Vec<u8>, u8, Vec<u8>);
+
+/// The error status and variable bindings of a response
+fn response_bindings(response: &[u8]) -> (u8, Vec<Binding>) {
+  let (tag, message, _) = tlv(response);
+  assert_eq!(tag, 0x30);
+
+  let (_, _, rest) = tlv(message);
+  let (_, community, rest) = tlv(rest);
+  assert_eq!(community, PUBLIC);
+
+  let (tag, pdu, _) = tlv(rest);
+  assert_eq!(tag, 0xA2);
+
+  let (_, _, rest) = tlv(pdu);
+  let (_, status, rest) = tlv(rest);
+  let (_, _, rest) = tlv(rest);
+  let (_, mut list, _) = tlv(rest);
+
+  let mut bindings = Vec::new();
+  while !list.is_empty() {
+    let (_, binding, rest) = tlv(list);
+    let (_, oid, value) = tlv(binding);
+    let (tag, value, _) = tlv(value);
+    bindings.push((oid.to_vec(), tag, value.to_vec()));
+    list = rest;
+  }
+
+  (status[0], bindings)
+}
+
+/// A request as snmpget and friends send it, with request id 0x1234
+fn request(pdu_tag: u8, field1: u8, field2: u8, oids: &[&[u8]]) -> Vec<u8> {
+  let wrap = |tag: u8, value: &[u8]| [&[tag, value.len() as u8][..], value].concat();
+
+  let bindings: Vec<u8> = oids.iter()
+    .flat_map(|oid| wrap(0x30, &[wrap(0x06, oid), vec![0x05, 0x00]].concat()))
+    .collect();
+
+  let pdu = [
+    wrap(0x02, &[0x12, 0x34]),
+    wrap(0x02, &[field1]),
+    wrap(0x02, &[field2]),
+    wrap(0x30, &bindings)
+  ].concat();
+
+  let message = [wrap(0x02, &[0x01]), wrap(0x04, PUBLIC), wrap(pdu_tag, &pdu)].concat();
+  wrap(0x30, &message)
+}
+
+/// 1.3.6.1.4.1.32473.1.N
+fn oid_bytes(n: u8) -> Vec<u8> {
+  vec![0x2B, 0x06, 0x01, 0x04, 0x01, 0x81, 0xFD, 0x59, 0x01, n]
+}
+
+fn mib() -> Vec<(Oid, SnmpValue)> {
+  let base: Oid = "1.3.6.1.4.1.32473.1".parse().unwrap();
+  vec![
+    (base.child(&[1]), SnmpValue::Integer(-1)),
+    (base.child(&[2]), SnmpValue::OctetString(b"kitchen".to_vec())),
+    (base.child(&[3]), SnmpValue::Counter32(0x8000_0000))
+  ]
+}
+
+#[test]
+fn parses_and_formats_oids() {
+  let oid: Oid = ".1.3.6.1.4.1.32473.1".parse().unwrap();
+  assert_eq!(oid.to_string(), "1.3.6.1.4.1.32473.1");
+  assert!(oid < oid.child(&[0]));
+  assert!(oid.child(&[2]) < oid.child(&[10]));
+
+  for invalid in &["", "1", "1.3.x", "3.1"] {
+    assert!(invalid.parse::<Oid>().is_err(), "{}", invalid);
+  }
+}
+
+#[test]
+fn parses_requests() {
+  let datagram = request(0xA5, 0, 10, &[&oid_bytes(1)]);
+  let request = SnmpRequest::parse(&datagram).unwrap();
+
+  assert_eq!(request.community, PUBLIC);
+  assert_eq!(request.request_id, 0x1234);
+  assert_eq!(request.kind, SnmpRequestKind::GetBulk { non_repeaters: 0, max_repetitions: 10 });
+  assert_eq!(request.oids, vec!["1.3.6.1.4.1.32473.1.1".parse().unwrap()]);
+
+  // SNMPv1
+  let mut v1 = datagram.clone();
+  v1[4] = 0x00;
+  assert_eq!(SnmpRequest::parse(&v1), None);
+  assert_eq!(SnmpRequest::parse(&datagram[..datagram.len() - 1]), None);
+}
+
+#[test]
+fn answers_gets() {
+  let datagram = request(0xA0, 0, 0, &[&oid_bytes(1), &oid_bytes(3), &oid_bytes(4)]);
+  let response = SnmpRequest::parse(&datagram).unwrap().respond(&mib());
+
+  let (status, bindings) = response_bindings(&response);
+  assert_eq!(status, 0);
+  assert_eq!(bindings, vec![
+    (oid_bytes(1), 0x02, vec![0xFF]),
+    (oid_bytes(3), 0x41, vec![0x00, 0x80, 0x00, 0x00, 0x00]),
+    (oid_bytes(4), 0x81, vec![])
+  ]);
+}
+
+#[test]
+fn walks_with_get_next() {
+  let datagram = request(0xA1, 0, 0, &[&oid_bytes(1)[..9], &oid_bytes(3)]);
+  let response = SnmpRequest::parse(&datagram).unwrap().respond(&mib());
+
+  let (_, bindings) = response_bindings(&response);
+  assert_eq!(bindings, vec![
+    (oid_bytes(1), 0x02, vec![0xFF]),
+    (oid_bytes(3), 0x82, vec![])
+  ]);
+}
+
+#[test]
+fn answers_get_bulk() {
+  let datagram = request(0xA5, 0, 5, &[&oid_bytes(1)]);
+  let response = SnmpRequest::parse(&datagram).unwrap().respond(&mib());
+
+  // stops after the end of the MIB
+  let (_, bindings) = response_bindings(&response);
+  let tags: Vec<_> = bindings.iter().map(|(oid, tag, _)| (oid[9], *tag)).collect();
+  assert_eq!(tags, vec![(2, 0x04), (3, 0x41), (3, 0x82)]);
+}
+
+#[test]
+fn rejects_sets() {
+  let datagram = request(0xA3, 0, 0, &[&oid_bytes(1)]);
+  let response = SnmpRequest::parse(&datagram).unwrap().respond(&mib());
+
+  let (status, _) = response_bindings(&response);
+  assert_eq!(status, 17);
+}
@@ -0,0 +1,401 @@
+mod common;
+
+#[cfg(unix)]
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sds011_exporter::command::*;
+use sds011_exporter::response::*;
+#[cfg(feature = "async")]
+use sds011_exporter::util::*;
+#[cfg(unix)]
+use sds011_exporter::DeviceLock;
+use sds011_exporter::{
+  open_transport_channels, ControlMessage, Error, MockSensorTransport, RestartPolicy,
+  SensorOptions, Supervisor, SupervisorConfig, Transport
+};
+
+#[cfg(feature = "async")]
+use common::{config_with_timeout, DEVICE};
+use common::{firmware, reading};
+
+fn supervise(mock: &MockSensorTransport, config: SupervisorConfig) -> Supervisor {
+  let mock = mock.clone();
+  Supervisor::spawn_transport("mock", move || Ok(mock.clone()), config, |_, _| Ok(())).unwrap()
+}
+
+#[test]
+fn subscriptions_are_filtered_by_kind() {
+  let mock = MockSensorTransport::new();
+  let supervisor = supervise(&mock, SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    ..SupervisorConfig::default()
+  });
+
+  let all = supervisor.subscribe();
+  let measurements = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .responses();
+  let acks = supervisor.subscription().filter(ResponseKindSet::ACKS).responses();
+
+  mock.respond(firmware());
+  supervisor.commands().send(GetFirmwareVersion.to_cmd()).unwrap();
+  mock.report(&Resp::from(reading(1.0, 2.0)).to_frame());
+
+  let timeout = Duration::from_secs(1);
+  assert_eq!(measurements.recv_timeout(timeout).unwrap().kind(), ResponseKind::Measurement);
+  assert_eq!(acks.recv_timeout(timeout).unwrap().kind(), ResponseKind::Ack);
+  assert!(all.recv_timeout(timeout).is_ok());
+  assert!(all.recv_timeout(timeout).is_ok());
+
+  assert!(measurements.recv_timeout(Duration::from_millis(50)).is_err());
+  assert!(acks.recv_timeout(Duration::from_millis(50)).is_err());
+}
+
+#[test]
+fn kind_sets_combine() {
+  let set = ResponseKindSet::MEASUREMENTS | ResponseKindSet::ACKS;
+
+  assert_eq!(set, ResponseKindSet::ALL);
+  assert!(set.matches(&Resp::from(reading(1.0, 2.0))));
+  assert!(!ResponseKindSet::ACKS.matches(&Resp::from(reading(1.0, 2.0))));
+  assert!(!ResponseKindSet::NONE.contains(ResponseKind::Ack));
+}
+
+#[test]
+fn backlog_over_the_watermark_is_reported() {
+  let mock = MockSensorTransport::new();
+  let supervisor = supervise(&mock, SupervisorConfig {
+    sleep: Duration::from_millis(300),
+    backlog_watermark: Some(2),
+    ..SupervisorConfig::default()
+  });
+  let events = supervisor.events();
+
+  // all arrive while the supervisor is sleeping
+  let frame = Resp::from(reading(1.0, 2.0)).to_frame();
+  mock.report(&[frame, frame, frame].concat());
+
+  match events.recv_timeout(Duration::from_secs(1)) {
+    Ok(ControlMessage::Backlog { depth }) => assert_eq!(depth, 3),
+    other => panic!("expected a backlog warning, got {:?}", other)
+  }
+}
+
+#[test]
+fn watchers_see_the_latest_reading() {
+  let mock = MockSensorTransport::new();
+  let supervisor = supervise(&mock, SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    ..SupervisorConfig::default()
+  });
+
+  let mut watch = supervisor.watch();
+  assert!(!watch.has_changed());
+  assert!(watch.latest().is_none());
+
+  mock.report(&Resp::from(reading(1.0, 2.0)).to_frame());
+  assert!(watch.wait(Duration::from_secs(1)));
+  assert_eq!(watch.latest().map(|(_, r)| r), Some(reading(1.0, 2.0)));
+  assert!(!watch.has_changed());
+
+  // a new watcher hasn't seen the current reading yet
+  assert!(supervisor.watch().has_changed());
+
+  mock.close();
+  assert!(watch.wait(Duration::from_secs(1)));
+  assert!(watch.latest().is_none());
+  assert!(!watch.wait(Duration::from_secs(1)));
+  assert!(watch.is_closed());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_watchers_see_the_latest_reading() {
+  let mock = MockSensorTransport::new();
+  let supervisor = supervise(&mock, SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    ..SupervisorConfig::default()
+  });
+
+  let mut watch = supervisor.watch_async();
+  assert_eq!(watch.recv().await, Some(None));
+
+  mock.report(&Resp::from(reading(1.0, 2.0)).to_frame());
+  let latest = watch.recv().await.unwrap();
+  assert_eq!(latest.map(|(_, r)| r), Some(reading(1.0, 2.0)));
+
+  mock.close();
+  assert_eq!(watch.recv().await, Some(None));
+  assert_eq!(watch.recv().await, None);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn query_fallback_switch_is_acked_beside_a_reading_cache() {
+  let mock = MockSensorTransport::new();
+  let supervisor = supervise(&mock, SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    ..SupervisorConfig::default()
+  });
+
+  // as in the exporter's poller, readings are consumed elsewhere
+  let readings = supervisor.subscription()
+    .filter(ResponseKindSet::MEASUREMENTS)
+    .responses();
+
+  let ack = SetReportingModeResponse { query: false, mode: ReportingMode::Query, device: DEVICE };
+  mock.respond(ack.clone());
+
+  let config = config_with_timeout(1, Duration::from_secs(1));
+  let switch = SetReportingMode { query: false, mode: ReportingMode::Query };
+  assert_eq!(supervisor.send_async(switch, &config).await.unwrap(), ack);
+  assert_eq!(mock.commands(), vec![switch.to_cmd().bytes()]);
+  assert!(readings.recv_timeout(Duration::from_millis(50)).is_err());
+
+  mock.respond(reading(1.0, 2.0));
+  assert_eq!(supervisor.send_async(Query, &config).await.unwrap(), reading(1.0, 2.0));
+}
+
+/// A mock whose next write panics while `armed`
+#[derive(Clone)]
+struct PanicOnWrite {
+  mock: MockSensorTransport,
+  armed: Arc<AtomicBool>
+}
+
+impl Read for PanicOnWrite {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.mock.read(buf)
+  }
+}
+
+impl Write for PanicOnWrite {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if self.armed.swap(false, Ordering::SeqCst) {
+      panic!("injected write panic");
+    }
+
+    self.mock.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.mock.flush()
+  }
+}
+
+impl Transport for PanicOnWrite {
+  fn try_clone(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    self.mock.set_timeout(timeout)
+  }
+}
+
+#[test]
+fn thread_panics_restart_the_sensor() {
+  let transport = PanicOnWrite {
+    mock: MockSensorTransport::new(),
+    armed: Arc::new(AtomicBool::new(true))
+  };
+
+  let config = SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    restart: RestartPolicy::Restart {
+      delay: Duration::from_millis(10),
+      max_restarts: Some(1)
+    },
+    ..SupervisorConfig::default()
+  };
+
+  let open = move || Ok(transport.clone());
+  let supervisor = Supervisor::spawn_transport("mock", open, config, |_, _| Ok(())).unwrap();
+  let events = supervisor.events();
+
+  supervisor.commands().send(GetFirmwareVersion.to_cmd()).unwrap();
+
+  let timeout = Duration::from_secs(1);
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnecting { attempt: 1 })));
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnected)));
+
+  let stats = supervisor.stats();
+  assert_eq!(stats.fatal_errors, 1);
+  assert_eq!(stats.panics, 1);
+  assert_eq!(stats.restarts, 1);
+  assert!(stats.running);
+}
+
+#[test]
+fn failed_reopens_are_not_counted_as_restarts() {
+  let transport = PanicOnWrite {
+    mock: MockSensorTransport::new(),
+    armed: Arc::new(AtomicBool::new(true))
+  };
+
+  let config = SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    restart: RestartPolicy::Restart {
+      delay: Duration::from_millis(10),
+      max_restarts: Some(1)
+    },
+    ..SupervisorConfig::default()
+  };
+
+  // only the first open succeeds
+  let opened = AtomicBool::new(false);
+  let open = move || match opened.swap(true, Ordering::SeqCst) {
+    false => Ok(transport.clone()),
+    true => Err(Error::DeviceBusy("mock".into()))
+  };
+  let supervisor = Supervisor::spawn_transport("mock", open, config, |_, _| Ok(())).unwrap();
+  let events = supervisor.events();
+
+  supervisor.commands().send(GetFirmwareVersion.to_cmd()).unwrap();
+
+  let timeout = Duration::from_secs(1);
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnecting { attempt: 1 })));
+
+  // wait for it to give up
+  let deadline = Instant::now() + timeout;
+  while supervisor.stats().running {
+    assert!(Instant::now() < deadline, "supervisor never gave up");
+    thread::sleep(Duration::from_millis(5));
+  }
+
+  let stats = supervisor.stats();
+  assert_eq!(stats.fatal_errors, 2);
+  assert_eq!(stats.restarts, 0);
+  assert!(!stats.running);
+}
+
+/// A transport holding a lock on a device, as a serial port would, until its
+/// last handle is dropped
+#[cfg(unix)]
+#[derive(Clone)]
+struct LockedTransport {
+  inner: PanicOnWrite,
+  _lock: Arc<DeviceLock>
+}
+
+#[cfg(unix)]
+impl Read for LockedTransport {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.inner.read(buf)
+  }
+}
+
+#[cfg(unix)]
+impl Write for LockedTransport {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.inner.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(unix)]
+impl Transport for LockedTransport {
+  fn try_clone(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+
+  fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+    self.inner.set_timeout(timeout)
+  }
+}
+
+#[cfg(unix)]
+#[test]
+fn write_failures_release_the_lock_for_reopening() {
+  let path = std::env::temp_dir().join(format!("sds011-supervisor-{}", std::process::id()));
+  File::create(&path).unwrap();
+
+  let inner = PanicOnWrite {
+    mock: MockSensorTransport::new(),
+    armed: Arc::new(AtomicBool::new(true))
+  };
+
+  let config = SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    restart: RestartPolicy::Restart {
+      delay: Duration::from_millis(10),
+      max_restarts: Some(1)
+    },
+    ..SupervisorConfig::default()
+  };
+
+  let lock_path = path.clone();
+  let open = move || {
+    let lock = Arc::new(DeviceLock::acquire(&lock_path)?);
+    Ok(LockedTransport { inner: inner.clone(), _lock: lock })
+  };
+
+  let supervisor = Supervisor::spawn_transport("mock", open, config, |_, _| Ok(())).unwrap();
+  let events = supervisor.events();
+
+  // only the write thread dies; the read thread has to let go of the lock too
+  supervisor.commands().send(GetFirmwareVersion.to_cmd()).unwrap();
+
+  let timeout = Duration::from_secs(5);
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnecting { attempt: 1 })));
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnected)));
+  assert_eq!(supervisor.stats().fatal_errors, 1);
+
+  std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn dropping_the_response_receiver_closes_the_sensor() {
+  let mock = MockSensorTransport::new();
+  let (_commands, responses, control) =
+    open_transport_channels("mock", mock.clone(), SensorOptions::default()).unwrap();
+  assert!(matches!(control.recv_timeout(Duration::from_secs(1)), Ok(ControlMessage::Opened)));
+
+  drop(responses);
+  mock.report(&Resp::from(reading(1.0, 2.0)).to_frame());
+
+  let timeout = Duration::from_secs(1);
+  assert!(matches!(control.recv_timeout(timeout), Ok(ControlMessage::Closed)));
+
+  // the write thread stops with it
+  assert!(control.recv_timeout(timeout).is_err());
+}
+
+#[test]
+fn recent_events_are_kept() {
+  let transport = PanicOnWrite {
+    mock: MockSensorTransport::new(),
+    armed: Arc::new(AtomicBool::new(true))
+  };
+
+  let config = SupervisorConfig {
+    sleep: Duration::from_millis(5),
+    restart: RestartPolicy::Restart {
+      delay: Duration::from_millis(10),
+      max_restarts: Some(1)
+    },
+    event_history: 2,
+    ..SupervisorConfig::default()
+  };
+
+  let open = move || Ok(transport.clone());
+  let supervisor = Supervisor::spawn_transport("mock", open, config, |_, _| Ok(())).unwrap();
+  let events = supervisor.events();
+
+  supervisor.commands().send(GetFirmwareVersion.to_cmd()).unwrap();
+  let timeout = Duration::from_secs(1);
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnecting { .. })));
+  assert!(matches!(events.recv_timeout(timeout), Ok(ControlMessage::Reconnected)));
+
+  // older events, i.e. the panic, were dropped
+  let kinds: Vec<_> = supervisor.recent_events().iter().map(|e| e.kind).collect();
+  assert_eq!(kinds, vec!["reconnecting", "reconnected"]);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use sds011_exporter::command::*;
+use sds011_exporter::{
+  open_transport_channels, retry_send, DeviceId, Error, SensorOptions, Supervisor,
+  SupervisorConfig
+};
+
+use common::config_with_timeout;
+
+/// Firmware version reply: 2018-11-16, device 0xa1b2
+const FIRMWARE: [u8; 10] = [0xAA, 0xC5, 0x07, 0x12, 0x0B, 0x10, 0xA1, 0xB2, 0x87, 0xAB];
+
+/// Retry timeout allowing for TCP round trips
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Starts a TCP "serial bridge" that answers every command with the firmware
+/// version, returning its address
+fn bridge() -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap().to_string();
+
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = stream.unwrap();
+      thread::spawn(move || {
+        let mut command = [0u8; 19];
+        while stream.read_exact(&mut command).is_ok() {
+          if stream.write_all(&FIRMWARE).is_err() {
+            break;
+          }
+        }
+      });
+    }
+  });
+
+  addr
+}
+
+#[test]
+fn commands_work_over_tcp() {
+  let stream = TcpStream::connect(bridge()).unwrap();
+  let (tx, rx, _control) =
+    open_transport_channels("bridge", stream, SensorOptions::default()).unwrap();
+
+  let config = config_with_timeout(3, TIMEOUT);
+  let (firmware, _) = retry_send(GetFirmwareVersion, &tx, &rx, &config).unwrap();
+
+  assert_eq!((firmware.year, firmware.month, firmware.day), (18, 11, 16));
+}
+
+#[test]
+fn supervisor_works_over_tcp() {
+  let addr = bridge();
+  let supervisor = Supervisor::spawn_transport(
+    "bridge",
+    move || TcpStream::connect(&addr).map_err(Error::TransportError),
+    SupervisorConfig::default(),
+    |_, _| Ok(())
+  ).unwrap();
+
+  let responses = supervisor.subscribe();
+  let config = config_with_timeout(3, TIMEOUT);
+  let (firmware, _) =
+    retry_send(GetFirmwareVersion, &supervisor.commands(), &responses, &config).unwrap();
+
+  assert_eq!(firmware.device, DeviceId(0xa1b2));
+}
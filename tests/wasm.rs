@@ -0,0 +1,61 @@
+#![cfg(feature = "wasm")]
+
+use sds011_exporter::response::{QueryResponse, Resp};
+use sds011_exporter::util::{to_hex, DeviceId};
+use sds011_exporter::wasm::decode_hex;
+use sds011_exporter::{Command, Query};
+
+fn reading() -> Vec<u8> {
+  Resp::Query(QueryResponse { pm25: 12.3, pm10: 45.6, device: DeviceId(0xa1b2) })
+    .to_frame()
+    .to_vec()
+}
+
+#[test]
+fn decodes_a_dump_in_both_directions() {
+  let dump = format!("{} {}", to_hex(Query.to_cmd().bytes()), to_hex(&reading()));
+
+  let frames = decode_hex(&dump).unwrap();
+  assert_eq!(frames.len(), 2);
+  assert_eq!(frames[0].kind(), "command");
+
+  assert_eq!(frames[1].kind(), "reading");
+  assert_eq!(frames[1].hex(), to_hex(&reading()));
+  assert_eq!(frames[1].device().as_deref(), Some("0xa1b2"));
+  assert_eq!(frames[1].pm25(), Some(12.3));
+  assert_eq!(frames[1].aqi(), Some(57));
+}
+
+#[test]
+fn reports_garbage_and_incomplete_frames() {
+  let mut bytes = vec![0x01, 0x02];
+  bytes.extend(reading());
+  bytes.extend(&reading()[..4]);
+
+  let frames = decode_hex(&to_hex(&bytes)).unwrap();
+  let kinds: Vec<String> = frames.iter().map(|f| f.kind()).collect();
+  assert_eq!(kinds, ["garbage", "reading", "incomplete"]);
+  assert_eq!(frames[0].hex(), "01 02");
+  assert_eq!(frames[2].hex(), "AA C0 7B 00");
+}
+
+#[test]
+fn reports_invalid_frames() {
+  let mut frame = reading();
+  frame[8] ^= 0xFF;
+
+  let frames = decode_hex(&to_hex(&frame)).unwrap();
+  assert_eq!(frames[0].kind(), "invalid");
+  assert!(frames[0].description().contains("checksum"));
+  assert_eq!(frames[0].pm25(), None);
+}
+
+#[test]
+fn accepts_common_hex_formats() {
+  let frames = decode_hex("0xAA,0xC0 7b00c801a1b2 0x97 0xab").unwrap();
+  assert_eq!(frames.len(), 1);
+  assert_eq!(frames[0].kind(), "reading");
+
+  assert!(decode_hex("AA C").unwrap_err().contains("odd number of digits"));
+  assert!(decode_hex("AA XY").is_err());
+}
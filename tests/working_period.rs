@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use sds011_exporter::{Error, WorkingPeriod};
+
+#[test]
+fn parses_minutes_with_optional_units() {
+  for (s, expected) in &[
+    ("0", WorkingPeriod::Continuous),
+    ("5", WorkingPeriod::Periodic(5)),
+    ("5m", WorkingPeriod::Periodic(5)),
+    ("5 min", WorkingPeriod::Periodic(5)),
+    ("300s", WorkingPeriod::Periodic(5)),
+    ("10s", WorkingPeriod::Continuous),
+    ("30m", WorkingPeriod::Periodic(30))
+  ] {
+    assert_eq!(s.parse::<WorkingPeriod>().unwrap(), *expected, "s = {:?}", s);
+  }
+}
+
+#[test]
+fn rejects_invalid_periods() {
+  assert!(matches!("".parse::<WorkingPeriod>(), Err(Error::InvalidWorkingPeriod { .. })));
+  assert!(matches!("5h".parse::<WorkingPeriod>(), Err(Error::InvalidWorkingPeriodUnit { .. })));
+  assert!(matches!("31m".parse::<WorkingPeriod>(), Err(Error::WorkingPeriodOutOfRange(31))));
+  assert!(matches!("1900s".parse::<WorkingPeriod>(), Err(Error::WorkingPeriodOutOfRange(32))));
+}
+
+#[test]
+fn converts_to_and_from_durations() {
+  assert_eq!(WorkingPeriod::Continuous.as_duration(), Duration::from_secs(1));
+  assert_eq!(WorkingPeriod::Periodic(5).as_duration(), Duration::from_secs(300));
+
+  assert_eq!(WorkingPeriod::from_duration(Duration::from_secs(29)).unwrap(), WorkingPeriod::Continuous);
+  assert_eq!(WorkingPeriod::from_duration(Duration::from_secs(90)).unwrap(), WorkingPeriod::Periodic(2));
+  assert!(WorkingPeriod::from_duration(Duration::from_secs(31 * 60)).is_err());
+
+  for n in 1..=30 {
+    let period = WorkingPeriod::Periodic(n);
+    assert_eq!(WorkingPeriod::from_duration(period.as_duration()).unwrap(), period);
+  }
+}